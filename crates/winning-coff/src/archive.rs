@@ -0,0 +1,123 @@
+use color_eyre::Result;
+
+use crate::diagnostics::{Code, fatal};
+
+pub const ARCHIVE_MAGIC: &[u8] = b"!<arch>\n";
+const MEMBER_HEADER_SIZE: usize = 60;
+
+/// A `.lib`/`.a` archive. Only the first linker member is used for the symbol
+/// index, since both MSVC and GNU archives have it.
+pub struct Archive<'a> {
+    pub name: String,
+    data: &'a [u8],
+    long_names: Option<(usize, usize)>,
+    /// Symbol names and the offset of the header of the member defining them.
+    pub symbols: Vec<(&'a str, u32)>,
+}
+
+pub struct Member<'a> {
+    pub name: &'a str,
+    pub data: &'a [u8],
+}
+
+impl<'a> Archive<'a> {
+    pub fn parse(name: String, data: &'a [u8]) -> Result<Self> {
+        if !data.starts_with(ARCHIVE_MAGIC) {
+            return fatal(Code::CorruptInput, "not an archive");
+        }
+        let mut archive = Self {
+            name,
+            data,
+            long_names: None,
+            symbols: Vec::new(),
+        };
+
+        let mut offset = ARCHIVE_MAGIC.len();
+        let mut seen_index = false;
+        while offset + MEMBER_HEADER_SIZE <= archive.data.len() {
+            let (raw_name, start, size) = archive.member_header(offset)?;
+            match raw_name {
+                "/" if !seen_index => {
+                    seen_index = true;
+                    archive.symbols = parse_symbol_index(&archive.data[start..][..size])?;
+                }
+                "/" => {}
+                "//" => archive.long_names = Some((start, size)),
+                _ => break,
+            }
+            offset = start + size + size % 2;
+        }
+
+        Ok(archive)
+    }
+
+    /// Returns the raw name field, data offset and data size of the member at `offset`.
+    fn member_header(&self, offset: usize) -> Result<(&'a str, usize, usize)> {
+        let Some(header) = self.data.get(offset..offset + MEMBER_HEADER_SIZE) else {
+            return fatal(Code::CorruptInput, "archive member header out of bounds");
+        };
+        if &header[58..60] != b"`\n" {
+            return fatal(Code::CorruptInput, "invalid archive member header");
+        }
+        let name = std::str::from_utf8(&header[..16])
+            .unwrap_or_default()
+            .trim_end();
+        let size = std::str::from_utf8(&header[48..58])
+            .ok()
+            .and_then(|size| size.trim_end().parse::<usize>().ok());
+        let start = offset + MEMBER_HEADER_SIZE;
+        match size {
+            Some(size) if start + size <= self.data.len() => Ok((name, start, size)),
+            _ => fatal(Code::CorruptInput, "invalid archive member size"),
+        }
+    }
+
+    pub fn member_at(&self, offset: u32) -> Result<Member<'a>> {
+        let (raw_name, start, size) = self.member_header(offset as usize)?;
+        let name = match raw_name.strip_prefix('/') {
+            Some(index) if !index.is_empty() => {
+                let long = index.parse::<usize>().ok().zip(self.long_names).and_then(
+                    |(index, (start, size))| {
+                        let names = self.data[start..][..size].get(index..)?;
+                        let end = names.iter().position(|&b| b == b'\n' || b == 0)?;
+                        std::str::from_utf8(&names[..end]).ok()
+                    },
+                );
+                match long {
+                    Some(name) => name.trim_end_matches('/'),
+                    None => return fatal(Code::CorruptInput, "invalid long member name"),
+                }
+            }
+            _ => raw_name.trim_end_matches('/'),
+        };
+        Ok(Member {
+            name,
+            data: &self.data[start..][..size],
+        })
+    }
+}
+
+fn parse_symbol_index(data: &[u8]) -> Result<Vec<(&str, u32)>> {
+    let read_u32 = |pos: usize| {
+        data.get(pos..pos + 4)
+            .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+    };
+    let Some(count) = read_u32(0) else {
+        return fatal(Code::CorruptInput, "truncated archive symbol index");
+    };
+    let mut names = data.get(4 + count as usize * 4..).unwrap_or_default();
+    let mut symbols = Vec::with_capacity(count as usize);
+    for i in 0..count as usize {
+        let offset = read_u32(4 + i * 4);
+        let end = names.iter().position(|&b| b == 0);
+        let (Some(offset), Some(end)) = (offset, end) else {
+            return fatal(Code::CorruptInput, "truncated archive symbol index");
+        };
+        let Ok(name) = std::str::from_utf8(&names[..end]) else {
+            return fatal(Code::CorruptInput, "invalid archive symbol name");
+        };
+        symbols.push((name, offset));
+        names = &names[end + 1..];
+    }
+    Ok(symbols)
+}
@@ -0,0 +1,119 @@
+use std::ops::Index;
+
+const CHUNK_LEN: usize = 1 << 16;
+const PAGE_LEN: usize = 1 << 16;
+
+/// Append-only storage split into fixed-size chunks, so pushing never moves or
+/// reallocates the existing elements and growing to millions of entries doesn't
+/// require one giant contiguous allocation.
+pub struct Arena<T> {
+    chunks: Vec<Vec<T>>,
+    len: usize,
+}
+
+impl<T> Arena<T> {
+    pub fn new() -> Self {
+        Self {
+            chunks: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, value: T) -> u32 {
+        if self
+            .chunks
+            .last()
+            .is_none_or(|chunk| chunk.len() == CHUNK_LEN)
+        {
+            self.chunks.push(Vec::with_capacity(CHUNK_LEN));
+        }
+        self.chunks.last_mut().unwrap().push(value);
+        let idx = self.len;
+        self.len += 1;
+        idx.try_into().expect("more than u32::MAX arena entries")
+    }
+
+    pub fn get(&self, idx: u32) -> Option<&T> {
+        let idx = idx as usize;
+        self.chunks.get(idx / CHUNK_LEN)?.get(idx % CHUNK_LEN)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.chunks.iter().flatten()
+    }
+
+    /// The bytes allocated for the chunks, including their unused capacity.
+    pub fn allocated_bytes(&self) -> usize {
+        self.chunks.iter().map(Vec::capacity).sum::<usize>() * size_of::<T>()
+    }
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Index<u32> for Arena<T> {
+    type Output = T;
+
+    fn index(&self, idx: u32) -> &T {
+        self.get(idx).expect("arena index out of bounds")
+    }
+}
+
+/// A reference to bytes stored in a [`StrArena`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StrRef {
+    page: u32,
+    start: u32,
+    len: u32,
+}
+
+/// Paged byte storage for names. Strings never straddle a page boundary, so a
+/// [`StrRef`] always points at one contiguous slice.
+pub struct StrArena {
+    pages: Vec<Vec<u8>>,
+}
+
+impl StrArena {
+    pub fn new() -> Self {
+        Self { pages: Vec::new() }
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) -> StrRef {
+        let fits = self
+            .pages
+            .last()
+            .is_some_and(|page| page.capacity() - page.len() >= bytes.len());
+        if !fits {
+            self.pages
+                .push(Vec::with_capacity(PAGE_LEN.max(bytes.len())));
+        }
+        let page_idx = self.pages.len() - 1;
+        let page = &mut self.pages[page_idx];
+        let start = page.len();
+        page.extend_from_slice(bytes);
+        StrRef {
+            page: page_idx.try_into().unwrap(),
+            start: start.try_into().unwrap(),
+            len: bytes.len().try_into().unwrap(),
+        }
+    }
+
+    pub fn get(&self, r: StrRef) -> &[u8] {
+        let start = r.start as usize;
+        &self.pages[r.page as usize][start..][..r.len as usize]
+    }
+
+    /// The bytes allocated for the pages, including their unused capacity.
+    pub fn allocated_bytes(&self) -> usize {
+        self.pages.iter().map(Vec::capacity).sum()
+    }
+}
+
+impl Default for StrArena {
+    fn default() -> Self {
+        Self::new()
+    }
+}
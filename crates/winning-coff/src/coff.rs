@@ -0,0 +1,376 @@
+use std::{borrow::Cow, io, ops::Range, str::Utf8Error};
+
+use binrw::{BinRead, BinWrite};
+use color_eyre::Result;
+use tracing::debug;
+
+use crate::{
+    diagnostics::{Code, fatal},
+    support::{CLR_METADATA, check_machine, unsupported},
+    symbols::SymbolTable,
+};
+
+#[derive(Debug, Clone, Copy, BinRead, BinWrite)]
+#[br(little)]
+#[bw(little)]
+#[repr(C)]
+pub struct CoffHeader {
+    pub machine: u16,
+    pub number_of_sections: u16,
+    pub time_date_stamp: u32,
+    pub pointer_to_symbol_table: u32,
+    pub number_of_symbols: u32,
+    pub size_of_optional_header: u16,
+    #[br(map = |val: u16| Characteristics::from_bits_retain(val))]
+    #[bw(map = |val| val.bits())]
+    pub characteristics: Characteristics,
+}
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(C)]
+    pub struct Characteristics: u16 {
+        const IMAGE_FILE_RELOCS_STRIPPED = 0x0001; // Image only, Windows CE, and Microsoft Windows NT and later. This indicates that the file does not contain base relocations and must therefore be loaded at its preferred base address. If the base address is not available, the loader reports an error. The default behavior of the linker is to strip base relocations from executable (EXE) files.
+        const IMAGE_FILE_EXECUTABLE_IMAGE = 0x0002; // Image only. This indicates that the image file is valid and can be run. If this flag is not set, it indicates a linker error.
+        const IMAGE_FILE_LINE_NUMS_STRIPPED = 0x0004; // COFF line numbers have been removed. This flag is deprecated and should be zero.
+        const IMAGE_FILE_LOCAL_SYMS_STRIPPED = 0x0008; // COFF symbol table entries for local symbols have been removed. This flag is deprecated and should be zero.
+        const IMAGE_FILE_AGGRESSIVE_WS_TRIM = 0x0010; // Obsolete. Aggressively trim working set. This flag is deprecated for Windows 2000 and later and must be zero.
+        const IMAGE_FILE_LARGE_ADDRESS_AWARE = 0x0020; // Application can handle > 2-GB addresses.
+        const IMAGE_FILE_BYTES_REVERSED_LO = 0x0080; // Little endian: the least significant bit (LSB) precedes the most significant bit (MSB) in memory. This flag is deprecated and should be zero.
+        const IMAGE_FILE_32BIT_MACHINE = 0x0100; // Machine is based on a 32-bit-word architecture.
+        const IMAGE_FILE_DEBUG_STRIPPED = 0x0200; // Debugging information is removed from the image file.
+        const IMAGE_FILE_REMOVABLE_RUN_FROM_SWAP = 0x0400; // If the image is on removable media, fully load it and copy it to the swap file.
+        const IMAGE_FILE_NET_RUN_FROM_SWAP = 0x0800; // If the image is on network media, fully load it and copy it to the swap file.
+        const IMAGE_FILE_SYSTEM = 0x1000; // The image file is a system file, not a user program.
+        const IMAGE_FILE_DLL = 0x2000; // The image file is a dynamic-link library (DLL). Such files are considered executable files for almost all purposes, although they cannot be directly run.
+        const IMAGE_FILE_UP_SYSTEM_ONLY = 0x4000; // The file should be run only on a uniprocessor machine.
+        const IMAGE_FILE_BYTES_REVERSED_HI = 0x8000; // Big endian: the MSB precedes the LSB in memory. This flag is deprecated and should be zero.
+    }
+}
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(C)]
+    pub struct SectionFlags: u32 {
+     /// The section should not be padded to the next boundary. This flag is obsolete and is replaced by IMAGE_SCN_ALIGN_1BYTES. This is valid only for object files.
+    const IMAGE_SCN_TYPE_NO_PAD = 0x00000008;
+     /// The section contains executable code.
+    const IMAGE_SCN_CNT_CODE = 0x00000020;
+     /// The section contains initialized data.
+    const IMAGE_SCN_CNT_INITIALIZED_DATA = 0x00000040;
+     /// The section contains uninitialized data.
+    const IMAGE_SCN_CNT_UNINITIALIZED_DATA = 0x00000080;
+     /// Reserved for future use.
+    const IMAGE_SCN_LNK_OTHER = 0x00000100;
+     /// The section contains comments or other information. The .drectve section has this type. This is valid for object files only.
+    const IMAGE_SCN_LNK_INFO = 0x00000200;
+     /// The section will not become part of the image. This is valid only for object files.
+    const IMAGE_SCN_LNK_REMOVE = 0x00000800;
+     /// The section contains COMDAT data. For more information, see COMDAT Sections (Object Only). This is valid only for object files.
+    const IMAGE_SCN_LNK_COMDAT = 0x00001000;
+     /// The section contains data referenced through the global pointer (GP).
+    const IMAGE_SCN_GPREL = 0x00008000;
+     /// Reserved for future use.
+    const IMAGE_SCN_MEM_PURGEABLE = 0x00020000;
+     /// Reserved for future use.
+    const IMAGE_SCN_MEM_16BIT = 0x00020000;
+     /// Reserved for future use.
+    const IMAGE_SCN_MEM_LOCKED = 0x00040000;
+     /// Reserved for future use.
+    const IMAGE_SCN_MEM_PRELOAD = 0x00080000;
+     /// Align data on a 1-byte boundary. Valid only for object files.
+    const IMAGE_SCN_ALIGN_1BYTES = 0x00100000;
+     /// Align data on a 2-byte boundary. Valid only for object files.
+    const IMAGE_SCN_ALIGN_2BYTES = 0x00200000;
+     /// Align data on a 4-byte boundary. Valid only for object files.
+    const IMAGE_SCN_ALIGN_4BYTES = 0x00300000;
+     /// Align data on an 8-byte boundary. Valid only for object files.
+    const IMAGE_SCN_ALIGN_8BYTES = 0x00400000;
+     /// Align data on a 16-byte boundary. Valid only for object files.
+    const IMAGE_SCN_ALIGN_16BYTES = 0x00500000;
+     /// Align data on a 32-byte boundary. Valid only for object files.
+    const IMAGE_SCN_ALIGN_32BYTES = 0x00600000;
+     /// Align data on a 64-byte boundary. Valid only for object files.
+    const IMAGE_SCN_ALIGN_64BYTES = 0x00700000;
+     /// Align data on a 128-byte boundary. Valid only for object files.
+    const IMAGE_SCN_ALIGN_128BYTES = 0x00800000;
+     /// Align data on a 256-byte boundary. Valid only for object files.
+    const IMAGE_SCN_ALIGN_256BYTES = 0x00900000;
+     /// Align data on a 512-byte boundary. Valid only for object files.
+    const IMAGE_SCN_ALIGN_512BYTES = 0x00A00000;
+     /// Align data on a 1024-byte boundary. Valid only for object files.
+    const IMAGE_SCN_ALIGN_1024BYTES = 0x00B00000;
+     /// Align data on a 2048-byte boundary. Valid only for object files.
+    const IMAGE_SCN_ALIGN_2048BYTES = 0x00C00000;
+     /// Align data on a 4096-byte boundary. Valid only for object files.
+    const IMAGE_SCN_ALIGN_4096BYTES = 0x00D00000;
+     /// Align data on an 8192-byte boundary. Valid only for object files.
+    const IMAGE_SCN_ALIGN_8192BYTES = 0x00E00000;
+     /// The section contains extended relocations.
+    const IMAGE_SCN_LNK_NRELOC_OVFL = 0x01000000;
+    /// The section can be discarded as needed.
+    const IMAGE_SCN_MEM_DISCARDABLE = 0x02000000;
+    /// The section cannot be cached.
+    const IMAGE_SCN_MEM_NOT_CACHED = 0x04000000;
+    /// The section is not pageable.
+    const IMAGE_SCN_MEM_NOT_PAGED = 0x08000000;
+    /// The section can be shared in memory.
+    const IMAGE_SCN_MEM_SHARED = 0x10000000;
+    /// The section can be executed as code.
+    const IMAGE_SCN_MEM_EXECUTE = 0x20000000;
+     /// The section can be read.
+    const IMAGE_SCN_MEM_READ = 0x40000000;
+     /// The section can be written to.
+    const IMAGE_SCN_MEM_WRITE = 0x80000000;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, BinRead, BinWrite)]
+#[br(little)]
+#[bw(little)]
+#[repr(C)]
+pub struct SectionHeader<'a> {
+    /// Borrowed from the file by [`read_section_headers`], and only owned for
+    /// sections the linker makes up.
+    #[br(ignore)]
+    #[bw(map = |val| encode_section_header_name(val))]
+    pub name: Cow<'a, str>,
+    #[br(pad_before = 8)]
+    pub virtual_size: u32,
+    pub virtual_address: u32,
+    pub size_of_raw_data: u32,
+    pub pointer_to_raw_data: u32,
+    pub pointer_to_relocations: u32,
+    pub pointer_to_linenumbers: u32,
+    pub number_of_relocations: u16,
+    pub number_of_linenumbers: u16,
+    #[br(map = |val: u32| SectionFlags::from_bits_retain(val))]
+    #[bw(map = |val| val.bits())]
+    pub characteristics: SectionFlags,
+}
+
+impl SectionFlags {
+    const ALIGN_MASK: u32 = 0x00F00000;
+
+    /// The alignment requested by the `IMAGE_SCN_ALIGN_*` bits. Object files
+    /// that don't specify one get 16 bytes.
+    pub fn alignment(self) -> u32 {
+        match (self.bits() & Self::ALIGN_MASK) >> 20 {
+            0 => 16,
+            n => 1 << (n - 1),
+        }
+    }
+
+    /// The flags that are meaningful in an image section header.
+    pub fn image_flags(self) -> Self {
+        self & (Self::IMAGE_SCN_CNT_CODE
+            | Self::IMAGE_SCN_CNT_INITIALIZED_DATA
+            | Self::IMAGE_SCN_CNT_UNINITIALIZED_DATA
+            | Self::IMAGE_SCN_MEM_DISCARDABLE
+            | Self::IMAGE_SCN_MEM_NOT_CACHED
+            | Self::IMAGE_SCN_MEM_NOT_PAGED
+            | Self::IMAGE_SCN_MEM_SHARED
+            | Self::IMAGE_SCN_MEM_EXECUTE
+            | Self::IMAGE_SCN_MEM_READ
+            | Self::IMAGE_SCN_MEM_WRITE)
+    }
+}
+
+pub const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+
+pub const IMAGE_SYM_UNDEFINED: u16 = 0;
+pub const IMAGE_SYM_ABSOLUTE: u16 = 0xFFFF;
+pub const IMAGE_SYM_DEBUG: u16 = 0xFFFE;
+
+pub const IMAGE_SYM_DTYPE_FUNCTION: u16 = 2;
+
+pub const IMAGE_SYM_CLASS_EXTERNAL: u8 = 2;
+pub const IMAGE_SYM_CLASS_STATIC: u8 = 3;
+pub const IMAGE_SYM_CLASS_LABEL: u8 = 6;
+pub const IMAGE_SYM_CLASS_FUNCTION: u8 = 101;
+pub const IMAGE_SYM_CLASS_FILE: u8 = 103;
+pub const IMAGE_SYM_CLASS_SECTION: u8 = 104;
+pub const IMAGE_SYM_CLASS_WEAK_EXTERNAL: u8 = 105;
+
+pub const IMAGE_COMDAT_SELECT_ASSOCIATIVE: u8 = 5;
+
+pub const IMAGE_REL_AMD64_ABSOLUTE: u16 = 0x0000;
+pub const IMAGE_REL_AMD64_ADDR64: u16 = 0x0001;
+pub const IMAGE_REL_AMD64_ADDR32: u16 = 0x0002;
+pub const IMAGE_REL_AMD64_ADDR32NB: u16 = 0x0003;
+pub const IMAGE_REL_AMD64_REL32: u16 = 0x0004;
+pub const IMAGE_REL_AMD64_REL32_1: u16 = 0x0005;
+pub const IMAGE_REL_AMD64_REL32_2: u16 = 0x0006;
+pub const IMAGE_REL_AMD64_REL32_3: u16 = 0x0007;
+pub const IMAGE_REL_AMD64_REL32_4: u16 = 0x0008;
+pub const IMAGE_REL_AMD64_REL32_5: u16 = 0x0009;
+pub const IMAGE_REL_AMD64_SECTION: u16 = 0x000A;
+pub const IMAGE_REL_AMD64_SECREL: u16 = 0x000B;
+pub const IMAGE_REL_AMD64_SECREL7: u16 = 0x000C;
+pub const IMAGE_REL_AMD64_TOKEN: u16 = 0x000D;
+pub const IMAGE_REL_AMD64_SREL32: u16 = 0x000E;
+pub const IMAGE_REL_AMD64_PAIR: u16 = 0x000F;
+pub const IMAGE_REL_AMD64_SSPAN32: u16 = 0x0010;
+
+#[derive(Debug, Clone, Copy, BinRead)]
+#[br(little)]
+#[repr(C)]
+pub struct Relocation {
+    pub virtual_address: u32,
+    pub symbol_table_index: u32,
+    pub r#type: u16,
+}
+
+pub const RELOCATION_SIZE: usize = 10;
+pub const SECTION_HEADER_SIZE: usize = 40;
+
+/// A parsed COFF object file, borrowing section data from the file contents.
+pub struct ObjectFile<'a> {
+    pub name: String,
+    pub data: &'a [u8],
+    pub header: CoffHeader,
+    pub sections: Vec<SectionHeader<'a>>,
+    pub symbols: SymbolTable<'a>,
+}
+
+impl<'a> ObjectFile<'a> {
+    pub fn parse(name: String, data: &'a [u8]) -> Result<Self> {
+        let header = CoffHeader::read(&mut io::Cursor::new(data))?;
+        debug!(target: "headers", "{name}: {header:#?}");
+
+        check_machine(header.machine, &name)?;
+        if header.size_of_optional_header > 0 {
+            return fatal(Code::CorruptInput, "COFF object has optional header");
+        }
+
+        let symbols = SymbolTable::read(data, &header)?;
+
+        let mut sections =
+            read_section_headers(data, size_of::<CoffHeader>(), header.number_of_sections)?;
+        for section in &mut sections {
+            if let Some(offset) = section.name.strip_prefix('/') {
+                let Some(name) = offset
+                    .parse()
+                    .ok()
+                    .and_then(|offset| string_table_entry(data, &header, offset))
+                else {
+                    return fatal(
+                        Code::CorruptInput,
+                        format!("invalid long section name {}", section.name),
+                    );
+                };
+                section.name = Cow::Borrowed(name);
+            }
+            debug!(target: "sections", "{name}: {section:#?}");
+            if section.name == ".cormeta" {
+                return unsupported(&CLR_METADATA, &name);
+            }
+        }
+
+        let object = Self {
+            name,
+            data,
+            header,
+            sections,
+            symbols,
+        };
+        for idx in 0..object.sections.len() {
+            object.section_range(idx)?;
+        }
+        Ok(object)
+    }
+
+    fn section_range(&self, idx: usize) -> Result<Range<usize>> {
+        let section = &self.sections[idx];
+        if section.size_of_raw_data == 0
+            || section
+                .characteristics
+                .contains(SectionFlags::IMAGE_SCN_CNT_UNINITIALIZED_DATA)
+        {
+            return Ok(0..0);
+        }
+        let start = section.pointer_to_raw_data as usize;
+        let end = start + section.size_of_raw_data as usize;
+        if end > self.data.len() {
+            return fatal(
+                Code::CorruptInput,
+                format!("section {} data out of bounds", section.name),
+            );
+        }
+        Ok(start..end)
+    }
+
+    /// The raw data of the section at the 0-based `idx`. Empty for uninitialized
+    /// sections.
+    pub fn section_data(&self, idx: usize) -> &'a [u8] {
+        &self.data[self.section_range(idx).unwrap()]
+    }
+
+    /// The size the section occupies in memory.
+    pub fn section_size(&self, idx: usize) -> u32 {
+        self.sections[idx].size_of_raw_data
+    }
+
+    pub fn relocations(&self, idx: usize) -> Result<Vec<Relocation>> {
+        let section = &self.sections[idx];
+        let cursor = &mut io::Cursor::new(self.data);
+        cursor.set_position(section.pointer_to_relocations.into());
+
+        let mut count = usize::from(section.number_of_relocations);
+        let overflow = section
+            .characteristics
+            .contains(SectionFlags::IMAGE_SCN_LNK_NRELOC_OVFL);
+        if overflow && count == 0xFFFF {
+            // The real count is stored in the first relocation, which is included in it.
+            count = Relocation::read(cursor)?.virtual_address as usize - 1;
+        }
+
+        if section.pointer_to_relocations as usize + count * RELOCATION_SIZE > self.data.len() {
+            return fatal(
+                Code::CorruptInput,
+                format!("relocations of section {} out of bounds", section.name),
+            );
+        }
+        (0..count)
+            .map(|_| Relocation::read(cursor).map_err(Into::into))
+            .collect()
+    }
+}
+
+/// Looks up a NUL-terminated string in the string table following the symbol table.
+pub fn string_table_entry<'a>(data: &'a [u8], header: &CoffHeader, offset: u32) -> Option<&'a str> {
+    let start = header.pointer_to_symbol_table as usize
+        + header.number_of_symbols as usize * crate::symbols::SYMBOL_TABLE_ENTRY_SIZE as usize
+        + offset as usize;
+    let rest = data.get(start..)?;
+    std::str::from_utf8(&rest[..rest.iter().position(|&b| b == 0)?]).ok()
+}
+
+/// Reads the section table at `offset`, with the names borrowed from `data`.
+pub fn read_section_headers(
+    data: &[u8],
+    offset: usize,
+    count: u16,
+) -> Result<Vec<SectionHeader<'_>>> {
+    let cursor = &mut io::Cursor::new(data);
+    cursor.set_position(offset as u64);
+    (0..usize::from(count))
+        .map(|idx| {
+            let mut section = SectionHeader::read(cursor)?;
+            let name = &data[offset + idx * SECTION_HEADER_SIZE..][..8];
+            section.name = Cow::Borrowed(parse_section_header_name(name)?);
+            Ok(section)
+        })
+        .collect()
+}
+
+pub fn parse_section_header_name(name: &[u8]) -> Result<&str, Utf8Error> {
+    let end = name.iter().position(|&d| d == 0).unwrap_or(name.len());
+    std::str::from_utf8(&name[..end])
+}
+
+pub fn encode_section_header_name(name: &str) -> [u8; 8] {
+    let mut bytes = [0; 8];
+    bytes[..name.len()].copy_from_slice(name.as_bytes());
+    bytes
+}
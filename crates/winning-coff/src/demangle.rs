@@ -0,0 +1,48 @@
+use std::fmt::{self, Display};
+
+const IMPORT_PREFIX: &str = "__imp_";
+
+/// Displays a symbol name, demangling Rust legacy (`_ZN...E`) and v0 (`_R...`)
+/// names when enabled. Import thunk pointers keep their `__imp_` prefix.
+pub struct SymbolName<'a> {
+    name: &'a str,
+    demangle: bool,
+}
+
+pub fn symbol_name(name: &str, demangle: bool) -> SymbolName<'_> {
+    SymbolName { name, demangle }
+}
+
+/// A C name without its 32-bit x86 calling convention decoration: `_name`
+/// for `__cdecl`, `_name@N` for `__stdcall`, `@name@N` for `__fastcall` and
+/// `name@@N` for `__vectorcall`. C++ names, which start with `?`, are kept.
+pub fn undecorated(name: &str) -> &str {
+    if name.starts_with('?') {
+        return name;
+    }
+    let name = match name.rsplit_once('@') {
+        Some((base, size))
+            if !base.is_empty() && !size.is_empty() && size.bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            base.strip_suffix('@').unwrap_or(base)
+        }
+        _ => name,
+    };
+    name.strip_prefix(['_', '@']).unwrap_or(name)
+}
+
+impl Display for SymbolName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.demangle {
+            return f.pad(self.name);
+        }
+        let (prefix, name) = match self.name.strip_prefix(IMPORT_PREFIX) {
+            Some(rest) => (IMPORT_PREFIX, rest),
+            None => ("", self.name),
+        };
+        match rustc_demangle::try_demangle(name) {
+            Ok(demangled) => f.pad(&format!("{prefix}{demangled:#}")),
+            Err(_) => f.pad(self.name),
+        }
+    }
+}
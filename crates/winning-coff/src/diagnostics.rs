@@ -0,0 +1,251 @@
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+    io::Write,
+};
+
+use color_eyre::{Result, eyre::bail};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// Stable diagnostic codes. The numbers follow the link.exe `LNKxxxx` codes
+/// for the equivalent condition where one exists, so they can be searched for
+/// and suppressed the same way: 1xxx are fatal errors, 4xxx are warnings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+    /// `--self-check` found the output doesn't match what we meant to write.
+    SelfCheckFailed,
+    /// The output file could not be written.
+    CannotOpenOutput,
+    /// The input is malformed.
+    CorruptInput,
+    /// A command-line option has an invalid value.
+    InvalidOption,
+    /// An input file could not be read.
+    CannotOpenInput,
+    /// The input is for a machine type we can't link.
+    UnsupportedMachine,
+    /// A relocation is malformed or can't be applied.
+    InvalidRelocation,
+    /// A symbol is defined more than once.
+    DuplicateSymbol,
+    /// A relocation target is out of range of the fixup.
+    RelocationOverflow,
+    /// A referenced symbol is never defined.
+    UnresolvedSymbol,
+    /// The input needs a feature we don't implement. The message says whether
+    /// it's planned and how to work around it.
+    UnsupportedFeature,
+    /// An existing image can't be modified the way we were asked to.
+    UnsupportedImage,
+    /// The link was cancelled, by Ctrl-C or through a cancellation token.
+    Cancelled,
+    /// Unwind info in `.xdata` is malformed, so exceptions can't propagate
+    /// through the function.
+    InvalidUnwindInfo,
+    /// `--link-record` replayed a link, but didn't reproduce its output.
+    RelinkMismatch,
+    /// Modifying an image invalidated its Authenticode signature, which was removed.
+    SignatureRemoved,
+    /// A command-line option wasn't recognized and was ignored.
+    UnknownOption,
+    /// The entry point isn't in an executable section, so the process would
+    /// crash before running any code.
+    InvalidEntryPoint,
+    /// The entry point is in a section that's writable as well as executable.
+    WritableEntryPoint,
+    /// A startup order or `/ORDER` entry doesn't name a section that can be
+    /// placed, and was ignored.
+    StartupOrderIgnored,
+    /// Two `.res` inputs have a resource with the same type, name and language.
+    DuplicateResource,
+    /// A `/SECTION` override names a section that isn't in the image.
+    UnknownSection,
+    /// Two objects have different values for a `/FAILIFMISMATCH` key, like
+    /// objects built for different CRTs.
+    MismatchDetected,
+    /// `/FORCE:MULTIPLE` kept the first of several definitions of a symbol.
+    DuplicateSymbolIgnored,
+    /// `/FORCE:UNRESOLVED` made a symbol nothing defines zero.
+    UnresolvedSymbolIgnored,
+    /// `/FORCE` turned unresolved or duplicate symbols into warnings, so the
+    /// image may not run.
+    ForcedOutput,
+    /// Objects ask for the default libraries of different CRTs, like `/MT`
+    /// and `/MD` ones, which define the same symbols.
+    DefaultLibConflict,
+    /// `winning verify` found an image breaks a rule of the loader.
+    InvalidImage,
+    /// `winning diff` found two images differ.
+    ImagesDiffer,
+    /// `winning sign` can't sign with the key or certificates it was given.
+    InvalidSigningKey,
+}
+
+impl Code {
+    pub fn number(self) -> u32 {
+        match self {
+            Self::SelfCheckFailed => 1000,
+            Self::CannotOpenOutput => 1104,
+            Self::CorruptInput => 1107,
+            Self::InvalidOption => 1117,
+            Self::CannotOpenInput => 1181,
+            Self::UnsupportedMachine => 1112,
+            Self::InvalidRelocation => 1190,
+            Self::DuplicateSymbol => 2005,
+            Self::RelocationOverflow => 2017,
+            Self::MismatchDetected => 2038,
+            Self::UnresolvedSymbol => 2019,
+            Self::UnsupportedImage => 1501,
+            Self::UnsupportedFeature => 1502,
+            Self::Cancelled => 1503,
+            Self::InvalidEntryPoint => 1504,
+            Self::DuplicateResource => 1505,
+            Self::InvalidImage => 1506,
+            Self::ImagesDiffer => 1507,
+            Self::InvalidSigningKey => 1508,
+            Self::SignatureRemoved => 4501,
+            Self::InvalidUnwindInfo => 4502,
+            Self::RelinkMismatch => 4503,
+            Self::WritableEntryPoint => 4505,
+            Self::UnknownOption => 4044,
+            Self::StartupOrderIgnored => 4037,
+            Self::UnknownSection => 4039,
+            Self::ForcedOutput => 4088,
+            Self::DuplicateSymbolIgnored => 4006,
+            Self::UnresolvedSymbolIgnored => 4506,
+            Self::DefaultLibConflict => 4098,
+        }
+    }
+
+    pub fn severity(self) -> Severity {
+        if self.number() >= 4000 {
+            Severity::Warning
+        } else {
+            Severity::Error
+        }
+    }
+}
+
+impl Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "WIN{}", self.number())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Code,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(code: Code, message: impl Display) -> Self {
+        Self {
+            severity: code.severity(),
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{severity} {}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// Shorthand for returning a fatal diagnostic from a function producing an
+/// [`color_eyre::Result`].
+pub fn fatal<T>(code: Code, message: impl Display) -> Result<T> {
+    Err(Diagnostic::new(code, message).into())
+}
+
+#[derive(Default)]
+pub struct DiagnosticOptions {
+    /// `/WX`: promote all warnings to errors.
+    pub warnings_as_errors: bool,
+    /// `/IGNORE:code`: warnings that aren't reported at all.
+    pub ignored: HashSet<u32>,
+}
+
+/// Collects non-fatal diagnostics during a link. Fatal problems are returned
+/// as [`Diagnostic`] errors instead.
+pub struct Diagnostics<'a> {
+    options: &'a DiagnosticOptions,
+    out: &'a mut dyn Write,
+    errors: usize,
+    reported: Vec<Diagnostic>,
+}
+
+impl<'a> Diagnostics<'a> {
+    pub fn new(options: &'a DiagnosticOptions, out: &'a mut dyn Write) -> Self {
+        Self {
+            options,
+            out,
+            errors: 0,
+            reported: Vec::new(),
+        }
+    }
+
+    pub fn emit(&mut self, mut diagnostic: Diagnostic) {
+        if diagnostic.severity == Severity::Warning {
+            if self.options.ignored.contains(&diagnostic.code.number()) {
+                return;
+            }
+            if self.options.warnings_as_errors {
+                diagnostic.severity = Severity::Error;
+            }
+        }
+        if diagnostic.severity == Severity::Error {
+            self.errors += 1;
+        }
+        // Failing to print a diagnostic is not worth aborting the link over.
+        let _ = writeln!(self.out, "{diagnostic}");
+        self.reported.push(diagnostic);
+    }
+
+    pub fn warn(&mut self, code: Code, message: impl Display) {
+        self.emit(Diagnostic::new(code, message));
+    }
+
+    pub fn error(&mut self, code: Code, message: impl Display) {
+        self.emit(Diagnostic {
+            severity: Severity::Error,
+            code,
+            message: message.to_string(),
+        });
+    }
+
+    /// Fails if any errors were reported so far.
+    pub fn check(&self) -> Result<()> {
+        if self.errors > 0 {
+            bail!("link failed with {} error(s)", self.errors);
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        self.check()
+    }
+
+    /// Everything reported so far, after `/IGNORE` and `/WX` were applied.
+    pub fn into_reported(self) -> Vec<Diagnostic> {
+        self.reported
+    }
+}
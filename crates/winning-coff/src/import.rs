@@ -0,0 +1,98 @@
+use std::io;
+
+use binrw::BinRead;
+use color_eyre::Result;
+
+use crate::diagnostics::{Code, fatal};
+
+pub const IMPORT_OBJECT_HDR_SIG2: u16 = 0xFFFF;
+
+pub const IMPORT_OBJECT_CODE: u16 = 0;
+
+pub const IMPORT_OBJECT_ORDINAL: u16 = 0;
+pub const IMPORT_OBJECT_NAME: u16 = 1;
+pub const IMPORT_OBJECT_NAME_NOPREFIX: u16 = 2;
+pub const IMPORT_OBJECT_NAME_UNDECORATE: u16 = 3;
+pub const IMPORT_OBJECT_NAME_EXPORTAS: u16 = 4;
+
+#[derive(Debug, BinRead)]
+#[br(little, magic = b"\0\0\xff\xff")]
+#[repr(C)]
+struct ImportObjectHeader {
+    _version: u16,
+    machine: u16,
+    _time_date_stamp: u32,
+    size_of_data: u32,
+    ordinal_or_hint: u16,
+    name_type: u16,
+}
+
+/// A short import object, as found in import libraries: one per imported symbol.
+#[derive(Debug)]
+pub struct ImportObject<'a> {
+    pub machine: u16,
+    pub symbol: &'a str,
+    pub dll: &'a str,
+    pub import_type: u16,
+    pub name_type: u16,
+    pub ordinal_or_hint: u16,
+    /// Only present for `IMPORT_OBJECT_NAME_EXPORTAS`.
+    pub export_name: Option<&'a str>,
+}
+
+pub fn is_import_object(data: &[u8]) -> bool {
+    data.len() >= 4 && data[..2] == [0, 0] && data[2..4] == IMPORT_OBJECT_HDR_SIG2.to_le_bytes()
+}
+
+impl<'a> ImportObject<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        let header = ImportObjectHeader::read(&mut io::Cursor::new(data))?;
+        let header_size = 20;
+        let Some(strings) = data.get(header_size..header_size + header.size_of_data as usize)
+        else {
+            return fatal(Code::CorruptInput, "truncated import object");
+        };
+        let Ok(strings) = std::str::from_utf8(strings) else {
+            return fatal(Code::CorruptInput, "invalid import object name");
+        };
+        let mut strings = strings.split('\0');
+        let (Some(symbol), Some(dll)) = (strings.next(), strings.next()) else {
+            return fatal(Code::CorruptInput, "import object is missing names");
+        };
+        let name_type = (header.name_type >> 2) & 0b111;
+        let export_name = if name_type == IMPORT_OBJECT_NAME_EXPORTAS {
+            strings.next()
+        } else {
+            None
+        };
+
+        Ok(Self {
+            machine: header.machine,
+            symbol,
+            dll,
+            import_type: header.name_type & 0b11,
+            name_type,
+            ordinal_or_hint: header.ordinal_or_hint,
+            export_name,
+        })
+    }
+
+    /// The name the loader looks up in the DLL's export table, derived from the
+    /// symbol name according to the name type. `None` for imports by ordinal.
+    pub fn import_name(&self) -> Option<&'a str> {
+        match self.name_type {
+            IMPORT_OBJECT_ORDINAL => None,
+            IMPORT_OBJECT_NAME => Some(self.symbol),
+            IMPORT_OBJECT_NAME_NOPREFIX => Some(strip_prefix(self.symbol)),
+            IMPORT_OBJECT_NAME_UNDECORATE => {
+                let name = strip_prefix(self.symbol);
+                Some(name.split('@').next().unwrap_or(name))
+            }
+            _ => Some(self.export_name.unwrap_or(self.symbol)),
+        }
+    }
+}
+
+fn strip_prefix(name: &str) -> &str {
+    name.strip_prefix(['?', '@', '_']).unwrap_or(name)
+}
@@ -0,0 +1,84 @@
+use std::hash::{BuildHasher, RandomState};
+
+use hashbrown::HashTable;
+
+use crate::arena::{StrArena, StrRef};
+
+/// An interned symbol name. Ids are dense, starting at 0, so they can index
+/// per-symbol tables directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SymbolId(u32);
+
+impl SymbolId {
+    pub fn new(index: u32) -> Self {
+        Self(index)
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Deduplicates symbol names across all inputs. Each name is stored once, in
+/// a [`StrArena`], and the hash table only holds ids.
+#[derive(Default)]
+pub struct Interner {
+    names: StrArena,
+    refs: Vec<StrRef>,
+    table: HashTable<SymbolId>,
+    hasher: RandomState,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn intern(&mut self, name: &str) -> SymbolId {
+        let hash = self.hasher.hash_one(name);
+        if let Some(&id) = self.table.find(hash, |&id| self.resolve(id) == name) {
+            return id;
+        }
+        let id = SymbolId(self.refs.len().try_into().expect("too many symbols"));
+        self.refs.push(self.names.push(name.as_bytes()));
+        let Self {
+            names,
+            refs,
+            table,
+            hasher,
+        } = self;
+        table.insert_unique(hash, id, |&id| {
+            hasher.hash_one(str_of(names.get(refs[id.index()])))
+        });
+        id
+    }
+
+    /// Looks up a name without interning it.
+    pub fn get(&self, name: &str) -> Option<SymbolId> {
+        let hash = self.hasher.hash_one(name);
+        self.table
+            .find(hash, |&id| self.resolve(id) == name)
+            .copied()
+    }
+
+    pub fn resolve(&self, id: SymbolId) -> &str {
+        str_of(self.names.get(self.refs[id.index()]))
+    }
+
+    pub fn len(&self) -> usize {
+        self.refs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.refs.is_empty()
+    }
+
+    /// The bytes allocated for the names themselves.
+    pub fn allocated_bytes(&self) -> usize {
+        self.names.allocated_bytes()
+    }
+}
+
+fn str_of(bytes: &[u8]) -> &str {
+    std::str::from_utf8(bytes).expect("interned names are UTF-8")
+}
@@ -0,0 +1,11 @@
+//! Parsing of COFF objects, archives and import objects.
+
+pub mod archive;
+pub mod arena;
+pub mod coff;
+pub mod demangle;
+pub mod diagnostics;
+pub mod import;
+pub mod intern;
+pub mod support;
+pub mod symbols;
@@ -0,0 +1,121 @@
+use std::fmt::{self, Display};
+
+use color_eyre::Result;
+
+use crate::{
+    coff::IMAGE_FILE_MACHINE_AMD64,
+    diagnostics::{Code, fatal},
+};
+
+/// Whether we intend to implement an unsupported feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Planned,
+    NotPlanned,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Planned => "support is planned",
+            Self::NotPlanned => "support is not planned",
+        })
+    }
+}
+
+/// Something an input can ask for that we don't implement.
+#[derive(Debug)]
+pub struct Feature {
+    pub name: &'static str,
+    pub status: Status,
+    pub workaround: &'static str,
+}
+
+pub const X86: Feature = Feature {
+    name: "32-bit x86",
+    status: Status::Planned,
+    workaround: "build for x86_64 instead",
+};
+
+pub const ARM64: Feature = Feature {
+    name: "ARM64",
+    status: Status::Planned,
+    workaround: "build for x86_64 instead, or link with link.exe or lld-link",
+};
+
+pub const ARM64EC: Feature = Feature {
+    name: "ARM64EC/ARM64X",
+    status: Status::NotPlanned,
+    workaround: "link with link.exe",
+};
+
+pub const LTCG: Feature = Feature {
+    name: "link-time code generation (/GL)",
+    status: Status::NotPlanned,
+    workaround: "compile without /GL",
+};
+
+pub const LLVM_BITCODE: Feature = Feature {
+    name: "LLVM bitcode (LTO)",
+    status: Status::Planned,
+    workaround: "compile without -flto, or link with lld-link",
+};
+
+pub const CLR_METADATA: Feature = Feature {
+    name: "CLR metadata (/clr)",
+    status: Status::NotPlanned,
+    workaround: "link mixed-mode code with link.exe",
+};
+
+/// Every known unsupported feature.
+pub const FEATURES: &[Feature] = &[X86, ARM64, ARM64EC, LTCG, LLVM_BITCODE, CLR_METADATA];
+
+const IMAGE_FILE_MACHINE_I386: u16 = 0x014C;
+const IMAGE_FILE_MACHINE_ARM64: u16 = 0xAA64;
+const IMAGE_FILE_MACHINE_ARM64EC: u16 = 0xA641;
+const IMAGE_FILE_MACHINE_ARM64X: u16 = 0xA64E;
+
+const LLVM_BITCODE_MAGIC: &[u8] = b"BC\xC0\xDE";
+const LLVM_BITCODE_WRAPPER_MAGIC: &[u8] = &[0xDE, 0xC0, 0x17, 0x0B];
+
+/// Fails with a diagnostic naming the feature, whether it's planned and what
+/// to do instead.
+pub fn unsupported<T>(feature: &Feature, source: impl Display) -> Result<T> {
+    fatal(
+        Code::UnsupportedFeature,
+        format!(
+            "{source}: unsupported feature '{}' ({}); workaround: {}",
+            feature.name, feature.status, feature.workaround
+        ),
+    )
+}
+
+/// Fails unless `machine` is x86-64.
+pub fn check_machine(machine: u16, source: impl Display) -> Result<()> {
+    let feature = match machine {
+        IMAGE_FILE_MACHINE_AMD64 => return Ok(()),
+        IMAGE_FILE_MACHINE_I386 => &X86,
+        IMAGE_FILE_MACHINE_ARM64 => &ARM64,
+        IMAGE_FILE_MACHINE_ARM64EC | IMAGE_FILE_MACHINE_ARM64X => &ARM64EC,
+        _ => {
+            return fatal(
+                Code::UnsupportedMachine,
+                format!("{source}: machine type {machine:#x} is not x86-64"),
+            );
+        }
+    };
+    unsupported(feature, source)
+}
+
+/// Fails for inputs that aren't COFF at all but compiler IR for link-time
+/// code generation. Anonymous objects share the import object signature but
+/// have a nonzero version; MSVC writes them for `/GL`.
+pub fn check_input_format(data: &[u8], source: impl Display) -> Result<()> {
+    if data.starts_with(LLVM_BITCODE_MAGIC) || data.starts_with(LLVM_BITCODE_WRAPPER_MAGIC) {
+        return unsupported(&LLVM_BITCODE, source);
+    }
+    if data.len() >= 6 && data[..4] == [0, 0, 0xFF, 0xFF] && data[4..6] != [0, 0] {
+        return unsupported(&LTCG, source);
+    }
+    Ok(())
+}
@@ -0,0 +1,172 @@
+use std::io;
+
+use binrw::BinRead;
+use color_eyre::{
+    Result,
+    eyre::{Context, ContextCompat},
+};
+
+use crate::{arena::Arena, coff::CoffHeader};
+
+pub const SYMBOL_TABLE_ENTRY_SIZE: u32 = 18;
+
+#[derive(Debug, BinRead)]
+#[br(little)]
+#[repr(C)]
+struct SymbolTableEntry {
+    name: [u8; 8],
+    value: u32,
+    section_number: u16,
+    r#type: u16,
+    storage_class: u8,
+    number_of_aux_symbols: u8,
+}
+
+pub struct Symbol<'a> {
+    /// Borrowed from the symbol or string table.
+    pub name: &'a [u8],
+    pub value: u32,
+    pub section_number: u16,
+    pub r#type: u16,
+    pub storage_class: u8,
+    pub number_of_aux_symbols: u8,
+}
+
+/// One slot of the COFF symbol table. Aux records are kept in place so that
+/// arena indices are the same as the symbol table indices used by relocations.
+pub enum SymbolRecord<'a> {
+    Symbol(Symbol<'a>),
+    Aux(&'a [u8; SYMBOL_TABLE_ENTRY_SIZE as usize]),
+}
+
+/// The symbol table of an object file. Records live in a chunked arena
+/// instead of one heap allocation per symbol, and names are borrowed from
+/// the file.
+pub struct SymbolTable<'a> {
+    records: Arena<SymbolRecord<'a>>,
+}
+
+impl<'a> SymbolTable<'a> {
+    pub fn read(file: &'a [u8], header: &CoffHeader) -> Result<Self> {
+        let mut table = Self {
+            records: Arena::new(),
+        };
+
+        let string_table_start = header.pointer_to_symbol_table as usize
+            + header.number_of_symbols as usize * SYMBOL_TABLE_ENTRY_SIZE as usize;
+        let string_table = file.get(string_table_start..).unwrap_or_default();
+
+        let cursor = &mut io::Cursor::new(file);
+        cursor.set_position(header.pointer_to_symbol_table.into());
+
+        let mut remaining_aux = 0;
+        for _ in 0..header.number_of_symbols {
+            let start = cursor.position() as usize;
+            let raw = file
+                .get(start..start + SYMBOL_TABLE_ENTRY_SIZE as usize)
+                .wrap_err("symbol table out of bounds")?;
+            if remaining_aux > 0 {
+                remaining_aux -= 1;
+                cursor.set_position((start + raw.len()) as u64);
+                table
+                    .records
+                    .push(SymbolRecord::Aux(raw.try_into().unwrap()));
+                continue;
+            }
+
+            let entry = SymbolTableEntry::read(cursor)?;
+            remaining_aux = entry.number_of_aux_symbols;
+
+            let name = if entry.name[..4].iter().all(|&v| v == 0) {
+                let offset = u32::from_le_bytes(entry.name[4..].try_into().unwrap());
+                string_table
+                    .get(offset as usize..)
+                    .and_then(|rest| Some(&rest[..rest.iter().position(|&b| b == 0)?]))
+                    .wrap_err_with(|| format!("invalid symbol long string offset {offset}"))?
+            } else {
+                let end = entry.name.iter().position(|&b| b == 0).unwrap_or(8);
+                &raw[..end]
+            };
+
+            table.records.push(SymbolRecord::Symbol(Symbol {
+                name,
+                value: entry.value,
+                section_number: entry.section_number,
+                r#type: entry.r#type,
+                storage_class: entry.storage_class,
+                number_of_aux_symbols: entry.number_of_aux_symbols,
+            }));
+        }
+
+        Ok(table)
+    }
+
+    pub fn name(&self, sym: &Symbol<'a>) -> Result<&'a str> {
+        std::str::from_utf8(sym.name).wrap_err("invalid symbol name")
+    }
+
+    pub fn get(&self, idx: u32) -> Option<&SymbolRecord<'a>> {
+        self.records.get(idx)
+    }
+
+    pub fn symbol(&self, idx: u32) -> Option<&Symbol<'a>> {
+        match self.get(idx)? {
+            SymbolRecord::Symbol(sym) => Some(sym),
+            SymbolRecord::Aux(_) => None,
+        }
+    }
+
+    fn aux(&self, idx: u32) -> Option<&'a [u8; SYMBOL_TABLE_ENTRY_SIZE as usize]> {
+        match self.get(idx + 1)? {
+            SymbolRecord::Aux(aux) => Some(aux),
+            SymbolRecord::Symbol(_) => None,
+        }
+    }
+
+    /// The section definition aux record of the section symbol at `idx`.
+    pub fn section_definition(&self, idx: u32) -> Option<SectionDefinition> {
+        let aux = self.aux(idx)?;
+        Some(SectionDefinition {
+            number: u16::from_le_bytes(aux[12..14].try_into().unwrap()),
+            selection: aux[14],
+        })
+    }
+
+    /// The aux record of the weak external at `idx`.
+    pub fn weak_external(&self, idx: u32) -> Option<WeakExternal> {
+        let aux = self.aux(idx)?;
+        Some(WeakExternal {
+            tag_index: u32::from_le_bytes(aux[0..4].try_into().unwrap()),
+        })
+    }
+
+    /// Iterates over all symbols with their symbol table index, skipping aux records.
+    pub fn symbols(&self) -> impl Iterator<Item = (u32, &Symbol<'a>)> {
+        self.records
+            .iter()
+            .zip(0..)
+            .filter_map(|(record, idx)| match record {
+                SymbolRecord::Symbol(sym) => Some((idx, sym)),
+                SymbolRecord::Aux(_) => None,
+            })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SymbolRecord<'a>> {
+        self.records.iter()
+    }
+
+    /// The bytes allocated for the records.
+    pub fn allocated_bytes(&self) -> usize {
+        self.records.allocated_bytes()
+    }
+}
+
+pub struct SectionDefinition {
+    /// For associative COMDATs, the 1-based number of the associated section.
+    pub number: u16,
+    pub selection: u8,
+}
+
+pub struct WeakExternal {
+    pub tag_index: u32,
+}
@@ -0,0 +1,216 @@
+//! AES (FIPS 197) decryption in CBC mode, which encrypted private keys use.
+
+/// The S-box and its inverse, built from the multiplicative inverse in
+/// GF(2^8) and the affine transformation rather than spelled out.
+fn s_boxes() -> ([u8; 256], [u8; 256]) {
+    let mut forward = [0u8; 256];
+    let mut inverse = [0u8; 256];
+    forward[0] = 0x63;
+    // `p` walks the field by multiplying with 3, a generator, and `q`
+    // follows with its inverse by dividing by 3.
+    let (mut p, mut q) = (1u8, 1u8);
+    loop {
+        p ^= p << 1 ^ if p & 0x80 != 0 { 0x1b } else { 0 };
+        q ^= q << 1;
+        q ^= q << 2;
+        q ^= q << 4;
+        if q & 0x80 != 0 {
+            q ^= 0x09;
+        }
+        forward[p as usize] =
+            q ^ q.rotate_left(1) ^ q.rotate_left(2) ^ q.rotate_left(3) ^ q.rotate_left(4) ^ 0x63;
+        if p == 1 {
+            break;
+        }
+    }
+    for (value, &substitute) in forward.iter().enumerate() {
+        inverse[substitute as usize] = value as u8;
+    }
+    (forward, inverse)
+}
+
+/// Multiplication in GF(2^8) with the AES polynomial.
+fn multiply(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        a = a << 1 ^ if a & 0x80 != 0 { 0x1b } else { 0 };
+        b >>= 1;
+    }
+    product
+}
+
+struct Decryptor {
+    round_keys: Vec<[u8; 16]>,
+    inverse_s_box: [u8; 256],
+}
+
+impl Decryptor {
+    fn new(key: &[u8]) -> Self {
+        let (s_box, inverse_s_box) = s_boxes();
+        let key_words = key.len() / 4;
+        let rounds = key_words + 6;
+        let mut words = key
+            .chunks_exact(4)
+            .map(|word| <[u8; 4]>::try_from(word).unwrap())
+            .collect::<Vec<_>>();
+        let mut round_constant = 1u8;
+        for idx in key_words..4 * (rounds + 1) {
+            let mut word = words[idx - 1];
+            if idx % key_words == 0 {
+                word.rotate_left(1);
+                word = word.map(|byte| s_box[byte as usize]);
+                word[0] ^= round_constant;
+                round_constant = multiply(round_constant, 2);
+            } else if key_words > 6 && idx % key_words == 4 {
+                word = word.map(|byte| s_box[byte as usize]);
+            }
+            let previous = words[idx - key_words];
+            words.push(std::array::from_fn(|byte| previous[byte] ^ word[byte]));
+        }
+        let round_keys = words
+            .chunks_exact(4)
+            .map(|round| std::array::from_fn(|idx| round[idx / 4][idx % 4]))
+            .collect();
+        Self {
+            round_keys,
+            inverse_s_box,
+        }
+    }
+
+    fn decrypt_block(&self, block: &mut [u8; 16]) {
+        let add_round_key = |block: &mut [u8; 16], round: usize| {
+            for (byte, key) in block.iter_mut().zip(self.round_keys[round]) {
+                *byte ^= key;
+            }
+        };
+        // The block is column-major: byte `row + 4 * column`.
+        let inverse_shift_and_substitute = |block: &mut [u8; 16]| {
+            let shifted = *block;
+            for (idx, byte) in block.iter_mut().enumerate() {
+                let (row, column) = (idx % 4, idx / 4);
+                *byte = self.inverse_s_box[shifted[row + 4 * ((column + 4 - row) % 4)] as usize];
+            }
+        };
+
+        let rounds = self.round_keys.len() - 1;
+        add_round_key(block, rounds);
+        for round in (1..rounds).rev() {
+            inverse_shift_and_substitute(block);
+            add_round_key(block, round);
+            for column in block.chunks_exact_mut(4) {
+                let [a, b, c, d] = [column[0], column[1], column[2], column[3]];
+                for (row, factors) in [
+                    [14, 11, 13, 9],
+                    [9, 14, 11, 13],
+                    [13, 9, 14, 11],
+                    [11, 13, 9, 14],
+                ]
+                .into_iter()
+                .enumerate()
+                {
+                    column[row] = multiply(a, factors[0])
+                        ^ multiply(b, factors[1])
+                        ^ multiply(c, factors[2])
+                        ^ multiply(d, factors[3]);
+                }
+            }
+        }
+        inverse_shift_and_substitute(block);
+        add_round_key(block, 0);
+    }
+}
+
+/// Decrypts `data` with AES-128, AES-192 or AES-256 depending on the length
+/// of `key`, and removes the PKCS#7 padding. `None` if the key length is
+/// wrong or the padding is, which is what a wrong password looks like.
+pub fn decrypt_cbc(key: &[u8], iv: &[u8; 16], data: &[u8]) -> Option<Vec<u8>> {
+    if !matches!(key.len(), 16 | 24 | 32) || data.is_empty() || !data.len().is_multiple_of(16) {
+        return None;
+    }
+    let decryptor = Decryptor::new(key);
+    let mut previous = *iv;
+    let mut out = Vec::with_capacity(data.len());
+    for chunk in data.chunks_exact(16) {
+        let mut block: [u8; 16] = chunk.try_into().unwrap();
+        decryptor.decrypt_block(&mut block);
+        out.extend(block.iter().zip(previous).map(|(byte, mask)| byte ^ mask));
+        previous = chunk.try_into().unwrap();
+    }
+    let padding = *out.last()? as usize;
+    if padding == 0
+        || padding > 16
+        || !out[out.len() - padding..]
+            .iter()
+            .all(|&byte| byte as usize == padding)
+    {
+        return None;
+    }
+    out.truncate(out.len() - padding);
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|idx| u8::from_str_radix(&hex[idx..idx + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn fips_197_appendix_c() {
+        let plaintext = bytes("00112233445566778899aabbccddeeff");
+        for (key, ciphertext) in [
+            (
+                "000102030405060708090a0b0c0d0e0f",
+                "69c4e0d86a7b0430d8cdb78070b4c55a",
+            ),
+            (
+                "000102030405060708090a0b0c0d0e0f1011121314151617",
+                "dda97ca4864cdfe06eaf70a0ec0d7191",
+            ),
+            (
+                "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+                "8ea2b7ca516745bfeafc49904b496089",
+            ),
+        ] {
+            let mut block = bytes(ciphertext).try_into().unwrap();
+            Decryptor::new(&bytes(key)).decrypt_block(&mut block);
+            assert_eq!(block[..], plaintext[..], "key {key}");
+        }
+    }
+
+    #[test]
+    fn cbc_padding() {
+        let key = bytes("000102030405060708090a0b0c0d0e0f");
+        let iv = bytes("101112131415161718191a1b1c1d1e1f")
+            .try_into()
+            .unwrap();
+        assert_eq!(
+            decrypt_cbc(&key, &iv, &bytes("94a32ff64eb6fe363a58ef9b5fd44fee")).unwrap(),
+            b"attack at dawn"
+        );
+        // A whole block of padding after a message that fills its block.
+        assert_eq!(
+            decrypt_cbc(
+                &key,
+                &iv,
+                &bytes("3128cc0107cd808fd03c94f0c1e12b38b7a7f1cc717ccf1a463b9223d853c6f6")
+            )
+            .unwrap(),
+            b"sixteen byte msg"
+        );
+        // The wrong key garbles the padding.
+        let wrong = bytes("0f0e0d0c0b0a09080706050403020100");
+        assert_eq!(
+            decrypt_cbc(&wrong, &iv, &bytes("94a32ff64eb6fe363a58ef9b5fd44fee")),
+            None
+        );
+    }
+}
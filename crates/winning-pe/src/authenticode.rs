@@ -0,0 +1,74 @@
+//! Authenticode, the code signing of PE images. The signature goes in the
+//! certificate table at the end of the file, and signs a digest of everything
+//! except itself and the header fields that change when it's added.
+
+use color_eyre::Result;
+use winning_coff::{
+    coff::CoffHeader,
+    diagnostics::{Code, fatal},
+};
+
+use crate::{
+    digest::Sha256,
+    image::ImageModel,
+    pe::{OptionalHeader, OptionalHeader32, PE_SIGNATURE},
+};
+
+/// Certificate table entries are 8-byte aligned, and so is the table.
+pub const CERTIFICATE_ALIGNMENT: u32 = 8;
+
+/// The SHA-256 Authenticode digest of `image`, as signtool computes it: the
+/// headers without the checksum and the certificate table directory, the
+/// sections in file order, and whatever follows them except the certificate
+/// table. It doesn't change when the certificate table is filled in, so it
+/// can be signed before.
+pub fn authenticode_digest(image: &ImageModel<'_>) -> Result<[u8; 32]> {
+    let data = image.data();
+    let optional = &image.optional_header;
+    let optional_offset = image.pe_offset + PE_SIGNATURE.len() + size_of::<CoffHeader>();
+    let checksum_offset = optional_offset + OptionalHeader::CHECKSUM_OFFSET;
+    let directories_offset = optional_offset
+        + match image.optional_header_32 {
+            Some(_) => OptionalHeader32::DATA_DIRECTORIES_OFFSET,
+            None => OptionalHeader::DATA_DIRECTORIES_OFFSET,
+        };
+    let headers_end = optional.size_of_headers as usize;
+    if headers_end > data.len() || headers_end < directories_offset {
+        return fatal(Code::CorruptInput, "size of headers is out of bounds");
+    }
+
+    let mut hasher = Sha256::default();
+    hasher.update(&data[..checksum_offset]);
+    if optional.number_of_rva_and_sizes as usize > OptionalHeader::CERTIFICATE_TABLE_INDEX {
+        let certificate_offset = directories_offset + OptionalHeader::CERTIFICATE_TABLE_INDEX * 8;
+        hasher.update(&data[checksum_offset + 4..certificate_offset]);
+        hasher.update(&data[certificate_offset + 8..headers_end]);
+    } else {
+        hasher.update(&data[checksum_offset + 4..headers_end]);
+    }
+
+    let mut sections = image
+        .sections
+        .iter()
+        .filter(|section| section.size_of_raw_data > 0)
+        .collect::<Vec<_>>();
+    sections.sort_by_key(|section| section.pointer_to_raw_data);
+    let mut hashed = headers_end;
+    for section in sections {
+        let Some(contents) = image.section_data(section) else {
+            return fatal(
+                Code::CorruptInput,
+                format!("section {} data out of bounds", section.name),
+            );
+        };
+        hasher.update(contents);
+        hashed += contents.len();
+    }
+
+    let certificate = optional.certificate_table;
+    let end = data.len().saturating_sub(certificate.size as usize);
+    if hashed < end {
+        hasher.update(&data[hashed..end]);
+    }
+    Ok(hasher.finish())
+}
@@ -0,0 +1,35 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use color_eyre::Result;
+use winning_coff::diagnostics::{Code, fatal};
+
+/// Aborts a link from another thread or a signal handler. The link checks it
+/// between phases and between inputs and sections within them, and fails
+/// with [`Code::Cancelled`].
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fails if the link was cancelled.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return fatal(Code::Cancelled, "link cancelled");
+        }
+        Ok(())
+    }
+}
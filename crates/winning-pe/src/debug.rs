@@ -0,0 +1,80 @@
+pub const IMAGE_DEBUG_TYPE_CODEVIEW: u32 = 2;
+pub const IMAGE_DEBUG_TYPE_REPRO: u32 = 16;
+
+pub const DEBUG_DIRECTORY_ENTRY_SIZE: u32 = 28;
+const RSDS_SIGNATURE: &[u8] = b"RSDS";
+
+/// What to describe in the debug directory.
+#[derive(Debug, Clone, Default)]
+pub struct DebugOptions {
+    /// `/Brepro`: mark the image as built deterministically.
+    pub repro: bool,
+    /// `/DEBUG`: the PDB path for debuggers to look for.
+    pub pdb: Option<String>,
+    /// `/DEBUG:DWARF`: keep the DWARF sections of MinGW objects in the image.
+    pub dwarf: bool,
+    /// Identifies the build, as the PDB GUID and the REPRO hash. Has to be
+    /// derived from the inputs for the build to be reproducible.
+    pub build_id: [u8; 16],
+}
+
+/// The debug directory and the data of its entries: an RSDS CodeView record
+/// naming the PDB, and a REPRO entry holding the build id.
+pub struct DebugTables {
+    entries: Vec<(u32, Vec<u8>)>,
+}
+
+impl DebugTables {
+    pub fn new(options: &DebugOptions) -> Self {
+        let mut entries = Vec::new();
+        if let Some(pdb) = &options.pdb {
+            let mut rsds = RSDS_SIGNATURE.to_vec();
+            rsds.extend_from_slice(&options.build_id);
+            // The age, counting PDB updates that keep the GUID.
+            rsds.extend_from_slice(&1u32.to_le_bytes());
+            rsds.extend_from_slice(pdb.as_bytes());
+            rsds.push(0);
+            entries.push((IMAGE_DEBUG_TYPE_CODEVIEW, rsds));
+        }
+        if options.repro {
+            let mut repro = (options.build_id.len() as u32).to_le_bytes().to_vec();
+            repro.extend_from_slice(&options.build_id);
+            entries.push((IMAGE_DEBUG_TYPE_REPRO, repro));
+        }
+        Self { entries }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The size of the directory itself, excluding the entry data.
+    pub fn directory_size(&self) -> u32 {
+        self.entries.len() as u32 * DEBUG_DIRECTORY_ENTRY_SIZE
+    }
+
+    pub fn size(&self) -> u32 {
+        self.directory_size()
+            + self
+                .entries
+                .iter()
+                .map(|(_, data)| data.len().next_multiple_of(4) as u32)
+                .sum::<u32>()
+    }
+
+    /// Writes the directory followed by the entry data, which the entries
+    /// point to both by RVA and by file offset.
+    pub fn write(&self, rva: u32, file_offset: u32, out: &mut [u8]) {
+        let mut data_offset = self.directory_size();
+        for (idx, (r#type, data)) in self.entries.iter().enumerate() {
+            let entry = &mut out[idx * DEBUG_DIRECTORY_ENTRY_SIZE as usize..];
+            // Characteristics, TimeDateStamp, MajorVersion and MinorVersion are all zero.
+            entry[12..16].copy_from_slice(&r#type.to_le_bytes());
+            entry[16..20].copy_from_slice(&(data.len() as u32).to_le_bytes());
+            entry[20..24].copy_from_slice(&(rva + data_offset).to_le_bytes());
+            entry[24..28].copy_from_slice(&(file_offset + data_offset).to_le_bytes());
+            out[data_offset as usize..][..data.len()].copy_from_slice(data);
+            data_offset += data.len().next_multiple_of(4) as u32;
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! Module-definition (`.def`) files, given with `/DEF`.
+
+use color_eyre::Result;
+use winning_coff::diagnostics::{Code, fatal};
+
+use crate::{
+    export::ExportSpec,
+    link::{Reservation, parse_major_minor},
+};
+
+/// The statements of a module-definition file that we support. `NAME`,
+/// `DESCRIPTION`, `SECTIONS` and `STUB` are accepted but ignored.
+#[derive(Debug, Clone, Default)]
+pub struct ModuleDefinition {
+    /// `LIBRARY name`: the DLL's file name, with `.dll` appended if it has
+    /// no extension.
+    pub library: Option<String>,
+    pub exports: Vec<ExportSpec>,
+    /// `STACKSIZE reserve[,commit]`.
+    pub stack: Option<Reservation>,
+    /// `HEAPSIZE reserve[,commit]`.
+    pub heap: Option<Reservation>,
+    /// `VERSION major[.minor]`: the image version.
+    pub version: Option<(u16, u16)>,
+}
+
+/// Statements that end the `EXPORTS` section.
+const KEYWORDS: &[&str] = &[
+    "NAME",
+    "LIBRARY",
+    "DESCRIPTION",
+    "STACKSIZE",
+    "HEAPSIZE",
+    "SECTIONS",
+    "VERSION",
+    "STUB",
+];
+
+impl ModuleDefinition {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut def = Self::default();
+        let mut in_exports = false;
+        for line in text.lines() {
+            let line = line.split_once(';').map_or(line, |(line, _)| line).trim();
+            let mut words = line.split_whitespace();
+            let Some(first) = words.next() else {
+                continue;
+            };
+            let keyword = first.to_ascii_uppercase();
+            if keyword == "EXPORTS" {
+                in_exports = true;
+                // An entry may follow on the same line.
+                let entry = line[first.len()..].trim_start();
+                if !entry.is_empty() {
+                    def.exports.push(ExportSpec::parse_def_entry(entry)?);
+                }
+                continue;
+            }
+            if !KEYWORDS.contains(&keyword.as_str()) {
+                if !in_exports {
+                    return fatal(
+                        Code::InvalidOption,
+                        format!("unknown module-definition statement `{line}`"),
+                    );
+                }
+                def.exports.push(ExportSpec::parse_def_entry(line)?);
+                continue;
+            }
+            in_exports = false;
+            let arguments = words.collect::<Vec<_>>().join(" ");
+            let invalid = || fatal(Code::InvalidOption, format!("invalid `{line}`"));
+            match keyword.as_str() {
+                "LIBRARY" => {
+                    // `BASE=address` may follow the name.
+                    let Some(name) = arguments
+                        .split_whitespace()
+                        .next()
+                        .filter(|name| !name.starts_with("BASE="))
+                    else {
+                        continue;
+                    };
+                    let name = name.trim_matches('"');
+                    def.library = Some(if name.contains('.') {
+                        name.to_owned()
+                    } else {
+                        format!("{name}.dll")
+                    });
+                }
+                "STACKSIZE" => match Reservation::parse(&arguments) {
+                    Some(stack) => def.stack = Some(stack),
+                    None => return invalid(),
+                },
+                "HEAPSIZE" => match Reservation::parse(&arguments) {
+                    Some(heap) => def.heap = Some(heap),
+                    None => return invalid(),
+                },
+                "VERSION" => match parse_major_minor(&arguments) {
+                    Some(version) => def.version = Some(version),
+                    None => return invalid(),
+                },
+                _ => {}
+            }
+        }
+        Ok(def)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::ExportTarget;
+
+    fn names(def: &ModuleDefinition) -> Vec<&str> {
+        def.exports.iter().map(|spec| spec.name.as_str()).collect()
+    }
+
+    #[test]
+    fn exports() {
+        let def = ModuleDefinition::parse(
+            "EXPORTS first ; the rest follow\n\
+             \tsecond @2 NONAME\n\
+             \n\
+             ; a whole-line comment\n\
+             third=internal DATA\n\
+             fourth=other.Target\n",
+        )
+        .unwrap();
+        assert_eq!(names(&def), ["first", "second", "third", "fourth"]);
+        assert_eq!(def.exports[1].ordinal, Some(2));
+        assert!(def.exports[1].noname);
+        assert!(def.exports[2].data);
+        assert_eq!(
+            def.exports[2].target,
+            ExportTarget::Symbol("internal".to_owned())
+        );
+        assert_eq!(
+            def.exports[3].target,
+            ExportTarget::Forwarder("other.Target".to_owned())
+        );
+    }
+
+    #[test]
+    fn keyword_ends_exports() {
+        let def = ModuleDefinition::parse("exports\n  one\nVERSION 3\n  two\n");
+        // `two` is no longer in `EXPORTS`, so it's an unknown statement.
+        assert!(def.is_err());
+        let def =
+            ModuleDefinition::parse("EXPORTS\n  one\nheapsize 0x1000\nEXPORTS two\n").unwrap();
+        assert_eq!(names(&def), ["one", "two"]);
+        assert_eq!(def.heap.unwrap().reserve, 0x1000);
+    }
+
+    #[test]
+    fn library() {
+        let library = |text| ModuleDefinition::parse(text).unwrap().library;
+        assert_eq!(library("LIBRARY \"x\""), Some("x.dll".to_owned()));
+        assert_eq!(library("LIBRARY x.ocx"), Some("x.ocx".to_owned()));
+        assert_eq!(library("LIBRARY \"x.dll\""), Some("x.dll".to_owned()));
+        assert_eq!(
+            library("LIBRARY x BASE=0x10000000"),
+            Some("x.dll".to_owned())
+        );
+        assert_eq!(library("LIBRARY BASE=0x10000000"), None);
+        assert_eq!(library("LIBRARY"), None);
+    }
+
+    #[test]
+    fn sizes_and_version() {
+        let def = ModuleDefinition::parse("STACKSIZE 0x200000,0x2000\nHEAPSIZE 65536").unwrap();
+        let stack = def.stack.unwrap();
+        assert_eq!((stack.reserve, stack.commit), (0x20_0000, Some(0x2000)));
+        let heap = def.heap.unwrap();
+        assert_eq!((heap.reserve, heap.commit), (65536, None));
+
+        let version = |text| ModuleDefinition::parse(text).unwrap().version;
+        assert_eq!(version("VERSION 1.2"), Some((1, 2)));
+        assert_eq!(version("VERSION 7"), Some((7, 0)));
+        assert!(ModuleDefinition::parse("VERSION one").is_err());
+        assert!(ModuleDefinition::parse("STACKSIZE").is_err());
+    }
+
+    #[test]
+    fn unknown_statement() {
+        let err = ModuleDefinition::parse("LIBRARY x\nIMPORTS foo").unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("unknown module-definition statement `IMPORTS foo`")
+        );
+    }
+}
@@ -0,0 +1,283 @@
+//! Just enough DER (X.690) to read keys and certificates and to write the
+//! PKCS#7 of Authenticode signatures. Only tags below 31 and definite lengths
+//! are supported, which is all DER needs for these.
+
+pub const INTEGER: u8 = 0x02;
+pub const BIT_STRING: u8 = 0x03;
+pub const OCTET_STRING: u8 = 0x04;
+pub const NULL: u8 = 0x05;
+pub const OBJECT_IDENTIFIER: u8 = 0x06;
+pub const BMP_STRING: u8 = 0x1e;
+pub const SEQUENCE: u8 = 0x30;
+pub const SET: u8 = 0x31;
+
+/// The tag of a constructed `[number]` field, `EXPLICIT` or of a constructed
+/// type.
+pub const fn context(number: u8) -> u8 {
+    0xa0 | number
+}
+
+/// The tag of a primitive `[number] IMPLICIT` field.
+pub const fn context_primitive(number: u8) -> u8 {
+    0x80 | number
+}
+
+/// One encoded value.
+#[derive(Debug, Clone, Copy)]
+pub struct Value<'a> {
+    pub tag: u8,
+    pub contents: &'a [u8],
+    /// The whole encoding, with the tag and length.
+    pub encoding: &'a [u8],
+}
+
+impl<'a> Value<'a> {
+    /// The values a constructed value consists of.
+    pub fn children(&self) -> Reader<'a> {
+        Reader::new(self.contents)
+    }
+
+    /// The bytes of an unsigned `INTEGER`, without the leading zero that
+    /// keeps it positive.
+    pub fn unsigned(&self) -> Option<&'a [u8]> {
+        if self.tag != INTEGER || self.contents.first().is_none_or(|&byte| byte & 0x80 != 0) {
+            return None;
+        }
+        match self.contents {
+            [0, rest @ ..] if !rest.is_empty() => Some(rest),
+            contents => Some(contents),
+        }
+    }
+
+    /// A small non-negative `INTEGER`, like a version or an iteration count.
+    pub fn small_integer(&self) -> Option<u32> {
+        let bytes = self.unsigned()?;
+        if bytes.len() > 4 {
+            return None;
+        }
+        Some(
+            bytes
+                .iter()
+                .fold(0, |value, &byte| value << 8 | u32::from(byte)),
+        )
+    }
+}
+
+/// Reads a sequence of values.
+#[derive(Debug, Clone)]
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The next value, or `None` at the end or if it's malformed.
+    pub fn read(&mut self) -> Option<Value<'a>> {
+        let (&tag, rest) = self.data.split_first()?;
+        if tag & 0x1f == 0x1f {
+            return None;
+        }
+        let (&first, rest) = rest.split_first()?;
+        let (length, rest) = if first < 0x80 {
+            (first as usize, rest)
+        } else {
+            let count = (first & 0x7f) as usize;
+            if count == 0 || count > 4 || rest.len() < count {
+                return None;
+            }
+            let length = rest[..count]
+                .iter()
+                .fold(0, |length, &byte| length << 8 | byte as usize);
+            (length, &rest[count..])
+        };
+        if rest.len() < length {
+            return None;
+        }
+        let header = self.data.len() - rest.len();
+        let value = Value {
+            tag,
+            contents: &rest[..length],
+            encoding: &self.data[..header + length],
+        };
+        self.data = &rest[length..];
+        Some(value)
+    }
+
+    /// The next value, if it has `tag`.
+    pub fn expect(&mut self, tag: u8) -> Option<Value<'a>> {
+        self.read().filter(|value| value.tag == tag)
+    }
+
+    /// The next value if it has `tag`, for `OPTIONAL` and `DEFAULT` fields,
+    /// leaving anything else to be read.
+    pub fn optional(&mut self, tag: u8) -> Option<Value<'a>> {
+        let mut ahead = self.clone();
+        let value = ahead.expect(tag)?;
+        *self = ahead;
+        Some(value)
+    }
+}
+
+/// Encodes a value from its contents.
+pub fn encode(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let length = contents.len();
+    if length < 0x80 {
+        out.push(length as u8);
+    } else {
+        let bytes = length.to_be_bytes();
+        let skip = bytes.iter().take_while(|&&byte| byte == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+    out.extend_from_slice(contents);
+    out
+}
+
+pub fn sequence(values: &[Vec<u8>]) -> Vec<u8> {
+    encode(SEQUENCE, &values.concat())
+}
+
+/// A `SET OF`, which DER sorts by encoding. `tag` is [`SET`] unless the set
+/// is an `IMPLICIT` field.
+pub fn set_of(tag: u8, values: &[Vec<u8>]) -> Vec<u8> {
+    let mut values = values.to_vec();
+    values.sort();
+    encode(tag, &values.concat())
+}
+
+/// An `AlgorithmIdentifier` whose parameters are `NULL`.
+pub fn algorithm(oid: &[u8]) -> Vec<u8> {
+    sequence(&[encode(OBJECT_IDENTIFIER, oid), encode(NULL, &[])])
+}
+
+/// The object identifiers we use, as the contents of their encoding.
+pub mod oid {
+    pub const RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+    pub const SHA256: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+    pub const PBES2: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x05, 0x0d];
+    pub const PBKDF2: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x05, 0x0c];
+    pub const HMAC_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x09];
+    pub const AES128_CBC: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x02];
+    pub const AES192_CBC: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x16];
+    pub const AES256_CBC: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x2a];
+    pub const COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+
+    pub const DATA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x01];
+    pub const SIGNED_DATA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x02];
+    pub const ENCRYPTED_DATA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x06];
+    pub const CONTENT_TYPE: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x03];
+    pub const MESSAGE_DIGEST: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x04];
+    pub const X509_CERTIFICATE: &[u8] =
+        &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x16, 0x01];
+
+    /// The PKCS#12 bag types, which follow this prefix.
+    pub const PKCS12_BAG_TYPES: &[u8] =
+        &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x0c, 0x0a, 0x01];
+    pub const KEY_BAG: u8 = 1;
+    pub const SHROUDED_KEY_BAG: u8 = 2;
+    pub const CERT_BAG: u8 = 3;
+    /// The password-based encryption of PKCS#12 itself, with RC2 or 3DES,
+    /// which follow this prefix.
+    pub const PKCS12_PBE: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x0c, 0x01];
+
+    pub const SPC_INDIRECT_DATA: &[u8] =
+        &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x01, 0x04];
+    pub const SPC_STATEMENT_TYPE: &[u8] =
+        &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x01, 0x0b];
+    pub const SPC_SP_OPUS_INFO: &[u8] =
+        &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x01, 0x0c];
+    pub const SPC_PE_IMAGE_DATA: &[u8] =
+        &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x01, 0x0f];
+    pub const SPC_INDIVIDUAL_SP_KEY_PURPOSE: &[u8] =
+        &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x01, 0x15];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lengths() {
+        assert_eq!(encode(OCTET_STRING, &[1, 2]), [0x04, 0x02, 1, 2]);
+        let long = encode(OCTET_STRING, &[0; 0x80]);
+        assert_eq!(long[..3], [0x04, 0x81, 0x80]);
+        let longer = encode(OCTET_STRING, &[0; 0x1234]);
+        assert_eq!(longer[..4], [0x04, 0x82, 0x12, 0x34]);
+
+        for (encoding, length) in [(&long, 0x80), (&longer, 0x1234)] {
+            let mut reader = Reader::new(encoding);
+            let value = reader.read().unwrap();
+            assert_eq!(value.contents.len(), length);
+            assert_eq!(value.encoding, &encoding[..]);
+            assert!(reader.is_empty());
+        }
+    }
+
+    #[test]
+    fn malformed() {
+        // Indefinite length, a high tag number, and contents past the end.
+        for data in [
+            &[0x30, 0x80, 0x00, 0x00][..],
+            &[0x1f, 0x22, 0x00],
+            &[0x04, 0x03, 0x00],
+        ] {
+            assert!(Reader::new(data).read().is_none(), "{data:02x?}");
+        }
+    }
+
+    #[test]
+    fn nested_and_optional() {
+        let data = sequence(&[
+            encode(context(0), &encode(INTEGER, &[2])),
+            encode(INTEGER, &[0x00, 0x80]),
+        ]);
+        let mut fields = Reader::new(&data).expect(SEQUENCE).unwrap().children();
+        assert!(fields.optional(context(1)).is_none());
+        let version = fields.optional(context(0)).unwrap();
+        assert_eq!(version.children().read().unwrap().small_integer(), Some(2));
+        assert!(fields.optional(context(0)).is_none());
+        assert_eq!(
+            fields.expect(INTEGER).unwrap().unsigned(),
+            Some(&[0x80][..])
+        );
+        assert!(fields.is_empty());
+    }
+
+    #[test]
+    fn integers() {
+        fn integer(contents: &[u8]) -> Value<'_> {
+            Value {
+                tag: INTEGER,
+                contents,
+                encoding: &[],
+            }
+        }
+        assert_eq!(integer(&[0]).unsigned(), Some(&[0][..]));
+        assert_eq!(integer(&[0x00, 0xff]).unsigned(), Some(&[0xff][..]));
+        assert_eq!(integer(&[0xff]).unsigned(), None);
+        assert_eq!(integer(&[]).unsigned(), None);
+        assert_eq!(integer(&[0x01, 0x00, 0x00]).small_integer(), Some(0x10000));
+        assert_eq!(integer(&[1, 2, 3, 4, 5]).small_integer(), None);
+    }
+
+    #[test]
+    fn sets_are_sorted() {
+        let set = set_of(SET, &[encode(INTEGER, &[2]), encode(INTEGER, &[1])]);
+        assert_eq!(set, [0x31, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02]);
+        assert_eq!(
+            algorithm(oid::SHA256),
+            [
+                0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05,
+                0x00
+            ]
+        );
+    }
+}
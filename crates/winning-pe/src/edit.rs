@@ -0,0 +1,202 @@
+use std::io;
+
+use binrw::BinWrite;
+use color_eyre::Result;
+use winning_coff::{
+    coff::Characteristics,
+    diagnostics::{Code, Diagnostics, fatal},
+};
+
+use crate::{
+    image::ImageModel,
+    link::Reservation,
+    pe::{
+        DllCharacteristics, IMAGE_REL_BASED_DIR64, IMAGE_REL_BASED_HIGHLOW, OptionalHeader,
+        PE_SIGNATURE, Subsystem, checksum,
+    },
+};
+
+/// Header fields of an existing image to change, like editbin does. Fields
+/// that are `None` or empty are kept.
+#[derive(Debug, Clone, Default)]
+pub struct HeaderEdits {
+    pub subsystem: Option<Subsystem>,
+    pub subsystem_version: Option<(u16, u16)>,
+    /// Rebases the image, applying its base relocations.
+    pub image_base: Option<u64>,
+    /// A missing commit size keeps the one the image has.
+    pub stack: Option<Reservation>,
+    pub heap: Option<Reservation>,
+    pub set: DllCharacteristics,
+    pub clear: DllCharacteristics,
+    pub large_address_aware: Option<bool>,
+}
+
+/// Applies `edits` to the headers of an image without moving anything. The
+/// certificate table is dropped since the signature no longer matches, and
+/// the checksum is recomputed if the image had one.
+pub fn edit(data: &[u8], edits: &HeaderEdits, diag: &mut Diagnostics<'_>) -> Result<Vec<u8>> {
+    let image = ImageModel::parse(data)?;
+    let mut header = image.header;
+    let mut optional = image.optional_header.clone();
+    let is_pe32 = image.optional_header_32.is_some();
+    let mut out = data.to_vec();
+
+    if let Some(subsystem) = edits.subsystem {
+        optional.subsystem = subsystem.value();
+    }
+    if let Some((major, minor)) = edits.subsystem_version {
+        optional.major_subsystem_version = major;
+        optional.minor_subsystem_version = minor;
+    }
+
+    let size_limit = if is_pe32 { u32::MAX.into() } else { u64::MAX };
+    for (name, reservation, reserve, commit) in [
+        (
+            "stack",
+            edits.stack,
+            &mut optional.size_of_stack_reserve,
+            &mut optional.size_of_stack_commit,
+        ),
+        (
+            "heap",
+            edits.heap,
+            &mut optional.size_of_heap_reserve,
+            &mut optional.sizeof_heap_commit,
+        ),
+    ] {
+        let Some(reservation) = reservation else {
+            continue;
+        };
+        let new_commit = reservation.commit.unwrap_or(*commit);
+        if reservation.reserve > size_limit || new_commit > reservation.reserve {
+            return fatal(
+                Code::InvalidOption,
+                format!(
+                    "invalid {name} size {:#x},{new_commit:#x}: the commit size must fit in the reserve size",
+                    reservation.reserve
+                ),
+            );
+        }
+        *reserve = reservation.reserve;
+        *commit = new_commit;
+    }
+
+    optional.dll_characteristics = (optional.dll_characteristics | edits.set) - edits.clear;
+    if is_pe32
+        && optional
+            .dll_characteristics
+            .contains(DllCharacteristics::IMAGE_DLLCHARACTERISTICS_HIGH_ENTROPY_VA)
+    {
+        return fatal(
+            Code::InvalidOption,
+            "/HIGHENTROPYVA only applies to 64-bit images",
+        );
+    }
+    // An image without absolute addresses has an empty base relocation table.
+    let has_relocations = !header
+        .characteristics
+        .contains(Characteristics::IMAGE_FILE_RELOCS_STRIPPED);
+    if edits
+        .set
+        .contains(DllCharacteristics::IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE)
+        && !has_relocations
+    {
+        return fatal(
+            Code::UnsupportedImage,
+            "the image has no base relocations, so it can't be /DYNAMICBASE",
+        );
+    }
+    match edits.large_address_aware {
+        Some(true) => header.characteristics |= Characteristics::IMAGE_FILE_LARGE_ADDRESS_AWARE,
+        Some(false) => header.characteristics -= Characteristics::IMAGE_FILE_LARGE_ADDRESS_AWARE,
+        None => {}
+    }
+
+    if let Some(base) = edits.image_base.filter(|&base| base != optional.image_base) {
+        if !base.is_multiple_of(0x1_0000)
+            || base
+                .checked_add(optional.size_of_image.into())
+                .is_none_or(|end| end > size_limit)
+        {
+            return fatal(
+                Code::InvalidOption,
+                format!("invalid base address {base:#x}"),
+            );
+        }
+        if !has_relocations {
+            return fatal(
+                Code::UnsupportedImage,
+                "the image has no base relocations, so it can't be rebased",
+            );
+        }
+        let sites = match optional.base_relocation_table.size {
+            0 => Some(Vec::new()),
+            _ => image.base_relocations(),
+        };
+        let Some(sites) = sites else {
+            return fatal(Code::CorruptInput, "base relocations are out of bounds");
+        };
+        let delta = base.wrapping_sub(optional.image_base);
+        for (rva, kind) in sites {
+            let size = match kind {
+                IMAGE_REL_BASED_HIGHLOW => 4,
+                IMAGE_REL_BASED_DIR64 => 8,
+                _ => {
+                    return fatal(
+                        Code::UnsupportedImage,
+                        format!("cannot rebase a base relocation of type {kind} at {rva:#x}"),
+                    );
+                }
+            };
+            let Some(offset) = image.section_containing(rva).and_then(|section| {
+                let offset = rva - section.virtual_address;
+                (offset + size <= section.size_of_raw_data)
+                    .then(|| (section.pointer_to_raw_data + offset) as usize)
+            }) else {
+                return fatal(
+                    Code::CorruptInput,
+                    format!("base relocation at {rva:#x} is out of bounds"),
+                );
+            };
+            let site = &mut out[offset..offset + size as usize];
+            if size == 4 {
+                let value = u32::from_le_bytes(site.try_into().unwrap());
+                site.copy_from_slice(&value.wrapping_add(delta as u32).to_le_bytes());
+            } else {
+                let value = u64::from_le_bytes(site.try_into().unwrap());
+                site.copy_from_slice(&value.wrapping_add(delta).to_le_bytes());
+            }
+        }
+        optional.image_base = base;
+    }
+
+    // Everything but the checksum and the certificate table is signed.
+    let certificate = optional.certificate_table;
+    if certificate.size > 0 {
+        diag.warn(
+            Code::SignatureRemoved,
+            "the image was signed; the signature has been removed",
+        );
+        optional.certificate_table = Default::default();
+        if (certificate.virtual_address + certificate.size) as usize == out.len() {
+            out.truncate(certificate.virtual_address as usize);
+        }
+    }
+
+    let cursor = &mut io::Cursor::new(&mut out);
+    cursor.set_position((image.pe_offset + PE_SIGNATURE.len()) as u64);
+    header.write(cursor)?;
+    let optional_offset = cursor.position() as usize;
+    let had_checksum = optional.check_sum != 0;
+    optional.check_sum = 0;
+    image.write_optional_header(&optional, cursor)?;
+
+    if had_checksum {
+        let checksum_offset = optional_offset + OptionalHeader::CHECKSUM_OFFSET;
+        let sum = checksum(&out, checksum_offset);
+        out[checksum_offset..checksum_offset + 4].copy_from_slice(&sum.to_le_bytes());
+    }
+
+    Ok(out)
+}
@@ -0,0 +1,516 @@
+use std::collections::HashMap;
+
+use color_eyre::Result;
+use winning_coff::{
+    demangle,
+    diagnostics::{Code, fatal},
+};
+
+/// Which defined symbols an image exports, read from an export list file:
+/// one symbol name or glob per line, where `*` matches any run of characters
+/// and `?` any single one. A line starting with `!` hides matching symbols
+/// again; the last matching line wins, as with the `global:`/`local:`
+/// sections of a GNU version script. `#` starts a comment.
+///
+/// Patterns match either the raw or the demangled symbol name.
+#[derive(Debug, Clone, Default)]
+pub struct ExportList {
+    patterns: Vec<Pattern>,
+}
+
+#[derive(Debug, Clone)]
+struct Pattern {
+    glob: String,
+    exported: bool,
+}
+
+impl ExportList {
+    pub fn parse(text: &str) -> Self {
+        let patterns = text
+            .lines()
+            .map(|line| line.split_once('#').map_or(line, |(line, _)| line).trim())
+            .filter(|line| !line.is_empty())
+            .map(|line| match line.strip_prefix('!') {
+                Some(glob) => Pattern {
+                    glob: glob.trim_start().to_owned(),
+                    exported: false,
+                },
+                None => Pattern {
+                    glob: line.to_owned(),
+                    exported: true,
+                },
+            })
+            .collect();
+        Self { patterns }
+    }
+
+    pub fn is_exported(&self, name: &str) -> bool {
+        let demangled = demangle::symbol_name(name, true).to_string();
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| {
+                glob_match(pattern.glob.as_bytes(), name.as_bytes())
+                    || glob_match(pattern.glob.as_bytes(), demangled.as_bytes())
+            })
+            .is_some_and(|pattern| pattern.exported)
+    }
+}
+
+/// An export named explicitly with `/EXPORT` or in the `EXPORTS` of a
+/// module-definition file, as `name[=internal]`. An `internal` name with a
+/// dot, like `OTHER.Bar`, forwards the export to `Bar` in `OTHER.dll`.
+#[derive(Debug, Clone)]
+pub struct ExportSpec {
+    pub name: String,
+    pub target: ExportTarget,
+    /// `@n`, a fixed ordinal.
+    pub ordinal: Option<u16>,
+    /// `NONAME`: only exported by ordinal, and left out of the name table.
+    pub noname: bool,
+    /// `DATA`: a variable, imported without a thunk.
+    pub data: bool,
+    /// `PRIVATE`: left out of the import library.
+    pub private: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExportTarget {
+    Symbol(String),
+    Forwarder(String),
+}
+
+impl ExportSpec {
+    /// Parses the value of `/EXPORT`, where attributes follow after commas.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut parts = spec.split(',');
+        let entry = parts.next().unwrap_or_default();
+        Self::parse_entry(entry, parts, spec)
+    }
+
+    /// An entry in the `EXPORTS` of a module-definition file, where
+    /// attributes follow after spaces.
+    pub(crate) fn parse_def_entry(entry: &str) -> Result<Self> {
+        let mut parts = entry.split_whitespace();
+        let name = parts.next().unwrap_or_default();
+        Self::parse_entry(name, parts, entry)
+    }
+
+    fn parse_entry<'a>(
+        entry: &str,
+        attributes: impl Iterator<Item = &'a str>,
+        spec: &str,
+    ) -> Result<Self> {
+        let invalid = || fatal(Code::InvalidOption, format!("invalid export `{spec}`"));
+        // A decorated name is exported without its decoration, but still
+        // refers to the decorated symbol.
+        let (name, internal) = entry.split_once('=').unwrap_or((export_name(entry), entry));
+        if name.is_empty() || internal.is_empty() {
+            return invalid();
+        }
+        let target = if internal.contains('.') {
+            ExportTarget::Forwarder(internal.to_owned())
+        } else {
+            ExportTarget::Symbol(internal.to_owned())
+        };
+        let mut ordinal = None;
+        let mut noname = false;
+        let mut data = false;
+        let mut private = false;
+        for attribute in attributes {
+            if let Some(number) = attribute.strip_prefix('@') {
+                match number.parse() {
+                    Ok(number) if number > 0 => ordinal = Some(number),
+                    _ => return invalid(),
+                }
+            } else if attribute.eq_ignore_ascii_case("NONAME") {
+                noname = true;
+            } else if attribute.eq_ignore_ascii_case("DATA") {
+                data = true;
+            } else if attribute.eq_ignore_ascii_case("PRIVATE") {
+                private = true;
+            } else {
+                return invalid();
+            }
+        }
+        if noname && ordinal.is_none() {
+            return fatal(
+                Code::InvalidOption,
+                format!("NONAME export without an ordinal: `{spec}`"),
+            );
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            target,
+            ordinal,
+            noname,
+            data,
+            private,
+        })
+    }
+}
+
+/// Matches `name` against a glob of `*` and `?` wildcards, backtracking to the
+/// last `*` on a mismatch.
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// The export directory of an image: the directory table, the export
+/// address table, the name pointer and ordinal tables, and the names. Named
+/// exports are sorted by name, as the loader binary-searches them. Exports
+/// without a fixed ordinal are numbered in that order after the highest fixed
+/// one, like lld does, and the address table has gaps for unused ordinals.
+pub struct ExportTables {
+    image_name: String,
+    exports: Vec<ExportEntry>,
+    /// The ordinal of each export.
+    ordinals: Vec<u16>,
+    base: u16,
+    address_count: u32,
+    named_count: u32,
+    size: u32,
+}
+
+pub struct ExportEntry {
+    pub name: String,
+    /// The `dll.name` the loader resolves the export to instead, which the
+    /// address table entry points to.
+    pub forwarder: Option<String>,
+    pub ordinal: Option<u16>,
+    pub noname: bool,
+    pub data: bool,
+    pub private: bool,
+}
+
+const EXPORT_DIRECTORY_SIZE: u32 = 40;
+
+impl ExportTables {
+    /// `exports` must be sorted by name.
+    pub fn new(image_name: String, exports: Vec<ExportEntry>) -> Result<Self> {
+        let mut used = HashMap::new();
+        for export in &exports {
+            if let Some(ordinal) = export.ordinal
+                && let Some(other) = used.insert(ordinal, &export.name)
+            {
+                return fatal(
+                    Code::InvalidOption,
+                    format!(
+                        "ordinal {ordinal} is used by both {other} and {}",
+                        export.name
+                    ),
+                );
+            }
+        }
+        let mut next = used.keys().copied().max().unwrap_or(0);
+        let mut ordinals = Vec::with_capacity(exports.len());
+        for export in &exports {
+            let ordinal = match export.ordinal {
+                Some(ordinal) => ordinal,
+                None => {
+                    let Some(ordinal) = next.checked_add(1) else {
+                        return fatal(Code::InvalidOption, "too many exports for 16-bit ordinals");
+                    };
+                    next = ordinal;
+                    ordinal
+                }
+            };
+            ordinals.push(ordinal);
+        }
+        let base = ordinals.iter().copied().min().unwrap_or(1);
+        let address_count = ordinals
+            .iter()
+            .map(|&ordinal| u32::from(ordinal - base) + 1)
+            .max()
+            .unwrap_or(0);
+        let named_count = exports.iter().filter(|export| !export.noname).count() as u32;
+        let strings: u32 = exports
+            .iter()
+            .map(|export| {
+                let name = if export.noname {
+                    0
+                } else {
+                    export.name.len() as u32 + 1
+                };
+                name + export
+                    .forwarder
+                    .as_ref()
+                    .map_or(0, |forwarder| forwarder.len() as u32 + 1)
+            })
+            .sum();
+        Ok(Self {
+            size: EXPORT_DIRECTORY_SIZE
+                + address_count * 4
+                + named_count * 6
+                + image_name.len() as u32
+                + 1
+                + strings,
+            image_name,
+            exports,
+            ordinals,
+            base,
+            address_count,
+            named_count,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exports.is_empty()
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// The exports in the order of the names, with their ordinal and, for
+    /// named ones, the index in the name table that import hints refer to.
+    pub fn entries(&self) -> impl Iterator<Item = (&ExportEntry, u16, Option<u16>)> {
+        let mut named = 0;
+        self.exports
+            .iter()
+            .zip(&self.ordinals)
+            .map(move |(export, &ordinal)| {
+                let hint = (!export.noname).then(|| {
+                    named += 1;
+                    named - 1
+                });
+                (export, ordinal, hint)
+            })
+    }
+
+    /// `functions` holds the RVA of each export, in the order of the names.
+    /// Forwarded exports point to their forwarder string instead.
+    pub fn write(&self, functions: &[u32], directory_rva: u32, out: &mut [u8]) {
+        let functions_offset = EXPORT_DIRECTORY_SIZE;
+        let name_pointers_offset = functions_offset + self.address_count * 4;
+        let ordinals_offset = name_pointers_offset + self.named_count * 4;
+        let image_name_offset = ordinals_offset + self.named_count * 2;
+
+        put(out, 12, directory_rva + image_name_offset);
+        put(out, 16, self.base.into());
+        put(out, 20, self.address_count);
+        put(out, 24, self.named_count);
+        put(out, 28, directory_rva + functions_offset);
+        put(out, 32, directory_rva + name_pointers_offset);
+        put(out, 36, directory_rva + ordinals_offset);
+
+        let mut string_offset = image_name_offset + self.image_name.len() as u32 + 1;
+        let mut named = 0;
+        for ((export, &function), &ordinal) in
+            self.exports.iter().zip(functions).zip(&self.ordinals)
+        {
+            let index = u32::from(ordinal - self.base);
+            if !export.noname {
+                let name = &export.name;
+                put(
+                    out,
+                    name_pointers_offset + named * 4,
+                    directory_rva + string_offset,
+                );
+                out[(ordinals_offset + named * 2) as usize..][..2]
+                    .copy_from_slice(&(index as u16).to_le_bytes());
+                out[string_offset as usize..][..name.len()].copy_from_slice(name.as_bytes());
+                string_offset += name.len() as u32 + 1;
+                named += 1;
+            }
+            match &export.forwarder {
+                Some(forwarder) => {
+                    put(
+                        out,
+                        functions_offset + index * 4,
+                        directory_rva + string_offset,
+                    );
+                    out[string_offset as usize..][..forwarder.len()]
+                        .copy_from_slice(forwarder.as_bytes());
+                    string_offset += forwarder.len() as u32 + 1;
+                }
+                None => put(out, functions_offset + index * 4, function),
+            }
+        }
+        out[image_name_offset as usize..][..self.image_name.len()]
+            .copy_from_slice(self.image_name.as_bytes());
+    }
+}
+
+fn put(out: &mut [u8], offset: u32, value: u32) {
+    out[offset as usize..][..4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// The name a 32-bit x86 `__stdcall`, `__fastcall` or `__vectorcall`
+/// symbol is exported under, without its decoration. Other names are kept:
+/// a leading `_` alone is also part of plain x64 names.
+fn export_name(name: &str) -> &str {
+    let has_size = name
+        .rsplit_once('@')
+        .is_some_and(|(_, size)| !size.is_empty() && size.bytes().all(|b| b.is_ascii_digit()));
+    if has_size {
+        demangle::undecorated(name)
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, ordinal: Option<u16>, noname: bool) -> ExportEntry {
+        ExportEntry {
+            name: name.to_owned(),
+            forwarder: None,
+            ordinal,
+            noname,
+            data: false,
+            private: false,
+        }
+    }
+
+    fn read_u32(data: &[u8], offset: u32) -> u32 {
+        u32::from_le_bytes(data[offset as usize..][..4].try_into().unwrap())
+    }
+
+    #[test]
+    fn parse_attributes() {
+        let spec = ExportSpec::parse("Foo=internal,@7,NONAME,PRIVATE").unwrap();
+        assert_eq!(spec.name, "Foo");
+        assert_eq!(spec.target, ExportTarget::Symbol("internal".to_owned()));
+        assert_eq!(spec.ordinal, Some(7));
+        assert!(spec.noname && spec.private && !spec.data);
+
+        let spec = ExportSpec::parse_def_entry("Bar=OTHER.Baz @65535 data").unwrap();
+        assert_eq!(spec.target, ExportTarget::Forwarder("OTHER.Baz".to_owned()));
+        assert_eq!(spec.ordinal, Some(65535));
+        assert!(spec.data);
+    }
+
+    #[test]
+    fn decorated_names() {
+        for (spec, name, target) in [
+            ("_foo@8", "foo", "_foo@8"),
+            ("@foo@8", "foo", "@foo@8"),
+            ("foo@@16", "foo", "foo@@16"),
+            ("_foo", "_foo", "_foo"),
+            ("?foo@@YAXXZ", "?foo@@YAXXZ", "?foo@@YAXXZ"),
+            ("bar=_foo@8", "bar", "_foo@8"),
+        ] {
+            let parsed = ExportSpec::parse(spec).unwrap();
+            assert_eq!(parsed.name, name, "{spec}");
+            assert_eq!(parsed.target, ExportTarget::Symbol(target.to_owned()));
+        }
+        let spec = ExportSpec::parse_def_entry("_foo@8 @2").unwrap();
+        assert_eq!((spec.name.as_str(), spec.ordinal), ("foo", Some(2)));
+    }
+
+    #[test]
+    fn parse_rejects() {
+        for spec in [
+            "Foo,@0",
+            "Foo,@70000",
+            "Foo,@",
+            "Foo,@x",
+            "Foo,NONAME",
+            "Foo,BOGUS",
+            "=Foo",
+            "Foo=",
+            "",
+        ] {
+            assert!(ExportSpec::parse(spec).is_err(), "{spec}");
+        }
+    }
+
+    #[test]
+    fn duplicate_ordinals() {
+        let exports = vec![entry("a", Some(2), false), entry("b", Some(2), true)];
+        let Err(err) = ExportTables::new("x.dll".to_owned(), exports) else {
+            panic!("duplicate ordinal accepted");
+        };
+        assert!(
+            err.to_string()
+                .contains("ordinal 2 is used by both a and b")
+        );
+    }
+
+    #[test]
+    fn ordinals_and_gaps() {
+        // Sorted by name, like the linker passes them.
+        let exports = vec![
+            entry("alpha", None, false),
+            entry("bar", Some(1), true),
+            entry("foo", Some(3), false),
+            entry("zeta", None, false),
+        ];
+        let tables = ExportTables::new("b.dll".to_owned(), exports).unwrap();
+        let entries = tables
+            .entries()
+            .map(|(export, ordinal, hint)| (export.name.as_str(), ordinal, hint))
+            .collect::<Vec<_>>();
+        // Unfixed ordinals follow the highest fixed one, in name order, and
+        // only named exports get hints.
+        assert_eq!(
+            entries,
+            [
+                ("alpha", 4, Some(0)),
+                ("bar", 1, None),
+                ("foo", 3, Some(1)),
+                ("zeta", 5, Some(2)),
+            ]
+        );
+
+        let directory_rva = 0x2000;
+        let mut out = vec![0; tables.size() as usize];
+        tables.write(&[0x1010, 0x1020, 0x1030, 0x1040], directory_rva, &mut out);
+        assert_eq!(read_u32(&out, 16), 1, "ordinal base");
+        assert_eq!(read_u32(&out, 20), 5, "address table entries");
+        assert_eq!(read_u32(&out, 24), 3, "names");
+        let functions = read_u32(&out, 28) - directory_rva;
+        let addresses = (0..5)
+            .map(|idx| read_u32(&out, functions + idx * 4))
+            .collect::<Vec<_>>();
+        // Ordinal 2 is unused and left zero.
+        assert_eq!(addresses, [0x1020, 0, 0x1030, 0x1010, 0x1040]);
+
+        let name_pointers = read_u32(&out, 32) - directory_rva;
+        let ordinals = read_u32(&out, 36) - directory_rva;
+        let names = (0..3)
+            .map(|idx| {
+                let start = (read_u32(&out, name_pointers + idx * 4) - directory_rva) as usize;
+                let end = start + out[start..].iter().position(|&b| b == 0).unwrap();
+                let index = u16::from_le_bytes(
+                    out[(ordinals + idx * 2) as usize..][..2]
+                        .try_into()
+                        .unwrap(),
+                );
+                (std::str::from_utf8(&out[start..end]).unwrap(), index)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(names, [("alpha", 3), ("foo", 2), ("zeta", 4)]);
+    }
+
+    #[test]
+    fn too_many_ordinals() {
+        let exports = vec![entry("a", None, false), entry("b", Some(u16::MAX), false)];
+        assert!(ExportTables::new("x.dll".to_owned(), exports).is_err());
+    }
+}
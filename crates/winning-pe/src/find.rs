@@ -0,0 +1,135 @@
+//! Listing the external symbols of inputs without linking them, for finding
+//! out where a symbol is defined or who references it.
+
+use std::fmt::{self, Display};
+
+use color_eyre::Result;
+use winning_coff::{
+    archive::{ARCHIVE_MAGIC, Archive},
+    coff::{
+        IMAGE_SYM_ABSOLUTE, IMAGE_SYM_CLASS_EXTERNAL, IMAGE_SYM_CLASS_WEAK_EXTERNAL,
+        IMAGE_SYM_DEBUG, IMAGE_SYM_UNDEFINED, ObjectFile,
+    },
+    import::{ImportObject, is_import_object},
+};
+
+use crate::{image::ImageModel, input::Input, pe::DOS_MAGIC};
+
+pub enum SymbolKind {
+    Defined {
+        section: String,
+    },
+    Absolute,
+    Common {
+        size: u32,
+    },
+    /// A weak external, which falls back to another symbol if nothing defines it.
+    Weak,
+    Undefined,
+    /// Defined by an import object, or a DLL's export directory.
+    Imported {
+        dll: String,
+    },
+}
+
+impl Display for SymbolKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Defined { section } => write!(f, "defined in {section}"),
+            Self::Absolute => f.write_str("absolute"),
+            Self::Common { size } => write!(f, "common, {size} bytes"),
+            Self::Weak => f.write_str("weak reference"),
+            Self::Undefined => f.write_str("reference"),
+            Self::Imported { dll } => write!(f, "imported from {dll}"),
+        }
+    }
+}
+
+pub struct SymbolEntry {
+    /// The input, or `library(member)` for archive members.
+    pub location: String,
+    pub name: String,
+    pub kind: SymbolKind,
+}
+
+/// The external symbols of an object, a short import object, a DLL's
+/// exports, or of every archive member listed in the archive's symbol
+/// index. Members the index doesn't list define nothing, so they can only
+/// contribute references and are never pulled into a link anyway.
+pub fn external_symbols(input: &Input) -> Result<Vec<SymbolEntry>> {
+    let data = input.data();
+    let mut entries = Vec::new();
+    if data.starts_with(ARCHIVE_MAGIC) {
+        let archive = Archive::parse(input.name.clone(), data)?;
+        let mut offsets = archive
+            .symbols
+            .iter()
+            .map(|&(_, offset)| offset)
+            .collect::<Vec<_>>();
+        offsets.sort_unstable();
+        offsets.dedup();
+        for offset in offsets {
+            let member = archive.member_at(offset)?;
+            let location = format!("{}({})", archive.name, member.name);
+            member_symbols(location, member.data, &mut entries)?;
+        }
+    } else if data.starts_with(DOS_MAGIC) {
+        let image = ImageModel::parse(data)?;
+        if let Some(exports) = image.exports() {
+            for (name, _) in exports.named() {
+                entries.push(SymbolEntry {
+                    location: input.name.clone(),
+                    name: name.to_owned(),
+                    kind: SymbolKind::Imported {
+                        dll: exports.dll.to_owned(),
+                    },
+                });
+            }
+        }
+    } else {
+        member_symbols(input.name.clone(), data, &mut entries)?;
+    }
+    Ok(entries)
+}
+
+fn member_symbols(location: String, data: &[u8], entries: &mut Vec<SymbolEntry>) -> Result<()> {
+    if is_import_object(data) {
+        let import = ImportObject::parse(data)?;
+        for name in [import.symbol.to_owned(), format!("__imp_{}", import.symbol)] {
+            entries.push(SymbolEntry {
+                location: location.clone(),
+                name,
+                kind: SymbolKind::Imported {
+                    dll: import.dll.to_owned(),
+                },
+            });
+        }
+        return Ok(());
+    }
+
+    let object = ObjectFile::parse(location.clone(), data)?;
+    for (_, sym) in object.symbols.symbols() {
+        let kind = match (sym.storage_class, sym.section_number) {
+            (IMAGE_SYM_CLASS_WEAK_EXTERNAL, _) => SymbolKind::Weak,
+            (IMAGE_SYM_CLASS_EXTERNAL, IMAGE_SYM_UNDEFINED) if sym.value > 0 => {
+                SymbolKind::Common { size: sym.value }
+            }
+            (IMAGE_SYM_CLASS_EXTERNAL, IMAGE_SYM_UNDEFINED) => SymbolKind::Undefined,
+            (IMAGE_SYM_CLASS_EXTERNAL, IMAGE_SYM_ABSOLUTE) => SymbolKind::Absolute,
+            (IMAGE_SYM_CLASS_EXTERNAL, IMAGE_SYM_DEBUG) => continue,
+            (IMAGE_SYM_CLASS_EXTERNAL, number) => SymbolKind::Defined {
+                section: object
+                    .sections
+                    .get(usize::from(number) - 1)
+                    .map_or_else(|| format!("section {number}"), |s| s.name.to_string()),
+            },
+            _ => continue,
+        };
+        entries.push(SymbolEntry {
+            location: location.clone(),
+            name: object.symbols.name(sym)?.to_owned(),
+            kind,
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,71 @@
+//! Control Flow Guard metadata: the tables of valid indirect call targets
+//! that the CRT's load configuration points to through `__guard_*` symbols.
+
+/// The `@feat.00` bit of objects compiled with `/guard:cf`, which list their
+/// address-taken functions in `.gfids$y` instead of leaving the linker to
+/// guess from relocations.
+pub const FEAT_GUARD_CF: u32 = 0x800;
+pub const FEAT_SYMBOL: &str = "@feat.00";
+
+pub const IMAGE_GUARD_CF_INSTRUMENTED: u32 = 0x0000_0100;
+pub const IMAGE_GUARD_CF_FUNCTION_TABLE_PRESENT: u32 = 0x0000_0400;
+pub const IMAGE_GUARD_CF_LONGJUMP_TABLE_PRESENT: u32 = 0x0001_0000;
+
+/// The value of `__guard_flags`. Table entries are plain RVAs without
+/// metadata bytes, so the stride bits stay zero.
+pub const GUARD_FLAGS: u32 = IMAGE_GUARD_CF_INSTRUMENTED
+    | IMAGE_GUARD_CF_FUNCTION_TABLE_PRESENT
+    | IMAGE_GUARD_CF_LONGJUMP_TABLE_PRESENT;
+pub const GUARD_FLAGS_SYMBOL: &str = "__guard_flags";
+
+/// Symbols the load configuration references for features we don't
+/// implement, which are defined as zero so the CRT still links.
+pub const UNUSED_LOAD_CONFIG_SYMBOLS: &[&str] = &[
+    "__guard_eh_cont_table",
+    "__guard_eh_cont_count",
+    "__enclave_config",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GuardTable {
+    /// Functions that may be called indirectly.
+    Functions,
+    /// IAT slots whose address is taken, so the loader checks what it binds there.
+    Iat,
+    /// Valid `longjmp` targets.
+    LongJmp,
+}
+
+impl GuardTable {
+    pub const ALL: [Self; 3] = [Self::Functions, Self::Iat, Self::LongJmp];
+
+    /// The section that `/guard:cf` objects list the table's symbols in,
+    /// as symbol table indices.
+    pub fn section_name(self) -> &'static str {
+        match self {
+            Self::Functions => ".gfids$y",
+            Self::Iat => ".giats$y",
+            Self::LongJmp => ".gljmp$y",
+        }
+    }
+
+    pub fn table_symbol(self) -> &'static str {
+        match self {
+            Self::Functions => "__guard_fids_table",
+            Self::Iat => "__guard_iat_table",
+            Self::LongJmp => "__guard_longjmp_table",
+        }
+    }
+
+    pub fn count_symbol(self) -> &'static str {
+        match self {
+            Self::Functions => "__guard_fids_count",
+            Self::Iat => "__guard_iat_count",
+            Self::LongJmp => "__guard_longjmp_count",
+        }
+    }
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
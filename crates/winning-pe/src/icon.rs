@@ -0,0 +1,58 @@
+//! Application icons from `.ico` files, for `--icon`.
+
+use color_eyre::Result;
+use winning_coff::diagnostics::{Code, fatal};
+
+use crate::resources::{Resource, ResourceId};
+
+pub const RT_ICON: u16 = 3;
+pub const RT_GROUP_ICON: u16 = 14;
+const LANG_EN_US: u16 = 0x409;
+const ICONDIR_SIZE: usize = 6;
+const ICONDIRENTRY_SIZE: usize = 16;
+
+/// Splits an `.ico` file into an `RT_ICON` resource per image, numbered from
+/// 1, and the `RT_GROUP_ICON` directory naming them, which is what Explorer
+/// shows. The directory is `#1`, so it's the image's first icon.
+pub fn icon_resources(data: &[u8], source: &str) -> Result<Vec<Resource>> {
+    let invalid = || fatal(Code::CorruptInput, format!("{source}: invalid .ico file"));
+    let read_u16 = |offset: usize| {
+        data.get(offset..offset + 2)
+            .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+    };
+    let read_u32 = |offset: usize| {
+        data.get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+    };
+    let (Some(0), Some(1), Some(count @ 1..)) = (read_u16(0), read_u16(2), read_u16(4)) else {
+        return invalid();
+    };
+
+    let resource = |r#type, id, data| Resource {
+        r#type: ResourceId::Id(r#type),
+        name: ResourceId::Id(id),
+        language: LANG_EN_US,
+        code_page: 0,
+        data,
+    };
+    let mut group = data[..ICONDIR_SIZE].to_vec();
+    let mut resources = Vec::with_capacity(usize::from(count) + 1);
+    for (idx, id) in (0..usize::from(count)).zip(1..) {
+        let entry = ICONDIR_SIZE + idx * ICONDIRENTRY_SIZE;
+        let (Some(size), Some(offset)) = (read_u32(entry + 8), read_u32(entry + 12)) else {
+            return invalid();
+        };
+        let Some(image) = data
+            .get(offset as usize..)
+            .and_then(|rest| rest.get(..size as usize))
+        else {
+            return invalid();
+        };
+        // The group entry is the file's, with the image offset replaced by its id.
+        group.extend_from_slice(&data[entry..entry + 12]);
+        group.extend_from_slice(&u16::to_le_bytes(id));
+        resources.push(resource(RT_ICON, id, image.to_vec()));
+    }
+    resources.push(resource(RT_GROUP_ICON, 1, group));
+    Ok(resources)
+}
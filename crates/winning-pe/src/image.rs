@@ -0,0 +1,370 @@
+use std::io;
+
+use binrw::{BinRead, BinWrite};
+use color_eyre::Result;
+
+use winning_coff::{
+    coff::{CoffHeader, SectionFlags, SectionHeader, read_section_headers},
+    diagnostics::{Code, fatal},
+};
+
+use crate::{
+    import::{IMAGE_ORDINAL_FLAG32, IMAGE_ORDINAL_FLAG64},
+    pe::{
+        DataDirectory, DosHeader, OptionalHeader, OptionalHeader32, PE_SIGNATURE, PE32_MAGIC,
+        decode_base_relocations,
+    },
+};
+
+/// A parsed view of a PE image, used to check what we wrote against what we
+/// planned to write.
+pub struct ImageModel<'a> {
+    data: &'a [u8],
+    pub dos_header: DosHeader,
+    /// File offset of the PE signature.
+    pub pe_offset: usize,
+    pub header: CoffHeader,
+    /// Widened from [`optional_header_32`](Self::optional_header_32) for
+    /// PE32 images.
+    pub optional_header: OptionalHeader,
+    /// The header as it is in PE32 images, `None` for PE32+.
+    pub optional_header_32: Option<OptionalHeader32>,
+    pub sections: Vec<SectionHeader<'a>>,
+}
+
+/// An export of an image.
+pub struct ImageExport<'a> {
+    /// `None` for exports by ordinal only.
+    pub name: Option<&'a str>,
+    /// The ordinal, with the directory's ordinal base added.
+    pub ordinal: u32,
+    pub rva: u32,
+    /// Whether the export is code, or forwarded to another DLL.
+    pub is_code: bool,
+    /// The `DLL.name` the export is forwarded to, if it is.
+    pub forwarder: Option<&'a str>,
+}
+
+pub struct ImageExports<'a> {
+    /// The DLL name recorded in the export directory.
+    pub dll: &'a str,
+    /// In ordinal order, once for each name of exports with several.
+    pub exports: Vec<ImageExport<'a>>,
+    /// Indices into `exports`, in the order of the name pointer table.
+    names: Vec<usize>,
+}
+
+impl<'a> ImageExports<'a> {
+    /// The named exports in the order of the name pointer table, which is
+    /// sorted by name and which import hints index.
+    pub fn named(&self) -> impl Iterator<Item = (&'a str, &ImageExport<'a>)> {
+        self.names.iter().map(|&idx| {
+            let export = &self.exports[idx];
+            (export.name.unwrap(), export)
+        })
+    }
+}
+
+pub struct ImportDescriptor {
+    pub import_lookup_table: u32,
+    pub name: u32,
+    pub import_address_table: u32,
+}
+
+/// What an image imports from one DLL.
+pub struct ImageImports<'a> {
+    pub dll: &'a str,
+    pub import_lookup_table: u32,
+    pub import_address_table: u32,
+    pub imports: Vec<ImageImport<'a>>,
+}
+
+pub enum ImageImport<'a> {
+    Name { name: &'a str, hint: u16 },
+    Ordinal(u16),
+}
+
+impl<'a> ImageModel<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        let Ok(dos_header) = DosHeader::read(&mut io::Cursor::new(data)) else {
+            return fatal(Code::CorruptInput, "image is too small for a DOS header");
+        };
+        let pe_offset = dos_header.e_lfanew as usize;
+        if data.get(pe_offset..pe_offset + 4) != Some(PE_SIGNATURE) {
+            return fatal(Code::CorruptInput, "missing PE signature");
+        }
+
+        let cursor = &mut io::Cursor::new(data);
+        cursor.set_position((pe_offset + PE_SIGNATURE.len()) as u64);
+        let header = CoffHeader::read(cursor)?;
+        // Images may leave out trailing data directories, which we read as empty.
+        let optional_offset = cursor.position() as usize;
+        let optional_size = usize::from(header.size_of_optional_header);
+        let Some(optional) = data.get(optional_offset..optional_offset + optional_size) else {
+            return fatal(
+                Code::CorruptInput,
+                "image is too small for its optional header",
+            );
+        };
+        let mut padded = optional.to_vec();
+        padded.resize(padded.len().max(size_of::<OptionalHeader>()), 0);
+        let (optional_header, optional_header_32) = if padded.starts_with(&PE32_MAGIC.to_le_bytes())
+        {
+            let header = OptionalHeader32::read(&mut io::Cursor::new(padded))?;
+            (header.widen(), Some(header))
+        } else {
+            (OptionalHeader::read(&mut io::Cursor::new(padded))?, None)
+        };
+        let sections = read_section_headers(
+            data,
+            optional_offset + optional_size,
+            header.number_of_sections,
+        )?;
+
+        Ok(Self {
+            data,
+            dos_header,
+            pe_offset,
+            header,
+            optional_header,
+            optional_header_32,
+            sections,
+        })
+    }
+
+    /// The whole file.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Writes `header` as this image's optional header: as PE32 for PE32
+    /// images, and without the data directories the image leaves out.
+    pub fn write_optional_header(
+        &self,
+        header: &OptionalHeader,
+        out: &mut io::Cursor<&mut Vec<u8>>,
+    ) -> Result<()> {
+        let mut bytes = io::Cursor::new(Vec::new());
+        match &self.optional_header_32 {
+            Some(header_32) => header_32.narrow(header).write(&mut bytes)?,
+            None => header.write(&mut bytes)?,
+        }
+        let mut bytes = bytes.into_inner();
+        bytes.truncate(usize::from(self.header.size_of_optional_header));
+        io::Write::write_all(out, &bytes)?;
+        Ok(())
+    }
+
+    /// The raw data of a section in the file.
+    pub fn section_data(&self, section: &SectionHeader) -> Option<&'a [u8]> {
+        let start = section.pointer_to_raw_data as usize;
+        self.data
+            .get(start..start + section.size_of_raw_data as usize)
+    }
+
+    pub fn section_containing(&self, rva: u32) -> Option<&SectionHeader<'a>> {
+        self.sections.iter().find(|section| {
+            let size = section.virtual_size.max(section.size_of_raw_data);
+            rva >= section.virtual_address && rva - section.virtual_address < size
+        })
+    }
+
+    /// The section the whole data directory lies in, if any.
+    pub fn directory_section(&self, directory: DataDirectory) -> Option<&SectionHeader<'a>> {
+        let section = self.section_containing(directory.virtual_address)?;
+        let end = u64::from(directory.virtual_address) + u64::from(directory.size);
+        (end <= u64::from(section.virtual_address) + u64::from(section.virtual_size))
+            .then_some(section)
+    }
+
+    /// Reads `len` bytes at `rva` from the file, as long as they are backed by
+    /// the raw data of a single section.
+    pub fn read(&self, rva: u32, len: u32) -> Option<&'a [u8]> {
+        let section = self.section_containing(rva)?;
+        let offset = rva - section.virtual_address;
+        if u64::from(offset) + u64::from(len) > u64::from(section.size_of_raw_data) {
+            return None;
+        }
+        let start = section.pointer_to_raw_data as usize + offset as usize;
+        self.data.get(start..start + len as usize)
+    }
+
+    pub fn base_relocations(&self) -> Option<Vec<(u32, u16)>> {
+        let directory = self.optional_header.base_relocation_table;
+        decode_base_relocations(self.read(directory.virtual_address, directory.size)?)
+    }
+
+    /// Reads the NUL-terminated string at `rva`.
+    pub fn read_str(&self, rva: u32) -> Option<&'a str> {
+        let section = self.section_containing(rva)?;
+        let data = self.section_data(section)?;
+        let rest = data.get((rva - section.virtual_address) as usize..)?;
+        std::str::from_utf8(&rest[..rest.iter().position(|&b| b == 0)?]).ok()
+    }
+
+    /// The exports, from the export address table, with the names the name
+    /// pointer table gives them. Unused ordinals are skipped.
+    pub fn exports(&self) -> Option<ImageExports<'a>> {
+        let directory = self.optional_header.export_table;
+        let table = self.read(directory.virtual_address, 40)?;
+        let read_u32 = |data: &[u8], offset: usize| {
+            Some(u32::from_le_bytes(
+                data.get(offset..offset + 4)?.try_into().unwrap(),
+            ))
+        };
+        let ordinal_base = read_u32(table, 16)?;
+        let function_count = read_u32(table, 20)?;
+        let name_count = read_u32(table, 24)?;
+        let functions = self.read(read_u32(table, 28)?, function_count.checked_mul(4)?)?;
+        let names = self.read(read_u32(table, 32)?, name_count.checked_mul(4)?)?;
+        let ordinals = self.read(read_u32(table, 36)?, name_count.checked_mul(2)?)?;
+
+        // The names of each function, with their index in the name table.
+        let mut function_names = vec![Vec::new(); function_count as usize];
+        for idx in 0..name_count as usize {
+            let name = self.read_str(read_u32(names, idx * 4)?)?;
+            let ordinal = u16::from_le_bytes(ordinals[idx * 2..][..2].try_into().unwrap());
+            function_names
+                .get_mut(usize::from(ordinal))?
+                .push((idx, name));
+        }
+
+        let forwarded = directory.virtual_address..directory.virtual_address + directory.size;
+        let mut exports = Vec::with_capacity(function_count as usize);
+        let mut name_order = vec![0; name_count as usize];
+        for (idx, names) in function_names.into_iter().enumerate() {
+            let rva = read_u32(functions, idx * 4)?;
+            // Ordinals between the ones that are used are zero.
+            if rva == 0 && names.is_empty() {
+                continue;
+            }
+            let forwarder = if forwarded.contains(&rva) {
+                Some(self.read_str(rva)?)
+            } else {
+                None
+            };
+            let is_code = forwarder.is_some()
+                || self.section_containing(rva).is_some_and(|section| {
+                    section
+                        .characteristics
+                        .contains(SectionFlags::IMAGE_SCN_MEM_EXECUTE)
+                });
+            let names = match names.is_empty() {
+                true => vec![None],
+                false => names.into_iter().map(Some).collect(),
+            };
+            for name in names {
+                if let Some((name_idx, _)) = name {
+                    name_order[name_idx] = exports.len();
+                }
+                exports.push(ImageExport {
+                    name: name.map(|(_, name)| name),
+                    ordinal: ordinal_base.wrapping_add(idx as u32),
+                    rva,
+                    is_code,
+                    forwarder,
+                });
+            }
+        }
+        Some(ImageExports {
+            dll: self.read_str(read_u32(table, 12)?)?,
+            exports,
+            names: name_order,
+        })
+    }
+
+    /// The imports by DLL, from the import lookup tables, or the IATs for
+    /// descriptors without one.
+    pub fn imports(&self) -> Option<Vec<ImageImports<'a>>> {
+        let mut dlls = Vec::new();
+        for descriptor in self.import_descriptors()? {
+            let table = match descriptor.import_lookup_table {
+                0 => descriptor.import_address_table,
+                table => table,
+            };
+            dlls.push(ImageImports {
+                dll: self.read_str(descriptor.name)?,
+                import_lookup_table: descriptor.import_lookup_table,
+                import_address_table: descriptor.import_address_table,
+                imports: self.lookup_table(table)?,
+            });
+        }
+        Some(dlls)
+    }
+
+    /// The delay-loaded imports by DLL, from the delay import name tables.
+    /// The lookup and address tables are the name table and the delay IAT.
+    /// Descriptors are assumed to hold RVAs, which everything since Visual
+    /// C++ 7 writes.
+    pub fn delay_imports(&self) -> Option<Vec<ImageImports<'a>>> {
+        let directory = self.optional_header.delay_import_descriptor;
+        if directory.size == 0 {
+            return Some(Vec::new());
+        }
+        let data = self.read(directory.virtual_address, directory.size)?;
+        let read_u32 = |entry: &[u8], offset: usize| {
+            u32::from_le_bytes(entry[offset..offset + 4].try_into().unwrap())
+        };
+        let mut dlls = Vec::new();
+        for entry in data
+            .chunks_exact(32)
+            .take_while(|entry| entry.iter().any(|&b| b != 0))
+        {
+            let name_table = read_u32(entry, 16);
+            dlls.push(ImageImports {
+                dll: self.read_str(read_u32(entry, 4))?,
+                import_lookup_table: name_table,
+                import_address_table: read_u32(entry, 12),
+                imports: self.lookup_table(name_table)?,
+            });
+        }
+        Some(dlls)
+    }
+
+    /// Reads an import lookup table up to its terminating null entry. Its
+    /// entries are 4 bytes in PE32 images and 8 in PE32+ ones.
+    fn lookup_table(&self, mut rva: u32) -> Option<Vec<ImageImport<'a>>> {
+        let (size, ordinal_flag) = match self.optional_header_32 {
+            Some(_) => (4, u64::from(IMAGE_ORDINAL_FLAG32)),
+            None => (8, IMAGE_ORDINAL_FLAG64),
+        };
+        let mut imports = Vec::new();
+        loop {
+            let mut entry = [0; 8];
+            entry[..size as usize].copy_from_slice(self.read(rva, size)?);
+            let entry = u64::from_le_bytes(entry);
+            if entry == 0 {
+                break;
+            }
+            if entry & ordinal_flag != 0 {
+                imports.push(ImageImport::Ordinal(entry as u16));
+            } else {
+                let hint_name = entry as u32 & 0x7FFF_FFFF;
+                let hint = u16::from_le_bytes(self.read(hint_name, 2)?.try_into().unwrap());
+                let name = self.read_str(hint_name + 2)?;
+                imports.push(ImageImport::Name { name, hint });
+            }
+            rva += size;
+        }
+        Some(imports)
+    }
+
+    /// The import descriptors, without the terminating null descriptor.
+    pub fn import_descriptors(&self) -> Option<Vec<ImportDescriptor>> {
+        let directory = self.optional_header.import_table;
+        let data = self.read(directory.virtual_address, directory.size)?;
+        let read_u32 = |entry: &[u8], offset: usize| {
+            u32::from_le_bytes(entry[offset..offset + 4].try_into().unwrap())
+        };
+        Some(
+            data.chunks_exact(20)
+                .take_while(|entry| entry.iter().any(|&b| b != 0))
+                .map(|entry| ImportDescriptor {
+                    import_lookup_table: read_u32(entry, 0),
+                    name: read_u32(entry, 12),
+                    import_address_table: read_u32(entry, 16),
+                })
+                .collect(),
+        )
+    }
+}
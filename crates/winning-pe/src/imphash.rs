@@ -0,0 +1,84 @@
+//! The import hash of malware triage tools: the MD5 of the imports, in the
+//! order of the import directory, as `pefile` computes it.
+
+use crate::{
+    digest::{hex, md5},
+    image::{ImageImport, ImageImports, ImageModel},
+    ordlookup::ordinal_name,
+};
+
+/// The import hash of `image` as lowercase hex, empty without imports like
+/// `pefile` returns it, or `None` if the import directory is unreadable.
+/// Delay imports aren't part of it.
+pub fn imphash(image: &ImageModel<'_>) -> Option<String> {
+    if image.optional_header.import_table.size == 0 {
+        return Some(String::new());
+    }
+    Some(hash_imports(&image.imports()?))
+}
+
+fn hash_imports(dlls: &[ImageImports<'_>]) -> String {
+    let mut imports = Vec::new();
+    for dll in dlls {
+        let dll_name = dll.dll.to_lowercase();
+        let library = match dll_name.rsplit_once('.') {
+            Some((stem, "dll" | "ocx" | "sys")) => stem,
+            _ => &dll_name,
+        };
+        for import in &dll.imports {
+            let function = match *import {
+                ImageImport::Name { name, .. } => name.to_lowercase(),
+                ImageImport::Ordinal(ordinal) => match ordinal_name(&dll_name, ordinal) {
+                    Some(name) => name.to_lowercase(),
+                    None => format!("ord{ordinal}"),
+                },
+            };
+            imports.push(format!("{library}.{function}"));
+        }
+    }
+    hex(&md5(imports.join(",").as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dll<'a>(dll: &'a str, imports: Vec<ImageImport<'a>>) -> ImageImports<'a> {
+        ImageImports {
+            dll,
+            import_lookup_table: 0,
+            import_address_table: 0,
+            imports,
+        }
+    }
+
+    #[test]
+    fn matches_pefile() {
+        let name = |name| ImageImport::Name { name, hint: 0 };
+        let dlls = [
+            dll(
+                "KERNEL32.dll",
+                vec![name("ExitProcess"), name("GetStdHandle")],
+            ),
+            dll(
+                "WS2_32.dll",
+                vec![
+                    ImageImport::Ordinal(115),
+                    ImageImport::Ordinal(23),
+                    ImageImport::Ordinal(98),
+                ],
+            ),
+            dll("WSOCK32.dll", vec![ImageImport::Ordinal(3)]),
+            dll(
+                "OLEAUT32.dll",
+                vec![
+                    ImageImport::Ordinal(2),
+                    ImageImport::Ordinal(6),
+                    ImageImport::Ordinal(9),
+                    ImageImport::Ordinal(1000),
+                ],
+            ),
+        ];
+        assert_eq!(hash_imports(&dlls), "d74558f4f75485d414e81261e65dfe7f");
+    }
+}
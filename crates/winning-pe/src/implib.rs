@@ -0,0 +1,538 @@
+use std::io::Cursor;
+
+use binrw::BinWrite;
+use color_eyre::Result;
+use winning_coff::{
+    archive::ARCHIVE_MAGIC,
+    coff::{
+        Characteristics, CoffHeader, IMAGE_FILE_MACHINE_AMD64, IMAGE_REL_AMD64_ADDR32NB,
+        IMAGE_SYM_CLASS_EXTERNAL, SectionFlags, SectionHeader,
+    },
+    diagnostics::{Code, fatal},
+    import::{IMPORT_OBJECT_HDR_SIG2, IMPORT_OBJECT_NAME, IMPORT_OBJECT_ORDINAL},
+};
+
+use crate::{
+    def::ModuleDefinition,
+    export::{ExportEntry, ExportTables, ExportTarget},
+    image::ImageModel,
+};
+
+/// An export as an import library describes it.
+pub struct ImportLibraryEntry {
+    pub name: String,
+    /// With `NONAME`, the import is by ordinal, otherwise the ordinal is the
+    /// starting point for the loader's name lookup.
+    pub ordinal: u16,
+    pub hint: u16,
+    pub noname: bool,
+    /// Code imports also get a thunk under the export's own name.
+    pub is_code: bool,
+}
+
+const IMPORT_OBJECT_DATA: u16 = 1;
+const IMAGE_SYM_CLASS_STATIC: u8 = 3;
+const IMAGE_SYM_CLASS_SECTION: u8 = 104;
+const SYMBOL_SIZE: u32 = 18;
+const RELOCATION_SIZE: u32 = 10;
+
+/// Writes an import library for `dll`, in the layout of link.exe's: a short
+/// import object per export, behind the import descriptor and the null
+/// terminators that linkers building the import tables from objects need.
+pub fn write_import_library(dll: &str, entries: &[ImportLibraryEntry]) -> Result<Vec<u8>> {
+    let library = dll.rsplit_once('.').map_or(dll, |(stem, _)| stem);
+    let descriptor_symbol = format!("__IMPORT_DESCRIPTOR_{library}");
+    let null_thunk_symbol = format!("\x7f{library}_NULL_THUNK_DATA");
+
+    let mut members = vec![
+        (
+            import_descriptor(dll, &descriptor_symbol, &null_thunk_symbol)?,
+            vec![descriptor_symbol.clone()],
+        ),
+        (
+            null_import_descriptor()?,
+            vec![NULL_IMPORT_DESCRIPTOR.to_owned()],
+        ),
+        (null_thunk(&null_thunk_symbol)?, vec![null_thunk_symbol]),
+    ];
+    for entry in entries {
+        let mut symbols = vec![format!("__imp_{}", entry.name)];
+        if entry.is_code {
+            symbols.push(entry.name.clone());
+        }
+        members.push((short_import(dll, entry), symbols));
+    }
+    Ok(write_archive(dll, &members))
+}
+
+/// The import library entries for the named exports of a DLL, for when it
+/// shipped without one. Exports only by ordinal have no name to import by.
+pub fn image_import_entries(image: &ImageModel<'_>) -> Result<Vec<ImportLibraryEntry>> {
+    if image.header.machine != IMAGE_FILE_MACHINE_AMD64 {
+        return fatal(
+            Code::UnsupportedImage,
+            format!(
+                "import libraries for machine {:#x} are not supported",
+                image.header.machine
+            ),
+        );
+    }
+    if image.optional_header.export_table.size == 0 {
+        return Ok(Vec::new());
+    }
+    let Some(exports) = image.exports() else {
+        return fatal(Code::CorruptInput, "export directory is out of bounds");
+    };
+    let mut entries = Vec::with_capacity(exports.exports.len());
+    // Hints index the name pointer table.
+    for (hint, (name, export)) in exports.named().enumerate() {
+        let Ok(ordinal) = u16::try_from(export.ordinal) else {
+            return fatal(
+                Code::CorruptInput,
+                format!("export {name} has ordinal {}", export.ordinal),
+            );
+        };
+        entries.push(ImportLibraryEntry {
+            name: name.to_owned(),
+            ordinal,
+            hint: hint as u16,
+            noname: false,
+            is_code: export.is_code,
+        });
+    }
+    Ok(entries)
+}
+
+/// The import library entries for the `EXPORTS` of a module-definition file,
+/// numbered and hinted like the exports of a DLL linked with it would be.
+pub fn def_import_entries(def: &ModuleDefinition) -> Result<Vec<ImportLibraryEntry>> {
+    let mut exports = def
+        .exports
+        .iter()
+        .map(|spec| ExportEntry {
+            name: spec.name.clone(),
+            forwarder: match &spec.target {
+                ExportTarget::Symbol(_) => None,
+                ExportTarget::Forwarder(forwarder) => Some(forwarder.clone()),
+            },
+            ordinal: spec.ordinal,
+            noname: spec.noname,
+            data: spec.data,
+            private: spec.private,
+        })
+        .collect::<Vec<_>>();
+    exports.sort_by(|a, b| a.name.cmp(&b.name));
+    exports.dedup_by(|a, b| a.name == b.name);
+    let tables = ExportTables::new(def.library.clone().unwrap_or_default(), exports)?;
+    Ok(tables
+        .entries()
+        .filter(|(export, ..)| !export.private)
+        .map(|(export, ordinal, hint)| ImportLibraryEntry {
+            name: export.name.clone(),
+            ordinal,
+            hint: hint.unwrap_or(0),
+            noname: export.noname,
+            is_code: !export.data,
+        })
+        .collect())
+}
+
+const NULL_IMPORT_DESCRIPTOR: &str = "__NULL_IMPORT_DESCRIPTOR";
+
+fn short_import(dll: &str, entry: &ImportLibraryEntry) -> Vec<u8> {
+    let (name_type, ordinal_or_hint) = if entry.noname {
+        (IMPORT_OBJECT_ORDINAL, entry.ordinal)
+    } else {
+        (IMPORT_OBJECT_NAME, entry.hint)
+    };
+    let import_type = if entry.is_code { 0 } else { IMPORT_OBJECT_DATA };
+    let size_of_data = entry.name.len() + 1 + dll.len() + 1;
+    let mut out = Vec::with_capacity(20 + size_of_data);
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&IMPORT_OBJECT_HDR_SIG2.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&IMAGE_FILE_MACHINE_AMD64.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&(size_of_data as u32).to_le_bytes());
+    out.extend_from_slice(&ordinal_or_hint.to_le_bytes());
+    out.extend_from_slice(&(import_type | name_type << 2).to_le_bytes());
+    for name in [entry.name.as_str(), dll] {
+        out.extend_from_slice(name.as_bytes());
+        out.push(0);
+    }
+    out
+}
+
+struct ObjectSection {
+    name: &'static str,
+    flags: SectionFlags,
+    data: Vec<u8>,
+    /// Offset, symbol index and type.
+    relocations: Vec<(u32, u32, u16)>,
+}
+
+struct ObjectSymbol<'a> {
+    name: &'a str,
+    /// One-based, or zero for undefined symbols.
+    section: i16,
+    class: u8,
+}
+
+const IDATA_FLAGS: SectionFlags = SectionFlags::IMAGE_SCN_CNT_INITIALIZED_DATA
+    .union(SectionFlags::IMAGE_SCN_MEM_READ)
+    .union(SectionFlags::IMAGE_SCN_MEM_WRITE);
+
+/// The `.idata$2` entry for the DLL, with its name in `.idata$6`. It refers
+/// to the lookup table and IAT by section, which the null thunk terminates.
+fn import_descriptor(dll: &str, symbol: &str, null_thunk: &str) -> Result<Vec<u8>> {
+    let mut name = dll.as_bytes().to_vec();
+    name.push(0);
+    name.resize(name.len().next_multiple_of(2), 0);
+    write_object(
+        &[
+            ObjectSection {
+                name: ".idata$2",
+                flags: IDATA_FLAGS | SectionFlags::IMAGE_SCN_ALIGN_4BYTES,
+                data: vec![0; 20],
+                relocations: vec![
+                    (12, 2, IMAGE_REL_AMD64_ADDR32NB),
+                    (0, 3, IMAGE_REL_AMD64_ADDR32NB),
+                    (16, 4, IMAGE_REL_AMD64_ADDR32NB),
+                ],
+            },
+            ObjectSection {
+                name: ".idata$6",
+                flags: IDATA_FLAGS | SectionFlags::IMAGE_SCN_ALIGN_2BYTES,
+                data: name,
+                relocations: Vec::new(),
+            },
+        ],
+        &[
+            ObjectSymbol {
+                name: symbol,
+                section: 1,
+                class: IMAGE_SYM_CLASS_EXTERNAL,
+            },
+            ObjectSymbol {
+                name: ".idata$2",
+                section: 1,
+                class: IMAGE_SYM_CLASS_SECTION,
+            },
+            ObjectSymbol {
+                name: ".idata$6",
+                section: 2,
+                class: IMAGE_SYM_CLASS_STATIC,
+            },
+            ObjectSymbol {
+                name: ".idata$4",
+                section: 0,
+                class: IMAGE_SYM_CLASS_SECTION,
+            },
+            ObjectSymbol {
+                name: ".idata$5",
+                section: 0,
+                class: IMAGE_SYM_CLASS_SECTION,
+            },
+            ObjectSymbol {
+                name: NULL_IMPORT_DESCRIPTOR,
+                section: 0,
+                class: IMAGE_SYM_CLASS_EXTERNAL,
+            },
+            ObjectSymbol {
+                name: null_thunk,
+                section: 0,
+                class: IMAGE_SYM_CLASS_EXTERNAL,
+            },
+        ],
+    )
+}
+
+/// The all-zero descriptor ending the import directory.
+fn null_import_descriptor() -> Result<Vec<u8>> {
+    write_object(
+        &[ObjectSection {
+            name: ".idata$3",
+            flags: IDATA_FLAGS | SectionFlags::IMAGE_SCN_ALIGN_4BYTES,
+            data: vec![0; 20],
+            relocations: Vec::new(),
+        }],
+        &[ObjectSymbol {
+            name: NULL_IMPORT_DESCRIPTOR,
+            section: 1,
+            class: IMAGE_SYM_CLASS_EXTERNAL,
+        }],
+    )
+}
+
+/// The null entries ending the DLL's IAT and lookup table.
+fn null_thunk(symbol: &str) -> Result<Vec<u8>> {
+    let section = |name| ObjectSection {
+        name,
+        flags: IDATA_FLAGS | SectionFlags::IMAGE_SCN_ALIGN_8BYTES,
+        data: vec![0; 8],
+        relocations: Vec::new(),
+    };
+    write_object(
+        &[section(".idata$5"), section(".idata$4")],
+        &[ObjectSymbol {
+            name: symbol,
+            section: 1,
+            class: IMAGE_SYM_CLASS_EXTERNAL,
+        }],
+    )
+}
+
+fn write_object(sections: &[ObjectSection], symbols: &[ObjectSymbol]) -> Result<Vec<u8>> {
+    let headers_size = 20 + sections.len() as u32 * 40;
+    let mut offset = headers_size;
+    let mut headers = Vec::with_capacity(sections.len());
+    for section in sections {
+        let pointer_to_raw_data = offset;
+        offset += section.data.len() as u32;
+        let pointer_to_relocations = if section.relocations.is_empty() {
+            0
+        } else {
+            offset
+        };
+        offset += section.relocations.len() as u32 * RELOCATION_SIZE;
+        headers.push(SectionHeader {
+            name: section.name.into(),
+            virtual_size: 0,
+            virtual_address: 0,
+            size_of_raw_data: section.data.len() as u32,
+            pointer_to_raw_data,
+            pointer_to_relocations,
+            pointer_to_linenumbers: 0,
+            number_of_relocations: section.relocations.len() as u16,
+            number_of_linenumbers: 0,
+            characteristics: section.flags,
+        });
+    }
+
+    let mut out = Cursor::new(Vec::new());
+    CoffHeader {
+        machine: IMAGE_FILE_MACHINE_AMD64,
+        number_of_sections: sections.len() as u16,
+        time_date_stamp: 0,
+        pointer_to_symbol_table: offset,
+        number_of_symbols: symbols.len() as u32,
+        size_of_optional_header: 0,
+        characteristics: Characteristics::empty(),
+    }
+    .write(&mut out)?;
+    for header in &headers {
+        header.write(&mut out)?;
+    }
+    let mut out = out.into_inner();
+    for section in sections {
+        out.extend_from_slice(&section.data);
+        for &(offset, symbol, r#type) in &section.relocations {
+            out.extend_from_slice(&offset.to_le_bytes());
+            out.extend_from_slice(&symbol.to_le_bytes());
+            out.extend_from_slice(&r#type.to_le_bytes());
+        }
+    }
+
+    // Names that don't fit in the symbol go in the string table, after its size.
+    let mut strings = Vec::new();
+    for symbol in symbols {
+        let start = out.len();
+        if symbol.name.len() <= 8 {
+            out.extend_from_slice(symbol.name.as_bytes());
+            out.resize(start + 8, 0);
+        } else {
+            out.extend_from_slice(&0u32.to_le_bytes());
+            out.extend_from_slice(&(4 + strings.len() as u32).to_le_bytes());
+            strings.extend_from_slice(symbol.name.as_bytes());
+            strings.push(0);
+        }
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.extend_from_slice(&symbol.section.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.push(symbol.class);
+        out.push(0);
+        debug_assert_eq!(out.len() - start, SYMBOL_SIZE as usize);
+    }
+    out.extend_from_slice(&(4 + strings.len() as u32).to_le_bytes());
+    out.extend_from_slice(&strings);
+    Ok(out)
+}
+
+/// Writes an archive of `members` with the symbols each defines, indexed
+/// in both the first linker member, which GNU tools read, and the sorted
+/// second one link.exe prefers. All members are named after the DLL.
+fn write_archive(dll: &str, members: &[(Vec<u8>, Vec<String>)]) -> Vec<u8> {
+    let member_name = format!("{dll}/");
+    let long_names = if member_name.len() > 16 {
+        let mut names = member_name.clone().into_bytes();
+        names.push(b'\n');
+        Some(names)
+    } else {
+        None
+    };
+    let header_name = if long_names.is_some() {
+        "/0".to_owned()
+    } else {
+        member_name
+    };
+
+    let symbol_count = members
+        .iter()
+        .map(|(_, symbols)| symbols.len())
+        .sum::<usize>();
+    let names_size = members
+        .iter()
+        .flat_map(|(_, symbols)| symbols)
+        .map(|name| name.len() + 1)
+        .sum::<usize>();
+    let first_size = 4 + symbol_count * 4 + names_size;
+    let second_size = 4 + members.len() * 4 + 4 + symbol_count * 2 + names_size;
+    let padded = |size: usize| 60 + size + size % 2;
+    let mut offset = ARCHIVE_MAGIC.len()
+        + padded(first_size)
+        + padded(second_size)
+        + long_names.as_ref().map_or(0, |names| padded(names.len()));
+    let mut offsets = Vec::with_capacity(members.len());
+    for (data, _) in members {
+        offsets.push(offset as u32);
+        offset += padded(data.len());
+    }
+
+    let mut out = ARCHIVE_MAGIC.to_vec();
+    let mut first = Vec::with_capacity(first_size);
+    first.extend_from_slice(&(symbol_count as u32).to_be_bytes());
+    for ((_, symbols), offset) in members.iter().zip(&offsets) {
+        for _ in symbols {
+            first.extend_from_slice(&offset.to_be_bytes());
+        }
+    }
+    for name in members.iter().flat_map(|(_, symbols)| symbols) {
+        first.extend_from_slice(name.as_bytes());
+        first.push(0);
+    }
+    write_member(&mut out, "/", &first);
+
+    let mut sorted = members
+        .iter()
+        .enumerate()
+        .flat_map(|(idx, (_, symbols))| symbols.iter().map(move |name| (name, idx as u16 + 1)))
+        .collect::<Vec<_>>();
+    sorted.sort();
+    let mut second = Vec::with_capacity(second_size);
+    second.extend_from_slice(&(members.len() as u32).to_le_bytes());
+    for offset in &offsets {
+        second.extend_from_slice(&offset.to_le_bytes());
+    }
+    second.extend_from_slice(&(symbol_count as u32).to_le_bytes());
+    for &(_, member) in &sorted {
+        second.extend_from_slice(&member.to_le_bytes());
+    }
+    for &(name, _) in &sorted {
+        second.extend_from_slice(name.as_bytes());
+        second.push(0);
+    }
+    write_member(&mut out, "/", &second);
+
+    if let Some(names) = &long_names {
+        write_member(&mut out, "//", names);
+    }
+    for (data, _) in members {
+        write_member(&mut out, &header_name, data);
+    }
+    out
+}
+
+fn write_member(out: &mut Vec<u8>, name: &str, data: &[u8]) {
+    // Timestamps, owners and modes are zeroed so the output is reproducible.
+    let header = format!(
+        "{name:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+        0,
+        0,
+        0,
+        0,
+        data.len()
+    );
+    out.extend_from_slice(header.as_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(b'\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winning_coff::{
+        archive::Archive,
+        import::{IMPORT_OBJECT_CODE, ImportObject},
+    };
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let def = ModuleDefinition::parse(
+            "LIBRARY lib.dll\n\
+             EXPORTS\n\
+             \tnamed\n\
+             \tnoname @5 NONAME\n\
+             \thidden PRIVATE\n\
+             \tvalue DATA\n",
+        )
+        .unwrap();
+        let library = write_import_library("lib.dll", &def_import_entries(&def).unwrap()).unwrap();
+        let archive = Archive::parse("lib.lib".to_owned(), &library).unwrap();
+
+        let mut symbols = archive
+            .symbols
+            .iter()
+            .map(|&(name, _)| name)
+            .collect::<Vec<_>>();
+        symbols.sort();
+        assert_eq!(
+            symbols,
+            [
+                "__IMPORT_DESCRIPTOR_lib",
+                "__NULL_IMPORT_DESCRIPTOR",
+                "__imp_named",
+                "__imp_noname",
+                "__imp_value",
+                "named",
+                "noname",
+                "\x7flib_NULL_THUNK_DATA",
+            ]
+        );
+
+        let import = |symbol: &str| {
+            let &(_, offset) = archive
+                .symbols
+                .iter()
+                .find(|&&(name, _)| name == symbol)
+                .unwrap();
+            let member = archive.member_at(offset).unwrap();
+            let import = ImportObject::parse(member.data).unwrap();
+            assert_eq!(import.dll, "lib.dll");
+            assert_eq!(import.machine, IMAGE_FILE_MACHINE_AMD64);
+            (
+                import.symbol,
+                import.import_type,
+                import.name_type,
+                import.ordinal_or_hint,
+            )
+        };
+        // Named imports carry the hint, which counts the private export before
+        // them, and `NONAME` ones the ordinal.
+        assert_eq!(
+            import("__imp_named"),
+            ("named", IMPORT_OBJECT_CODE, IMPORT_OBJECT_NAME, 1)
+        );
+        assert_eq!(import("named"), import("__imp_named"));
+        assert_eq!(
+            import("__imp_noname"),
+            ("noname", IMPORT_OBJECT_CODE, IMPORT_OBJECT_ORDINAL, 5)
+        );
+        assert_eq!(
+            import("__imp_value"),
+            ("value", IMPORT_OBJECT_DATA, IMPORT_OBJECT_NAME, 2)
+        );
+    }
+}
@@ -0,0 +1,193 @@
+pub struct Import {
+    pub dll: String,
+    pub name: ImportName,
+    /// Code imports also get a thunk jumping through their IAT slot.
+    pub is_code: bool,
+}
+
+/// How the loader finds an import in its DLL.
+pub enum ImportName {
+    /// By name, starting the search at `hint` in the DLL's export name table.
+    Name {
+        name: String,
+        hint: u16,
+    },
+    Ordinal(u16),
+}
+
+/// Set in lookup table entries that import by ordinal instead of by name.
+pub(crate) const IMAGE_ORDINAL_FLAG64: u64 = 1 << 63;
+/// [`IMAGE_ORDINAL_FLAG64`] for the 4-byte entries of PE32 images.
+pub(crate) const IMAGE_ORDINAL_FLAG32: u32 = 1 << 31;
+
+struct DllImports {
+    name: String,
+    imports: Vec<usize>,
+}
+
+/// The import directory of an image. The IAT is laid out separately from the
+/// rest of the tables so it can be placed in its own page-aligned region.
+pub struct ImportTables {
+    dlls: Vec<DllImports>,
+    /// Offset of each import's slot in the IAT (and in its lookup table).
+    slots: Vec<u32>,
+    iat_size: u32,
+    hint_names_offset: u32,
+    dll_names_offset: u32,
+    directory_size: u32,
+}
+
+const IMPORT_DESCRIPTOR_SIZE: u32 = 20;
+const THUNK_SIZE: u32 = 8;
+
+impl ImportTables {
+    pub fn new(imports: &[Import]) -> Self {
+        let mut dlls = Vec::<DllImports>::new();
+        for (idx, import) in imports.iter().enumerate() {
+            match dlls
+                .iter_mut()
+                .find(|dll| dll.name.eq_ignore_ascii_case(&import.dll))
+            {
+                Some(dll) => dll.imports.push(idx),
+                None => dlls.push(DllImports {
+                    name: import.dll.clone(),
+                    imports: vec![idx],
+                }),
+            }
+        }
+
+        let mut slots = vec![0; imports.len()];
+        let mut offset = 0;
+        for dll in &dlls {
+            for &import in &dll.imports {
+                slots[import] = offset;
+                offset += THUNK_SIZE;
+            }
+            offset += THUNK_SIZE;
+        }
+        let iat_size = offset;
+
+        let descriptors_size = (dlls.len() as u32 + 1) * IMPORT_DESCRIPTOR_SIZE;
+        let hint_names_offset = descriptors_size + iat_size;
+        let hint_names_size: u32 = imports
+            .iter()
+            .map(|import| hint_name_size(&import.name))
+            .sum();
+        let dll_names_offset = hint_names_offset + hint_names_size;
+        let dll_names_size: u32 = dlls.iter().map(|dll| dll.name.len() as u32 + 1).sum();
+
+        Self {
+            dlls,
+            slots,
+            iat_size,
+            hint_names_offset,
+            dll_names_offset,
+            directory_size: dll_names_offset + dll_names_size,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dlls.is_empty()
+    }
+
+    pub fn iat_size(&self) -> u32 {
+        self.iat_size
+    }
+
+    /// Size of the descriptors, lookup tables, hint/name table and DLL names.
+    pub fn directory_size(&self) -> u32 {
+        self.directory_size
+    }
+
+    /// Size of the import descriptor table, as referenced by the data directory.
+    pub fn descriptors_size(&self) -> u32 {
+        (self.dlls.len() as u32 + 1) * IMPORT_DESCRIPTOR_SIZE
+    }
+
+    pub fn slot(&self, import: usize) -> u32 {
+        self.slots[import]
+    }
+
+    /// Offsets of each import's hint/name entry, relative to the directory.
+    fn hint_name_offsets<'a>(&'a self, imports: &'a [Import]) -> impl Iterator<Item = u32> + 'a {
+        imports
+            .iter()
+            .scan(self.hint_names_offset, |offset, import| {
+                let current = *offset;
+                *offset += hint_name_size(&import.name);
+                Some(current)
+            })
+    }
+
+    /// Writes the IAT, which before binding holds the same entries as the lookup tables.
+    pub fn write_iat(&self, imports: &[Import], directory_rva: u32, out: &mut [u8]) {
+        self.write_thunks(imports, directory_rva, out);
+    }
+
+    fn write_thunks(&self, imports: &[Import], directory_rva: u32, out: &mut [u8]) {
+        for ((idx, import), hint_name) in imports
+            .iter()
+            .enumerate()
+            .zip(self.hint_name_offsets(imports))
+        {
+            let slot = self.slots[idx] as usize;
+            let value = match import.name {
+                ImportName::Name { .. } => u64::from(directory_rva + hint_name),
+                ImportName::Ordinal(ordinal) => IMAGE_ORDINAL_FLAG64 | u64::from(ordinal),
+            };
+            out[slot..][..8].copy_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    pub fn write_directory(
+        &self,
+        imports: &[Import],
+        iat_rva: u32,
+        directory_rva: u32,
+        out: &mut [u8],
+    ) {
+        let descriptors_size = self.descriptors_size();
+        let lookup_rva = directory_rva + descriptors_size;
+
+        let mut dll_name_offset = self.dll_names_offset;
+        for (idx, dll) in self.dlls.iter().enumerate() {
+            let first_slot = self.slots[dll.imports[0]];
+            let descriptor = &mut out[idx * IMPORT_DESCRIPTOR_SIZE as usize..]
+                [..IMPORT_DESCRIPTOR_SIZE as usize];
+            descriptor[0..4].copy_from_slice(&(lookup_rva + first_slot).to_le_bytes());
+            descriptor[12..16].copy_from_slice(&(directory_rva + dll_name_offset).to_le_bytes());
+            descriptor[16..20].copy_from_slice(&(iat_rva + first_slot).to_le_bytes());
+
+            let name = &mut out[dll_name_offset as usize..][..dll.name.len()];
+            name.copy_from_slice(dll.name.as_bytes());
+            dll_name_offset += dll.name.len() as u32 + 1;
+        }
+
+        self.write_thunks(
+            imports,
+            directory_rva,
+            &mut out[descriptors_size as usize..][..self.iat_size as usize],
+        );
+
+        for (import, offset) in imports.iter().zip(self.hint_name_offsets(imports)) {
+            let ImportName::Name { name, hint } = &import.name else {
+                continue;
+            };
+            let entry = &mut out[offset as usize..][..2 + name.len()];
+            entry[..2].copy_from_slice(&hint.to_le_bytes());
+            entry[2..].copy_from_slice(name.as_bytes());
+        }
+    }
+}
+
+/// A hint/name entry: a 2-byte hint followed by the NUL-terminated name, padded
+/// to an even size. Imports by ordinal don't have one.
+fn hint_name_size(name: &ImportName) -> u32 {
+    match name {
+        ImportName::Name { name, .. } => {
+            let size = 2 + name.len() as u32 + 1;
+            size + size % 2
+        }
+        ImportName::Ordinal(_) => 0,
+    }
+}
@@ -0,0 +1,46 @@
+use std::fs::File;
+
+use color_eyre::Result;
+use memmap2::Mmap;
+use winning_coff::diagnostics::{Code, fatal};
+
+enum Contents {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+/// An input file: a COFF object, an archive or a short import object. Parsed
+/// objects borrow from it, so archive members are never copied out.
+pub struct Input {
+    pub name: String,
+    contents: Contents,
+}
+
+impl Input {
+    pub fn new(name: impl Into<String>, data: Vec<u8>) -> Self {
+        Self {
+            name: name.into(),
+            contents: Contents::Owned(data),
+        }
+    }
+
+    /// Maps the file at `path` into memory.
+    pub fn open(path: &str) -> Result<Self> {
+        // Like every linker, we assume inputs aren't modified while we read them.
+        let map = File::open(path).and_then(|file| unsafe { Mmap::map(&file) });
+        match map {
+            Ok(map) => Ok(Self {
+                name: path.to_owned(),
+                contents: Contents::Mapped(map),
+            }),
+            Err(err) => fatal(Code::CannotOpenInput, format!("cannot open {path}: {err}")),
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        match &self.contents {
+            Contents::Mapped(map) => map,
+            Contents::Owned(data) => data,
+        }
+    }
+}
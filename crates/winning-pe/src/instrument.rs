@@ -0,0 +1,161 @@
+use color_eyre::Result;
+use winning_coff::{
+    diagnostics::{Code, fatal},
+    intern::SymbolId,
+};
+
+use crate::link::relocation_size;
+
+/// A hook for inserting instrumentation stubs, like call-tracing prologues,
+/// into code sections. It runs for each code section of every object that's
+/// linked in, before layout. The linker then moves the original code behind
+/// the stubs and fixes up relocations, symbols and addends pointing into it.
+///
+/// Symbols at a stub's offset end up pointing at the stub, so a stub at a
+/// function's offset runs before every call to it. Unwind information in
+/// `.pdata`/`.xdata` is not adjusted, so stubs in functions with unwind info
+/// must not need it. Neither are jumps within a section that the compiler
+/// resolved without a relocation, so stubs have to go where no such jump
+/// crosses them, like at the start of functions in their own sections.
+pub trait Instrument: Send + Sync {
+    fn instrument(&self, section: &CodeSection<'_>) -> Vec<Stub>;
+}
+
+pub struct CodeSection<'a> {
+    /// The object the section is from, as named in diagnostics.
+    pub object: &'a str,
+    pub name: &'a str,
+    pub data: &'a [u8],
+    /// The symbols defined in the section, except for the section symbol.
+    pub symbols: Vec<SectionSymbol<'a>>,
+}
+
+pub struct SectionSymbol<'a> {
+    pub name: &'a str,
+    pub offset: u32,
+    pub is_function: bool,
+}
+
+/// Code to insert at `offset` of the original section.
+pub struct Stub {
+    pub offset: u32,
+    pub code: Vec<u8>,
+    pub relocations: Vec<StubRelocation>,
+}
+
+/// A relocation within a stub, against a global symbol.
+pub struct StubRelocation {
+    /// Offset within the stub code.
+    pub offset: u32,
+    pub symbol: String,
+    /// An `IMAGE_REL_AMD64_*` type.
+    pub r#type: u16,
+}
+
+/// The stubs inserted into one input section, sorted by offset.
+pub(crate) struct SectionRewrite {
+    pub stubs: Vec<Stub>,
+    /// The symbols of the stub relocations, in order, once interned.
+    pub symbols: Vec<SymbolId>,
+}
+
+impl SectionRewrite {
+    pub fn new(object: &str, section: &str, size: usize, mut stubs: Vec<Stub>) -> Result<Self> {
+        stubs.sort_by_key(|stub| stub.offset);
+        for stub in &stubs {
+            if stub.offset as usize > size {
+                return fatal(
+                    Code::InvalidOption,
+                    format!(
+                        "{object}: instrumentation stub at {:#x} is outside of {section}",
+                        stub.offset
+                    ),
+                );
+            }
+            if let Some(reloc) = stub.relocations.iter().find(|reloc| {
+                reloc.offset as usize + relocation_size(reloc.r#type) > stub.code.len()
+            }) {
+                return fatal(
+                    Code::InvalidRelocation,
+                    format!(
+                        "{object}: relocation at {:#x} is outside of its instrumentation stub in {section}",
+                        reloc.offset
+                    ),
+                );
+            }
+        }
+        Ok(Self {
+            stubs,
+            symbols: Vec::new(),
+        })
+    }
+
+    pub fn inserted_size(&self) -> u32 {
+        self.stubs.iter().map(|stub| stub.code.len() as u32).sum()
+    }
+
+    /// Where a symbol or address at `offset` of the original section ends
+    /// up. At a stub's offset, that's the stub.
+    pub fn map_address(&self, offset: u32) -> u32 {
+        offset + self.inserted_before(|stub| stub.offset < offset)
+    }
+
+    /// Where the original code at `offset` ends up, which is behind any stub
+    /// inserted at the same offset.
+    pub fn map_code(&self, offset: u32) -> u32 {
+        offset + self.inserted_before(|stub| stub.offset <= offset)
+    }
+
+    /// Whether a stub goes between `start` and `end` of the original section.
+    pub fn splits(&self, start: u32, end: u32) -> bool {
+        self.stubs
+            .iter()
+            .any(|stub| start < stub.offset && stub.offset < end)
+    }
+
+    /// The addend for an address `addend` bytes from the symbol at `value`,
+    /// so it still points to the same code.
+    pub fn move_addend(&self, value: u32, addend: i64) -> i64 {
+        match u32::try_from(i64::from(value) + addend) {
+            Ok(address) => {
+                i64::from(self.map_address(address)) - i64::from(self.map_address(value))
+            }
+            Err(_) => addend,
+        }
+    }
+
+    /// The stub relocations, at their offsets in the rewritten section.
+    pub fn stub_relocations(&self) -> impl Iterator<Item = (u32, &StubRelocation)> {
+        let mut inserted = 0;
+        self.stubs.iter().flat_map(move |stub| {
+            let start = stub.offset + inserted;
+            inserted += stub.code.len() as u32;
+            stub.relocations
+                .iter()
+                .map(move |reloc| (start + reloc.offset, reloc))
+        })
+    }
+
+    fn inserted_before(&self, before: impl Fn(&Stub) -> bool) -> u32 {
+        self.stubs
+            .iter()
+            .take_while(|stub| before(stub))
+            .map(|stub| stub.code.len() as u32)
+            .sum()
+    }
+
+    /// Interleaves the original section contents with the stubs.
+    pub fn write(&self, original: &[u8], out: &mut [u8]) {
+        let mut pos = 0;
+        let mut written = 0;
+        for stub in &self.stubs {
+            let code = &original[pos..stub.offset as usize];
+            out[written..][..code.len()].copy_from_slice(code);
+            written += code.len();
+            out[written..][..stub.code.len()].copy_from_slice(&stub.code);
+            written += stub.code.len();
+            pos = stub.offset as usize;
+        }
+        out[written..][..original.len() - pos].copy_from_slice(&original[pos..]);
+    }
+}
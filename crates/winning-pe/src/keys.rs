@@ -0,0 +1,625 @@
+//! The private key and certificates to sign with, from PEM files or a PKCS#12
+//! (PFX) file. Only RSA keys are supported. Encrypted keys need PBES2 with
+//! PBKDF2-HMAC-SHA256 and AES-CBC, which OpenSSL 3 uses by default and Windows
+//! uses when exporting with AES256-SHA256; the RC2 and 3DES of older PFX files
+//! aren't supported.
+
+use color_eyre::Result;
+use winning_coff::diagnostics::{Code, fatal};
+
+use crate::{
+    aes,
+    der::{self, OBJECT_IDENTIFIER, OCTET_STRING, Reader, SEQUENCE, SET, Value, context, oid},
+    digest::{Sha256, hmac_sha256, pbkdf2_sha256},
+    rsa::RsaPrivateKey,
+};
+
+#[derive(Debug, Clone)]
+pub struct SigningIdentity {
+    pub key: RsaPrivateKey,
+    /// DER encoded, the certificate of the key first and then the rest of its
+    /// chain, which goes into the signature so it can be verified without
+    /// looking up intermediate certificates.
+    pub certificates: Vec<Vec<u8>>,
+}
+
+/// The fields of an X.509 certificate a signature refers to.
+#[derive(Debug, Clone)]
+pub struct CertificateInfo<'a> {
+    /// The encoded `Name` and `INTEGER`, which together identify the
+    /// certificate.
+    pub issuer: &'a [u8],
+    pub serial_number: &'a [u8],
+    /// The modulus of an RSA public key, big-endian.
+    pub modulus: Option<&'a [u8]>,
+    pub common_name: Option<String>,
+}
+
+/// Reads the fields of a DER-encoded certificate we need.
+pub fn certificate_info(certificate: &[u8]) -> Option<CertificateInfo<'_>> {
+    let certificate = Reader::new(certificate).expect(SEQUENCE)?;
+    let mut fields = certificate.children().expect(SEQUENCE)?.children();
+    fields.optional(context(0));
+    let serial_number = fields.expect(der::INTEGER)?.encoding;
+    fields.expect(SEQUENCE)?;
+    let issuer = fields.expect(SEQUENCE)?.encoding;
+    fields.expect(SEQUENCE)?;
+    let subject = fields.expect(SEQUENCE)?;
+    let mut public_key = fields.expect(SEQUENCE)?.children();
+
+    let mut common_name = None;
+    let mut names = subject.children();
+    while let Some(name) = names.expect(SET) {
+        let mut attribute = name.children().expect(SEQUENCE)?.children();
+        if attribute.expect(OBJECT_IDENTIFIER)?.contents == oid::COMMON_NAME {
+            common_name = Some(String::from_utf8_lossy(attribute.read()?.contents).into_owned());
+        }
+    }
+
+    let algorithm = public_key.expect(SEQUENCE)?;
+    let modulus = if algorithm.children().expect(OBJECT_IDENTIFIER)?.contents == oid::RSA_ENCRYPTION
+    {
+        // The key is an `RSAPublicKey` in a `BIT STRING` without unused bits.
+        let bits = public_key.expect(der::BIT_STRING)?;
+        let (0, key) = bits.contents.split_first()? else {
+            return None;
+        };
+        let key = Reader::new(key).expect(SEQUENCE)?;
+        key.children().expect(der::INTEGER)?.unsigned()
+    } else {
+        None
+    };
+    Some(CertificateInfo {
+        issuer,
+        serial_number,
+        modulus,
+        common_name,
+    })
+}
+
+impl SigningIdentity {
+    /// From the concatenation of PEM files with the private key and the
+    /// certificates. `password` decrypts an `ENCRYPTED PRIVATE KEY`.
+    pub fn from_pem(text: &str, password: Option<&str>) -> Result<Self> {
+        let mut key = None;
+        let mut certificates = Vec::new();
+        for (label, contents) in pem_blocks(text)? {
+            let block_key = match label {
+                "CERTIFICATE" => {
+                    certificates.push(contents);
+                    continue;
+                }
+                "PRIVATE KEY" => private_key_info(&contents)?,
+                "RSA PRIVATE KEY" => match RsaPrivateKey::from_pkcs1(&contents) {
+                    Some(key) => key,
+                    None => return fatal(Code::InvalidSigningKey, "invalid RSA private key"),
+                },
+                "ENCRYPTED PRIVATE KEY" => {
+                    let Some(password) = password else {
+                        return fatal(
+                            Code::InvalidSigningKey,
+                            "the private key is encrypted, but no password was given",
+                        );
+                    };
+                    private_key_info(&encrypted_private_key_info(&contents, password)?)?
+                }
+                "EC PRIVATE KEY" | "DSA PRIVATE KEY" => {
+                    return fatal(
+                        Code::InvalidSigningKey,
+                        format!("{label} isn't supported, only RSA keys can sign"),
+                    );
+                }
+                _ => continue,
+            };
+            if key.replace(block_key).is_some() {
+                return fatal(Code::InvalidSigningKey, "more than one private key given");
+            }
+        }
+        let Some(key) = key else {
+            return fatal(Code::InvalidSigningKey, "no private key given");
+        };
+        Self::new(key, certificates)
+    }
+
+    /// From a PKCS#12 file, whose MAC is checked with `password` if it's
+    /// HMAC-SHA256.
+    pub fn from_pkcs12(data: &[u8], password: &str) -> Result<Self> {
+        let Some((version, auth_safe, mac)) = Reader::new(data).expect(SEQUENCE).and_then(|pfx| {
+            let mut fields = pfx.children();
+            let version = fields.expect(der::INTEGER)?.small_integer()?;
+            let auth_safe = fields.expect(SEQUENCE)?;
+            Some((version, auth_safe, fields.optional(SEQUENCE)))
+        }) else {
+            return fatal(Code::CorruptInput, "not a PKCS#12 file");
+        };
+        if version != 3 {
+            return fatal(
+                Code::InvalidSigningKey,
+                format!("unsupported PKCS#12 version {version}"),
+            );
+        }
+        let Some(auth_safe) = data_content(auth_safe)? else {
+            return fatal(
+                Code::InvalidSigningKey,
+                "PKCS#12 files protected with a public key aren't supported",
+            );
+        };
+        if let Some(mac) = mac {
+            check_pkcs12_mac(mac, auth_safe, password)?;
+        }
+
+        let mut key = None;
+        let mut certificates = Vec::new();
+        let Some(mut contents) = Reader::new(auth_safe)
+            .expect(SEQUENCE)
+            .map(|value| value.children())
+        else {
+            return malformed_pkcs12();
+        };
+        while !contents.is_empty() {
+            let Some(content) = contents.expect(SEQUENCE) else {
+                return malformed_pkcs12();
+            };
+            let safe_contents = match data_content(content)? {
+                Some(safe_contents) => safe_contents.to_vec(),
+                None => encrypted_content(content, password)?,
+            };
+            let Some(mut bags) = Reader::new(&safe_contents)
+                .expect(SEQUENCE)
+                .map(|value| value.children())
+            else {
+                return malformed_pkcs12();
+            };
+            while !bags.is_empty() {
+                let Some((bag_type, value)) = bags.expect(SEQUENCE).and_then(|bag| {
+                    let mut fields = bag.children();
+                    let bag_type = fields.expect(OBJECT_IDENTIFIER)?.contents;
+                    Some((bag_type, fields.expect(context(0))?.children().read()?))
+                }) else {
+                    return malformed_pkcs12();
+                };
+                let bag_key = match bag_type.strip_prefix(oid::PKCS12_BAG_TYPES) {
+                    Some([oid::KEY_BAG]) => private_key_info(value.encoding)?,
+                    Some([oid::SHROUDED_KEY_BAG]) => {
+                        private_key_info(&encrypted_private_key_info(value.encoding, password)?)?
+                    }
+                    Some([oid::CERT_BAG]) => {
+                        let Some(certificate) = cert_bag(value) else {
+                            return malformed_pkcs12();
+                        };
+                        if let Some(certificate) = certificate {
+                            certificates.push(certificate.to_vec());
+                        }
+                        continue;
+                    }
+                    // CRLs and secrets.
+                    _ => continue,
+                };
+                if key.replace(bag_key).is_some() {
+                    return fatal(
+                        Code::InvalidSigningKey,
+                        "the PKCS#12 file has more than one private key",
+                    );
+                }
+            }
+        }
+        let Some(key) = key else {
+            return fatal(
+                Code::InvalidSigningKey,
+                "the PKCS#12 file has no private key",
+            );
+        };
+        Self::new(key, certificates)
+    }
+
+    /// Puts the certificate of `key` first, and drops certificates given
+    /// twice, like in both the key file and the certificate file.
+    fn new(key: RsaPrivateKey, mut certificates: Vec<Vec<u8>>) -> Result<Self> {
+        certificates.sort();
+        certificates.dedup();
+        let mut signer = None;
+        for (idx, certificate) in certificates.iter().enumerate() {
+            let Some(info) = certificate_info(certificate) else {
+                return fatal(Code::InvalidSigningKey, "invalid certificate");
+            };
+            if info.modulus == Some(key.modulus()) {
+                signer = Some(idx);
+            }
+        }
+        let Some(signer) = signer else {
+            return fatal(
+                Code::InvalidSigningKey,
+                "no certificate for the private key given",
+            );
+        };
+        let signer = certificates.remove(signer);
+        certificates.insert(0, signer);
+        Ok(Self { key, certificates })
+    }
+
+    /// The certificate of the key.
+    pub fn signer(&self) -> CertificateInfo<'_> {
+        certificate_info(&self.certificates[0]).unwrap()
+    }
+}
+
+/// The label and decoded contents of each PEM block in `text`.
+fn pem_blocks(text: &str) -> Result<Vec<(&str, Vec<u8>)>> {
+    let mut blocks = Vec::new();
+    let mut current: Option<(&str, String)> = None;
+    for line in text.lines().map(str::trim) {
+        if let Some(label) = line
+            .strip_prefix("-----BEGIN ")
+            .and_then(|line| line.strip_suffix("-----"))
+        {
+            current = Some((label, String::new()));
+        } else if let Some(label) = line
+            .strip_prefix("-----END ")
+            .and_then(|line| line.strip_suffix("-----"))
+        {
+            let Some((begin, base64)) = current.take() else {
+                continue;
+            };
+            let Some(contents) = decode_base64(&base64).filter(|_| begin == label) else {
+                return fatal(Code::CorruptInput, format!("invalid PEM block {label}"));
+            };
+            blocks.push((label, contents));
+        } else if let Some((label, base64)) = &mut current {
+            if line.contains(':') {
+                // The headers of the encryption OpenSSL used before PKCS#8.
+                return fatal(
+                    Code::InvalidSigningKey,
+                    format!(
+                        "{label} uses the legacy PEM encryption, convert it with \
+                         `openssl pkcs8 -topk8`"
+                    ),
+                );
+            }
+            base64.push_str(line);
+        }
+    }
+    Ok(blocks)
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for byte in text.trim_end_matches('=').bytes() {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = bits << 6 | u32::from(value);
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// The RSA key of a PKCS#8 `PrivateKeyInfo`.
+fn private_key_info(der: &[u8]) -> Result<RsaPrivateKey> {
+    let Some((algorithm, key)) = Reader::new(der).expect(SEQUENCE).and_then(|info| {
+        let mut fields = info.children();
+        fields.expect(der::INTEGER)?;
+        let algorithm = fields.expect(SEQUENCE)?;
+        let algorithm = algorithm.children().expect(OBJECT_IDENTIFIER)?.contents;
+        Some((algorithm, fields.expect(OCTET_STRING)?.contents))
+    }) else {
+        return fatal(Code::InvalidSigningKey, "invalid private key");
+    };
+    if algorithm != oid::RSA_ENCRYPTION {
+        return fatal(Code::InvalidSigningKey, "only RSA keys can sign");
+    }
+    match RsaPrivateKey::from_pkcs1(key) {
+        Some(key) => Ok(key),
+        None => fatal(Code::InvalidSigningKey, "invalid RSA private key"),
+    }
+}
+
+/// Decrypts a PKCS#8 `EncryptedPrivateKeyInfo` into a `PrivateKeyInfo`.
+fn encrypted_private_key_info(der: &[u8], password: &str) -> Result<Vec<u8>> {
+    let Some((algorithm, encrypted)) = Reader::new(der).expect(SEQUENCE).and_then(|info| {
+        let mut fields = info.children();
+        Some((fields.expect(SEQUENCE)?, fields.expect(OCTET_STRING)?))
+    }) else {
+        return fatal(Code::InvalidSigningKey, "invalid encrypted private key");
+    };
+    decrypt(algorithm, encrypted.contents, password)
+}
+
+/// The parameters of PBES2 we support.
+struct Pbes2<'a> {
+    salt: &'a [u8],
+    iterations: u32,
+    prf: Option<&'a [u8]>,
+    cipher: &'a [u8],
+    iv: &'a [u8],
+}
+
+/// Decrypts `data` with a PKCS#5 password-based encryption `algorithm`.
+fn decrypt(algorithm: Value<'_>, data: &[u8], password: &str) -> Result<Vec<u8>> {
+    let mut fields = algorithm.children();
+    let scheme = fields.expect(OBJECT_IDENTIFIER).map(|oid| oid.contents);
+    if scheme.is_some_and(|scheme| scheme.starts_with(oid::PKCS12_PBE)) {
+        return fatal(
+            Code::InvalidSigningKey,
+            "the key is encrypted with RC2 or 3DES, re-export it with AES, like \
+             `openssl pkcs12 -export` does without `-legacy`",
+        );
+    }
+    if scheme != Some(oid::PBES2) {
+        return fatal(
+            Code::InvalidSigningKey,
+            "unsupported key encryption, only PBES2 is supported",
+        );
+    }
+    let Some(parameters) = fields.expect(SEQUENCE).and_then(|parameters| {
+        let mut fields = parameters.children();
+        let mut derivation = fields.expect(SEQUENCE)?.children();
+        let mut encryption = fields.expect(SEQUENCE)?.children();
+        if derivation.expect(OBJECT_IDENTIFIER)?.contents != oid::PBKDF2 {
+            return None;
+        }
+        let mut derivation = derivation.expect(SEQUENCE)?.children();
+        let salt = derivation.expect(OCTET_STRING)?.contents;
+        let iterations = derivation.expect(der::INTEGER)?.small_integer()?;
+        derivation.optional(der::INTEGER);
+        let prf = match derivation.optional(SEQUENCE) {
+            Some(prf) => Some(prf.children().expect(OBJECT_IDENTIFIER)?.contents),
+            None => None,
+        };
+        Some(Pbes2 {
+            salt,
+            iterations,
+            prf,
+            cipher: encryption.expect(OBJECT_IDENTIFIER)?.contents,
+            iv: encryption.expect(OCTET_STRING)?.contents,
+        })
+    }) else {
+        return fatal(
+            Code::InvalidSigningKey,
+            "unsupported PBES2 parameters, only PBKDF2 is supported",
+        );
+    };
+    if parameters.prf != Some(oid::HMAC_WITH_SHA256) {
+        return fatal(
+            Code::InvalidSigningKey,
+            "unsupported key derivation, only PBKDF2 with HMAC-SHA256 is supported",
+        );
+    }
+    let key_size = match parameters.cipher {
+        oid::AES128_CBC => 16,
+        oid::AES192_CBC => 24,
+        oid::AES256_CBC => 32,
+        _ => {
+            return fatal(
+                Code::InvalidSigningKey,
+                "unsupported key encryption, only AES-CBC is supported",
+            );
+        }
+    };
+    let Ok(iv) = parameters.iv.try_into() else {
+        return fatal(Code::InvalidSigningKey, "invalid AES initialization vector");
+    };
+    let key = pbkdf2_sha256(
+        password.as_bytes(),
+        parameters.salt,
+        parameters.iterations,
+        key_size,
+    );
+    match aes::decrypt_cbc(&key, iv, data) {
+        Some(decrypted) => Ok(decrypted),
+        None => fatal(
+            Code::InvalidSigningKey,
+            "cannot decrypt the key, wrong password?",
+        ),
+    }
+}
+
+fn malformed_pkcs12<T>() -> Result<T> {
+    fatal(Code::CorruptInput, "malformed PKCS#12 file")
+}
+
+/// The contents of a PKCS#7 `ContentInfo` of type data, or `None` if it has
+/// another type.
+fn data_content<'a>(content: Value<'a>) -> Result<Option<&'a [u8]>> {
+    let mut fields = content.children();
+    let Some(content_type) = fields.expect(OBJECT_IDENTIFIER) else {
+        return malformed_pkcs12();
+    };
+    if content_type.contents != oid::DATA {
+        return Ok(None);
+    }
+    match fields
+        .expect(context(0))
+        .and_then(|data| data.children().expect(OCTET_STRING))
+    {
+        Some(data) => Ok(Some(data.contents)),
+        None => malformed_pkcs12(),
+    }
+}
+
+/// Decrypts a PKCS#7 `ContentInfo` of type encrypted data.
+fn encrypted_content(content: Value<'_>, password: &str) -> Result<Vec<u8>> {
+    let Some((algorithm, encrypted)) = encrypted_data(content) else {
+        return fatal(
+            Code::InvalidSigningKey,
+            "unsupported content in the PKCS#12 file",
+        );
+    };
+    decrypt(algorithm, encrypted, password)
+}
+
+/// The algorithm and encrypted contents of a PKCS#7 `EncryptedData`.
+fn encrypted_data(content: Value<'_>) -> Option<(Value<'_>, &[u8])> {
+    let mut fields = content.children();
+    if fields.expect(OBJECT_IDENTIFIER)?.contents != oid::ENCRYPTED_DATA {
+        return None;
+    }
+    let mut encrypted_data = fields
+        .expect(context(0))?
+        .children()
+        .expect(SEQUENCE)?
+        .children();
+    encrypted_data.expect(der::INTEGER)?;
+    let mut info = encrypted_data.expect(SEQUENCE)?.children();
+    info.expect(OBJECT_IDENTIFIER)?;
+    let algorithm = info.expect(SEQUENCE)?;
+    Some((algorithm, info.expect(der::context_primitive(0))?.contents))
+}
+
+/// The certificate of a `CertBag`, or `None` inside if it isn't X.509.
+fn cert_bag(bag: Value<'_>) -> Option<Option<&[u8]>> {
+    let mut fields = bag.children();
+    if fields.expect(OBJECT_IDENTIFIER)?.contents != oid::X509_CERTIFICATE {
+        return Some(None);
+    }
+    let certificate = fields.expect(context(0))?.children().expect(OCTET_STRING)?;
+    Some(Some(certificate.contents))
+}
+
+struct MacData<'a> {
+    algorithm: &'a [u8],
+    expected: &'a [u8],
+    salt: &'a [u8],
+    iterations: u32,
+}
+
+impl<'a> MacData<'a> {
+    fn parse(mac: Value<'a>) -> Option<Self> {
+        let mut fields = mac.children();
+        let mut digest_info = fields.expect(SEQUENCE)?.children();
+        let algorithm = digest_info.expect(SEQUENCE)?;
+        let algorithm = algorithm.children().expect(OBJECT_IDENTIFIER)?.contents;
+        let expected = digest_info.expect(OCTET_STRING)?.contents;
+        let salt = fields.expect(OCTET_STRING)?.contents;
+        let iterations = match fields.optional(der::INTEGER) {
+            Some(iterations) => iterations.small_integer()?,
+            None => 1,
+        };
+        Some(Self {
+            algorithm,
+            expected,
+            salt,
+            iterations,
+        })
+    }
+}
+
+/// Checks the `MacData` of a PKCS#12 file over `auth_safe`, which shows the
+/// password is right before anything is decrypted.
+fn check_pkcs12_mac(mac: Value<'_>, auth_safe: &[u8], password: &str) -> Result<()> {
+    let Some(MacData {
+        algorithm,
+        expected,
+        salt,
+        iterations,
+    }) = MacData::parse(mac)
+    else {
+        return malformed_pkcs12();
+    };
+    if algorithm != oid::SHA256 {
+        return fatal(
+            Code::InvalidSigningKey,
+            "unsupported PKCS#12 MAC, re-export it with SHA-256, like \
+             `openssl pkcs12 -export` does without `-legacy`",
+        );
+    }
+
+    // The password is a null-terminated BMPString. An empty one can also be
+    // no password at all, which leaves out the terminator.
+    let mut encoded = password
+        .encode_utf16()
+        .flat_map(u16::to_be_bytes)
+        .collect::<Vec<_>>();
+    encoded.extend_from_slice(&[0, 0]);
+    let mut candidates = vec![encoded];
+    if password.is_empty() {
+        candidates.push(Vec::new());
+    }
+    for password in candidates {
+        let key = pkcs12_mac_key(&password, salt, iterations);
+        if hmac_sha256(&key, auth_safe) == expected {
+            return Ok(());
+        }
+    }
+    fatal(
+        Code::InvalidSigningKey,
+        "the PKCS#12 MAC doesn't match, wrong password?",
+    )
+}
+
+/// The MAC key the key derivation of PKCS#12 (RFC 7292, appendix B) derives
+/// with SHA-256. The key is as long as one digest, so there's only one round.
+fn pkcs12_mac_key(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+    const BLOCK: usize = 64;
+    let repeat = |data: &[u8]| {
+        let size = data.len().div_ceil(BLOCK) * BLOCK;
+        data.iter().copied().cycle().take(size).collect::<Vec<_>>()
+    };
+    let mut hasher = Sha256::default();
+    // The identifier of MAC keys.
+    hasher.update(&[3; BLOCK]);
+    hasher.update(&repeat(salt));
+    hasher.update(&repeat(password));
+    let mut key = hasher.finish();
+    for _ in 1..iterations {
+        key = Sha256::digest(&key);
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A self-signed certificate for `CN=Winning Test Signer` with serial
+    /// number 0x1234, its key encrypted with PBES2 and AES-256-CBC by
+    /// `openssl pkcs8 -topk8`, and both in a PKCS#12 file made by
+    /// `openssl pkcs12 -export`. The password of both is `hunter2`.
+    const CERTIFICATE: &str = include_str!("testdata/cert.pem");
+    const ENCRYPTED_KEY: &str = include_str!("testdata/key-encrypted.pem");
+    const PKCS12: &[u8] = include_bytes!("testdata/identity.pfx");
+    /// The same key unencrypted, as PKCS#1.
+    const KEY: &[u8] = include_bytes!("testdata/rsa-1024.der");
+
+    fn check(identity: &SigningIdentity) {
+        let expected = RsaPrivateKey::from_pkcs1(KEY).unwrap();
+        assert_eq!(identity.key.modulus(), expected.modulus());
+        assert_eq!(identity.certificates.len(), 1);
+        let signer = identity.signer();
+        assert_eq!(signer.common_name.as_deref(), Some("Winning Test Signer"));
+        assert_eq!(signer.serial_number, [0x02, 0x02, 0x12, 0x34]);
+        assert_eq!(signer.modulus, Some(expected.modulus()));
+        // Both sign like the key they were decrypted from.
+        let digest = [0x5a; 32];
+        assert_eq!(
+            identity.key.sign_sha256(&digest),
+            expected.sign_sha256(&digest)
+        );
+    }
+
+    #[test]
+    fn pem() {
+        let text = format!("{ENCRYPTED_KEY}\n{CERTIFICATE}");
+        check(&SigningIdentity::from_pem(&text, Some("hunter2")).unwrap());
+        assert!(SigningIdentity::from_pem(&text, Some("hunter3")).is_err());
+        assert!(SigningIdentity::from_pem(&text, None).is_err());
+        assert!(SigningIdentity::from_pem(CERTIFICATE, None).is_err());
+    }
+
+    #[test]
+    fn pkcs12() {
+        check(&SigningIdentity::from_pkcs12(PKCS12, "hunter2").unwrap());
+        assert!(SigningIdentity::from_pkcs12(PKCS12, "hunter3").is_err());
+        assert!(SigningIdentity::from_pkcs12(&PKCS12[..100], "hunter2").is_err());
+    }
+}
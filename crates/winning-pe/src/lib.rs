@@ -0,0 +1,46 @@
+//! Linking COFF objects into PE images, and reading images back.
+
+pub mod aes;
+pub mod authenticode;
+pub mod cancel;
+pub mod debug;
+pub mod def;
+pub mod der;
+pub mod digest;
+pub mod edit;
+pub mod export;
+pub mod find;
+pub mod guard;
+pub mod icon;
+pub mod image;
+pub mod imphash;
+pub mod implib;
+pub mod import;
+pub mod input;
+pub mod instrument;
+pub mod keys;
+pub mod link;
+pub mod linker;
+pub mod load_config;
+pub mod manifest;
+pub mod order;
+pub mod ordlookup;
+pub mod pack;
+pub mod pdb;
+pub mod pe;
+pub mod resources;
+pub mod rich;
+pub mod rsa;
+pub mod sanitizer;
+pub mod sign;
+pub mod stats;
+pub mod strip;
+pub mod summary;
+pub mod timing;
+pub mod tls;
+pub mod unwind;
+pub mod update;
+pub mod verify;
+pub mod version_info;
+
+pub use linker::{LinkError, Linker};
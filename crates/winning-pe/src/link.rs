@@ -0,0 +1,4134 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    io::{self, Read, Seek, SeekFrom, Write},
+    time::Instant,
+};
+
+use binrw::BinWrite;
+use color_eyre::Result;
+use rayon::prelude::*;
+use tracing::{Level, debug, info, trace};
+
+use winning_coff::{
+    archive::{ARCHIVE_MAGIC, Archive},
+    coff::{
+        Characteristics, CoffHeader, IMAGE_COMDAT_SELECT_ASSOCIATIVE, IMAGE_FILE_MACHINE_AMD64,
+        IMAGE_REL_AMD64_ABSOLUTE, IMAGE_REL_AMD64_ADDR32, IMAGE_REL_AMD64_ADDR32NB,
+        IMAGE_REL_AMD64_ADDR64, IMAGE_REL_AMD64_REL32, IMAGE_REL_AMD64_REL32_5,
+        IMAGE_REL_AMD64_SECREL, IMAGE_REL_AMD64_SECTION, IMAGE_SYM_ABSOLUTE,
+        IMAGE_SYM_CLASS_EXTERNAL, IMAGE_SYM_CLASS_WEAK_EXTERNAL, IMAGE_SYM_DEBUG,
+        IMAGE_SYM_DTYPE_FUNCTION, IMAGE_SYM_UNDEFINED, ObjectFile, SectionFlags, SectionHeader,
+    },
+    demangle,
+    diagnostics::{Code, Diagnostics, fatal},
+    import::{IMPORT_OBJECT_CODE, ImportObject, is_import_object},
+    intern::{Interner, SymbolId},
+    support::{check_input_format, check_machine},
+    symbols::SymbolRecord,
+};
+
+use crate::{
+    authenticode::CERTIFICATE_ALIGNMENT,
+    cancel::CancellationToken,
+    debug::{DebugOptions, DebugTables},
+    export::{ExportEntry, ExportList, ExportSpec, ExportTables, ExportTarget},
+    guard::{
+        FEAT_GUARD_CF, FEAT_SYMBOL, GUARD_FLAGS, GUARD_FLAGS_SYMBOL, GuardTable,
+        UNUSED_LOAD_CONFIG_SYMBOLS,
+    },
+    image::{ImageExports, ImageModel},
+    implib::{ImportLibraryEntry, write_import_library},
+    import::{Import, ImportName, ImportTables},
+    input::Input,
+    instrument::{CodeSection, Instrument, SectionRewrite, SectionSymbol},
+    load_config::{DEPENDENT_LOAD_FLAGS_OFFSET, LOAD_CONFIG_SIZE, LoadConfig},
+    order::{OrderEntry, STARTUP_ANCHORS, StartupOrder},
+    pdb::{self, Contribution, Module, Public},
+    pe::{
+        ChecksumWriter, DOS_MAGIC, DataDirectory, DllCharacteristics, IMAGE_REL_BASED_DIR64,
+        IMAGE_REL_BASED_HIGHLOW, MSDOS_STUB, OptionalHeader, Subsystem, TINY_DOS_STUB,
+        encode_base_relocations,
+    },
+    resources::{Resource, build_resource_section, is_res, parse_res},
+    rich::{RichHeader, insert_rich_header, object_entries},
+    sanitizer,
+    stats::Stats,
+    summary::Summary,
+    timing::{SharedDuration, Timings},
+    tls::{
+        TLS_DIRECTORY_SIZE, TLS_INDEX_SYMBOL, TLS_USED_SYMBOL, TlsDirectory, is_callback_section,
+    },
+    unwind::{self, RUNTIME_FUNCTION_SIZE},
+    verify::verify,
+};
+
+pub const DEFAULT_IMAGE_BASE: u64 = 0x1_4000_0000;
+/// The granularity of the loader's address space reservations.
+const IMAGE_BASE_ALIGNMENT: u64 = 0x1_0000;
+/// The end of the user-mode address space on x64.
+const USER_ADDRESS_SPACE_END: u64 = 0x8000_0000_0000;
+pub const SECTION_ALIGNMENT: u32 = 0x1000;
+pub const FILE_ALIGNMENT: u32 = 0x200;
+const DEFAULT_OS_VERSION: (u16, u16) = (6, 0);
+const DEFAULT_RESERVE: u64 = 0x10_0000;
+const DEFAULT_COMMIT: u64 = 0x1000;
+const MIN_ALIGNMENT: u32 = 0x200;
+const MAX_ALIGNMENT: u32 = 0x1_0000;
+const DEFAULT_ENTRY: &str = "mainCRTStartup";
+const DRIVER_ENTRY: &str = "DriverEntry";
+/// The CRT's DLL entry point, which calls `DllMain`.
+const DLL_ENTRY: &str = "_DllMainCRTStartup";
+/// What GNU-EFI and Rust's UEFI targets call the image entry point.
+const EFI_ENTRY: &str = "efi_main";
+const IMAGE_BASE_SYMBOL: &str = "__ImageBase";
+/// The CRT's `IMAGE_LOAD_CONFIG_DIRECTORY64`, for the load config directory.
+const LOAD_CONFIG_SYMBOL: &str = "_load_config_used";
+const SECURITY_COOKIE_SYMBOL: &str = "__security_cookie";
+const GUARD_CHECK_FUNCTION_SYMBOL: &str = "__guard_check_icall_fptr";
+const GUARD_DISPATCH_FUNCTION_SYMBOL: &str = "__guard_dispatch_icall_fptr";
+const IMPORT_THUNK_SIZE: u32 = 6;
+const SECTION_HEADER_SIZE: u32 = 40;
+/// The string table starts with its own size.
+const STRING_TABLE_SIZE_FIELD: u32 = 4;
+/// `int3`, which code sections are padded with, so that stray jumps trap.
+const CODE_PADDING: u8 = 0xCC;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Definition {
+    Undefined,
+    /// A weak external, resolved through its aux record if nothing else defines it.
+    Weak {
+        object: usize,
+        symbol: u32,
+    },
+    /// A common symbol, allocated in `.bss` by the linker.
+    Common {
+        size: u32,
+    },
+    Section {
+        object: usize,
+        section: usize,
+        value: u32,
+    },
+    Absolute(u32),
+    /// The thunk jumping through the IAT slot of an import.
+    Import(usize),
+    /// The `__imp_` IAT slot of an import.
+    ImportPointer(usize),
+    ImageBase,
+    /// One of [`STARTUP_ANCHORS`], by index.
+    StartupAnchor(usize),
+    /// The start of a non-empty Control Flow Guard table.
+    GuardTable(GuardTable),
+    /// The load configuration the linker writes without one from the CRT.
+    LoadConfig,
+    /// The TLS directory the linker writes without one from the CRT.
+    TlsDirectory,
+    /// The TLS index slot the linker allocates without one from the CRT.
+    TlsIndex,
+    /// Another symbol, by `/ALTERNATENAME`.
+    Alias(SymbolId),
+}
+
+struct GlobalSymbol {
+    def: Definition,
+    /// The object that first referenced the symbol, for diagnostics.
+    referenced_by: Option<usize>,
+}
+
+/// The kinds of `/DRIVER`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Driver {
+    Plain,
+    /// `/DRIVER:WDM`, a Windows Driver Model driver.
+    Wdm,
+    /// `/DRIVER:UPONLY`, for uniprocessor systems only.
+    UpOnly,
+}
+
+/// How much address space to reserve for the stack or heap, and how much of
+/// it to commit up front.
+#[derive(Debug, Clone, Copy)]
+pub struct Reservation {
+    pub reserve: u64,
+    pub commit: Option<u64>,
+}
+
+impl Reservation {
+    /// Parses `reserve[,commit]`, in decimal or `0x` hex.
+    pub fn parse(value: &str) -> Option<Self> {
+        let number = |value: &str| {
+            let value = value.trim();
+            match value
+                .strip_prefix("0x")
+                .or_else(|| value.strip_prefix("0X"))
+            {
+                Some(hex) => u64::from_str_radix(hex, 16).ok(),
+                None => value.parse().ok(),
+            }
+        };
+        let (reserve, commit) = match value.split_once(',') {
+            Some((reserve, commit)) => (number(reserve)?, Some(number(commit)?)),
+            None => (number(value)?, None),
+        };
+        Some(Self { reserve, commit })
+    }
+
+    fn sizes(this: Option<Self>, reserve: u64, commit: u64) -> (u64, u64) {
+        match this {
+            Some(this) => (this.reserve, this.commit.unwrap_or(commit)),
+            None => (reserve, commit),
+        }
+    }
+}
+
+/// `/SECTION:name,attributes`: changes the memory attributes of an output
+/// section. Each of `DEKPRSW` sets an attribute and `!` before one clears it;
+/// the others are kept.
+#[derive(Debug, Clone)]
+pub struct SectionOverride {
+    pub name: String,
+    pub set: SectionFlags,
+    pub clear: SectionFlags,
+}
+
+impl SectionOverride {
+    pub fn parse(value: &str) -> Option<Self> {
+        let (name, attributes) = value.split_once(',')?;
+        let mut this = Self {
+            name: name.to_owned(),
+            set: SectionFlags::empty(),
+            clear: SectionFlags::empty(),
+        };
+        let mut negated = false;
+        for letter in attributes.chars() {
+            if letter == '!' {
+                negated = true;
+                continue;
+            }
+            let flag = match letter.to_ascii_uppercase() {
+                'D' => SectionFlags::IMAGE_SCN_MEM_DISCARDABLE,
+                'E' => SectionFlags::IMAGE_SCN_MEM_EXECUTE,
+                'K' => SectionFlags::IMAGE_SCN_MEM_NOT_CACHED,
+                'P' => SectionFlags::IMAGE_SCN_MEM_NOT_PAGED,
+                'R' => SectionFlags::IMAGE_SCN_MEM_READ,
+                'S' => SectionFlags::IMAGE_SCN_MEM_SHARED,
+                'W' => SectionFlags::IMAGE_SCN_MEM_WRITE,
+                _ => return None,
+            };
+            if negated {
+                this.clear |= flag;
+            } else {
+                this.set |= flag;
+            }
+            negated = false;
+        }
+        (!name.is_empty() && !negated).then_some(this)
+    }
+}
+
+/// `/MERGE:from=to`: puts the contributions of output section `from` after
+/// those of `to`, with the attributes of both.
+#[derive(Debug, Clone)]
+pub struct SectionMerge {
+    pub from: String,
+    pub to: String,
+}
+
+impl SectionMerge {
+    pub fn parse(value: &str) -> Option<Self> {
+        let (from, to) = value.split_once('=')?;
+        (!from.is_empty() && !to.is_empty()).then(|| Self {
+            from: from.to_owned(),
+            to: to.to_owned(),
+        })
+    }
+}
+
+/// Parses a `major[.minor]` version, like `/VERSION` takes.
+pub fn parse_major_minor(value: &str) -> Option<(u16, u16)> {
+    let (major, minor) = value.split_once('.').unwrap_or((value, "0"));
+    Some((major.trim().parse().ok()?, minor.trim().parse().ok()?))
+}
+
+/// `/ALTERNATENAME:from=to`: resolves `from` to `to` if nothing defines it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlternateName {
+    pub from: String,
+    pub to: String,
+}
+
+impl AlternateName {
+    pub fn parse(value: &str) -> Option<Self> {
+        let (from, to) = value.split_once('=')?;
+        (!from.is_empty() && !to.is_empty()).then(|| Self {
+            from: from.to_owned(),
+            to: to.to_owned(),
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct LinkOptions {
+    /// Defaults to `mainCRTStartup`, `DriverEntry` for drivers, `efi_main`
+    /// for UEFI images or `_DllMainCRTStartup` for DLLs.
+    pub entry: Option<String>,
+    pub subsystem: Subsystem,
+    /// `/BASE`, defaulting to [`DEFAULT_IMAGE_BASE`].
+    pub image_base: Option<u64>,
+    /// `/FIXED`: leave out base relocations, so the image only loads at its base.
+    /// Implies `no_dynamic_base`.
+    pub fixed: bool,
+    /// `/FORCE:UNRESOLVED`: warn about unresolved symbols, which become zero.
+    pub force_unresolved: bool,
+    /// `/FORCE:MULTIPLE`: warn about duplicate symbols, keeping the first.
+    pub force_multiple: bool,
+    /// `/DYNAMICBASE:NO`: always load at the base instead of where ASLR puts it.
+    pub no_dynamic_base: bool,
+    /// `/HIGHENTROPYVA:NO`: only let ASLR pick addresses below 4G.
+    pub no_high_entropy_va: bool,
+    /// `/GUARD:CF`: emit Control Flow Guard tables, so that indirect calls
+    /// checked by `/guard:cf` code can only reach address-taken functions.
+    pub guard_cf: bool,
+    /// `/DEPENDENTLOADFLAG`: the `LOAD_LIBRARY_SEARCH_*` flags the loader
+    /// searches for the image's imports with.
+    pub dependent_load_flags: u16,
+    /// `/LARGEADDRESSAWARE:NO`: keep the process below 2G, for code that
+    /// truncates pointers. Also rules out high-entropy addresses.
+    pub no_large_address_aware: bool,
+    /// `/NXCOMPAT:NO`: let the image execute data pages, for code that
+    /// predates DEP.
+    pub no_nx_compat: bool,
+    /// `--startup-order`: input sections to place first in `.text` and `.rdata`.
+    pub startup_order: Option<StartupOrder>,
+    /// `/ORDER:@file`: functions whose COMDAT sections go in this order in
+    /// `.text`, after the startup order and before the other functions.
+    pub order: Vec<String>,
+    /// Resources for `.rsrc`, like an application manifest.
+    pub resources: Vec<Resource>,
+    /// Symbols to export, from `--export-list`.
+    pub exports: Option<ExportList>,
+    /// `/EXPORT` and the `EXPORTS` of `/DEF` files.
+    pub export_specs: Vec<ExportSpec>,
+    /// What to describe in the debug directory.
+    pub debug: DebugOptions,
+    /// The image's file name, recorded in its export directory.
+    pub image_name: Option<String>,
+    /// `/STUB`, from [`crate::pe::custom_dos_stub`], instead of [`MSDOS_STUB`].
+    pub dos_stub: Option<Vec<u8>>,
+    /// `/DLL`, or `LIBRARY` in a `/DEF` file: a DLL rather than an executable.
+    pub dll: bool,
+    /// `/DRIVER`: a kernel-mode driver. Its `INIT` section is discardable,
+    /// and it always gets a checksum, which the kernel checks.
+    pub driver: Option<Driver>,
+    /// `/INTEGRITYCHECK`: only load the image with a valid signature.
+    pub integrity_check: bool,
+    /// `/RELEASE`: write the image checksum, which only drivers need.
+    pub checksum: bool,
+    /// `--reserve-certificate`: zeroed space for an Authenticode signature at
+    /// the end of the image, so it can be signed without relinking.
+    pub certificate_reserve: Option<u32>,
+    /// `--rich-header`: an MSVC-style Rich header after the DOS program.
+    pub rich_header: Option<RichHeader>,
+    /// `--tiny`: the smallest image the loader accepts, with a bare DOS
+    /// header, no trailing empty data directories, and sections at the file
+    /// alignment, so they are mapped at their file offsets.
+    pub tiny: bool,
+    /// `/MERGE`, before those in `.drectve` sections.
+    pub merges: Vec<SectionMerge>,
+    /// `/ALTERNATENAME`, before those in `.drectve` sections.
+    pub alternate_names: Vec<AlternateName>,
+    /// `/SECTION`, applied in order to the merged output sections.
+    pub section_overrides: Vec<SectionOverride>,
+    /// `/ALIGN`: where sections are placed in memory, defaulting to a page.
+    pub section_alignment: Option<u32>,
+    /// `/FILEALIGN`: where sections are placed in the file, defaulting to 512.
+    pub file_alignment: Option<u32>,
+    /// `--data-padding`: the byte between the contributions of data sections.
+    /// Code sections are always padded with `int3`.
+    pub data_padding: u8,
+    /// `/STACK`, or the `STACKSIZE` of a `/DEF` file, defaulting to 1M
+    /// reserved and 4K committed like link.exe.
+    pub stack: Option<Reservation>,
+    /// `/HEAP`, or the `HEAPSIZE` of a `/DEF` file, with the same defaults.
+    pub heap: Option<Reservation>,
+    /// `/VERSION`, or the `VERSION` of a `/DEF` file, defaulting to 1.1.
+    pub image_version: Option<(u16, u16)>,
+    /// `/OSVERSION`: the Windows version the image needs, defaulting to 6.0,
+    /// Vista.
+    pub os_version: Option<(u16, u16)>,
+    /// The `,major.minor` of `/SUBSYSTEM`, with the same default.
+    pub subsystem_version: Option<(u16, u16)>,
+    /// Demangle symbol names in diagnostics.
+    pub demangle: bool,
+    /// `--trace-symbol`: symbols whose references, definitions and the archive
+    /// members extracted for them are logged under the `trace-symbol` target.
+    pub trace_symbols: Vec<String>,
+    /// Inserts stubs into code sections, see [`Instrument`].
+    pub instrument: Option<Box<dyn Instrument>>,
+    /// Re-parse the output and verify it against the link plan, with
+    /// [`Output::check`].
+    pub self_check: bool,
+    /// Checked throughout the link and while writing outputs.
+    pub cancel: CancellationToken,
+}
+
+impl LinkOptions {
+    fn characteristics(&self) -> Characteristics {
+        let mut characteristics = Characteristics::IMAGE_FILE_EXECUTABLE_IMAGE;
+        if self.fixed {
+            characteristics |= Characteristics::IMAGE_FILE_RELOCS_STRIPPED;
+        }
+        if !self.no_large_address_aware {
+            characteristics |= Characteristics::IMAGE_FILE_LARGE_ADDRESS_AWARE;
+        }
+        if self.driver == Some(Driver::UpOnly) {
+            characteristics |= Characteristics::IMAGE_FILE_UP_SYSTEM_ONLY;
+        }
+        if self.dll {
+            characteristics |= Characteristics::IMAGE_FILE_DLL;
+        }
+        characteristics
+    }
+
+    fn entry(&self) -> &str {
+        match (&self.entry, self.driver) {
+            (Some(entry), _) => entry,
+            (None, _) if self.subsystem.is_efi() => EFI_ENTRY,
+            (None, Some(_)) => DRIVER_ENTRY,
+            (None, None) if self.dll => DLL_ENTRY,
+            (None, None) => DEFAULT_ENTRY,
+        }
+    }
+
+    /// The section and file alignment.
+    fn alignments(&self) -> (u32, u32) {
+        let default = if self.tiny {
+            FILE_ALIGNMENT
+        } else {
+            SECTION_ALIGNMENT
+        };
+        (
+            self.section_alignment.unwrap_or(default),
+            self.file_alignment.unwrap_or(FILE_ALIGNMENT),
+        )
+    }
+
+    /// ASLR needs base relocations to move the image, and high-entropy
+    /// addresses only matter when it does.
+    fn dll_characteristics(&self) -> DllCharacteristics {
+        let mut characteristics = DllCharacteristics::empty();
+        if !self.no_nx_compat {
+            characteristics |= DllCharacteristics::IMAGE_DLLCHARACTERISTICS_NX_COMPAT;
+        }
+        if !self.fixed && !self.no_dynamic_base {
+            characteristics |= DllCharacteristics::IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE;
+            if !self.no_high_entropy_va && !self.no_large_address_aware {
+                characteristics |= DllCharacteristics::IMAGE_DLLCHARACTERISTICS_HIGH_ENTROPY_VA;
+            }
+        }
+        if self.driver == Some(Driver::Wdm) {
+            characteristics |= DllCharacteristics::IMAGE_DLLCHARACTERISTICS_WDM_DRIVER;
+        }
+        if self.integrity_check {
+            characteristics |= DllCharacteristics::IMAGE_DLLCHARACTERISTICS_FORCE_INTEGRITY;
+        }
+        characteristics
+    }
+}
+
+/// An archive member that was pulled into the link for an undefined symbol.
+#[derive(Debug, Clone)]
+pub struct Extraction {
+    /// As `archive(member)`.
+    pub member: String,
+    pub symbol: String,
+    /// The object that referenced the symbol first, or the option, like
+    /// `/ENTRY`.
+    pub referenced_by: String,
+}
+
+/// A global symbol in the cross-reference listing.
+#[derive(Debug, Clone)]
+pub struct CrossReference {
+    pub symbol: String,
+    /// The object, or DLL for imports, that defines the symbol.
+    pub defined_in: String,
+    pub referenced_by: Vec<String>,
+}
+
+/// A resolved and laid out link. Every offset in the image is known, so it
+/// can be streamed out section by section.
+pub struct Output<'a> {
+    context: LinkContext<'a>,
+    plan: LinkPlan,
+    timings: Timings,
+    write_time: SharedDuration,
+}
+
+impl<'a> Output<'a> {
+    /// All objects that went into the image, including archive members.
+    pub fn objects(&self) -> &[ObjectFile<'a>] {
+        &self.context.objects
+    }
+
+    /// The archive members that were extracted, and why.
+    pub fn extractions(&self) -> &[Extraction] {
+        &self.context.extractions
+    }
+
+    /// Every defined global symbol, by name, with where it's defined and the
+    /// objects that reference it, like `ld --cref`.
+    pub fn cross_references(&self) -> Vec<CrossReference> {
+        let context = &self.context;
+        let mut references = vec![Vec::new(); context.symbols.len()];
+        for (object_idx, object) in context.objects.iter().enumerate() {
+            for (idx, sym) in object.symbols.symbols() {
+                if sym.section_number == IMAGE_SYM_UNDEFINED
+                    && sym.value == 0
+                    && let Some(id) = context.symbol_ids[object_idx][idx as usize]
+                    && references[id.index()].last() != Some(&object_idx)
+                {
+                    references[id.index()].push(object_idx);
+                }
+            }
+        }
+        let mut cross_references = context
+            .symbols
+            .iter()
+            .zip(references)
+            .zip(0..)
+            .filter(|((sym, _), _)| sym.def != Definition::Undefined)
+            .map(|((sym, references), id)| CrossReference {
+                symbol: context.names.resolve(SymbolId::new(id)).to_owned(),
+                defined_in: context.definition_source(sym.def).to_owned(),
+                referenced_by: references
+                    .into_iter()
+                    .map(|object| context.objects[object].name.clone())
+                    .collect(),
+            })
+            .collect::<Vec<_>>();
+        cross_references.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        cross_references
+    }
+
+    /// Writes the image to `out`, holding at most one section in memory.
+    pub fn write(&self, out: &mut (impl Write + Seek)) -> Result<()> {
+        let start = Instant::now();
+        let result = self.write_checksummed(out);
+        self.write_time.add(start.elapsed());
+        result
+    }
+
+    fn write_checksummed(&self, out: &mut (impl Write + Seek)) -> Result<()> {
+        let options = self.context.options;
+        if !options.checksum && options.driver.is_none() {
+            return self.context.write_image(&self.plan, out);
+        }
+        // The checksum covers the whole file, so it's summed up while writing
+        // and patched in at the end.
+        let offset =
+            self.plan.dos_stub.len() + size_of::<CoffHeader>() + OptionalHeader::CHECKSUM_OFFSET;
+        let start = out.stream_position()?;
+        let mut writer = ChecksumWriter::new(&mut *out, offset);
+        self.context.write_image(&self.plan, &mut writer)?;
+        let sum = writer.checksum();
+        let end = out.stream_position()?;
+        out.seek(SeekFrom::Start(start + offset as u64))?;
+        out.write_all(&sum.to_le_bytes())?;
+        out.seek(SeekFrom::Start(end))?;
+        Ok(())
+    }
+
+    /// How long the phases of the link took so far.
+    pub fn timings(&self) -> Timings {
+        Timings {
+            relocation: self.context.relocation_time.get(),
+            write: self.write_time.get(),
+            ..self.timings
+        }
+    }
+
+    /// Writes the image into memory.
+    pub fn image(&self) -> Result<Vec<u8>> {
+        let mut out = io::Cursor::new(Vec::new());
+        self.write(&mut out)?;
+        Ok(out.into_inner())
+    }
+
+    /// Writes a PDB with the public symbols of the image, for the path and
+    /// build id in [`DebugOptions`].
+    pub fn write_pdb(&self, out: &mut impl Write) -> Result<()> {
+        self.context.options.cancel.check()?;
+        let publics = self.context.publics(&self.plan)?;
+        let modules = self.context.pdb_modules(&self.plan)?;
+        pdb::write_pdb(
+            out,
+            self.context.options.debug.build_id,
+            1,
+            &self.plan.section_headers()?,
+            &publics,
+            &modules,
+        )
+    }
+
+    pub fn summary(&self) -> Summary {
+        let options = self.context.options;
+        let dlls = self
+            .context
+            .imports
+            .iter()
+            .map(|import| import.dll.to_ascii_lowercase())
+            .collect::<HashSet<_>>();
+        Summary {
+            subsystem: options.subsystem,
+            size_of_image: self.plan.size_of_image,
+            entry: options.entry().to_owned(),
+            sections: self.plan.sections.len(),
+            imports: self.context.imports.len(),
+            import_dlls: dlls.len(),
+            characteristics: options.characteristics(),
+            dll_characteristics: self.plan.dll_characteristics,
+        }
+    }
+
+    /// Counts and memory use, without the peak RSS, which is the process's.
+    pub fn stats(&self) -> Stats {
+        let context = &self.context;
+        Stats {
+            objects: context.objects.len(),
+            archives: context.archives.len(),
+            input_sections: context
+                .objects
+                .iter()
+                .map(|object| object.sections.len())
+                .sum(),
+            output_sections: self.plan.sections.len(),
+            imports: context.imports.len(),
+            names: context.names.len(),
+            name_bytes: context.names.allocated_bytes(),
+            symbol_table_bytes: context
+                .objects
+                .iter()
+                .map(|object| object.symbols.allocated_bytes())
+                .sum(),
+            peak_rss: None,
+        }
+    }
+
+    /// An import library for the image's exports, or `None` without any.
+    pub fn import_library(&self) -> Result<Option<Vec<u8>>> {
+        let tables = &self.plan.export_tables;
+        if tables.is_empty() {
+            return Ok(None);
+        }
+        let entries = tables
+            .entries()
+            .zip(&self.plan.exports)
+            .filter(|((export, ..), _)| !export.private)
+            .map(|((export, ordinal, hint), id)| ImportLibraryEntry {
+                name: export.name.clone(),
+                ordinal,
+                hint: hint.unwrap_or(0),
+                noname: export.noname,
+                // Forwarded exports are assumed to be functions, like link.exe does.
+                is_code: !export.data
+                    && id.is_none_or(|id| {
+                        self.context
+                            .global_guard_target(id)
+                            .is_some_and(|target| self.context.is_code(target))
+                    }),
+            })
+            .collect::<Vec<_>>();
+        let dll = self
+            .context
+            .options
+            .image_name
+            .as_deref()
+            .unwrap_or_default();
+        write_import_library(dll, &entries).map(Some)
+    }
+
+    /// For `--self-check`: verifies an image written by [`Output::write`]
+    /// against the link plan.
+    pub fn check(&self, image: &[u8]) -> Result<()> {
+        self.plan.check(&ImageModel::parse(image)?)
+    }
+}
+
+pub fn link<'a>(
+    options: &'a LinkOptions,
+    inputs: &'a [Input],
+    diag: &mut Diagnostics<'_>,
+) -> Result<Output<'a>> {
+    if inputs.is_empty() {
+        return fatal(Code::InvalidOption, "no input files");
+    }
+    if let Some(base) = options.image_base
+        && (base == 0 || base % IMAGE_BASE_ALIGNMENT != 0)
+    {
+        return fatal(
+            Code::InvalidOption,
+            format!("/BASE:{base:#x} is not a non-zero multiple of 64K"),
+        );
+    }
+    check_alignments(options.alignments())?;
+
+    let mut timings = Timings::default();
+    let start = Instant::now();
+    let mut linker = LinkContext {
+        options,
+        objects: Vec::new(),
+        discarded: Vec::new(),
+        member_of: Vec::new(),
+        archives: Vec::new(),
+        loaded_members: HashSet::new(),
+        imports: Vec::new(),
+        symbols: Vec::new(),
+        names: Interner::new(),
+        symbol_ids: Vec::new(),
+        images: Vec::new(),
+        resources: options.resources.clone(),
+        rewrites: HashMap::new(),
+        pinned: HashMap::new(),
+        ordered: HashMap::new(),
+        guard_tables: Default::default(),
+        merges: Vec::new(),
+        alternate_names: Vec::new(),
+        mismatch_keys: HashMap::new(),
+        forced: false,
+        extractions: Vec::new(),
+        relocation_time: SharedDuration::default(),
+        crt_models: Vec::new(),
+    };
+
+    // Inputs are parsed in parallel, but merged in command-line order so that
+    // symbol resolution and the first error reported don't depend on timing.
+    let parsed = par_map(inputs, |input| {
+        options.cancel.check()?;
+        info!("loading {}", input.name);
+        parse_input(input.name.clone(), input.data())
+    });
+    for parsed in parsed {
+        linker.add_parsed(diag, parsed?, None)?;
+    }
+    options.cancel.check()?;
+    timings.parse = start.elapsed();
+
+    let start = Instant::now();
+    let entry = options.entry();
+    let entry_id = linker.intern(entry);
+    linker.reference(entry_id, None);
+    // Like the entry point, what `/EXPORT` names is looked for in libraries.
+    for spec in &options.export_specs {
+        if let ExportTarget::Symbol(name) = &spec.target {
+            linker.intern(name);
+        }
+    }
+    // Not referenced, but pulled in from the CRT if it's there, like link.exe does.
+    linker.intern(LOAD_CONFIG_SYMBOL);
+    linker.load_archive_members(diag)?;
+    // The targets of alternate names can pull in more members.
+    while linker.apply_alternate_names()? {
+        linker.load_archive_members(diag)?;
+    }
+    linker.resolve_decorated_exports();
+    linker.import_from_images();
+    linker.define_guard_symbols(entry_id)?;
+    linker.define_load_config();
+    linker.define_tls_directory();
+    linker.check_undefined(diag);
+    linker.pin_startup_sections(diag);
+    linker.order_functions(diag);
+    linker.check_unwind_info(diag)?;
+    diag.check()?;
+    options.cancel.check()?;
+    timings.resolve = start.elapsed();
+
+    let start = Instant::now();
+    if linker.forced {
+        diag.warn(
+            Code::ForcedOutput,
+            "image being generated due to /FORCE option; image may not run",
+        );
+    }
+    let plan = linker.plan(entry)?;
+    for section_override in &options.section_overrides {
+        if !plan
+            .sections
+            .iter()
+            .any(|section| section.name == section_override.name)
+        {
+            diag.warn(
+                Code::UnknownSection,
+                format_args!(
+                    "/SECTION: section {} does not exist; ignored",
+                    section_override.name
+                ),
+            );
+        }
+    }
+    linker.check_entry(&plan, entry_id, diag)?;
+    diag.check()?;
+    timings.layout = start.elapsed();
+    Ok(Output {
+        context: linker,
+        plan,
+        timings,
+        write_time: SharedDuration::default(),
+    })
+}
+
+struct LinkContext<'a> {
+    options: &'a LinkOptions,
+    objects: Vec<ObjectFile<'a>>,
+    /// Per object and section, whether it was discarded as a duplicate COMDAT.
+    discarded: Vec<Vec<bool>>,
+    /// Per object, the archive it was extracted from.
+    member_of: Vec<Option<usize>>,
+    archives: Vec<Archive<'a>>,
+    loaded_members: HashSet<(usize, u32)>,
+    imports: Vec<Import>,
+    /// EXE and DLL inputs, whose exports are imported on demand.
+    images: Vec<ImageExports<'a>>,
+    /// The resources for `.rsrc`, from the options and `.res` inputs.
+    resources: Vec<Resource>,
+    /// Indexed by [`SymbolId`].
+    symbols: Vec<GlobalSymbol>,
+    names: Interner,
+    /// Per object and symbol table index, the id of external symbols.
+    symbol_ids: Vec<Vec<Option<SymbolId>>>,
+    /// Input sections with instrumentation stubs, by object and section.
+    rewrites: HashMap<(usize, usize), SectionRewrite>,
+    /// Position in the startup order of pinned input sections, by object and section.
+    pinned: HashMap<(usize, usize), usize>,
+    /// Position in `/ORDER` of input sections, by object and section.
+    ordered: HashMap<(usize, usize), usize>,
+    /// The entries of each [`GuardTable`], by [`GuardTable::index`].
+    guard_tables: [Vec<GuardTarget>; 3],
+    /// The `/MERGE` directives of the objects' `.drectve` sections.
+    merges: Vec<SectionMerge>,
+    /// The `/ALTERNATENAME` directives of the objects' `.drectve` sections.
+    alternate_names: Vec<AlternateName>,
+    /// The first `/FAILIFMISMATCH` value of each key, with the object it's from.
+    mismatch_keys: HashMap<String, (String, String)>,
+    /// Whether `/FORCE` let an error through.
+    forced: bool,
+    /// The archive members that were extracted, in order.
+    extractions: Vec<Extraction>,
+    relocation_time: SharedDuration,
+    /// For each CRT that objects asked for, its compiler flag and the first
+    /// default library and object asking for it.
+    crt_models: Vec<(&'static str, String, String)>,
+}
+
+/// An address in a Control Flow Guard table, known before layout so the
+/// tables can be sized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum GuardTarget {
+    Section {
+        object: usize,
+        section: usize,
+        value: u32,
+    },
+    Thunk(usize),
+    IatSlot(usize),
+}
+
+/// Maps `items` in parallel, keeping the order, and with the caller's log
+/// subscriber also used on the worker threads.
+fn par_map<I, R>(items: I, f: impl Fn(I::Item) -> R + Sync + Send) -> Vec<R>
+where
+    I: IntoParallelIterator,
+    R: Send,
+{
+    let dispatch = tracing::dispatcher::get_default(Clone::clone);
+    items
+        .into_par_iter()
+        .map(|item| tracing::dispatcher::with_default(&dispatch, || f(item)))
+        .collect()
+}
+
+/// An input or archive member, parsed on its own before its symbols are merged.
+enum Parsed<'a> {
+    Archive(Archive<'a>),
+    Import(String, ImportObject<'a>),
+    /// An EXE or DLL linked against directly, as if through an import library.
+    Image(String, ImageExports<'a>),
+    /// A compiled `.res` file, whose resources go into `.rsrc`.
+    Resources(String, Vec<Resource>),
+    Object(ObjectFile<'a>),
+}
+
+fn parse_input(name: String, data: &[u8]) -> Result<Parsed<'_>> {
+    check_input_format(data, &name)?;
+    if data.starts_with(ARCHIVE_MAGIC) {
+        Ok(Parsed::Archive(Archive::parse(name, data)?))
+    } else if is_import_object(data) {
+        let import = ImportObject::parse(data)?;
+        Ok(Parsed::Import(name, import))
+    } else if is_res(data) {
+        let resources = parse_res(data)?;
+        Ok(Parsed::Resources(name, resources))
+    } else if data.starts_with(DOS_MAGIC) {
+        let image = ImageModel::parse(data)?;
+        check_machine(image.header.machine, &name)?;
+        let Some(exports) = image.exports() else {
+            return fatal(
+                Code::UnsupportedImage,
+                format!("{name}: image has no export directory to link against"),
+            );
+        };
+        Ok(Parsed::Image(name, exports))
+    } else {
+        Ok(Parsed::Object(ObjectFile::parse(name, data)?))
+    }
+}
+
+impl<'a> LinkContext<'a> {
+    fn add_parsed(
+        &mut self,
+        diag: &mut Diagnostics<'_>,
+        parsed: Parsed<'a>,
+        archive: Option<usize>,
+    ) -> Result<()> {
+        match parsed {
+            Parsed::Archive(archive) => {
+                self.archives.push(archive);
+                Ok(())
+            }
+            Parsed::Import(name, import) => self.add_import(&name, import),
+            Parsed::Image(name, image) => {
+                debug!("{name}: {} exports of {}", image.exports.len(), image.dll);
+                self.images.push(image);
+                Ok(())
+            }
+            Parsed::Resources(name, resources) => {
+                debug!("{name}: {} resources", resources.len());
+                for resource in resources {
+                    if self.resources.iter().any(|other| other.same_key(&resource)) {
+                        diag.error(
+                            Code::DuplicateResource,
+                            format!(
+                                "duplicate resource in {name}: type {}, name {}, language {:#x}",
+                                resource.r#type, resource.name, resource.language
+                            ),
+                        );
+                    } else {
+                        self.resources.push(resource);
+                    }
+                }
+                Ok(())
+            }
+            Parsed::Object(object) => self.add_object(diag, object, archive),
+        }
+    }
+
+    /// Repeatedly searches each archive in order for members defining currently
+    /// undefined symbols, until the archive has nothing more to offer.
+    /// Sanitizer runtimes are searched first, see [`sanitizer`].
+    fn load_archive_members(&mut self, diag: &mut Diagnostics<'_>) -> Result<()> {
+        let (runtimes, others) = (0..self.archives.len())
+            .partition::<Vec<_>, _>(|&idx| sanitizer::is_runtime(&self.archives[idx].name));
+        let order = runtimes.into_iter().chain(others).collect::<Vec<_>>();
+        // Members of later archives can need ones of earlier archives, so
+        // archives are scanned until none has anything left to contribute,
+        // like `--start-group` around all of them.
+        let mut loaded = true;
+        while loaded {
+            loaded = false;
+            for &archive_idx in &order {
+                loop {
+                    let mut wanted = self.archives[archive_idx]
+                        .symbols
+                        .iter()
+                        .filter(|(name, offset)| {
+                            let wanted = !self.loaded_members.contains(&(archive_idx, *offset))
+                                && self.names.get(name).is_some_and(|id| {
+                                    matches!(self.symbols[id.index()].def, Definition::Undefined)
+                                });
+                            if wanted && self.is_traced(name) {
+                                let archive = &self.archives[archive_idx];
+                                info!(
+                                    target: "trace-symbol",
+                                    "{}({}) is extracted for {name}",
+                                    archive.name,
+                                    archive.member_at(*offset).map_or("?", |member| member.name)
+                                );
+                            }
+                            wanted
+                        })
+                        .map(|&(name, offset)| (offset, name))
+                        .collect::<Vec<_>>();
+                    // A member defining several wanted symbols is extracted
+                    // for the first.
+                    wanted.retain(|&(offset, _)| self.loaded_members.insert((archive_idx, offset)));
+                    if wanted.is_empty() {
+                        break;
+                    }
+                    loaded = true;
+
+                    let archive = &self.archives[archive_idx];
+                    for &(offset, symbol) in &wanted {
+                        let id = self.names.get(symbol).unwrap();
+                        self.extractions.push(Extraction {
+                            member: format!(
+                                "{}({})",
+                                archive.name,
+                                archive.member_at(offset)?.name
+                            ),
+                            symbol: symbol.to_owned(),
+                            referenced_by: self.referenced_by(id).to_owned(),
+                        });
+                    }
+                    let parsed = par_map(&wanted, |&(offset, _)| {
+                        self.options.cancel.check()?;
+                        let member = archive.member_at(offset)?;
+                        let name = format!("{}({})", archive.name, member.name);
+                        info!(target: "archives", "extracting {name}");
+                        match parse_input(name, member.data)? {
+                            Parsed::Archive(archive) => fatal(
+                                Code::CorruptInput,
+                                format!("{} is a nested archive", archive.name),
+                            ),
+                            parsed => Ok(parsed),
+                        }
+                    });
+                    for parsed in parsed {
+                        self.add_parsed(diag, parsed?, Some(archive_idx))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Imports the exports of image inputs that are still undefined, after
+    /// everything else had a chance to define them. Like archive members,
+    /// only what's referenced ends up in the import table.
+    fn import_from_images(&mut self) {
+        let is_undefined = |this: &Self, name: &str| {
+            this.names
+                .get(name)
+                .is_some_and(|id| matches!(this.symbols[id.index()].def, Definition::Undefined))
+        };
+        let images = std::mem::take(&mut self.images);
+        for image in &images {
+            // The hint is the index in the export name table.
+            for (idx, (export_name, export)) in image.named().enumerate() {
+                if !self.is_imported(export_name)
+                    && (is_undefined(self, export_name)
+                        || is_undefined(self, &format!("__imp_{export_name}")))
+                {
+                    info!("importing {export_name} from {}", image.dll);
+                    let name = ImportName::Name {
+                        name: export_name.to_owned(),
+                        hint: u16::try_from(idx).unwrap_or(0),
+                    };
+                    self.define_import(export_name, image.dll, name, export.is_code);
+                }
+            }
+        }
+        self.images = images;
+    }
+
+    fn intern(&mut self, name: &str) -> SymbolId {
+        let id = self.names.intern(name);
+        if id.index() == self.symbols.len() {
+            self.symbols.push(GlobalSymbol {
+                def: Definition::Undefined,
+                referenced_by: None,
+            });
+        }
+        id
+    }
+
+    /// Resolves the alternate names whose symbol is still undefined, and
+    /// returns whether there were any.
+    fn apply_alternate_names(&mut self) -> Result<bool> {
+        let alternate_names = self
+            .options
+            .alternate_names
+            .iter()
+            .chain(&self.alternate_names)
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut applied = false;
+        for (idx, alternate) in alternate_names.iter().enumerate() {
+            if let Some(other) = alternate_names[..idx]
+                .iter()
+                .find(|other| other.from == alternate.from && other.to != alternate.to)
+            {
+                return fatal(
+                    Code::InvalidOption,
+                    format!(
+                        "/ALTERNATENAME:{}={} conflicts with /ALTERNATENAME:{}={}",
+                        alternate.from, alternate.to, other.from, other.to
+                    ),
+                );
+            }
+            let Some(from) = self.names.get(&alternate.from) else {
+                continue;
+            };
+            if !matches!(self.symbols[from.index()].def, Definition::Undefined) {
+                continue;
+            }
+            let to = self.intern(&alternate.to);
+            // A cycle of alternate names leaves the symbol undefined.
+            let mut target = to;
+            while let Definition::Alias(next) = self.symbols[target.index()].def {
+                target = next;
+            }
+            if target == from {
+                continue;
+            }
+            self.reference(to, self.symbols[from.index()].referenced_by);
+            self.symbols[from.index()].def = Definition::Alias(to);
+            applied = true;
+        }
+        Ok(applied)
+    }
+
+    /// Binds export targets that aren't defined to the symbol that is, under
+    /// another 32-bit x86 calling convention decoration, so that
+    /// `/EXPORT:foo` exports `_foo@8` like link.exe does. Ambiguous names are
+    /// left for [`Self::check_undefined`] to report.
+    fn resolve_decorated_exports(&mut self) {
+        let undefined = self
+            .options
+            .export_specs
+            .iter()
+            .filter_map(|spec| match &spec.target {
+                ExportTarget::Symbol(name) => self.names.get(name),
+                ExportTarget::Forwarder(_) => None,
+            })
+            .filter(|id| matches!(self.symbols[id.index()].def, Definition::Undefined))
+            .collect::<Vec<_>>();
+        if undefined.is_empty() {
+            return;
+        }
+        let definitions = self.definitions_by_undecorated_name();
+        let aliases = undefined
+            .into_iter()
+            .filter_map(|from| {
+                let name = demangle::undecorated(self.names.resolve(from));
+                Some((from, (*definitions.get(name)?)?))
+            })
+            .collect::<Vec<_>>();
+        for (from, to) in aliases {
+            // An alternate name for the export target mustn't make a cycle.
+            let mut target = to;
+            while let Definition::Alias(next) = self.symbols[target.index()].def {
+                target = next;
+            }
+            if target != from {
+                self.symbols[from.index()].def = Definition::Alias(to);
+            }
+        }
+    }
+
+    /// Defined symbols by their name without 32-bit x86 calling convention
+    /// decoration, or `None` for names that several of them have.
+    fn definitions_by_undecorated_name(&self) -> HashMap<&str, Option<SymbolId>> {
+        let mut definitions = HashMap::new();
+        for (sym, id) in self.symbols.iter().zip(0..) {
+            if matches!(sym.def, Definition::Undefined) {
+                continue;
+            }
+            let id = SymbolId::new(id);
+            definitions
+                .entry(demangle::undecorated(self.names.resolve(id)))
+                .and_modify(|other| *other = None)
+                .or_insert(Some(id));
+        }
+        definitions
+    }
+
+    /// What first referenced a symbol, for diagnostics.
+    fn referenced_by(&self, id: SymbolId) -> &str {
+        let name = self.names.resolve(id);
+        match self.symbols[id.index()].referenced_by {
+            Some(object) => &self.objects[object].name,
+            None if self.options.export_specs.iter().any(
+                |spec| matches!(&spec.target, ExportTarget::Symbol(target) if target == name),
+            ) =>
+            {
+                "/EXPORT"
+            }
+            None if name == self.options.entry() => "/ENTRY",
+            None => "<linker>",
+        }
+    }
+
+    fn is_traced(&self, name: &str) -> bool {
+        self.options
+            .trace_symbols
+            .iter()
+            .any(|traced| traced == name)
+    }
+
+    fn reference(&mut self, id: SymbolId, object: Option<usize>) {
+        let name = self.names.resolve(id);
+        if self.is_traced(name) {
+            let source = object.map_or("<linker>", |object| &self.objects[object].name);
+            info!(target: "trace-symbol", "{source} references {name}");
+        }
+        let sym = &mut self.symbols[id.index()];
+        if sym.referenced_by.is_none() {
+            sym.referenced_by = object;
+        }
+    }
+
+    fn is_comdat(&self, def: Definition) -> bool {
+        match def {
+            Definition::Section {
+                object, section, ..
+            } => self.objects[object].sections[section]
+                .characteristics
+                .contains(SectionFlags::IMAGE_SCN_LNK_COMDAT),
+            _ => false,
+        }
+    }
+
+    fn define(&mut self, diag: &mut Diagnostics<'_>, id: SymbolId, def: Definition) {
+        let existing = self.symbols[id.index()].def;
+        let new = match (existing, def) {
+            (Definition::Undefined | Definition::Alias(_), _) => def,
+            (Definition::Weak { .. }, Definition::Weak { .. }) => existing,
+            (Definition::Weak { .. }, _) => def,
+            (_, Definition::Weak { .. }) => existing,
+            (Definition::Common { size: a }, Definition::Common { size: b }) => {
+                Definition::Common { size: a.max(b) }
+            }
+            (Definition::Common { .. }, _) => def,
+            (_, Definition::Common { .. }) => existing,
+            (Definition::ImportPointer(_) | Definition::Import(_), _)
+                if matches!(def, Definition::ImportPointer(_) | Definition::Import(_)) =>
+            {
+                existing
+            }
+            _ if self.is_comdat(existing) && self.is_comdat(def) => existing,
+            _ if self.overrides(def, existing) => {
+                debug!(
+                    "{} from {} overrides the one in {}",
+                    self.names.resolve(id),
+                    self.definition_source(def),
+                    self.definition_source(existing)
+                );
+                def
+            }
+            _ if self.overrides(existing, def) => existing,
+            _ => {
+                let message = format!(
+                    "{} already defined in {}; second definition in {}",
+                    demangle::symbol_name(self.names.resolve(id), self.options.demangle),
+                    self.definition_source(existing),
+                    self.definition_source(def),
+                );
+                if self.options.force_multiple {
+                    diag.warn(
+                        Code::DuplicateSymbolIgnored,
+                        format_args!("{message}; ignored"),
+                    );
+                    self.forced = true;
+                } else {
+                    diag.error(Code::DuplicateSymbol, message);
+                }
+                existing
+            }
+        };
+        let name = self.names.resolve(id);
+        if self.is_traced(name) {
+            let source = self.definition_source(def);
+            match existing {
+                Definition::Undefined => {
+                    info!(target: "trace-symbol", "{source} defines {name}");
+                }
+                _ if new == existing => info!(
+                    target: "trace-symbol",
+                    "{source} defines {name}, ignored for the one in {}",
+                    self.definition_source(existing)
+                ),
+                _ => info!(
+                    target: "trace-symbol",
+                    "{source} defines {name}, replacing the one in {}",
+                    self.definition_source(existing)
+                ),
+            }
+        }
+        self.symbols[id.index()].def = new;
+    }
+
+    /// Whether `def` is from a sanitizer runtime and replaces `other` from a
+    /// member of another library, like the CRT function it intercepts.
+    fn overrides(&self, def: Definition, other: Definition) -> bool {
+        let is_runtime = |def| match def {
+            Definition::Section { object, .. } => self.member_of[object]
+                .map(|archive| sanitizer::is_runtime(&self.archives[archive].name)),
+            _ => None,
+        };
+        is_runtime(def) == Some(true) && is_runtime(other) == Some(false)
+    }
+
+    fn definition_source(&self, def: Definition) -> &str {
+        match def {
+            Definition::Weak { object, .. } | Definition::Section { object, .. } => {
+                &self.objects[object].name
+            }
+            Definition::Import(import) | Definition::ImportPointer(import) => {
+                &self.imports[import].dll
+            }
+            _ => "<linker>",
+        }
+    }
+
+    /// Whether the `__imp_` pointer of `symbol` is already defined.
+    fn is_imported(&self, symbol: &str) -> bool {
+        self.names
+            .get(&format!("__imp_{symbol}"))
+            .is_some_and(|id| !matches!(self.symbols[id.index()].def, Definition::Undefined))
+    }
+
+    fn add_import(&mut self, source: &str, import: ImportObject) -> Result<()> {
+        check_machine(import.machine, source)?;
+        if self.is_imported(import.symbol) {
+            return Ok(());
+        }
+        // For imports by name, the ordinal field is the hint.
+        let name = match import.import_name() {
+            Some(name) => ImportName::Name {
+                name: name.to_owned(),
+                hint: import.ordinal_or_hint,
+            },
+            None => ImportName::Ordinal(import.ordinal_or_hint),
+        };
+        self.define_import(
+            import.symbol,
+            import.dll,
+            name,
+            import.import_type == IMPORT_OBJECT_CODE,
+        );
+        Ok(())
+    }
+
+    /// Defines the `__imp_` pointer for `symbol`, and for code also a thunk
+    /// under the symbol's own name, unless something else defines it.
+    fn define_import(&mut self, symbol: &str, dll: &str, name: ImportName, is_code: bool) {
+        let idx = self.imports.len();
+        self.imports.push(Import {
+            dll: dll.to_owned(),
+            name,
+            is_code,
+        });
+
+        let id = self.intern(&format!("__imp_{symbol}"));
+        self.symbols[id.index()].def = Definition::ImportPointer(idx);
+        if is_code {
+            let id = self.intern(symbol);
+            if matches!(self.symbols[id.index()].def, Definition::Undefined) {
+                self.symbols[id.index()].def = Definition::Import(idx);
+            }
+        }
+    }
+
+    fn add_object(
+        &mut self,
+        diag: &mut Diagnostics<'_>,
+        object: ObjectFile<'a>,
+        archive: Option<usize>,
+    ) -> Result<()> {
+        // Only for the log, so the symbol table isn't walked otherwise, and
+        // names that don't decode are reported when they're used instead.
+        if tracing::enabled!(target: "symbols", Level::TRACE) {
+            for record in object.symbols.iter() {
+                match record {
+                    SymbolRecord::Symbol(sym) => {
+                        let name = object.symbols.name(sym).unwrap_or("<unreadable name>");
+                        trace!(
+                            target: "symbols",
+                            "{: <20} value={:#x} section={} type={:#x} class={} aux={}",
+                            demangle::symbol_name(name, self.options.demangle),
+                            sym.value,
+                            sym.section_number,
+                            sym.r#type,
+                            sym.storage_class,
+                            sym.number_of_aux_symbols
+                        );
+                    }
+                    SymbolRecord::Aux(aux) => trace!(target: "symbols", "{:20} aux {aux:x?}", ""),
+                }
+            }
+        }
+
+        let object_idx = self.objects.len();
+        let discarded = self.discarded_comdats(&object)?;
+        let rewrites = self.instrument(&object, &discarded)?;
+
+        let mut definitions = Vec::new();
+        let mut references = Vec::new();
+        let mut ids = object.symbols.iter().map(|_| None).collect::<Vec<_>>();
+        for (idx, sym) in object.symbols.symbols() {
+            if sym.storage_class != IMAGE_SYM_CLASS_EXTERNAL
+                && sym.storage_class != IMAGE_SYM_CLASS_WEAK_EXTERNAL
+            {
+                continue;
+            }
+            let name = object.symbols.name(sym)?;
+            let id = self.intern(name);
+            ids[idx as usize] = Some(id);
+            let def = match sym.section_number {
+                IMAGE_SYM_UNDEFINED if sym.storage_class == IMAGE_SYM_CLASS_WEAK_EXTERNAL => {
+                    Definition::Weak {
+                        object: object_idx,
+                        symbol: idx,
+                    }
+                }
+                IMAGE_SYM_UNDEFINED if sym.value > 0 => Definition::Common { size: sym.value },
+                IMAGE_SYM_UNDEFINED => {
+                    references.push(id);
+                    continue;
+                }
+                IMAGE_SYM_ABSOLUTE => Definition::Absolute(sym.value),
+                IMAGE_SYM_DEBUG => continue,
+                number => {
+                    let section = usize::from(number) - 1;
+                    if section >= object.sections.len() {
+                        return fatal(
+                            Code::CorruptInput,
+                            format!("{}: symbol {name} has invalid section number", object.name),
+                        );
+                    }
+                    if discarded[section] {
+                        continue;
+                    }
+                    Definition::Section {
+                        object: object_idx,
+                        section,
+                        value: sym.value,
+                    }
+                }
+            };
+            definitions.push((id, def));
+        }
+
+        for (name, value) in directives(&object) {
+            match name.as_str() {
+                "merge" => self.merges.extend(SectionMerge::parse(&value)),
+                "alternatename" => self.alternate_names.extend(AlternateName::parse(&value)),
+                "defaultlib" => {
+                    let Some(model) = crt_model(&value) else {
+                        continue;
+                    };
+                    if self.crt_models.iter().any(|&(other, ..)| other == model) {
+                        continue;
+                    }
+                    if let Some((first, library, first_object)) = self.crt_models.first() {
+                        diag.warn(
+                            Code::DefaultLibConflict,
+                            format_args!(
+                                "{} uses the {model} CRT ({value}), but {first_object} uses the {first} CRT ({library}); build them with the same /MT, /MTd, /MD or /MDd",
+                                object.name
+                            ),
+                        );
+                    }
+                    self.crt_models.push((model, value, object.name.clone()));
+                }
+                "failifmismatch" => {
+                    let Some((key, value)) = value.split_once('=') else {
+                        continue;
+                    };
+                    let (expected, first) = self
+                        .mismatch_keys
+                        .entry(key.to_owned())
+                        .or_insert_with(|| (value.to_owned(), object.name.clone()));
+                    if expected != value {
+                        diag.error(
+                            Code::MismatchDetected,
+                            format_args!(
+                                "mismatch detected for '{key}': value '{expected}' in {first} doesn't match value '{value}' in {}",
+                                object.name
+                            ),
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.objects.push(object);
+        self.discarded.push(discarded);
+        self.member_of.push(archive);
+        self.symbol_ids.push(ids);
+        for id in references {
+            self.reference(id, Some(object_idx));
+        }
+        for (id, def) in definitions {
+            self.define(diag, id, def);
+        }
+        for (section, mut rewrite) in rewrites {
+            for stub in &rewrite.stubs {
+                for reloc in &stub.relocations {
+                    let id = self.intern(&reloc.symbol);
+                    self.reference(id, Some(object_idx));
+                    rewrite.symbols.push(id);
+                }
+            }
+            self.rewrites.insert((object_idx, section), rewrite);
+        }
+        Ok(())
+    }
+
+    /// Runs the instrumentation hook on the code sections of `object` that
+    /// are linked in.
+    fn instrument(
+        &self,
+        object: &ObjectFile<'_>,
+        discarded: &[bool],
+    ) -> Result<Vec<(usize, SectionRewrite)>> {
+        let Some(hook) = &self.options.instrument else {
+            return Ok(Vec::new());
+        };
+        let mut rewrites = Vec::new();
+        for (idx, section) in object.sections.iter().enumerate() {
+            let data = object.section_data(idx);
+            if discarded[idx]
+                || is_discarded_input(section)
+                || !section
+                    .characteristics
+                    .contains(SectionFlags::IMAGE_SCN_CNT_CODE)
+                || data.is_empty()
+            {
+                continue;
+            }
+            let mut symbols = Vec::new();
+            for (_, sym) in object.symbols.symbols() {
+                let name = object.symbols.name(sym)?;
+                let is_section_symbol = sym.storage_class != IMAGE_SYM_CLASS_EXTERNAL
+                    && sym.number_of_aux_symbols > 0
+                    && name == section.name;
+                if usize::from(sym.section_number) == idx + 1 && !is_section_symbol {
+                    symbols.push(SectionSymbol {
+                        name,
+                        offset: sym.value,
+                        is_function: (sym.r#type >> 4) & 3 == IMAGE_SYM_DTYPE_FUNCTION,
+                    });
+                }
+            }
+            let stubs = hook.instrument(&CodeSection {
+                object: &object.name,
+                name: &section.name,
+                data,
+                symbols,
+            });
+            if !stubs.is_empty() {
+                let rewrite = SectionRewrite::new(&object.name, &section.name, data.len(), stubs)?;
+                rewrites.push((idx, rewrite));
+            }
+        }
+        Ok(rewrites)
+    }
+
+    /// The size of an input section, including instrumentation stubs.
+    fn input_size(&self, object: usize, section: usize) -> u32 {
+        let size = self.objects[object].section_size(section);
+        match self.rewrites.get(&(object, section)) {
+            Some(rewrite) => size + rewrite.inserted_size(),
+            None => size,
+        }
+    }
+
+    /// Finds the COMDAT sections of `object` whose leader symbol is already
+    /// defined, and the associative sections that go away with them.
+    fn discarded_comdats(&self, object: &ObjectFile<'_>) -> Result<Vec<bool>> {
+        let mut discarded = vec![false; object.sections.len()];
+        let mut associated = vec![None; object.sections.len()];
+        let mut seen_section_symbol = vec![false; object.sections.len()];
+        let mut seen_leader = vec![false; object.sections.len()];
+
+        for (idx, sym) in object.symbols.symbols() {
+            let number = usize::from(sym.section_number);
+            if number == 0 || number > object.sections.len() {
+                continue;
+            }
+            let section = number - 1;
+            if !object.sections[section]
+                .characteristics
+                .contains(SectionFlags::IMAGE_SCN_LNK_COMDAT)
+            {
+                continue;
+            }
+
+            if !seen_section_symbol[section] {
+                seen_section_symbol[section] = true;
+                if let Some(def) = object.symbols.section_definition(idx)
+                    && def.selection == IMAGE_COMDAT_SELECT_ASSOCIATIVE
+                {
+                    associated[section] = Some(usize::from(def.number));
+                    seen_leader[section] = true;
+                }
+                continue;
+            }
+            if seen_leader[section] {
+                continue;
+            }
+            seen_leader[section] = true;
+
+            if sym.storage_class == IMAGE_SYM_CLASS_EXTERNAL {
+                let name = object.symbols.name(sym)?;
+                discarded[section] = self.names.get(name).is_some_and(|id| {
+                    matches!(
+                        self.symbols[id.index()].def,
+                        Definition::Section { .. } | Definition::Absolute(_)
+                    )
+                });
+            }
+        }
+
+        for section in 0..discarded.len() {
+            if let Some(number) = associated[section]
+                && number >= 1
+                && number <= discarded.len()
+            {
+                discarded[section] = discarded[number - 1];
+            }
+        }
+
+        Ok(discarded)
+    }
+
+    /// Pins the sections named by the startup order, warning about entries
+    /// that don't resolve to a `.text` or `.rdata` input section.
+    fn pin_startup_sections(&mut self, diag: &mut Diagnostics<'_>) {
+        let Some(order) = &self.options.startup_order else {
+            return;
+        };
+        for entry in &order.entries {
+            let (description, found) = match entry {
+                OrderEntry::Symbol(name) => {
+                    let def = self.names.get(name).map(|id| self.symbols[id.index()].def);
+                    let found = match def {
+                        Some(Definition::Section {
+                            object, section, ..
+                        }) => vec![(object, section)],
+                        _ => Vec::new(),
+                    };
+                    (format!("symbol {name}"), found)
+                }
+                OrderEntry::Section { object, section } => {
+                    let found = self
+                        .objects
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, candidate)| object_matches(&candidate.name, object))
+                        .flat_map(|(object_idx, candidate)| {
+                            candidate
+                                .sections
+                                .iter()
+                                .enumerate()
+                                .filter(|(_, header)| header.name == section.as_str())
+                                .map(move |(idx, _)| (object_idx, idx))
+                        })
+                        .filter(|&(object, idx)| !self.discarded[object][idx])
+                        .collect();
+                    (format!("section {section} of {object}"), found)
+                }
+            };
+            if found.is_empty() {
+                diag.warn(
+                    Code::StartupOrderIgnored,
+                    format_args!("startup order {description} does not exist; ignored"),
+                );
+                continue;
+            }
+            for (object, section) in found {
+                let name = &self.objects[object].sections[section].name;
+                if !matches!(output_section_name(name), ".text" | ".rdata") {
+                    diag.warn(
+                        Code::StartupOrderIgnored,
+                        format_args!(
+                            "startup order {description} is in {name}, not .text or .rdata; ignored"
+                        ),
+                    );
+                    continue;
+                }
+                let rank = self.pinned.len();
+                self.pinned.entry((object, section)).or_insert(rank);
+            }
+        }
+    }
+
+    /// Orders the sections of the functions in `/ORDER`, which have to be
+    /// COMDATs in `.text` so that they can be moved independently.
+    fn order_functions(&mut self, diag: &mut Diagnostics<'_>) {
+        for name in &self.options.order {
+            let def = self.names.get(name).map(|id| self.symbols[id.index()].def);
+            let Some(Definition::Section {
+                object, section, ..
+            }) = def
+            else {
+                diag.warn(
+                    Code::StartupOrderIgnored,
+                    format_args!("/ORDER symbol {name} does not exist; ignored"),
+                );
+                continue;
+            };
+            let header = &self.objects[object].sections[section];
+            if !header
+                .characteristics
+                .contains(SectionFlags::IMAGE_SCN_LNK_COMDAT)
+                || output_section_name(&header.name) != ".text"
+            {
+                diag.warn(
+                    Code::StartupOrderIgnored,
+                    format_args!("/ORDER symbol {name} is not a COMDAT function; ignored"),
+                );
+                continue;
+            }
+            let rank = self.ordered.len();
+            self.ordered.entry((object, section)).or_insert(rank);
+        }
+    }
+
+    /// Catches `/ENTRY` naming data, or a section merge that made the code
+    /// around the entry point non-executable or writable.
+    fn check_entry(
+        &self,
+        plan: &LinkPlan,
+        entry: SymbolId,
+        diag: &mut Diagnostics<'_>,
+    ) -> Result<()> {
+        let name = demangle::symbol_name(self.names.resolve(entry), self.options.demangle);
+        let Some(out) = self.global_target(plan, entry)?.section else {
+            diag.error(
+                Code::InvalidEntryPoint,
+                format_args!("entry point {name} is not in a section"),
+            );
+            return Ok(());
+        };
+        let section = &plan.sections[out];
+        if !section
+            .characteristics
+            .contains(SectionFlags::IMAGE_SCN_MEM_EXECUTE)
+        {
+            diag.error(
+                Code::InvalidEntryPoint,
+                format_args!(
+                    "entry point {name} is in {}, which is not executable",
+                    section.name
+                ),
+            );
+        } else if section
+            .characteristics
+            .contains(SectionFlags::IMAGE_SCN_MEM_WRITE)
+        {
+            diag.warn(
+                Code::WritableEntryPoint,
+                format_args!(
+                    "entry point {name} is in {}, which is writable",
+                    section.name
+                ),
+            );
+        }
+        Ok(())
+    }
+
+    /// The exports sorted by name, with the symbol they refer to unless
+    /// they're forwarded. `/EXPORT` wins over the export list for a name.
+    fn exports(&self) -> Vec<(ExportEntry, Option<SymbolId>)> {
+        let mut exports = Vec::new();
+        for spec in &self.options.export_specs {
+            let (forwarder, id) = match &spec.target {
+                ExportTarget::Symbol(name) => (None, self.names.get(name)),
+                ExportTarget::Forwarder(forwarder) => (Some(forwarder.clone()), None),
+            };
+            let entry = ExportEntry {
+                name: spec.name.clone(),
+                forwarder,
+                ordinal: spec.ordinal,
+                noname: spec.noname,
+                data: spec.data,
+                private: spec.private,
+            };
+            exports.push((entry, id));
+        }
+        if let Some(list) = &self.options.exports {
+            for (sym, id) in self.symbols.iter().zip(0..) {
+                let id = SymbolId::new(id);
+                let name = self.names.resolve(id);
+                if matches!(sym.def, Definition::Section { .. }) && list.is_exported(name) {
+                    let entry = ExportEntry {
+                        name: name.to_owned(),
+                        forwarder: None,
+                        ordinal: None,
+                        noname: false,
+                        data: false,
+                        private: false,
+                    };
+                    exports.push((entry, Some(id)));
+                }
+            }
+        }
+        exports.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+        exports.dedup_by(|(a, _), (b, _)| a.name == b.name);
+        exports
+    }
+
+    fn exported_symbols(&self) -> Vec<SymbolId> {
+        self.exports()
+            .into_iter()
+            .filter_map(|(_, id)| id)
+            .collect()
+    }
+
+    /// Collects the Control Flow Guard tables with `/GUARD:CF`, and defines
+    /// the `__guard_*` symbols the CRT's load configuration refers to, as
+    /// zero without it.
+    fn define_guard_symbols(&mut self, entry: SymbolId) -> Result<()> {
+        if self.options.guard_cf {
+            self.collect_guard_tables(entry)?;
+        }
+        let mut definitions = vec![(
+            GUARD_FLAGS_SYMBOL,
+            Definition::Absolute(if self.options.guard_cf {
+                GUARD_FLAGS
+            } else {
+                0
+            }),
+        )];
+        for table in GuardTable::ALL {
+            let entries = &self.guard_tables[table.index()];
+            let start = if entries.is_empty() {
+                Definition::Absolute(0)
+            } else {
+                Definition::GuardTable(table)
+            };
+            definitions.push((table.table_symbol(), start));
+            definitions.push((
+                table.count_symbol(),
+                Definition::Absolute(entries.len() as u32),
+            ));
+        }
+        for name in UNUSED_LOAD_CONFIG_SYMBOLS {
+            definitions.push((name, Definition::Absolute(0)));
+        }
+        for (name, def) in definitions {
+            if let Some(id) = self.names.get(name)
+                && matches!(self.symbols[id.index()].def, Definition::Undefined)
+            {
+                self.symbols[id.index()].def = def;
+            }
+        }
+        Ok(())
+    }
+
+    /// Objects compiled with `/guard:cf` list their address-taken functions,
+    /// IAT slots and `longjmp` targets. For other objects, like assembly,
+    /// every function they refer to might be called indirectly. The entry
+    /// point and exports are called from outside.
+    fn collect_guard_tables(&mut self, entry: SymbolId) -> Result<()> {
+        let mut tables: [BTreeSet<GuardTarget>; 3] = Default::default();
+        for (object_idx, object) in self.objects.iter().enumerate() {
+            let instrumented = object.symbols.symbols().any(|(_, sym)| {
+                sym.name == FEAT_SYMBOL.as_bytes()
+                    && sym.section_number == IMAGE_SYM_ABSOLUTE
+                    && sym.value & FEAT_GUARD_CF != 0
+            });
+            for (idx, section) in object.sections.iter().enumerate() {
+                if self.discarded[object_idx][idx] {
+                    continue;
+                }
+                if !instrumented {
+                    if is_discarded_input(section) {
+                        continue;
+                    }
+                    for relocation in object.relocations(idx)? {
+                        if let Some(target) =
+                            self.guard_target(object_idx, relocation.symbol_table_index)
+                            && self.is_code(target)
+                        {
+                            tables[GuardTable::Functions.index()].insert(target);
+                        }
+                    }
+                    continue;
+                }
+                let Some(table) = GuardTable::ALL
+                    .into_iter()
+                    .find(|table| section.name == table.section_name())
+                else {
+                    continue;
+                };
+                for index in object.section_data(idx).chunks_exact(4) {
+                    let index = u32::from_le_bytes(index.try_into().unwrap());
+                    let Some(target) = self.guard_target(object_idx, index) else {
+                        continue;
+                    };
+                    let wanted = match table {
+                        GuardTable::Iat => matches!(target, GuardTarget::IatSlot(_)),
+                        GuardTable::Functions | GuardTable::LongJmp => self.is_code(target),
+                    };
+                    if wanted {
+                        tables[table.index()].insert(target);
+                    }
+                }
+            }
+        }
+        for id in std::iter::once(entry).chain(self.exported_symbols()) {
+            if let Some(target) = self.global_guard_target(id)
+                && self.is_code(target)
+            {
+                tables[GuardTable::Functions.index()].insert(target);
+            }
+        }
+        self.guard_tables = tables.map(|table| table.into_iter().collect());
+        Ok(())
+    }
+
+    /// Without a load configuration from the CRT, writes one for what needs
+    /// it: `/GS` cookies, Control Flow Guard and `/DEPENDENTLOADFLAG`.
+    fn define_load_config(&mut self) {
+        let is_defined = |name| {
+            self.names.get(name).is_some_and(|id| {
+                matches!(self.symbols[id.index()].def, Definition::Section { .. })
+            })
+        };
+        if is_defined(LOAD_CONFIG_SYMBOL)
+            || !(self.options.guard_cf
+                || self.options.dependent_load_flags != 0
+                || is_defined(SECURITY_COOKIE_SYMBOL))
+        {
+            return;
+        }
+        let id = self.intern(LOAD_CONFIG_SYMBOL);
+        self.symbols[id.index()].def = Definition::LoadConfig;
+    }
+
+    /// Without a TLS directory from the CRT, writes one if there's a `.tls`
+    /// template, along with the index slot if nothing defines it.
+    fn define_tls_directory(&mut self) {
+        let def = |name| self.names.get(name).map(|id| self.symbols[id.index()].def);
+        let has_template = self.objects.iter().enumerate().any(|(object_idx, object)| {
+            object.sections.iter().enumerate().any(|(idx, section)| {
+                output_section_name(&section.name) == ".tls"
+                    && !self.discarded[object_idx][idx]
+                    && self.input_size(object_idx, idx) > 0
+            })
+        });
+        let index_defined = !matches!(def(TLS_INDEX_SYMBOL), None | Some(Definition::Undefined));
+        match def(TLS_USED_SYMBOL) {
+            Some(Definition::Section { .. }) => return,
+            Some(Definition::Undefined) => {}
+            _ if has_template => {}
+            _ => return,
+        }
+        let id = self.intern(TLS_USED_SYMBOL);
+        self.symbols[id.index()].def = Definition::TlsDirectory;
+        if !index_defined {
+            let id = self.intern(TLS_INDEX_SYMBOL);
+            self.symbols[id.index()].def = Definition::TlsIndex;
+        }
+    }
+
+    /// The TLS directory the linker writes, see [`Self::define_tls_directory`].
+    fn synthesized_tls_directory(&self, plan: &LinkPlan) -> Result<TlsDirectory> {
+        let mut directory = TlsDirectory::default();
+        if let Some(tls) = plan.sections.iter().find(|section| section.name == ".tls") {
+            directory.start = plan.image_base + u64::from(tls.rva);
+            directory.end = directory.start + u64::from(tls.virtual_size);
+            let align = tls
+                .chunks
+                .iter()
+                .map(|chunk| chunk.align)
+                .max()
+                .unwrap_or(1);
+            directory.characteristics = (align.trailing_zeros() + 1) << 20;
+        }
+        directory.index = self
+            .global_target(plan, self.names.get(TLS_INDEX_SYMBOL).unwrap())?
+            .va;
+        let callbacks = plan.sections.iter().find_map(|section| {
+            section.chunks.iter().find_map(|chunk| match chunk.kind {
+                ChunkKind::Input {
+                    object,
+                    section: idx,
+                } if is_callback_section(&self.objects[object].sections[idx].name) => {
+                    Some(section.rva + chunk.offset)
+                }
+                _ => None,
+            })
+        });
+        if let Some(rva) = callbacks {
+            directory.callbacks = plan.image_base + u64::from(rva);
+        }
+        Ok(directory)
+    }
+
+    /// The load configuration the linker writes, see [`Self::define_load_config`].
+    fn synthesized_load_config(&self, plan: &LinkPlan) -> Result<LoadConfig> {
+        let va = |name| -> Result<u64> {
+            match self.names.get(name) {
+                Some(id) if matches!(self.symbols[id.index()].def, Definition::Section { .. }) => {
+                    Ok(self.global_target(plan, id)?.va)
+                }
+                _ => Ok(0),
+            }
+        };
+        let mut guard_tables = [(0, 0); 3];
+        for table in GuardTable::ALL {
+            let entries = &self.guard_tables[table.index()];
+            if !entries.is_empty() {
+                let start = Self::synthetic_target(plan, ChunkKind::GuardTable(table), 0).va;
+                guard_tables[table.index()] = (start, entries.len() as u64);
+            }
+        }
+        Ok(LoadConfig {
+            dependent_load_flags: self.options.dependent_load_flags,
+            security_cookie: va(SECURITY_COOKIE_SYMBOL)?,
+            guard_check_function: va(GUARD_CHECK_FUNCTION_SYMBOL)?,
+            guard_dispatch_function: va(GUARD_DISPATCH_FUNCTION_SYMBOL)?,
+            guard_flags: if self.options.guard_cf {
+                GUARD_FLAGS
+            } else {
+                0
+            },
+            guard_tables,
+        })
+    }
+
+    /// What a symbol of `object` refers to, if it's an address in the image.
+    fn guard_target(&self, object: usize, symbol: u32) -> Option<GuardTarget> {
+        let sym = self.objects[object].symbols.symbol(symbol)?;
+        if sym.storage_class == IMAGE_SYM_CLASS_EXTERNAL
+            || sym.storage_class == IMAGE_SYM_CLASS_WEAK_EXTERNAL
+        {
+            return self.global_guard_target(self.symbol_ids[object][symbol as usize]?);
+        }
+        match sym.section_number {
+            IMAGE_SYM_ABSOLUTE | IMAGE_SYM_UNDEFINED | IMAGE_SYM_DEBUG => None,
+            number => Some(GuardTarget::Section {
+                object,
+                section: usize::from(number) - 1,
+                value: sym.value,
+            }),
+        }
+    }
+
+    fn global_guard_target(&self, id: SymbolId) -> Option<GuardTarget> {
+        match self.symbols[id.index()].def {
+            Definition::Section {
+                object,
+                section,
+                value,
+            } => Some(GuardTarget::Section {
+                object,
+                section,
+                value,
+            }),
+            Definition::Import(import) => Some(GuardTarget::Thunk(import)),
+            Definition::ImportPointer(import) => Some(GuardTarget::IatSlot(import)),
+            Definition::Alias(to) => self.global_guard_target(to),
+            Definition::Weak { object, symbol } => {
+                let weak = self.objects[object].symbols.weak_external(symbol)?;
+                self.guard_target(object, weak.tag_index)
+            }
+            _ => None,
+        }
+    }
+
+    fn is_code(&self, target: GuardTarget) -> bool {
+        match target {
+            GuardTarget::Section {
+                object, section, ..
+            } => {
+                !self.discarded[object][section]
+                    && self.objects[object].sections[section]
+                        .characteristics
+                        .contains(SectionFlags::IMAGE_SCN_MEM_EXECUTE)
+            }
+            GuardTarget::Thunk(_) => true,
+            GuardTarget::IatSlot(_) => false,
+        }
+    }
+
+    fn check_undefined(&mut self, diag: &mut Diagnostics<'_>) {
+        if let Some(id) = self.names.get(IMAGE_BASE_SYMBOL)
+            && matches!(self.symbols[id.index()].def, Definition::Undefined)
+        {
+            self.symbols[id.index()].def = Definition::ImageBase;
+        }
+        if self.options.startup_order.is_some() {
+            for (idx, (name, ..)) in STARTUP_ANCHORS.iter().enumerate() {
+                if let Some(id) = self.names.get(name)
+                    && matches!(self.symbols[id.index()].def, Definition::Undefined)
+                {
+                    self.symbols[id.index()].def = Definition::StartupAnchor(idx);
+                }
+            }
+        }
+
+        let load_config = self.names.get(LOAD_CONFIG_SYMBOL);
+        // Defined symbols by their undecorated name, to suggest for undefined
+        // ones with the wrong calling convention.
+        let mut decorated = None;
+        let mut unresolved = Vec::new();
+        for (sym, id) in self.symbols.iter().zip(0..) {
+            if let Definition::Undefined = sym.def {
+                // Only looked for in libraries, see `link`.
+                if sym.referenced_by.is_none() && load_config == Some(SymbolId::new(id)) {
+                    continue;
+                }
+                let name = self.names.resolve(SymbolId::new(id));
+                let referenced_by = self.referenced_by(SymbolId::new(id));
+                let decorated =
+                    decorated.get_or_insert_with(|| self.definitions_by_undecorated_name());
+                let suggestion = match decorated.get(demangle::undecorated(name)) {
+                    Some(&Some(other)) => format!(
+                        "; did you mean {}?",
+                        demangle::symbol_name(self.names.resolve(other), self.options.demangle)
+                    ),
+                    _ => String::new(),
+                };
+                let message = format!(
+                    "unresolved external symbol {} referenced in {referenced_by}{suggestion}",
+                    demangle::symbol_name(name, self.options.demangle)
+                );
+                if self.options.force_unresolved {
+                    diag.warn(Code::UnresolvedSymbolIgnored, message);
+                } else {
+                    diag.error(Code::UnresolvedSymbol, message);
+                }
+                unresolved.push(SymbolId::new(id));
+            }
+        }
+        if self.options.force_unresolved {
+            self.forced |= !unresolved.is_empty();
+            for id in unresolved {
+                self.symbols[id.index()].def = Definition::Absolute(0);
+            }
+        }
+    }
+}
+
+impl LinkContext<'_> {
+    /// Decodes the unwind info of every function in the `.pdata` sections
+    /// that get linked, and warns about entries that are malformed.
+    fn check_unwind_info(&self, diag: &mut Diagnostics<'_>) -> Result<()> {
+        for (object_idx, object) in self.objects.iter().enumerate() {
+            for (idx, section) in object.sections.iter().enumerate() {
+                if output_section_name(&section.name) != ".pdata"
+                    || self.discarded[object_idx][idx]
+                    || is_discarded_input(section)
+                {
+                    continue;
+                }
+                let data = object.section_data(idx);
+                if data.len() % RUNTIME_FUNCTION_SIZE != 0 {
+                    diag.warn(
+                        Code::InvalidUnwindInfo,
+                        format_args!(
+                            "{}: {} is {} bytes, not a whole number of function entries",
+                            object.name,
+                            section.name,
+                            data.len()
+                        ),
+                    );
+                }
+                let relocations = object
+                    .relocations(idx)?
+                    .into_iter()
+                    .map(|reloc| (reloc.virtual_address, reloc.symbol_table_index))
+                    .collect::<HashMap<_, _>>();
+                for (entry, fields) in data.chunks_exact(RUNTIME_FUNCTION_SIZE).enumerate() {
+                    let offset = (entry * RUNTIME_FUNCTION_SIZE) as u32;
+                    let addend = |field: usize| {
+                        u32::from_le_bytes(fields[field * 4..][..4].try_into().unwrap())
+                    };
+                    let function = match relocations.get(&offset) {
+                        Some(&symbol) => self.pdata_function_name(object_idx, symbol, addend(0)),
+                        None => format!("the function at {offset:#x} in {}", section.name),
+                    };
+                    let problem = match relocations.get(&(offset + 8)) {
+                        Some(&symbol) => self.unwind_info_problem(object_idx, symbol, addend(2)),
+                        None => Some("its unwind info address has no relocation".to_owned()),
+                    };
+                    if let Some(problem) = problem {
+                        diag.warn(
+                            Code::InvalidUnwindInfo,
+                            format_args!(
+                                "{}: invalid unwind info for {function}: {problem}",
+                                object.name
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn pdata_function_name(&self, object: usize, symbol: u32, addend: u32) -> String {
+        let obj = &self.objects[object];
+        let name = obj
+            .symbols
+            .symbol(symbol)
+            .and_then(|sym| obj.symbols.name(sym).ok())
+            .unwrap_or("?");
+        let name = demangle::symbol_name(name, self.options.demangle);
+        match addend {
+            0 => name.to_string(),
+            _ => format!("{name}+{addend:#x}"),
+        }
+    }
+
+    /// Finds the unwind info a `.pdata` entry points to and decodes it.
+    fn unwind_info_problem(&self, object: usize, symbol: u32, addend: u32) -> Option<String> {
+        let obj = &self.objects[object];
+        let sym = obj.symbols.symbol(symbol)?;
+        let (object, section, value) = match sym.storage_class {
+            IMAGE_SYM_CLASS_EXTERNAL | IMAGE_SYM_CLASS_WEAK_EXTERNAL => {
+                let id = self.symbol_ids[object][symbol as usize]?;
+                match self.symbols[id.index()].def {
+                    Definition::Section {
+                        object,
+                        section,
+                        value,
+                    } => (object, section, value),
+                    _ => return Some("its unwind info address is not in a section".to_owned()),
+                }
+            }
+            _ => match sym.section_number {
+                IMAGE_SYM_UNDEFINED | IMAGE_SYM_ABSOLUTE | IMAGE_SYM_DEBUG => {
+                    return Some("its unwind info address is not in a section".to_owned());
+                }
+                number => (object, usize::from(number) - 1, sym.value),
+            },
+        };
+        let data = self.objects[object].section_data(section);
+        match data.get(value as usize + addend as usize..) {
+            Some(info) => unwind::problem(info),
+            None => Some(format!(
+                "its unwind info address {:#x} is past the end of {}",
+                value + addend,
+                self.objects[object].sections[section].name
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChunkKind {
+    Input {
+        object: usize,
+        section: usize,
+    },
+    Common {
+        symbol: SymbolId,
+    },
+    ImportThunks,
+    Iat,
+    ImportDirectory,
+    ExportDirectory,
+    DebugDirectory,
+    Resources,
+    BaseRelocations,
+    GuardTable(GuardTable),
+    LoadConfig,
+    TlsDirectory,
+    TlsIndex,
+    /// The null pointer ending the TLS callbacks, for [`ChunkKind::TlsDirectory`].
+    TlsCallbacksEnd,
+    /// Zero-sized, marking where one of [`STARTUP_ANCHORS`] points.
+    StartupAnchor(usize),
+}
+
+struct Chunk {
+    kind: ChunkKind,
+    align: u32,
+    size: u32,
+    offset: u32,
+}
+
+struct OutputSection {
+    name: String,
+    characteristics: SectionFlags,
+    chunks: Vec<Chunk>,
+    rva: u32,
+    virtual_size: u32,
+    file_offset: u32,
+    raw_size: u32,
+}
+
+impl OutputSection {
+    fn is_uninitialized(&self) -> bool {
+        self.characteristics
+            .contains(SectionFlags::IMAGE_SCN_CNT_UNINITIALIZED_DATA)
+            && !self.characteristics.intersects(
+                SectionFlags::IMAGE_SCN_CNT_CODE | SectionFlags::IMAGE_SCN_CNT_INITIALIZED_DATA,
+            )
+    }
+
+    fn rank(&self) -> u8 {
+        let flags = self.characteristics;
+        if flags.contains(SectionFlags::IMAGE_SCN_MEM_DISCARDABLE) {
+            4
+        } else if flags.contains(SectionFlags::IMAGE_SCN_CNT_CODE) {
+            0
+        } else if self.is_uninitialized() {
+            3
+        } else if flags.contains(SectionFlags::IMAGE_SCN_MEM_WRITE) {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+struct LinkPlan {
+    sections: Vec<OutputSection>,
+    /// Per object and section, the output section index and offset within it.
+    placements: Vec<Vec<Option<(usize, u32)>>>,
+    import_tables: ImportTables,
+    export_tables: ExportTables,
+    /// The exported symbols, in the order of the export tables. `None` for
+    /// forwarded exports.
+    exports: Vec<Option<SymbolId>>,
+    debug_tables: DebugTables,
+    /// Index of each code import's thunk.
+    thunks: Vec<Option<u32>>,
+    base_relocation_sites: Vec<(u32, u16)>,
+    entry: u32,
+    headers_size: u32,
+    /// Where the string table goes, after all sections. Zero without one.
+    string_table_offset: u32,
+    /// Space reserved for a signature, holding a file offset, after the
+    /// string table.
+    certificate_table: DataDirectory,
+    size_of_image: u32,
+    /// The preferred load address, which absolute relocations are resolved
+    /// against and `.reloc` adjusts from.
+    image_base: u64,
+    dll_characteristics: DllCharacteristics,
+    /// Where the CRT's `_load_config_used` ended up, if it was linked in.
+    load_config: DataDirectory,
+    tls: DataDirectory,
+    /// The `.pdata` of all inputs, which the unwinder binary-searches and
+    /// so gets sorted by function.
+    exception_table: DataDirectory,
+    section_alignment: u32,
+    file_alignment: u32,
+    /// Everything before the COFF header.
+    dos_stub: Vec<u8>,
+}
+
+impl LinkPlan {
+    fn alignments(&self) -> (u32, u32) {
+        (self.section_alignment, self.file_alignment)
+    }
+
+    /// Section names longer than 8 bytes are stored in the string table,
+    /// and the header refers to them as `/offset`.
+    fn section_headers(&self) -> Result<Vec<SectionHeader<'_>>> {
+        let mut string_offset = STRING_TABLE_SIZE_FIELD;
+        self.sections
+            .iter()
+            .map(|section| {
+                let name = if section.name.len() > 8 {
+                    let name = format!("/{string_offset}");
+                    string_offset += section.name.len() as u32 + 1;
+                    if name.len() > 8 {
+                        return fatal(
+                            Code::CorruptInput,
+                            format!("too many long section names to encode {}", section.name),
+                        );
+                    }
+                    name.into()
+                } else {
+                    section.name.as_str().into()
+                };
+                Ok(SectionHeader {
+                    name,
+                    virtual_size: section.virtual_size,
+                    virtual_address: section.rva,
+                    size_of_raw_data: section.raw_size,
+                    pointer_to_raw_data: section.file_offset,
+                    pointer_to_relocations: 0,
+                    pointer_to_linenumbers: 0,
+                    number_of_relocations: 0,
+                    number_of_linenumbers: 0,
+                    characteristics: section.characteristics,
+                })
+            })
+            .collect()
+    }
+
+    /// The COFF string table holding long section names, which is empty
+    /// unless there are some.
+    fn string_table(&self) -> Vec<u8> {
+        let mut table = vec![0; STRING_TABLE_SIZE_FIELD as usize];
+        for section in &self.sections {
+            if section.name.len() > 8 {
+                table.extend_from_slice(section.name.as_bytes());
+                table.push(0);
+            }
+        }
+        if table.len() == STRING_TABLE_SIZE_FIELD as usize {
+            return Vec::new();
+        }
+        let size = table.len() as u32;
+        table[..4].copy_from_slice(&size.to_le_bytes());
+        table
+    }
+
+    /// The RVA and size of the first chunk of the given kind.
+    fn chunk(&self, kind: ChunkKind) -> Option<(u32, u32)> {
+        self.sections.iter().find_map(|section| {
+            let chunk = section.chunks.iter().find(|chunk| chunk.kind == kind)?;
+            Some((section.rva + chunk.offset, chunk.size))
+        })
+    }
+
+    fn directory(&self, kind: ChunkKind, size: impl FnOnce(u32) -> u32) -> DataDirectory {
+        match self.chunk(kind) {
+            Some((virtual_address, chunk_size)) => DataDirectory {
+                virtual_address,
+                size: size(chunk_size),
+            },
+            None => DataDirectory::default(),
+        }
+    }
+
+    fn import_table(&self) -> DataDirectory {
+        self.directory(ChunkKind::ImportDirectory, |_| {
+            self.import_tables.descriptors_size()
+        })
+    }
+
+    fn iat(&self) -> DataDirectory {
+        self.directory(ChunkKind::Iat, |_| self.import_tables.iat_size())
+    }
+
+    fn export_table(&self) -> DataDirectory {
+        self.directory(ChunkKind::ExportDirectory, |size| size)
+    }
+
+    fn debug_directory(&self) -> DataDirectory {
+        self.directory(ChunkKind::DebugDirectory, |_| {
+            self.debug_tables.directory_size()
+        })
+    }
+
+    fn resource_table(&self) -> DataDirectory {
+        self.directory(ChunkKind::Resources, |size| size)
+    }
+
+    fn base_relocation_table(&self) -> DataDirectory {
+        self.directory(ChunkKind::BaseRelocations, |size| size)
+    }
+
+    /// Cross-checks a re-parsed image against the plan it was written from.
+    fn check(&self, image: &ImageModel<'_>) -> Result<()> {
+        let mut failures = Vec::new();
+        let optional = &image.optional_header;
+
+        if image.header.machine != IMAGE_FILE_MACHINE_AMD64 {
+            failures.push(format!("machine type is {:#x}", image.header.machine));
+        }
+        if image.sections.len() != self.sections.len() {
+            failures.push(format!(
+                "image has {} sections, planned {}",
+                image.sections.len(),
+                self.sections.len()
+            ));
+        }
+        let headers = self.section_headers()?;
+        for ((planned, header), actual) in self.sections.iter().zip(&headers).zip(&image.sections) {
+            // Long names are compared as their `/offset` into the string table.
+            if actual.name != header.name
+                || actual.virtual_address != planned.rva
+                || actual.virtual_size != planned.virtual_size
+                || actual.pointer_to_raw_data != planned.file_offset
+                || actual.size_of_raw_data != planned.raw_size
+                || actual.characteristics != planned.characteristics
+            {
+                failures.push(format!(
+                    "section header {} doesn't match the plan",
+                    planned.name
+                ));
+            }
+        }
+        if image.header.pointer_to_symbol_table != self.string_table_offset {
+            failures.push("string table is misplaced".to_owned());
+        }
+        if optional.certificate_table != self.certificate_table {
+            failures.push("reserved certificate table is misplaced".to_owned());
+        }
+        if optional.image_base != self.image_base {
+            failures.push(format!(
+                "image base is {:#x}, planned {:#x}",
+                optional.image_base, self.image_base
+            ));
+        }
+        if optional.dll_characteristics != self.dll_characteristics {
+            failures.push(format!(
+                "DLL characteristics are {:?}, planned {:?}",
+                optional.dll_characteristics, self.dll_characteristics
+            ));
+        }
+        if optional.size_of_image != self.size_of_image {
+            failures.push(format!(
+                "size of image is {:#x}, planned {:#x}",
+                optional.size_of_image, self.size_of_image
+            ));
+        }
+
+        if optional.address_of_entry_point != self.entry {
+            failures.push(format!(
+                "entry point is {:#x}, planned {:#x}",
+                optional.address_of_entry_point, self.entry
+            ));
+        }
+        if !image
+            .section_containing(optional.address_of_entry_point)
+            .is_some_and(|section| {
+                section
+                    .characteristics
+                    .contains(SectionFlags::IMAGE_SCN_MEM_EXECUTE)
+            })
+        {
+            failures.push("entry point is not in an executable section".to_owned());
+        }
+
+        let directories = [
+            ("export", optional.export_table, self.export_table()),
+            ("debug", optional.debug, self.debug_directory()),
+            ("resource", optional.resource_table, self.resource_table()),
+            ("load config", optional.load_config_table, self.load_config),
+            ("TLS", optional.tls_table, self.tls),
+            ("exception", optional.exception_table, self.exception_table),
+            ("import", optional.import_table, self.import_table()),
+            ("IAT", optional.iat, self.iat()),
+            (
+                "base relocation",
+                optional.base_relocation_table,
+                self.base_relocation_table(),
+            ),
+        ];
+        for (name, actual, planned) in directories {
+            if actual != planned {
+                failures.push(format!(
+                    "{name} directory is {actual:x?}, planned {planned:x?}"
+                ));
+            } else if actual.size > 0 && image.directory_section(actual).is_none() {
+                failures.push(format!("{name} directory is not within a single section"));
+            }
+        }
+
+        if optional.iat.size > 0 {
+            if !optional
+                .iat
+                .virtual_address
+                .is_multiple_of(iat_alignment(self.section_alignment))
+            {
+                failures.push("IAT is not page-aligned".to_owned());
+            }
+            let iat_section = self
+                .sections
+                .iter()
+                .find(|section| {
+                    section
+                        .chunks
+                        .iter()
+                        .any(|chunk| matches!(chunk.kind, ChunkKind::Iat))
+                })
+                .map(|section| section.name.as_str());
+            match image.directory_section(optional.iat) {
+                Some(section)
+                    if iat_section == Some(&*section.name)
+                        && !section
+                            .characteristics
+                            .contains(SectionFlags::IMAGE_SCN_MEM_WRITE) => {}
+                _ => failures.push("IAT is not in its read-only section".to_owned()),
+            }
+            let iat_end = optional.iat.virtual_address + optional.iat.size;
+            match image.import_descriptors() {
+                Some(descriptors) => {
+                    for descriptor in descriptors {
+                        if image.read(descriptor.name, 1).is_none()
+                            || image.read(descriptor.import_lookup_table, 8).is_none()
+                        {
+                            failures.push(format!(
+                                "import descriptor name or lookup table at {:#x} is unreadable",
+                                descriptor.import_lookup_table
+                            ));
+                        }
+                        let thunks = descriptor.import_address_table;
+                        if thunks < optional.iat.virtual_address || thunks >= iat_end {
+                            failures.push(format!(
+                                "import descriptor thunks at {thunks:#x} are outside the IAT"
+                            ));
+                        }
+                    }
+                }
+                None => failures.push("import descriptors are unreadable".to_owned()),
+            }
+        }
+
+        match image.base_relocations() {
+            Some(sites) if sites == self.base_relocation_sites => {}
+            Some(_) => failures.push("base relocations don't cover the absolute fixups".to_owned()),
+            None if self.base_relocation_sites.is_empty() => {}
+            None => failures.push("base relocations are unreadable".to_owned()),
+        }
+
+        failures.extend(verify(image));
+        if !failures.is_empty() {
+            return fatal(Code::SelfCheckFailed, failures.join("; "));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Target {
+    va: u64,
+    /// The output section index, unless the target is an absolute symbol.
+    section: Option<usize>,
+    /// The input object, section and offset, for targets in input sections.
+    origin: Option<(usize, usize, u32)>,
+}
+
+/// A relocation applied within an input chunk.
+struct Fixup {
+    /// The offset in the chunk, after instrumentation.
+    offset: u32,
+    r#type: u16,
+    target: Target,
+}
+
+/// The size of the field a relocation patches.
+pub(crate) fn relocation_size(r#type: u16) -> usize {
+    match r#type {
+        IMAGE_REL_AMD64_ABSOLUTE => 0,
+        IMAGE_REL_AMD64_ADDR64 => 8,
+        IMAGE_REL_AMD64_SECTION => 2,
+        _ => 4,
+    }
+}
+
+const TEXT_FLAGS: SectionFlags = SectionFlags::IMAGE_SCN_CNT_CODE
+    .union(SectionFlags::IMAGE_SCN_MEM_EXECUTE)
+    .union(SectionFlags::IMAGE_SCN_MEM_READ);
+const RDATA_FLAGS: SectionFlags =
+    SectionFlags::IMAGE_SCN_CNT_INITIALIZED_DATA.union(SectionFlags::IMAGE_SCN_MEM_READ);
+const BSS_FLAGS: SectionFlags = SectionFlags::IMAGE_SCN_CNT_UNINITIALIZED_DATA
+    .union(SectionFlags::IMAGE_SCN_MEM_READ)
+    .union(SectionFlags::IMAGE_SCN_MEM_WRITE);
+const DATA_FLAGS: SectionFlags = RDATA_FLAGS.union(SectionFlags::IMAGE_SCN_MEM_WRITE);
+const RELOC_FLAGS: SectionFlags = RDATA_FLAGS.union(SectionFlags::IMAGE_SCN_MEM_DISCARDABLE);
+
+/// Output sections that input sections get merged into by default.
+fn output_section_name(input: &str) -> &str {
+    let name = input.split_once('$').map_or(input, |(name, _)| name);
+    match name {
+        ".idata" | ".edata" | ".xdata" | ".00cfg" => ".rdata",
+        _ => name,
+    }
+}
+
+/// The `/name:value` directives of an object's `.drectve` sections, with
+/// the name lowercased.
+fn directives(object: &ObjectFile<'_>) -> Vec<(String, String)> {
+    let mut directives = Vec::new();
+    for (idx, section) in object.sections.iter().enumerate() {
+        if section.name != ".drectve" {
+            continue;
+        }
+        let text = String::from_utf8_lossy(object.section_data(idx));
+        for directive in text.split_ascii_whitespace() {
+            let directive = directive.trim_start_matches(['/', '-']).replace('"', "");
+            if let Some((name, value)) = directive.split_once(':') {
+                directives.push((name.to_ascii_lowercase(), value.to_owned()));
+            }
+        }
+    }
+    directives
+}
+
+/// The CRT a default library is part of, as the compiler flag selecting it.
+fn crt_model(library: &str) -> Option<&'static str> {
+    let name = library.to_ascii_lowercase();
+    match name.strip_suffix(".lib").unwrap_or(&name) {
+        "libcmt" | "libucrt" | "libvcruntime" | "libcpmt" => Some("/MT"),
+        "libcmtd" | "libucrtd" | "libvcruntimed" | "libcpmtd" => Some("/MTd"),
+        "msvcrt" | "ucrt" | "vcruntime" | "msvcprt" => Some("/MD"),
+        "msvcrtd" | "ucrtd" | "vcruntimed" | "msvcprtd" => Some("/MDd"),
+        _ => None,
+    }
+}
+
+/// Sections that never go into the image. DWARF sections are only kept with
+/// `/DEBUG:DWARF`, see [`is_dwarf_section`], and Control Flow Guard metadata
+/// only feeds the guard tables.
+fn is_discarded_input(section: &SectionHeader) -> bool {
+    section
+        .characteristics
+        .intersects(SectionFlags::IMAGE_SCN_LNK_REMOVE | SectionFlags::IMAGE_SCN_LNK_INFO)
+        || (section.name.starts_with(".debug") && !is_dwarf_section(&section.name))
+        || GuardTable::ALL
+            .iter()
+            .any(|table| section.name == table.section_name())
+        || section.name == ".gehcont$y"
+}
+
+/// DWARF sections from MinGW objects, like `.debug_info`. They're kept as
+/// discardable sections at the end of the image, where gdb and lldb look for
+/// them by name.
+fn is_dwarf_section(name: &str) -> bool {
+    name.starts_with(".debug_")
+}
+
+/// Whether a startup order entry's `pattern` names the object `name`: by its
+/// full name, its file name, or as an archive member.
+fn object_matches(name: &str, pattern: &str) -> bool {
+    name == pattern
+        || name.rsplit(['/', '\\']).next() == Some(pattern)
+        || name
+            .strip_suffix(')')
+            .and_then(|name| name.rsplit_once('('))
+            .is_some_and(|(_, member)| member == pattern)
+}
+
+fn align_up(value: u32, align: u32) -> u32 {
+    value.next_multiple_of(align)
+}
+
+impl LinkContext<'_> {
+    /// Applies `/MERGE`, following chains like `.a=.b` and `.b=.c` to the end.
+    fn merge_sections(
+        &self,
+        sections: &mut Vec<OutputSection>,
+        section_idx: impl Fn(&mut Vec<OutputSection>, &str, SectionFlags) -> usize,
+    ) -> Result<()> {
+        let merges = self.options.merges.iter().chain(&self.merges);
+        // Where `name` ends up, if it's merged at all.
+        let target = |name: &str| -> Result<Option<String>> {
+            let mut to = None;
+            for _ in 0..=merges.clone().count() {
+                let from = to.as_deref().unwrap_or(name);
+                match merges.clone().find(|merge| merge.from == from) {
+                    Some(merge) => to = Some(merge.to.clone()),
+                    None => return Ok(to),
+                }
+            }
+            fatal(
+                Code::InvalidOption,
+                format!("/MERGE directives for {name} form a cycle"),
+            )
+        };
+        for merge in merges.clone() {
+            target(&merge.from)?;
+            // `.reloc` is only laid out after the other sections, and the
+            // loader finds resources in `.rsrc` by name.
+            for name in [&merge.from, &merge.to] {
+                if name == ".reloc" || name == ".rsrc" {
+                    return fatal(
+                        Code::InvalidOption,
+                        format!("/MERGE:{}={}: {name} can't be merged", merge.from, merge.to),
+                    );
+                }
+            }
+        }
+        for idx in 0..sections.len() {
+            let Some(to) = target(&sections[idx].name)? else {
+                continue;
+            };
+            let chunks = std::mem::take(&mut sections[idx].chunks);
+            let flags = sections[idx].characteristics;
+            let out = section_idx(sections, &to, flags);
+            sections[out].chunks.extend(chunks);
+        }
+        Ok(())
+    }
+
+    fn plan(&self, entry: &str) -> Result<LinkPlan> {
+        let mut sections = Vec::<OutputSection>::new();
+        let section_idx =
+            |sections: &mut Vec<OutputSection>, name: &str, flags: SectionFlags| match sections
+                .iter()
+                .position(|section| section.name == name)
+            {
+                Some(idx) => {
+                    sections[idx].characteristics |= flags;
+                    idx
+                }
+                None => {
+                    sections.push(OutputSection {
+                        name: name.to_owned(),
+                        characteristics: flags,
+                        chunks: Vec::new(),
+                        rva: 0,
+                        virtual_size: 0,
+                        file_offset: 0,
+                        raw_size: 0,
+                    });
+                    sections.len() - 1
+                }
+            };
+
+        let mut input_chunks = Vec::new();
+        for (object_idx, object) in self.objects.iter().enumerate() {
+            for (idx, section) in object.sections.iter().enumerate() {
+                if self.discarded[object_idx][idx]
+                    || is_discarded_input(section)
+                    || (is_dwarf_section(&section.name) && !self.options.debug.dwarf)
+                    || self.input_size(object_idx, idx) == 0
+                {
+                    continue;
+                }
+                let name = output_section_name(&section.name);
+                let mut flags = section.characteristics.image_flags();
+                // The kernel frees a driver's `INIT` code once `DriverEntry` returns.
+                if self.options.driver.is_some() && name == "INIT" {
+                    flags |= SectionFlags::IMAGE_SCN_MEM_DISCARDABLE;
+                }
+                let out = section_idx(&mut sections, name, flags);
+                // `None` sorts sections without a `$` first, which puts the
+                // CRT's `.tls` with `_tls_start` before the compiler's `.tls$`.
+                let suffix = section.name.split_once('$').map(|(_, suffix)| suffix);
+                input_chunks.push((
+                    out,
+                    suffix,
+                    Chunk {
+                        kind: ChunkKind::Input {
+                            object: object_idx,
+                            section: idx,
+                        },
+                        align: section.characteristics.alignment(),
+                        size: self.input_size(object_idx, idx),
+                        offset: 0,
+                    },
+                ));
+            }
+        }
+        // Grouped sections are ordered by the part of their name after the `$`,
+        // after the ones pinned by the startup order and then `/ORDER`.
+        input_chunks.sort_by_cached_key(|(out, suffix, chunk)| {
+            let rank = |ranks: &HashMap<_, usize>| match chunk.kind {
+                ChunkKind::Input { object, section } => ranks.get(&(object, section)).copied(),
+                _ => None,
+            };
+            (
+                *out,
+                rank(&self.pinned).unwrap_or(usize::MAX),
+                rank(&self.ordered).unwrap_or(usize::MAX),
+                *suffix,
+            )
+        });
+
+        let import_tables = ImportTables::new(&self.imports);
+        let mut thunks = vec![None; self.imports.len()];
+        let mut thunk_count = 0;
+        for (idx, import) in self.imports.iter().enumerate() {
+            if import.is_code {
+                thunks[idx] = Some(thunk_count);
+                thunk_count += 1;
+            }
+        }
+
+        let (section_alignment, file_alignment) = self.options.alignments();
+        if !import_tables.is_empty() {
+            // The IAT goes first in .rdata and is padded to whole pages, so the
+            // loader can make it writable while binding without exposing anything else.
+            let rdata = section_idx(&mut sections, ".rdata", RDATA_FLAGS);
+            let align = iat_alignment(section_alignment);
+            sections[rdata].chunks.push(Chunk {
+                kind: ChunkKind::Iat,
+                align,
+                size: align_up(import_tables.iat_size(), align),
+                offset: 0,
+            });
+        }
+
+        for (out, _, chunk) in input_chunks {
+            sections[out].chunks.push(chunk);
+        }
+        let tls_directory = self
+            .names
+            .get(TLS_USED_SYMBOL)
+            .is_some_and(|id| matches!(self.symbols[id.index()].def, Definition::TlsDirectory));
+        if tls_directory {
+            // Without the CRT's `__xl_z`, the callbacks need a terminator.
+            for section in &mut sections {
+                let last = section.chunks.iter().rposition(|chunk| match chunk.kind {
+                    ChunkKind::Input { object, section } => {
+                        is_callback_section(&self.objects[object].sections[section].name)
+                    }
+                    _ => false,
+                });
+                if let Some(last) = last {
+                    section.chunks.insert(
+                        last + 1,
+                        Chunk {
+                            kind: ChunkKind::TlsCallbacksEnd,
+                            align: 8,
+                            size: 8,
+                            offset: 0,
+                        },
+                    );
+                }
+            }
+        }
+        for (idx, &(_, name, end)) in STARTUP_ANCHORS.iter().enumerate() {
+            if !self
+                .symbols
+                .iter()
+                .any(|sym| matches!(sym.def, Definition::StartupAnchor(anchor) if anchor == idx))
+            {
+                continue;
+            }
+            let flags = if name == ".text" {
+                TEXT_FLAGS
+            } else {
+                RDATA_FLAGS
+            };
+            let out = section_idx(&mut sections, name, flags);
+            let chunks = &mut sections[out].chunks;
+            let is_pinned = |chunk: &Chunk| match chunk.kind {
+                ChunkKind::Input { object, section } => {
+                    self.pinned.contains_key(&(object, section))
+                }
+                _ => false,
+            };
+            // Without pinned sections, both anchors go after the IAT.
+            let leading = chunks
+                .iter()
+                .take_while(|chunk| match chunk.kind {
+                    ChunkKind::Iat => true,
+                    ChunkKind::StartupAnchor(_) => end,
+                    _ => false,
+                })
+                .count();
+            let position = if end {
+                chunks
+                    .iter()
+                    .rposition(is_pinned)
+                    .map_or(leading, |last| last + 1)
+            } else {
+                chunks.iter().position(is_pinned).unwrap_or(leading)
+            };
+            // The begin anchor takes the alignment of the first pinned section,
+            // so there's no padding between them.
+            let align = match chunks.get(position) {
+                Some(next) if !end => next.align,
+                _ => 1,
+            };
+            chunks.insert(
+                position,
+                Chunk {
+                    kind: ChunkKind::StartupAnchor(idx),
+                    align,
+                    size: 0,
+                    offset: 0,
+                },
+            );
+        }
+
+        if !import_tables.is_empty() {
+            let rdata = section_idx(&mut sections, ".rdata", RDATA_FLAGS);
+            sections[rdata].chunks.push(Chunk {
+                kind: ChunkKind::ImportDirectory,
+                align: 4,
+                size: import_tables.directory_size(),
+                offset: 0,
+            });
+        }
+        let (export_entries, exports) = self.exports().into_iter().unzip::<_, _, Vec<_>, Vec<_>>();
+        let export_tables = ExportTables::new(
+            self.options.image_name.clone().unwrap_or_default(),
+            export_entries,
+        )?;
+        if !export_tables.is_empty() {
+            let rdata = section_idx(&mut sections, ".rdata", RDATA_FLAGS);
+            sections[rdata].chunks.push(Chunk {
+                kind: ChunkKind::ExportDirectory,
+                align: 4,
+                size: export_tables.size(),
+                offset: 0,
+            });
+        }
+        let debug_tables = DebugTables::new(&self.options.debug);
+        if !debug_tables.is_empty() {
+            let debug = section_idx(&mut sections, ".debug", RDATA_FLAGS);
+            sections[debug].chunks.push(Chunk {
+                kind: ChunkKind::DebugDirectory,
+                align: 4,
+                size: debug_tables.size(),
+                offset: 0,
+            });
+        }
+        if self
+            .names
+            .get(LOAD_CONFIG_SYMBOL)
+            .is_some_and(|id| matches!(self.symbols[id.index()].def, Definition::LoadConfig))
+        {
+            let rdata = section_idx(&mut sections, ".rdata", RDATA_FLAGS);
+            sections[rdata].chunks.push(Chunk {
+                kind: ChunkKind::LoadConfig,
+                align: 8,
+                size: LOAD_CONFIG_SIZE,
+                offset: 0,
+            });
+        }
+        if tls_directory {
+            let rdata = section_idx(&mut sections, ".rdata", RDATA_FLAGS);
+            sections[rdata].chunks.push(Chunk {
+                kind: ChunkKind::TlsDirectory,
+                align: 8,
+                size: TLS_DIRECTORY_SIZE,
+                offset: 0,
+            });
+        }
+        if self
+            .names
+            .get(TLS_INDEX_SYMBOL)
+            .is_some_and(|id| matches!(self.symbols[id.index()].def, Definition::TlsIndex))
+        {
+            let data = section_idx(&mut sections, ".data", DATA_FLAGS);
+            sections[data].chunks.push(Chunk {
+                kind: ChunkKind::TlsIndex,
+                align: 4,
+                size: 4,
+                offset: 0,
+            });
+        }
+        for table in GuardTable::ALL {
+            let entries = &self.guard_tables[table.index()];
+            if !entries.is_empty() {
+                let rdata = section_idx(&mut sections, ".rdata", RDATA_FLAGS);
+                sections[rdata].chunks.push(Chunk {
+                    kind: ChunkKind::GuardTable(table),
+                    align: 4,
+                    size: entries.len() as u32 * 4,
+                    offset: 0,
+                });
+            }
+        }
+        if !self.resources.is_empty() {
+            let rsrc = section_idx(&mut sections, ".rsrc", RDATA_FLAGS);
+            sections[rsrc].chunks.push(Chunk {
+                kind: ChunkKind::Resources,
+                align: 4,
+                // The layout doesn't depend on where the section ends up.
+                size: build_resource_section(&self.resources, 0).len() as u32,
+                offset: 0,
+            });
+        }
+        if thunk_count > 0 {
+            let text = section_idx(&mut sections, ".text", TEXT_FLAGS);
+            sections[text].chunks.push(Chunk {
+                kind: ChunkKind::ImportThunks,
+                align: 16,
+                size: thunk_count * IMPORT_THUNK_SIZE,
+                offset: 0,
+            });
+        }
+        for (sym, id) in self.symbols.iter().zip(0..) {
+            if let Definition::Common { size } = sym.def {
+                let bss = section_idx(&mut sections, ".bss", BSS_FLAGS);
+                sections[bss].chunks.push(Chunk {
+                    kind: ChunkKind::Common {
+                        symbol: SymbolId::new(id),
+                    },
+                    align: size.next_power_of_two().min(32),
+                    size,
+                    offset: 0,
+                });
+            }
+        }
+
+        self.merge_sections(&mut sections, section_idx)?;
+        sections.retain(|section| !section.chunks.is_empty());
+        for section_override in &self.options.section_overrides {
+            for section in &mut sections {
+                if section.name == section_override.name {
+                    section.characteristics =
+                        section.characteristics.difference(section_override.clear)
+                            | section_override.set;
+                }
+            }
+        }
+        // Stable, so sections of the same kind stay in the order they were first seen.
+        sections.sort_by_key(OutputSection::rank);
+
+        // Leave room for a .reloc section header, which we only know we need after layout.
+        let dos_stub = self.dos_stub();
+        let headers_size = align_up(
+            (dos_stub.len() + size_of::<CoffHeader>() + size_of::<OptionalHeader>()) as u32
+                + (sections.len() as u32 + 1) * SECTION_HEADER_SIZE,
+            file_alignment,
+        );
+
+        let mut plan = LinkPlan {
+            sections,
+            placements: self
+                .objects
+                .iter()
+                .map(|object| vec![None; object.sections.len()])
+                .collect(),
+            import_tables,
+            export_tables,
+            exports,
+            debug_tables,
+            thunks,
+            base_relocation_sites: Vec::new(),
+            entry: 0,
+            headers_size,
+            string_table_offset: 0,
+            certificate_table: DataDirectory::default(),
+            size_of_image: 0,
+            image_base: self.options.image_base.unwrap_or(DEFAULT_IMAGE_BASE),
+            dll_characteristics: self.options.dll_characteristics(),
+            load_config: DataDirectory::default(),
+            tls: DataDirectory::default(),
+            exception_table: DataDirectory::default(),
+            section_alignment,
+            file_alignment,
+            dos_stub,
+        };
+
+        let mut rva = align_up(headers_size, section_alignment);
+        let mut file_offset = headers_size;
+        let alignments = plan.alignments();
+        for section in &mut plan.sections {
+            assign_addresses(section, &mut rva, &mut file_offset, alignments);
+        }
+        for (out, section) in plan.sections.iter().enumerate() {
+            for chunk in &section.chunks {
+                if let ChunkKind::Input { object, section } = chunk.kind {
+                    plan.placements[object][section] = Some((out, chunk.offset));
+                }
+            }
+        }
+
+        if !self.options.fixed {
+            plan.base_relocation_sites = self.base_relocation_sites(&plan)?;
+        }
+        if !plan.base_relocation_sites.is_empty() {
+            let mut section = OutputSection {
+                name: ".reloc".to_owned(),
+                characteristics: RELOC_FLAGS,
+                chunks: vec![Chunk {
+                    kind: ChunkKind::BaseRelocations,
+                    align: 4,
+                    size: encode_base_relocations(&plan.base_relocation_sites).len() as u32,
+                    offset: 0,
+                }],
+                rva: 0,
+                virtual_size: 0,
+                file_offset: 0,
+                raw_size: 0,
+            };
+            assign_addresses(&mut section, &mut rva, &mut file_offset, plan.alignments());
+            plan.sections.push(section);
+        }
+        plan.size_of_image = rva;
+        if plan.image_base + u64::from(rva) > USER_ADDRESS_SPACE_END {
+            return fatal(
+                Code::InvalidOption,
+                format!(
+                    "image at /BASE:{:#x} would extend past the user address space",
+                    plan.image_base
+                ),
+            );
+        }
+        let string_table_size = plan.string_table().len() as u32;
+        if string_table_size > 0 {
+            plan.string_table_offset = file_offset;
+        }
+        if let Some(size) = self.options.certificate_reserve {
+            plan.certificate_table = DataDirectory {
+                virtual_address: (file_offset + string_table_size)
+                    .next_multiple_of(CERTIFICATE_ALIGNMENT),
+                size: size.next_multiple_of(CERTIFICATE_ALIGNMENT),
+            };
+        }
+        let entry = self.global_target(&plan, self.names.get(entry).unwrap())?;
+        // Absolute entry points are reported by `check_entry`.
+        plan.entry = entry.va.wrapping_sub(plan.image_base) as u32;
+        plan.load_config = self.load_config(&plan)?;
+        plan.tls = self.tls_directory(&plan)?;
+        plan.exception_table = self.exception_table(&plan);
+        if self.options.guard_cf && plan.load_config.size > 0 {
+            plan.dll_characteristics |= DllCharacteristics::IMAGE_DLLCHARACTERISTICS_GUARD_CF;
+        }
+
+        for section in &plan.sections {
+            debug!(
+                target: "layout",
+                "{:<8} rva={:#x} size={:#x} file_offset={:#x} chunks={}",
+                section.name,
+                section.rva,
+                section.virtual_size,
+                section.file_offset,
+                section.chunks.len()
+            );
+        }
+
+        Ok(plan)
+    }
+
+    fn section_target(
+        &self,
+        plan: &LinkPlan,
+        object: usize,
+        section: usize,
+        value: u32,
+    ) -> Result<Target> {
+        let Some((out, offset)) = plan.placements[object][section] else {
+            return fatal(
+                Code::InvalidRelocation,
+                format!(
+                    "{}: reference to discarded section {}",
+                    self.objects[object].name, self.objects[object].sections[section].name
+                ),
+            );
+        };
+        let moved = match self.rewrites.get(&(object, section)) {
+            Some(rewrite) => rewrite.map_address(value),
+            None => value,
+        };
+        let rva = plan.sections[out].rva + offset + moved;
+        Ok(Target {
+            va: plan.image_base + u64::from(rva),
+            section: Some(out),
+            origin: Some((object, section, value)),
+        })
+    }
+
+    fn guard_rva(&self, plan: &LinkPlan, target: GuardTarget) -> Result<u32> {
+        let target = match target {
+            GuardTarget::Section {
+                object,
+                section,
+                value,
+            } => self.section_target(plan, object, section, value)?,
+            GuardTarget::Thunk(import) => Self::synthetic_target(
+                plan,
+                ChunkKind::ImportThunks,
+                plan.thunks[import].unwrap() * IMPORT_THUNK_SIZE,
+            ),
+            GuardTarget::IatSlot(import) => {
+                Self::synthetic_target(plan, ChunkKind::Iat, plan.import_tables.slot(import))
+            }
+        };
+        Ok((target.va - plan.image_base) as u32)
+    }
+
+    fn tls_directory(&self, plan: &LinkPlan) -> Result<DataDirectory> {
+        if let Some((virtual_address, _)) = plan.chunk(ChunkKind::TlsDirectory) {
+            return Ok(DataDirectory {
+                virtual_address,
+                size: TLS_DIRECTORY_SIZE,
+            });
+        }
+        let Some(Definition::Section {
+            object,
+            section,
+            value,
+        }) = self
+            .names
+            .get(TLS_USED_SYMBOL)
+            .map(|id| self.symbols[id.index()].def)
+        else {
+            return Ok(DataDirectory::default());
+        };
+        let target = self.section_target(plan, object, section, value)?;
+        Ok(DataDirectory {
+            virtual_address: (target.va - plan.image_base) as u32,
+            size: TLS_DIRECTORY_SIZE,
+        })
+    }
+
+    /// The exception directory covers the `.pdata` inputs, which end up next
+    /// to each other in one output section even when it's merged into another.
+    fn exception_table(&self, plan: &LinkPlan) -> DataDirectory {
+        for section in &plan.sections {
+            let mut pdata = section.chunks.iter().filter(|chunk| {
+                matches!(chunk.kind, ChunkKind::Input { object, section }
+                    if output_section_name(&self.objects[object].sections[section].name) == ".pdata")
+            });
+            let Some(first) = pdata.next() else {
+                continue;
+            };
+            let last = pdata.last().unwrap_or(first);
+            return DataDirectory {
+                virtual_address: section.rva + first.offset,
+                size: last.offset + last.size - first.offset,
+            };
+        }
+        DataDirectory::default()
+    }
+
+    /// The load config directory covers `_load_config_used` as far as its
+    /// leading `Size` field says, which tells the loader which version of
+    /// the structure the CRT has.
+    fn load_config(&self, plan: &LinkPlan) -> Result<DataDirectory> {
+        if let Some((virtual_address, size)) = plan.chunk(ChunkKind::LoadConfig) {
+            return Ok(DataDirectory {
+                virtual_address,
+                size,
+            });
+        }
+        let Some(Definition::Section {
+            object,
+            section,
+            value,
+        }) = self
+            .names
+            .get(LOAD_CONFIG_SYMBOL)
+            .map(|id| self.symbols[id.index()].def)
+        else {
+            return Ok(DataDirectory::default());
+        };
+        let Some(size) = self.objects[object]
+            .section_data(section)
+            .get(value as usize..)
+            .and_then(|data| data.get(..4))
+        else {
+            return fatal(
+                Code::CorruptInput,
+                format!(
+                    "{}: {LOAD_CONFIG_SYMBOL} is out of bounds of its section",
+                    self.objects[object].name
+                ),
+            );
+        };
+        let target = self.section_target(plan, object, section, value)?;
+        Ok(DataDirectory {
+            virtual_address: (target.va - plan.image_base) as u32,
+            size: u32::from_le_bytes(size.try_into().unwrap()),
+        })
+    }
+
+    /// Where `/DEPENDENTLOADFLAG` goes in the CRT's load configuration, if
+    /// it's in this section and large enough to have the field.
+    fn dependent_load_flags_offset(&self, object: usize, section: usize) -> Option<usize> {
+        if self.options.dependent_load_flags == 0 {
+            return None;
+        }
+        let id = self.names.get(LOAD_CONFIG_SYMBOL)?;
+        let Definition::Section {
+            object: def_object,
+            section: def_section,
+            value,
+        } = self.symbols[id.index()].def
+        else {
+            return None;
+        };
+        if (def_object, def_section) != (object, section) {
+            return None;
+        }
+        let data = self.objects[object]
+            .section_data(section)
+            .get(value as usize..)?;
+        let size = u32::from_le_bytes(data.get(..4)?.try_into().unwrap()) as usize;
+        let offset = value as usize + DEPENDENT_LOAD_FLAGS_OFFSET;
+        (size >= DEPENDENT_LOAD_FLAGS_OFFSET + 2 && data.len() >= DEPENDENT_LOAD_FLAGS_OFFSET + 2)
+            .then_some(offset)
+    }
+
+    fn synthetic_target(plan: &LinkPlan, kind: ChunkKind, offset: u32) -> Target {
+        let (out, chunk) = plan
+            .sections
+            .iter()
+            .enumerate()
+            .find_map(|(idx, section)| {
+                let chunk = section.chunks.iter().find(|chunk| chunk.kind == kind)?;
+                Some((idx, chunk))
+            })
+            .expect("synthetic chunk missing from the plan");
+        let rva = plan.sections[out].rva + chunk.offset + offset;
+        Target {
+            va: plan.image_base + u64::from(rva),
+            section: Some(out),
+            origin: None,
+        }
+    }
+
+    /// Each object with its relocated `.debug$S` sections and where its
+    /// sections were placed, for the PDB.
+    fn pdb_modules(&self, plan: &LinkPlan) -> Result<Vec<Module>> {
+        let mut modules = self
+            .objects
+            .iter()
+            .map(|object| Module {
+                name: object.name.clone(),
+                debug_s: Vec::new(),
+                contributions: Vec::new(),
+            })
+            .collect::<Vec<_>>();
+        for (out, section) in plan.sections.iter().enumerate() {
+            for chunk in &section.chunks {
+                if let ChunkKind::Input {
+                    object,
+                    section: idx,
+                } = chunk.kind
+                {
+                    modules[object].contributions.push(Contribution {
+                        section: out as u16 + 1,
+                        offset: chunk.offset,
+                        size: chunk.size,
+                        characteristics: self.objects[object].sections[idx].characteristics.bits(),
+                    });
+                }
+            }
+        }
+        for (object_idx, object) in self.objects.iter().enumerate() {
+            self.options.cancel.check()?;
+            for (idx, section) in object.sections.iter().enumerate() {
+                if section.name != ".debug$S" || self.discarded[object_idx][idx] {
+                    continue;
+                }
+                let mut data = object.section_data(idx).to_vec();
+                for fixup in self.fixups(plan, object_idx, idx)? {
+                    self.apply_relocation(plan, object_idx, 0, &mut data, &fixup)?;
+                }
+                modules[object_idx].debug_s.push(data);
+            }
+        }
+        Ok(modules)
+    }
+
+    /// The defined global symbols in image sections, for the PDB.
+    fn publics(&self, plan: &LinkPlan) -> Result<Vec<Public>> {
+        let mut publics = Vec::new();
+        for (sym, id) in self.symbols.iter().zip(0..) {
+            match sym.def {
+                Definition::Section {
+                    object, section, ..
+                } if plan.placements[object][section].is_some() => {}
+                Definition::Common { .. }
+                | Definition::Import(_)
+                | Definition::ImportPointer(_) => {}
+                _ => continue,
+            }
+            let id = SymbolId::new(id);
+            let Target {
+                va,
+                section: Some(out),
+                ..
+            } = self.global_target(plan, id)?
+            else {
+                continue;
+            };
+            let section = &plan.sections[out];
+            publics.push(Public {
+                name: self.names.resolve(id).to_owned(),
+                section: out as u16 + 1,
+                offset: (va - plan.image_base) as u32 - section.rva,
+                is_code: section
+                    .characteristics
+                    .contains(SectionFlags::IMAGE_SCN_MEM_EXECUTE),
+            });
+        }
+        Ok(publics)
+    }
+
+    fn global_target(&self, plan: &LinkPlan, id: SymbolId) -> Result<Target> {
+        let sym = &self.symbols[id.index()];
+        match sym.def {
+            Definition::Section {
+                object,
+                section,
+                value,
+            } => self.section_target(plan, object, section, value),
+            Definition::Absolute(value) => Ok(Target {
+                va: value.into(),
+                section: None,
+                origin: None,
+            }),
+            Definition::Common { .. } => Ok(Self::synthetic_target(
+                plan,
+                ChunkKind::Common { symbol: id },
+                0,
+            )),
+            Definition::Import(import) => Ok(Self::synthetic_target(
+                plan,
+                ChunkKind::ImportThunks,
+                plan.thunks[import].unwrap() * IMPORT_THUNK_SIZE,
+            )),
+            Definition::ImportPointer(import) => Ok(Self::synthetic_target(
+                plan,
+                ChunkKind::Iat,
+                plan.import_tables.slot(import),
+            )),
+            Definition::ImageBase => Ok(Target {
+                va: plan.image_base,
+                section: None,
+                origin: None,
+            }),
+            Definition::StartupAnchor(idx) => Ok(Self::synthetic_target(
+                plan,
+                ChunkKind::StartupAnchor(idx),
+                0,
+            )),
+            Definition::GuardTable(table) => Ok(Self::synthetic_target(
+                plan,
+                ChunkKind::GuardTable(table),
+                0,
+            )),
+            Definition::LoadConfig => Ok(Self::synthetic_target(plan, ChunkKind::LoadConfig, 0)),
+            Definition::TlsDirectory => {
+                Ok(Self::synthetic_target(plan, ChunkKind::TlsDirectory, 0))
+            }
+            Definition::TlsIndex => Ok(Self::synthetic_target(plan, ChunkKind::TlsIndex, 0)),
+            Definition::Alias(to) => self.global_target(plan, to),
+            Definition::Weak { object, symbol } => {
+                let Some(weak) = self.objects[object].symbols.weak_external(symbol) else {
+                    return fatal(
+                        Code::CorruptInput,
+                        format!(
+                            "{}: weak external {} has no aux record",
+                            self.objects[object].name,
+                            self.names.resolve(id)
+                        ),
+                    );
+                };
+                self.symbol_target(plan, object, weak.tag_index)
+            }
+            Definition::Undefined => fatal(
+                Code::UnresolvedSymbol,
+                format!("unresolved external symbol {}", self.names.resolve(id)),
+            ),
+        }
+    }
+
+    fn is_in_discarded_section(&self, plan: &LinkPlan, object: usize, symbol: u32) -> bool {
+        let Some(sym) = self.objects[object].symbols.symbol(symbol) else {
+            return false;
+        };
+        let section = match sym.storage_class {
+            IMAGE_SYM_CLASS_EXTERNAL | IMAGE_SYM_CLASS_WEAK_EXTERNAL => None,
+            _ => plan.placements[object].get(usize::from(sym.section_number).wrapping_sub(1)),
+        };
+        section.is_some_and(Option::is_none)
+    }
+
+    fn symbol_target(&self, plan: &LinkPlan, object: usize, symbol: u32) -> Result<Target> {
+        let obj = &self.objects[object];
+        let Some(sym) = obj.symbols.symbol(symbol) else {
+            return fatal(
+                Code::CorruptInput,
+                format!("{}: invalid symbol table index {symbol}", obj.name),
+            );
+        };
+        if sym.storage_class == IMAGE_SYM_CLASS_EXTERNAL
+            || sym.storage_class == IMAGE_SYM_CLASS_WEAK_EXTERNAL
+        {
+            let id =
+                self.symbol_ids[object][symbol as usize].expect("external symbols are interned");
+            return self.global_target(plan, id);
+        }
+        match sym.section_number {
+            IMAGE_SYM_ABSOLUTE => Ok(Target {
+                va: sym.value.into(),
+                section: None,
+                origin: None,
+            }),
+            IMAGE_SYM_UNDEFINED | IMAGE_SYM_DEBUG => fatal(
+                Code::InvalidRelocation,
+                format!(
+                    "{}: relocation against undefined local symbol {}",
+                    obj.name,
+                    obj.symbols.name(sym)?
+                ),
+            ),
+            number => self.section_target(plan, object, usize::from(number) - 1, sym.value),
+        }
+    }
+
+    /// Finds all absolute fixups the loader has to adjust if the image isn't
+    /// loaded at its preferred base, as RVAs and base relocation types.
+    fn base_relocation_sites(&self, plan: &LinkPlan) -> Result<Vec<(u32, u16)>> {
+        let mut sites = Vec::new();
+        for section in &plan.sections {
+            self.options.cancel.check()?;
+            // Debuggers relocate addresses in DWARF themselves.
+            if is_dwarf_section(&section.name) {
+                continue;
+            }
+            for chunk in &section.chunks {
+                let (object, idx) = match chunk.kind {
+                    ChunkKind::Input { object, section } => (object, section),
+                    ChunkKind::LoadConfig => {
+                        for offset in self.synthesized_load_config(plan)?.pointer_offsets() {
+                            sites
+                                .push((section.rva + chunk.offset + offset, IMAGE_REL_BASED_DIR64));
+                        }
+                        continue;
+                    }
+                    ChunkKind::TlsDirectory => {
+                        for offset in self.synthesized_tls_directory(plan)?.pointer_offsets() {
+                            sites
+                                .push((section.rva + chunk.offset + offset, IMAGE_REL_BASED_DIR64));
+                        }
+                        continue;
+                    }
+                    _ => continue,
+                };
+                for fixup in self.fixups(plan, object, idx)? {
+                    let kind = match fixup.r#type {
+                        IMAGE_REL_AMD64_ADDR64 => IMAGE_REL_BASED_DIR64,
+                        IMAGE_REL_AMD64_ADDR32 => IMAGE_REL_BASED_HIGHLOW,
+                        _ => continue,
+                    };
+                    if fixup.target.section.is_none() && fixup.target.va != plan.image_base {
+                        continue;
+                    }
+                    sites.push((section.rva + chunk.offset + fixup.offset, kind));
+                }
+            }
+        }
+        sites.sort();
+        Ok(sites)
+    }
+
+    /// The relocations of an input section with their targets resolved, at
+    /// their offsets after instrumentation, including those of the stubs.
+    fn fixups(&self, plan: &LinkPlan, object: usize, section: usize) -> Result<Vec<Fixup>> {
+        let obj = &self.objects[object];
+        let rewrite = self.rewrites.get(&(object, section));
+        let is_debug_info = obj.sections[section].name.starts_with(".debug");
+        let mut fixups = Vec::new();
+        for reloc in obj.relocations(section)? {
+            // Debug info describes discarded COMDATs too. Like other linkers,
+            // we leave those references pointing at address zero.
+            if is_debug_info && self.is_in_discarded_section(plan, object, reloc.symbol_table_index)
+            {
+                continue;
+            }
+            let mut offset = reloc.virtual_address;
+            if let Some(rewrite) = rewrite {
+                let end = offset + relocation_size(reloc.r#type) as u32;
+                if rewrite.splits(offset, end) {
+                    return fatal(
+                        Code::InvalidRelocation,
+                        format!(
+                            "{}: instrumentation stub splits the relocation at {offset:#x} in {}",
+                            obj.name, obj.sections[section].name
+                        ),
+                    );
+                }
+                offset = rewrite.map_code(offset);
+            }
+            fixups.push(Fixup {
+                offset,
+                r#type: reloc.r#type,
+                target: self.symbol_target(plan, object, reloc.symbol_table_index)?,
+            });
+        }
+        if let Some(rewrite) = rewrite {
+            for ((offset, reloc), &id) in rewrite.stub_relocations().zip(&rewrite.symbols) {
+                fixups.push(Fixup {
+                    offset,
+                    r#type: reloc.r#type,
+                    target: self.global_target(plan, id)?,
+                });
+            }
+        }
+        Ok(fixups)
+    }
+
+    /// How far an addend into an instrumented section has to move along
+    /// with the code it points to.
+    fn moved_addend(&self, target: Target, addend: i64) -> i64 {
+        let Some((object, section, value)) = target.origin else {
+            return addend;
+        };
+        match self.rewrites.get(&(object, section)) {
+            Some(rewrite) => rewrite.move_addend(value, addend),
+            None => addend,
+        }
+    }
+
+    fn apply_relocation(
+        &self,
+        plan: &LinkPlan,
+        object: usize,
+        place_rva: u32,
+        data: &mut [u8],
+        fixup: &Fixup,
+    ) -> Result<()> {
+        let obj = &self.objects[object];
+        let target = fixup.target;
+        let place_va = plan.image_base + u64::from(place_rva);
+        trace!(
+            target: "relocations",
+            "{}: type {:#x} at {place_va:#x} -> {:#x}",
+            obj.name,
+            fixup.r#type,
+            target.va
+        );
+
+        let size = relocation_size(fixup.r#type);
+        if size == 0 {
+            return Ok(());
+        }
+        let offset = fixup.offset as usize;
+        let Some(field) = data.get_mut(offset..offset + size) else {
+            return fatal(
+                Code::InvalidRelocation,
+                format!("{}: relocation at {offset:#x} out of bounds", obj.name),
+            );
+        };
+
+        let read_u32 = |field: &[u8]| u32::from_le_bytes(field.try_into().unwrap());
+        let overflow = || {
+            fatal::<()>(
+                Code::RelocationOverflow,
+                format!(
+                    "{}: relocation of type {:#x} at {offset:#x} overflows",
+                    obj.name, fixup.r#type
+                ),
+            )
+        };
+
+        let addend32 = |field: &[u8]| self.moved_addend(target, read_u32(field).into()) as u32;
+        match fixup.r#type {
+            IMAGE_REL_AMD64_ADDR64 => {
+                let addend = u64::from_le_bytes(field[..8].try_into().unwrap());
+                let addend = self.moved_addend(target, addend as i64) as u64;
+                field.copy_from_slice(&target.va.wrapping_add(addend).to_le_bytes());
+            }
+            IMAGE_REL_AMD64_ADDR32 => {
+                let value = target.va.wrapping_add(addend32(field).into());
+                let Ok(value) = u32::try_from(value) else {
+                    return overflow();
+                };
+                field.copy_from_slice(&value.to_le_bytes());
+            }
+            IMAGE_REL_AMD64_ADDR32NB => {
+                let rva = target.va.wrapping_sub(plan.image_base) as u32;
+                field.copy_from_slice(&rva.wrapping_add(addend32(field)).to_le_bytes());
+            }
+            IMAGE_REL_AMD64_REL32..=IMAGE_REL_AMD64_REL32_5 => {
+                let extra = u64::from(fixup.r#type - IMAGE_REL_AMD64_REL32);
+                let addend = self.moved_addend(target, (read_u32(field) as i32).into());
+                let value = (target.va as i64 + addend) - (place_va + 4 + extra) as i64;
+                // Calls to symbols `/FORCE:UNRESOLVED` made zero can't reach it.
+                let forced =
+                    self.options.force_unresolved && target.section.is_none() && target.va == 0;
+                let value = match i32::try_from(value) {
+                    Ok(value) => value,
+                    Err(_) if forced => value as i32,
+                    Err(_) => return overflow(),
+                };
+                field.copy_from_slice(&value.to_le_bytes());
+            }
+            IMAGE_REL_AMD64_SECTION => {
+                let number = target.section.map_or(0, |idx| idx as u16 + 1);
+                field.copy_from_slice(&number.to_le_bytes());
+            }
+            IMAGE_REL_AMD64_SECREL => {
+                let section_va = target
+                    .section
+                    .map_or(0, |idx| plan.image_base + u64::from(plan.sections[idx].rva));
+                let value = (target.va - section_va) as u32;
+                field.copy_from_slice(&value.wrapping_add(addend32(field)).to_le_bytes());
+            }
+            other => {
+                return fatal(
+                    Code::InvalidRelocation,
+                    format!("{}: unsupported relocation type {other:#x}", obj.name),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Chunks own disjoint parts of the section, so they're filled in and
+    /// relocated in parallel.
+    fn section_contents(&self, plan: &LinkPlan, section: &OutputSection) -> Result<Vec<u8>> {
+        let padding = if section
+            .characteristics
+            .contains(SectionFlags::IMAGE_SCN_MEM_EXECUTE)
+        {
+            CODE_PADDING
+        } else {
+            self.options.data_padding
+        };
+        let mut buf = vec![0; section.raw_size as usize];
+        let mut pieces = Vec::with_capacity(section.chunks.len());
+        let mut rest = &mut buf[..];
+        let mut end = 0;
+        for chunk in &section.chunks {
+            // Only the gaps between chunks are padded, not the file alignment.
+            let (gap, tail) = std::mem::take(&mut rest).split_at_mut((chunk.offset - end) as usize);
+            gap.fill(padding);
+            let (data, tail) = tail.split_at_mut(chunk.size as usize);
+            pieces.push((chunk, data));
+            rest = tail;
+            end = chunk.offset + chunk.size;
+        }
+
+        par_map(pieces, |(chunk, data)| {
+            self.options.cancel.check()?;
+            self.chunk_contents(plan, section, chunk, data)
+        })
+        .into_iter()
+        .collect::<Result<()>>()?;
+
+        let exceptions = plan.exception_table;
+        // Inputs are sorted by function within themselves, but not across
+        // objects.
+        if exceptions.size > 0
+            && (section.rva..section.rva + section.virtual_size)
+                .contains(&exceptions.virtual_address)
+        {
+            let start = (exceptions.virtual_address - section.rva) as usize;
+            let table = &mut buf[start..][..exceptions.size as usize];
+            let mut entries = table
+                .chunks_exact(RUNTIME_FUNCTION_SIZE)
+                .map(|entry| <[u8; RUNTIME_FUNCTION_SIZE]>::try_from(entry).unwrap())
+                .collect::<Vec<_>>();
+            entries.sort_by_key(|entry| u32::from_le_bytes(entry[..4].try_into().unwrap()));
+            for (out, entry) in table.chunks_exact_mut(RUNTIME_FUNCTION_SIZE).zip(entries) {
+                out.copy_from_slice(&entry);
+            }
+        }
+        Ok(buf)
+    }
+
+    fn chunk_contents(
+        &self,
+        plan: &LinkPlan,
+        section: &OutputSection,
+        chunk: &Chunk,
+        data: &mut [u8],
+    ) -> Result<()> {
+        let rva = section.rva + chunk.offset;
+        match chunk.kind {
+            ChunkKind::Input { object, section } => {
+                let input = &self.objects[object];
+                let original = input.section_data(section);
+                match self.rewrites.get(&(object, section)) {
+                    Some(rewrite) => rewrite.write(original, data),
+                    // Uninitialized inputs have no data in the object, but
+                    // take up space when merged into an initialized section.
+                    None if input.sections[section]
+                        .characteristics
+                        .contains(SectionFlags::IMAGE_SCN_CNT_UNINITIALIZED_DATA) =>
+                    {
+                        data.fill(0);
+                    }
+                    None => data.copy_from_slice(original),
+                }
+                if let Some(offset) = self.dependent_load_flags_offset(object, section) {
+                    data[offset..][..2]
+                        .copy_from_slice(&self.options.dependent_load_flags.to_le_bytes());
+                }
+                let start = Instant::now();
+                for fixup in self.fixups(plan, object, section)? {
+                    self.apply_relocation(plan, object, rva + fixup.offset, data, &fixup)?;
+                }
+                self.relocation_time.add(start.elapsed());
+            }
+            ChunkKind::Common { .. }
+            | ChunkKind::StartupAnchor(_)
+            | ChunkKind::TlsIndex
+            | ChunkKind::TlsCallbacksEnd => {}
+            ChunkKind::ImportThunks => {
+                for (import, thunk) in plan.thunks.iter().enumerate() {
+                    let Some(thunk) = thunk else { continue };
+                    let thunk_rva = rva + thunk * IMPORT_THUNK_SIZE;
+                    let slot = Self::synthetic_target(
+                        plan,
+                        ChunkKind::Iat,
+                        plan.import_tables.slot(import),
+                    );
+                    let slot_rva = (slot.va - plan.image_base) as u32;
+                    let disp = slot_rva.wrapping_sub(thunk_rva + IMPORT_THUNK_SIZE);
+                    // jmp qword ptr [rip + disp32]
+                    let code = &mut data[(thunk * IMPORT_THUNK_SIZE) as usize..][..6];
+                    code[..2].copy_from_slice(&[0xFF, 0x25]);
+                    code[2..].copy_from_slice(&disp.to_le_bytes());
+                }
+            }
+            ChunkKind::Iat => {
+                let directory = Self::synthetic_target(plan, ChunkKind::ImportDirectory, 0);
+                let directory_rva = (directory.va - plan.image_base) as u32;
+                plan.import_tables
+                    .write_iat(&self.imports, directory_rva, data);
+            }
+            ChunkKind::ImportDirectory => {
+                let iat = Self::synthetic_target(plan, ChunkKind::Iat, 0);
+                let iat_rva = (iat.va - plan.image_base) as u32;
+                plan.import_tables
+                    .write_directory(&self.imports, iat_rva, rva, data);
+            }
+            ChunkKind::ExportDirectory => {
+                let functions = plan
+                    .exports
+                    .iter()
+                    .map(|&id| match id {
+                        Some(id) => Ok((self.global_target(plan, id)?.va - plan.image_base) as u32),
+                        None => Ok(0),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                plan.export_tables.write(&functions, rva, data);
+            }
+            ChunkKind::LoadConfig => self.synthesized_load_config(plan)?.write(data),
+            ChunkKind::TlsDirectory => self.synthesized_tls_directory(plan)?.write(data),
+            ChunkKind::GuardTable(table) => {
+                let mut rvas = self.guard_tables[table.index()]
+                    .iter()
+                    .map(|&target| self.guard_rva(plan, target))
+                    .collect::<Result<Vec<_>>>()?;
+                rvas.sort_unstable();
+                for (rva, slot) in rvas.into_iter().zip(data.chunks_exact_mut(4)) {
+                    slot.copy_from_slice(&rva.to_le_bytes());
+                }
+            }
+            ChunkKind::DebugDirectory => {
+                let file_offset = section.file_offset + chunk.offset;
+                plan.debug_tables.write(rva, file_offset, data);
+            }
+            ChunkKind::Resources => {
+                data.copy_from_slice(&build_resource_section(&self.resources, rva));
+            }
+            ChunkKind::BaseRelocations => {
+                data.copy_from_slice(&encode_base_relocations(&plan.base_relocation_sites));
+            }
+        }
+        Ok(())
+    }
+
+    /// Everything before the COFF header.
+    fn dos_stub(&self) -> Vec<u8> {
+        let stub = match &self.options.dos_stub {
+            Some(stub) => stub,
+            None if self.options.tiny => TINY_DOS_STUB,
+            None => MSDOS_STUB,
+        };
+        match &self.options.rich_header {
+            None => stub.to_vec(),
+            Some(RichHeader::FromObjects) => {
+                insert_rich_header(stub, &object_entries(&self.objects))
+            }
+            Some(RichHeader::Entries(entries)) => insert_rich_header(stub, entries),
+        }
+    }
+
+    fn write_image(&self, plan: &LinkPlan, outfile: &mut (impl Write + Seek)) -> Result<()> {
+        outfile.write_all(&plan.dos_stub)?;
+
+        let (stack_reserve, stack_commit) =
+            Reservation::sizes(self.options.stack, DEFAULT_RESERVE, DEFAULT_COMMIT);
+        let (heap_reserve, heap_commit) =
+            Reservation::sizes(self.options.heap, DEFAULT_RESERVE, DEFAULT_COMMIT);
+        let image_version = self.options.image_version.unwrap_or((1, 1));
+        let os_version = self.options.os_version.unwrap_or(DEFAULT_OS_VERSION);
+        let subsystem_version = self.options.subsystem_version.unwrap_or(DEFAULT_OS_VERSION);
+        let sum_raw = |flag: SectionFlags| {
+            plan.sections
+                .iter()
+                .filter(|section| section.characteristics.contains(flag))
+                .map(|section| section.raw_size)
+                .sum()
+        };
+        let size_of_uninitialized_data = plan
+            .sections
+            .iter()
+            .filter(|section| section.is_uninitialized())
+            .map(|section| align_up(section.virtual_size, plan.file_alignment))
+            .sum();
+        let base_of_code = plan
+            .sections
+            .iter()
+            .find(|section| {
+                section
+                    .characteristics
+                    .contains(SectionFlags::IMAGE_SCN_CNT_CODE)
+            })
+            .map_or(0, |section| section.rva);
+
+        let mut optional = OptionalHeader {
+            major_linker_version: 1,
+            minor_linker_version: 1,
+            size_of_code: sum_raw(SectionFlags::IMAGE_SCN_CNT_CODE),
+            size_of_initialized_data: sum_raw(SectionFlags::IMAGE_SCN_CNT_INITIALIZED_DATA),
+            size_of_uninitialized_data,
+            address_of_entry_point: plan.entry,
+            base_of_code,
+            image_base: plan.image_base,
+            section_alignment: plan.section_alignment,
+            file_alignment: plan.file_alignment,
+            major_operating_system_version: os_version.0,
+            minor_operating_system_version: os_version.1,
+            major_image_version: image_version.0,
+            minor_image_version: image_version.1,
+            major_subsystem_version: subsystem_version.0,
+            minor_subsystem_version: subsystem_version.1,
+            win32_version_value: 0,
+            size_of_image: plan.size_of_image,
+            size_of_headers: plan.headers_size,
+            check_sum: 0,
+            subsystem: self.options.subsystem.value(),
+            dll_characteristics: plan.dll_characteristics,
+            size_of_stack_reserve: stack_reserve,
+            size_of_stack_commit: stack_commit,
+            size_of_heap_reserve: heap_reserve,
+            sizeof_heap_commit: heap_commit,
+            loader_flags: 0,
+            number_of_rva_and_sizes: 16,
+            export_table: plan.export_table(),
+            import_table: plan.import_table(),
+            resource_table: plan.resource_table(),
+            exception_table: plan.exception_table,
+            certificate_table: plan.certificate_table,
+            base_relocation_table: plan.base_relocation_table(),
+            debug: plan.debug_directory(),
+            architecture: DataDirectory::default(),
+            global_ptr: DataDirectory::default(),
+            tls_table: plan.tls,
+            load_config_table: plan.load_config,
+            bound_import: DataDirectory::default(),
+            iat: plan.iat(),
+            delay_import_descriptor: DataDirectory::default(),
+            clr_runtime_header: DataDirectory::default(),
+            _reserved: DataDirectory::default(),
+        };
+        let mut optional_bytes = io::Cursor::new(Vec::new());
+        if self.options.tiny {
+            let directories = optional.data_directories();
+            let used = directories
+                .iter()
+                .rposition(|directory| *directory != DataDirectory::default())
+                .map_or(0, |idx| idx + 1);
+            optional.number_of_rva_and_sizes = used as u32;
+            optional.write(&mut optional_bytes)?;
+            optional_bytes
+                .get_mut()
+                .truncate(OptionalHeader::DATA_DIRECTORIES_OFFSET + used * 8);
+        } else {
+            optional.write(&mut optional_bytes)?;
+        }
+        let optional_bytes = optional_bytes.into_inner();
+
+        CoffHeader {
+            machine: IMAGE_FILE_MACHINE_AMD64,
+            number_of_sections: plan.sections.len().try_into().unwrap(),
+            time_date_stamp: 0,
+            pointer_to_symbol_table: plan.string_table_offset,
+            number_of_symbols: 0,
+            size_of_optional_header: optional_bytes.len().try_into().unwrap(),
+            characteristics: self.options.characteristics(),
+        }
+        .write(outfile)?;
+        outfile.write_all(&optional_bytes)?;
+
+        for header in plan.section_headers()? {
+            header.write(outfile)?;
+        }
+
+        for section in &plan.sections {
+            if section.raw_size == 0 {
+                continue;
+            }
+            self.options.cancel.check()?;
+            let contents = self.section_contents(plan, section)?;
+            let padding = u64::from(section.file_offset) - outfile.stream_position()?;
+            io::copy(&mut io::repeat(0).take(padding), outfile)?;
+            outfile.write_all(&contents)?;
+        }
+
+        let string_table = plan.string_table();
+        if !string_table.is_empty() {
+            let padding = u64::from(plan.string_table_offset) - outfile.stream_position()?;
+            io::copy(&mut io::repeat(0).take(padding), outfile)?;
+            outfile.write_all(&string_table)?;
+        }
+
+        let certificate = plan.certificate_table;
+        if certificate.size > 0 {
+            let padding = u64::from(certificate.virtual_address) - outfile.stream_position()?;
+            io::copy(
+                &mut io::repeat(0).take(padding + u64::from(certificate.size)),
+                outfile,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The loader's rules: powers of two from 512 to 64K, with sections no
+/// sparser in the file than in memory, and mapped at their file offsets below
+/// page alignment.
+fn check_alignments((section_alignment, file_alignment): (u32, u32)) -> Result<()> {
+    for (flag, alignment) in [
+        ("/ALIGN", section_alignment),
+        ("/FILEALIGN", file_alignment),
+    ] {
+        if !alignment.is_power_of_two() || !(MIN_ALIGNMENT..=MAX_ALIGNMENT).contains(&alignment) {
+            return fatal(
+                Code::InvalidOption,
+                format!("{flag}:{alignment:#x} is not a power of two from 512 to 64K"),
+            );
+        }
+    }
+    if file_alignment > section_alignment {
+        return fatal(
+            Code::InvalidOption,
+            format!(
+                "file alignment {file_alignment:#x} is larger than section alignment {section_alignment:#x}"
+            ),
+        );
+    }
+    if section_alignment < SECTION_ALIGNMENT && file_alignment != section_alignment {
+        return fatal(
+            Code::InvalidOption,
+            format!(
+                "below page alignment, file alignment {file_alignment:#x} must equal section alignment {section_alignment:#x}"
+            ),
+        );
+    }
+    Ok(())
+}
+
+/// Where the IAT is aligned to. Below page alignment, the loader maps the
+/// image with the same protection throughout, so padding it does nothing.
+fn iat_alignment(section_alignment: u32) -> u32 {
+    if section_alignment < SECTION_ALIGNMENT {
+        8
+    } else {
+        SECTION_ALIGNMENT
+    }
+}
+
+fn assign_addresses(
+    section: &mut OutputSection,
+    rva: &mut u32,
+    file_offset: &mut u32,
+    (section_alignment, file_alignment): (u32, u32),
+) {
+    let mut offset = 0;
+    for chunk in &mut section.chunks {
+        offset = align_up(offset, chunk.align);
+        chunk.offset = offset;
+        offset += chunk.size;
+    }
+    section.rva = *rva;
+    section.virtual_size = offset;
+    if section.is_uninitialized() {
+        section.file_offset = 0;
+        section.raw_size = 0;
+    } else {
+        // Below page alignment, sections are mapped at their file offsets.
+        if section_alignment < SECTION_ALIGNMENT {
+            *file_offset = *rva;
+        }
+        section.file_offset = *file_offset;
+        section.raw_size = align_up(offset, file_alignment);
+        *file_offset += section.raw_size;
+    }
+    *rva = align_up(*rva + offset, section_alignment);
+}
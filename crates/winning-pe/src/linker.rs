@@ -0,0 +1,176 @@
+use std::{fmt, io, mem};
+
+use winning_coff::diagnostics::{Code, Diagnostic, DiagnosticOptions, Diagnostics};
+
+use crate::{
+    cancel::CancellationToken,
+    debug::DebugOptions,
+    input::Input,
+    instrument::Instrument,
+    link::{LinkOptions, link},
+    pack::{Packer, pack_image},
+    pe::Subsystem,
+};
+
+/// Drives a link in-process, from inputs already in memory.
+///
+/// ```no_run
+/// # let main_obj = Vec::new();
+/// # let kernel32_lib = Vec::new();
+/// let image = winning_pe::Linker::new()
+///     .add_object("main.obj", main_obj)
+///     .add_library("kernel32.lib", kernel32_lib)
+///     .set_entry("main")
+///     .link()?;
+/// # Ok::<_, winning_pe::LinkError>(())
+/// ```
+#[derive(Default)]
+pub struct Linker {
+    options: LinkOptions,
+    diagnostic_options: DiagnosticOptions,
+    packer: Option<Box<dyn Packer>>,
+    inputs: Vec<Input>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a COFF object.
+    pub fn add_object(&mut self, name: impl Into<String>, data: Vec<u8>) -> &mut Self {
+        self.add_input(name, data)
+    }
+
+    /// Adds an archive or a short import object. Archive members are only
+    /// pulled in to resolve symbols, in the order libraries were added.
+    pub fn add_library(&mut self, name: impl Into<String>, data: Vec<u8>) -> &mut Self {
+        self.add_input(name, data)
+    }
+
+    fn add_input(&mut self, name: impl Into<String>, data: Vec<u8>) -> &mut Self {
+        self.inputs.push(Input::new(name, data));
+        self
+    }
+
+    pub fn set_entry(&mut self, entry: impl Into<String>) -> &mut Self {
+        self.options.entry = Some(entry.into());
+        self
+    }
+
+    pub fn set_subsystem(&mut self, subsystem: Subsystem) -> &mut Self {
+        self.options.subsystem = subsystem;
+        self
+    }
+
+    pub fn set_debug_options(&mut self, options: DebugOptions) -> &mut Self {
+        self.options.debug = options;
+        self
+    }
+
+    pub fn set_self_check(&mut self, self_check: bool) -> &mut Self {
+        self.options.self_check = self_check;
+        self
+    }
+
+    /// Runs `hook` on every code section that's linked in, to insert stubs.
+    pub fn set_instrumentation(&mut self, hook: impl Instrument + 'static) -> &mut Self {
+        self.options.instrument = Some(Box::new(hook));
+        self
+    }
+
+    /// Packs the linked image with `packer`, after the self-check if enabled.
+    pub fn set_packer(&mut self, packer: impl Packer + 'static) -> &mut Self {
+        self.packer = Some(Box::new(packer));
+        self
+    }
+
+    /// Lets another thread abort [`Linker::link`], which then fails with
+    /// [`Code::Cancelled`].
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) -> &mut Self {
+        self.options.cancel = token;
+        self
+    }
+
+    pub fn set_diagnostic_options(&mut self, options: DiagnosticOptions) -> &mut Self {
+        self.diagnostic_options = options;
+        self
+    }
+
+    /// Links the inputs added so far into an image, consuming them.
+    pub fn link(&mut self) -> Result<Vec<u8>, LinkError> {
+        let inputs = mem::take(&mut self.inputs);
+        let mut sink = io::sink();
+        let mut diag = Diagnostics::new(&self.diagnostic_options, &mut sink);
+        let result = link(&self.options, &inputs, &mut diag).and_then(|output| {
+            let mut image = output.image()?;
+            if self.options.self_check {
+                output.check(&image)?;
+            }
+            diag.check()?;
+            if let Some(packer) = &self.packer {
+                image = pack_image(&image, packer.as_ref())?;
+            }
+            Ok(image)
+        });
+        let mut diagnostics = diag.into_reported();
+
+        match result {
+            Ok(image) => {
+                self.diagnostics = diagnostics;
+                Ok(image)
+            }
+            Err(err) => {
+                match err.downcast::<Diagnostic>() {
+                    Ok(diagnostic) => diagnostics.push(diagnostic),
+                    // Failing because of errors reported earlier.
+                    Err(_) if diagnostics.iter().any(Diagnostic::is_error) => {}
+                    Err(err) => {
+                        diagnostics.push(Diagnostic::new(Code::CorruptInput, format!("{err:#}")))
+                    }
+                }
+                self.diagnostics.clear();
+                Err(LinkError { diagnostics })
+            }
+        }
+    }
+
+    /// The warnings reported by the last successful [`Linker::link`].
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+}
+
+/// A failed link, with everything that was reported during it.
+#[derive(Debug)]
+pub struct LinkError {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl LinkError {
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.is_error())
+    }
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut errors = self.errors();
+        match errors.next() {
+            Some(first) => {
+                write!(f, "{first}")?;
+                let rest = errors.count();
+                if rest > 0 {
+                    write!(f, " (and {rest} more error(s))")?;
+                }
+                Ok(())
+            }
+            None => f.write_str("link failed"),
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
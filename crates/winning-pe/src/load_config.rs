@@ -0,0 +1,82 @@
+//! The `IMAGE_LOAD_CONFIG_DIRECTORY64` the linker writes when no CRT
+//! provides `_load_config_used`, so that the loader still randomizes the
+//! `/GS` security cookie and finds the Control Flow Guard tables.
+
+/// The structure up to `GuardEHContinuationCount`, the layout since Windows 10 1903.
+pub const LOAD_CONFIG_SIZE: u32 = 0x118;
+/// Where `DependentLoadFlags` is, which `/DEPENDENTLOADFLAG` also sets in
+/// a load configuration from the CRT.
+pub const DEPENDENT_LOAD_FLAGS_OFFSET: usize = 78;
+
+const SECURITY_COOKIE_OFFSET: usize = 88;
+const GUARD_CF_CHECK_FUNCTION_OFFSET: usize = 112;
+const GUARD_CF_DISPATCH_FUNCTION_OFFSET: usize = 120;
+const GUARD_CF_FUNCTION_TABLE_OFFSET: usize = 128;
+const GUARD_CF_FUNCTION_COUNT_OFFSET: usize = 136;
+const GUARD_FLAGS_OFFSET: usize = 144;
+const GUARD_IAT_TABLE_OFFSET: usize = 160;
+const GUARD_IAT_COUNT_OFFSET: usize = 168;
+const GUARD_LONGJMP_TABLE_OFFSET: usize = 176;
+const GUARD_LONGJMP_COUNT_OFFSET: usize = 184;
+
+/// The fields we fill in. Addresses are VAs, or zero when there's nothing
+/// to point to.
+#[derive(Debug, Clone, Default)]
+pub struct LoadConfig {
+    pub dependent_load_flags: u16,
+    /// `__security_cookie`, which the loader overwrites with a random value.
+    pub security_cookie: u64,
+    /// `__guard_check_icall_fptr`.
+    pub guard_check_function: u64,
+    /// `__guard_dispatch_icall_fptr`.
+    pub guard_dispatch_function: u64,
+    pub guard_flags: u32,
+    /// The start and length of the function, IAT and `longjmp` tables.
+    pub guard_tables: [(u64, u64); 3],
+}
+
+impl LoadConfig {
+    pub fn write(&self, data: &mut [u8]) {
+        data[..4].copy_from_slice(&LOAD_CONFIG_SIZE.to_le_bytes());
+        data[DEPENDENT_LOAD_FLAGS_OFFSET..][..2]
+            .copy_from_slice(&self.dependent_load_flags.to_le_bytes());
+        let [functions, iat, longjmp] = self.guard_tables;
+        for (offset, value) in [
+            (SECURITY_COOKIE_OFFSET, self.security_cookie),
+            (GUARD_CF_CHECK_FUNCTION_OFFSET, self.guard_check_function),
+            (
+                GUARD_CF_DISPATCH_FUNCTION_OFFSET,
+                self.guard_dispatch_function,
+            ),
+            (GUARD_CF_FUNCTION_TABLE_OFFSET, functions.0),
+            (GUARD_CF_FUNCTION_COUNT_OFFSET, functions.1),
+            (GUARD_IAT_TABLE_OFFSET, iat.0),
+            (GUARD_IAT_COUNT_OFFSET, iat.1),
+            (GUARD_LONGJMP_TABLE_OFFSET, longjmp.0),
+            (GUARD_LONGJMP_COUNT_OFFSET, longjmp.1),
+        ] {
+            data[offset..][..8].copy_from_slice(&value.to_le_bytes());
+        }
+        data[GUARD_FLAGS_OFFSET..][..4].copy_from_slice(&self.guard_flags.to_le_bytes());
+    }
+
+    /// The offsets of the addresses that are set, which need base relocations.
+    pub fn pointer_offsets(&self) -> Vec<u32> {
+        let [functions, iat, longjmp] = self.guard_tables;
+        [
+            (SECURITY_COOKIE_OFFSET, self.security_cookie),
+            (GUARD_CF_CHECK_FUNCTION_OFFSET, self.guard_check_function),
+            (
+                GUARD_CF_DISPATCH_FUNCTION_OFFSET,
+                self.guard_dispatch_function,
+            ),
+            (GUARD_CF_FUNCTION_TABLE_OFFSET, functions.0),
+            (GUARD_IAT_TABLE_OFFSET, iat.0),
+            (GUARD_LONGJMP_TABLE_OFFSET, longjmp.0),
+        ]
+        .into_iter()
+        .filter(|&(_, value)| value != 0)
+        .map(|(offset, _)| offset as u32)
+        .collect()
+    }
+}
@@ -0,0 +1,89 @@
+//! Application manifests, so programs get themed common controls, sharp text
+//! on high-DPI displays and no UAC installer detection without running `mt.exe`.
+
+use crate::resources::{Resource, ResourceId};
+
+pub const RT_MANIFEST: u16 = 24;
+/// `CREATEPROCESS_MANIFEST_RESOURCE_ID`, the manifest the loader applies to an EXE.
+const CREATEPROCESS_MANIFEST_RESOURCE_ID: u16 = 1;
+/// US English, which link.exe also uses for the manifests it embeds.
+const LANG_EN_US: u16 = 0x409;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ManifestOptions {
+    /// Run with the caller's privileges, instead of UAC guessing whether the
+    /// program is an installer that needs elevation.
+    pub uac: bool,
+    /// Per-monitor DPI awareness, so Windows doesn't scale the window as a bitmap.
+    pub dpi_aware: bool,
+    /// A dependency on comctl32 v6, for visual styles.
+    pub common_controls: bool,
+}
+
+impl ManifestOptions {
+    pub fn to_xml(self) -> String {
+        let mut xml = String::from(concat!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+            "\n",
+            r#"<assembly xmlns="urn:schemas-microsoft-com:asm.v1" manifestVersion="1.0">"#,
+            "\n",
+        ));
+        if self.uac {
+            xml.push_str(concat!(
+                r#"  <trustInfo xmlns="urn:schemas-microsoft-com:asm.v3">"#,
+                "\n",
+                "    <security>\n",
+                "      <requestedPrivileges>\n",
+                r#"        <requestedExecutionLevel level="asInvoker" uiAccess="false"/>"#,
+                "\n",
+                "      </requestedPrivileges>\n",
+                "    </security>\n",
+                "  </trustInfo>\n",
+            ));
+        }
+        if self.common_controls {
+            xml.push_str(concat!(
+                "  <dependency>\n",
+                "    <dependentAssembly>\n",
+                r#"      <assemblyIdentity type="win32" name="Microsoft.Windows.Common-Controls" "#,
+                r#"version="6.0.0.0" processorArchitecture="*" "#,
+                r#"publicKeyToken="6595b64144ccf1df" language="*"/>"#,
+                "\n",
+                "    </dependentAssembly>\n",
+                "  </dependency>\n",
+            ));
+        }
+        if self.dpi_aware {
+            // `dpiAware` is for Windows versions before 10 1607, which ignore `dpiAwareness`.
+            xml.push_str(concat!(
+                r#"  <application xmlns="urn:schemas-microsoft-com:asm.v3">"#,
+                "\n",
+                "    <windowsSettings>\n",
+                r#"      <dpiAware xmlns="http://schemas.microsoft.com/SMI/2005/WindowsSettings">true/pm</dpiAware>"#,
+                "\n",
+                r#"      <dpiAwareness xmlns="http://schemas.microsoft.com/SMI/2016/WindowsSettings">PerMonitorV2, PerMonitor</dpiAwareness>"#,
+                "\n",
+                "    </windowsSettings>\n",
+                "  </application>\n",
+            ));
+        }
+        xml.push_str("</assembly>\n");
+        xml
+    }
+
+    /// The manifest as the resource the loader looks for.
+    pub fn resource(self) -> Resource {
+        manifest_resource(self.to_xml())
+    }
+}
+
+/// A manifest as the resource the loader looks for.
+pub fn manifest_resource(xml: String) -> Resource {
+    Resource {
+        r#type: ResourceId::Id(RT_MANIFEST),
+        name: ResourceId::Id(CREATEPROCESS_MANIFEST_RESOURCE_ID),
+        language: LANG_EN_US,
+        code_page: 0,
+        data: xml.into_bytes(),
+    }
+}
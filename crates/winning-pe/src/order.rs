@@ -0,0 +1,57 @@
+use color_eyre::Result;
+use winning_coff::diagnostics::{Code, fatal};
+
+/// Input sections to place at the very start of `.text` and `.rdata`, so
+/// startup-critical code and data land in the first pages the loader reads,
+/// read from a startup order file. Each line names a symbol, whose section
+/// is pinned, or an input section as `section <object> <name>`, where
+/// `<object>` is an input or archive member name. `#` starts a comment.
+///
+/// Pinned sections go in the order listed, right after the IAT in `.rdata`.
+/// The pinned ranges are bracketed by [`STARTUP_ANCHORS`], which code can
+/// reference to prefetch them.
+#[derive(Debug, Clone, Default)]
+pub struct StartupOrder {
+    pub entries: Vec<OrderEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub enum OrderEntry {
+    Symbol(String),
+    Section { object: String, section: String },
+}
+
+/// Linker-defined symbols at the begin and end of the pinned sections, with
+/// the output section they're in.
+pub const STARTUP_ANCHORS: [(&str, &str, bool); 4] = [
+    ("__startup_text_begin", ".text", false),
+    ("__startup_text_end", ".text", true),
+    ("__startup_rdata_begin", ".rdata", false),
+    ("__startup_rdata_end", ".rdata", true),
+];
+
+impl StartupOrder {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut entries = Vec::new();
+        for line in text.lines() {
+            let line = line.split_once('#').map_or(line, |(line, _)| line).trim();
+            let mut words = line.split_whitespace();
+            let entry = match (words.next(), words.next(), words.next(), words.next()) {
+                (None, ..) => continue,
+                (Some(symbol), None, ..) => OrderEntry::Symbol(symbol.to_owned()),
+                (Some("section"), Some(object), Some(section), None) => OrderEntry::Section {
+                    object: object.to_owned(),
+                    section: section.to_owned(),
+                },
+                _ => {
+                    return fatal(
+                        Code::InvalidOption,
+                        format!("invalid startup order entry `{line}`"),
+                    );
+                }
+            };
+            entries.push(entry);
+        }
+        Ok(Self { entries })
+    }
+}
@@ -0,0 +1,271 @@
+//! Packing linked images the way UPX does: the sections are replaced by
+//! compressed data and a small stub, which becomes the entry point,
+//! decompresses them at startup and then jumps to the original entry point.
+//!
+//! We don't ship a compressor or a stub, only the layout around them. A
+//! [`Packer`] gets the laid-out image and returns the sections of the packed
+//! one at addresses of its choosing, and [`pack_image`] writes the headers
+//! for them.
+
+use std::io;
+
+use binrw::BinWrite;
+use color_eyre::Result;
+use winning_coff::{
+    coff::{CoffHeader, SectionFlags, SectionHeader},
+    diagnostics::{Code, fatal},
+};
+
+use crate::{
+    image::ImageModel,
+    pe::{DataDirectory, OptionalHeader, PE_SIGNATURE, checksum},
+};
+
+const SECTION_HEADER_SIZE: usize = 40;
+
+/// A hook turning a linked image into a packed one.
+///
+/// The loader still processes the packed image's directories before the stub
+/// runs, so those usually describe the stub's own imports and relocations,
+/// and the stub then processes the original ones itself. A common layout is
+/// one uninitialized section spanning the original sections, for the stub to
+/// decompress into, followed by one with the compressed data and the stub.
+/// That keeps every original RVA valid, including those in the PDB.
+pub trait Packer: Send + Sync {
+    fn pack(&self, image: &UnpackedImage<'_>) -> Result<PackedImage>;
+}
+
+/// The linked image, as handed to a [`Packer`].
+pub struct UnpackedImage<'a> {
+    pub image_base: u64,
+    pub section_alignment: u32,
+    /// The first section can start at the end of the headers, section-aligned.
+    pub size_of_headers: u32,
+    /// The RVA of the original entry point, for the stub to jump to.
+    pub entry_point: u32,
+    pub sections: Vec<ImageSection<'a>>,
+    /// Indexed like the data directories in the optional header.
+    pub data_directories: [DataDirectory; 16],
+}
+
+pub struct ImageSection<'a> {
+    pub name: &'a str,
+    pub rva: u32,
+    pub virtual_size: u32,
+    pub characteristics: SectionFlags,
+    /// The raw data, padded to the file alignment and so possibly longer or
+    /// shorter than the virtual size.
+    pub data: &'a [u8],
+}
+
+/// What a [`Packer`] turns an image into.
+pub struct PackedImage {
+    /// Sorted by RVA, and each aligned to the section alignment.
+    pub sections: Vec<PackedSection>,
+    /// The RVA of the stub.
+    pub entry_point: u32,
+    /// The certificate table is ignored, since it's not part of the image.
+    pub data_directories: [DataDirectory; 16],
+}
+
+pub struct PackedSection {
+    /// At most 8 bytes, as there's no string table for longer names.
+    pub name: String,
+    pub rva: u32,
+    pub virtual_size: u32,
+    pub characteristics: SectionFlags,
+    /// At most `virtual_size` bytes; the rest is zero-filled at load time.
+    pub data: Vec<u8>,
+}
+
+/// Packs an image with `packer`, keeping its headers apart from the section
+/// table and the fields that describe the sections.
+pub fn pack_image(data: &[u8], packer: &dyn Packer) -> Result<Vec<u8>> {
+    let image = ImageModel::parse(data)?;
+    let mut optional = image.optional_header.clone();
+    if optional.certificate_table.size > 0 {
+        return fatal(
+            Code::UnsupportedImage,
+            "signed images can't be packed; pack before signing",
+        );
+    }
+
+    let mut sections = Vec::with_capacity(image.sections.len());
+    for section in &image.sections {
+        let Some(contents) = image.section_data(section) else {
+            return fatal(
+                Code::CorruptInput,
+                format!("section {} data out of bounds", section.name),
+            );
+        };
+        sections.push(ImageSection {
+            name: &section.name,
+            rva: section.virtual_address,
+            virtual_size: section.virtual_size,
+            characteristics: section.characteristics,
+            data: contents,
+        });
+    }
+    let packed = packer.pack(&UnpackedImage {
+        image_base: optional.image_base,
+        section_alignment: optional.section_alignment,
+        size_of_headers: optional.size_of_headers,
+        entry_point: optional.address_of_entry_point,
+        sections,
+        data_directories: optional.data_directories(),
+    })?;
+
+    let section_alignment = optional.section_alignment;
+    let file_alignment = optional.file_alignment;
+    let section_table = image.pe_offset
+        + PE_SIGNATURE.len()
+        + size_of::<CoffHeader>()
+        + usize::from(image.header.size_of_optional_header);
+    if section_table + packed.sections.len() * SECTION_HEADER_SIZE
+        > optional.size_of_headers as usize
+    {
+        return fatal(
+            Code::InvalidOption,
+            format!(
+                "packer returned {} sections, more than fit in the headers",
+                packed.sections.len()
+            ),
+        );
+    }
+    let mut next_rva = optional.size_of_headers.next_multiple_of(section_alignment);
+    for section in &packed.sections {
+        let problem = if section.name.len() > 8 {
+            Some("has a name longer than 8 bytes")
+        } else if section.rva < next_rva || section.rva % section_alignment != 0 {
+            Some("is not section-aligned after the previous section")
+        } else if section.data.len() > section.virtual_size as usize {
+            Some("has more data than its virtual size")
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            return fatal(
+                Code::InvalidOption,
+                format!(
+                    "packed section {} at {:#x} {problem}",
+                    section.name, section.rva
+                ),
+            );
+        }
+        next_rva = (section.rva + section.virtual_size).next_multiple_of(section_alignment);
+    }
+    let entry_is_code = packed.sections.iter().any(|section| {
+        section
+            .characteristics
+            .contains(SectionFlags::IMAGE_SCN_MEM_EXECUTE)
+            && (section.rva..section.rva + section.virtual_size).contains(&packed.entry_point)
+    });
+    if !entry_is_code {
+        return fatal(
+            Code::InvalidOption,
+            format!(
+                "packed entry point {:#x} is not in an executable section",
+                packed.entry_point
+            ),
+        );
+    }
+
+    let mut file_offset = optional.size_of_headers.next_multiple_of(file_alignment);
+    let headers = packed
+        .sections
+        .iter()
+        .map(|section| {
+            let raw_size = (section.data.len() as u32).next_multiple_of(file_alignment);
+            let header = SectionHeader {
+                name: section.name.as_str().into(),
+                virtual_size: section.virtual_size,
+                virtual_address: section.rva,
+                size_of_raw_data: raw_size,
+                pointer_to_raw_data: if raw_size == 0 { 0 } else { file_offset },
+                pointer_to_relocations: 0,
+                pointer_to_linenumbers: 0,
+                number_of_relocations: 0,
+                number_of_linenumbers: 0,
+                characteristics: section.characteristics,
+            };
+            file_offset += raw_size;
+            header
+        })
+        .collect::<Vec<_>>();
+
+    let sum_raw = |flag: SectionFlags| {
+        headers
+            .iter()
+            .filter(|header| header.characteristics.contains(flag))
+            .map(|header| header.size_of_raw_data)
+            .sum()
+    };
+    optional.size_of_code = sum_raw(SectionFlags::IMAGE_SCN_CNT_CODE);
+    optional.size_of_initialized_data = sum_raw(SectionFlags::IMAGE_SCN_CNT_INITIALIZED_DATA);
+    optional.size_of_uninitialized_data = headers
+        .iter()
+        .filter(|header| {
+            header
+                .characteristics
+                .contains(SectionFlags::IMAGE_SCN_CNT_UNINITIALIZED_DATA)
+        })
+        .map(|header| header.virtual_size.next_multiple_of(file_alignment))
+        .sum();
+    optional.base_of_code = headers
+        .iter()
+        .find(|header| {
+            header
+                .characteristics
+                .contains(SectionFlags::IMAGE_SCN_CNT_CODE)
+        })
+        .map_or(0, |header| header.virtual_address);
+    optional.address_of_entry_point = packed.entry_point;
+    optional.size_of_image = next_rva;
+    for (idx, (directory, packed)) in optional
+        .data_directories_mut()
+        .into_iter()
+        .zip(packed.data_directories)
+        .enumerate()
+    {
+        if idx != OptionalHeader::CERTIFICATE_TABLE_INDEX {
+            *directory = packed;
+        }
+    }
+
+    let mut out = data[..optional.size_of_headers as usize].to_vec();
+    // Clear the old section table, which may have had more entries.
+    out[section_table..].fill(0);
+    for (header, section) in headers.iter().zip(&packed.sections) {
+        if header.size_of_raw_data > 0 {
+            out.resize(header.pointer_to_raw_data as usize, 0);
+            out.extend_from_slice(&section.data);
+            out.resize(
+                (header.pointer_to_raw_data + header.size_of_raw_data) as usize,
+                0,
+            );
+        }
+    }
+
+    // The string table, if any, named sections that are gone now.
+    let mut header = image.header;
+    header.number_of_sections = headers.len() as u16;
+    header.pointer_to_symbol_table = 0;
+    let cursor = &mut io::Cursor::new(&mut out);
+    cursor.set_position((image.pe_offset + PE_SIGNATURE.len()) as u64);
+    header.write(cursor)?;
+    let optional_offset = cursor.position() as usize;
+    let had_checksum = optional.check_sum != 0;
+    optional.check_sum = 0;
+    image.write_optional_header(&optional, cursor)?;
+    cursor.set_position(section_table as u64);
+    for header in &headers {
+        header.write(cursor)?;
+    }
+
+    if had_checksum {
+        let checksum_offset = optional_offset + OptionalHeader::CHECKSUM_OFFSET;
+        let sum = checksum(&out, checksum_offset);
+        out[checksum_offset..checksum_offset + 4].copy_from_slice(&sum.to_le_bytes());
+    }
+    Ok(out)
+}
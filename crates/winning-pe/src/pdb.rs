@@ -0,0 +1,782 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use binrw::BinWrite;
+use color_eyre::Result;
+use winning_coff::{
+    coff::{IMAGE_FILE_MACHINE_AMD64, SectionFlags, SectionHeader},
+    diagnostics::{Code, fatal},
+};
+
+const BLOCK_SIZE: usize = 4096;
+const MSF_MAGIC: &[u8; 32] = b"Microsoft C/C++ MSF 7.00\r\n\x1aDS\0\0\0";
+/// The superblock and the two free block maps of the first interval.
+const FIRST_DATA_BLOCK: u32 = 3;
+
+const PDB_INFO_VERSION_VC70: u32 = 20000404;
+const PDB_FEATURE_VC140: u32 = 20140508;
+const TPI_VERSION_V80: u32 = 20040203;
+const TPI_HEADER_SIZE: u32 = 56;
+const FIRST_TYPE_INDEX: u32 = 0x1000;
+const TPI_HASH_BUCKETS: u32 = 0x3FFFF;
+const DBI_VERSION_V70: u32 = 19990903;
+/// Major version 14 in the new build number format.
+const DBI_BUILD_NUMBER: u16 = 0x8000 | (14 << 8);
+const SECTION_CONTRIBUTION_VERSION_V60: u32 = 0xEFFE_0000 + 19970605;
+const SECTION_CONTRIBUTION_SIZE: usize = 28;
+const GSI_HASH_SIGNATURE: u32 = 0xFFFF_FFFF;
+const GSI_HASH_VERSION_V70: u32 = 0xEFFE_0000 + 19990810;
+const GSI_HASH_BUCKETS: u32 = 4096;
+/// The in-memory size of a hash record, which bucket offsets are counted in.
+const GSI_HASH_RECORD_CALC_SIZE: u32 = 12;
+const STRING_TABLE_SIGNATURE: u32 = 0xEFFE_EFFE;
+const NO_STREAM: u16 = 0xFFFF;
+
+const CV_SIGNATURE_C13: u32 = 4;
+const DEBUG_S_SYMBOLS: u32 = 0xF1;
+const DEBUG_S_STRINGTABLE: u32 = 0xF3;
+const DEBUG_S_FILECHKSMS: u32 = 0xF4;
+const DEBUG_S_IGNORE: u32 = 0x8000_0000;
+
+const S_END: u16 = 0x0006;
+const S_THUNK32: u16 = 0x1102;
+const S_BLOCK32: u16 = 0x1103;
+const S_LPROC32: u16 = 0x110F;
+const S_GPROC32: u16 = 0x1110;
+const S_SEPCODE: u16 = 0x1132;
+const S_LPROC32_ID: u16 = 0x1146;
+const S_GPROC32_ID: u16 = 0x1147;
+const S_INLINESITE: u16 = 0x114D;
+const S_INLINESITE_END: u16 = 0x114E;
+const S_PROC_ID_END: u16 = 0x114F;
+const S_PUB32: u16 = 0x110E;
+const PUBLIC_CODE: u32 = 1;
+const PUBLIC_FUNCTION: u32 = 2;
+
+const SECTION_MAP_READ: u16 = 1;
+const SECTION_MAP_WRITE: u16 = 2;
+const SECTION_MAP_EXECUTE: u16 = 4;
+const SECTION_MAP_ADDRESS_IS_32_BIT: u16 = 8;
+const SECTION_MAP_IS_SELECTOR: u16 = 0x100;
+const SECTION_MAP_IS_ABSOLUTE_ADDRESS: u16 = 0x200;
+
+// Fixed stream numbers, in the order the streams are written.
+const INFO_STREAM: u16 = 1;
+const TPI_STREAM: u16 = 2;
+const DBI_STREAM: u16 = 3;
+const IPI_STREAM: u16 = 4;
+const NAMES_STREAM: u16 = 5;
+const GLOBALS_STREAM: u16 = 6;
+const PUBLICS_STREAM: u16 = 7;
+const SYMBOL_RECORD_STREAM: u16 = 8;
+const SECTION_HEADER_STREAM: u16 = 9;
+/// Module streams follow the fixed ones.
+const FIRST_MODULE_STREAM: u16 = 10;
+
+/// A public symbol, at an offset in a 1-based image section.
+pub struct Public {
+    pub name: String,
+    pub section: u16,
+    pub offset: u32,
+    pub is_code: bool,
+}
+
+/// An input object, with its CodeView symbols and line info.
+pub struct Module {
+    pub name: String,
+    /// The relocated `.debug$S` sections.
+    pub debug_s: Vec<Vec<u8>>,
+    pub contributions: Vec<Contribution>,
+}
+
+/// A section of a module, at an offset in a 1-based image section.
+pub struct Contribution {
+    pub section: u16,
+    pub offset: u32,
+    pub size: u32,
+    pub characteristics: u32,
+}
+
+/// Writes a PDB with public symbols, the section headers, and the symbols and
+/// line info of each module. Type records aren't merged yet, so type indices
+/// in module symbols still refer to the object's own `.debug$T`. `guid` and
+/// `age` have to match the RSDS record in the image.
+pub fn write_pdb(
+    out: &mut impl Write,
+    guid: [u8; 16],
+    age: u32,
+    sections: &[SectionHeader<'_>],
+    publics: &[Public],
+    modules: &[Module],
+) -> Result<()> {
+    let (symbol_records, record_offsets) = symbol_records(publics);
+    let mut names = StringTable::default();
+    let module_streams = modules
+        .iter()
+        .map(|module| ModuleStream::new(module, &mut names))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut streams = vec![Vec::new(); usize::from(FIRST_MODULE_STREAM)];
+    streams[usize::from(INFO_STREAM)] = info_stream(guid, age);
+    streams[usize::from(TPI_STREAM)] = type_stream();
+    streams[usize::from(DBI_STREAM)] = dbi_stream(age, sections, modules, &module_streams);
+    streams[usize::from(IPI_STREAM)] = type_stream();
+    streams[usize::from(NAMES_STREAM)] = names.encode();
+    streams[usize::from(GLOBALS_STREAM)] = gsi_hash(&[]);
+    streams[usize::from(PUBLICS_STREAM)] = publics_stream(publics, &record_offsets);
+    streams[usize::from(SYMBOL_RECORD_STREAM)] = symbol_records;
+    let headers = &mut io::Cursor::new(Vec::new());
+    for section in sections {
+        section.write(headers)?;
+    }
+    streams[usize::from(SECTION_HEADER_STREAM)] = headers.get_ref().clone();
+    streams.extend(module_streams.iter().map(ModuleStream::encode));
+
+    write_msf(out, &streams)
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn info_stream(guid: [u8; 16], age: u32) -> Vec<u8> {
+    let mut out = Vec::new();
+    put_u32(&mut out, PDB_INFO_VERSION_VC70);
+    put_u32(&mut out, u32::from_le_bytes(guid[..4].try_into().unwrap()));
+    put_u32(&mut out, age);
+    out.extend_from_slice(&guid);
+
+    // The named stream map, a hash table with `/names` as its only entry.
+    let names = b"/names\0";
+    put_u32(&mut out, names.len() as u32);
+    out.extend_from_slice(names);
+    put_u32(&mut out, 1); // size
+    put_u32(&mut out, 1); // capacity
+    put_u32(&mut out, 1); // present bit vector words
+    put_u32(&mut out, 1);
+    put_u32(&mut out, 0); // deleted bit vector words
+    put_u32(&mut out, 0); // offset of the name
+    put_u32(&mut out, u32::from(NAMES_STREAM));
+
+    put_u32(&mut out, PDB_FEATURE_VC140);
+    out
+}
+
+/// An empty TPI or IPI stream.
+fn type_stream() -> Vec<u8> {
+    let mut out = Vec::new();
+    put_u32(&mut out, TPI_VERSION_V80);
+    put_u32(&mut out, TPI_HEADER_SIZE);
+    put_u32(&mut out, FIRST_TYPE_INDEX);
+    put_u32(&mut out, FIRST_TYPE_INDEX);
+    put_u32(&mut out, 0); // type record bytes
+    put_u16(&mut out, NO_STREAM); // hash stream
+    put_u16(&mut out, NO_STREAM); // hash aux stream
+    put_u32(&mut out, 4); // hash key size
+    put_u32(&mut out, TPI_HASH_BUCKETS);
+    // Offsets and sizes of the hash values, type index offsets and hash adjusters.
+    out.extend_from_slice(&[0; 24]);
+    out
+}
+
+/// The `/names` stream, where line info refers to file names.
+struct StringTable {
+    buffer: Vec<u8>,
+    offsets: HashMap<String, u32>,
+}
+
+impl Default for StringTable {
+    fn default() -> Self {
+        Self {
+            // Offset 0 is the empty string.
+            buffer: vec![0],
+            offsets: HashMap::new(),
+        }
+    }
+}
+
+impl StringTable {
+    fn insert(&mut self, name: &str) -> u32 {
+        if let Some(&offset) = self.offsets.get(name) {
+            return offset;
+        }
+        let offset = self.buffer.len() as u32;
+        self.buffer.extend_from_slice(name.as_bytes());
+        self.buffer.push(0);
+        self.offsets.insert(name.to_owned(), offset);
+        offset
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        // An open addressing hash table of the offsets, with linear probing.
+        let mut buckets = vec![0; self.offsets.len() * 2 + 1];
+        let mut names = self.offsets.iter().collect::<Vec<_>>();
+        names.sort_by_key(|&(_, &offset)| offset);
+        for (name, &offset) in names {
+            let mut slot = hash_string(name) as usize % buckets.len();
+            while buckets[slot] != 0 {
+                slot = (slot + 1) % buckets.len();
+            }
+            buckets[slot] = offset;
+        }
+
+        let mut out = Vec::new();
+        put_u32(&mut out, STRING_TABLE_SIGNATURE);
+        put_u32(&mut out, 1); // hash version
+        put_u32(&mut out, self.buffer.len() as u32);
+        out.extend_from_slice(&self.buffer);
+        put_u32(&mut out, buckets.len() as u32);
+        for bucket in buckets {
+            put_u32(&mut out, bucket);
+        }
+        put_u32(&mut out, self.offsets.len() as u32);
+        out
+    }
+}
+
+/// The contents of a module stream, built from the `.debug$S` sections of an
+/// object: its symbol records with their scopes linked up, and the other
+/// subsections as C13 line info, with file names moved to `/names`.
+struct ModuleStream {
+    symbols: Vec<u8>,
+    lines: Vec<u8>,
+    /// The source files, from the file checksums.
+    files: Vec<String>,
+}
+
+impl ModuleStream {
+    fn new(module: &Module, names: &mut StringTable) -> Result<Self> {
+        let mut stream = Self {
+            symbols: CV_SIGNATURE_C13.to_le_bytes().to_vec(),
+            lines: Vec::new(),
+            files: Vec::new(),
+        };
+        let mut scopes = Vec::new();
+        for section in &module.debug_s {
+            let subsections = subsections(&module.name, section)?;
+            let strings = subsections
+                .iter()
+                .find(|&&(kind, _)| kind == DEBUG_S_STRINGTABLE)
+                .map_or(&[][..], |&(_, data)| data);
+            for &(kind, data) in &subsections {
+                match kind {
+                    DEBUG_S_SYMBOLS => stream.add_symbols(&module.name, data, &mut scopes)?,
+                    DEBUG_S_STRINGTABLE => {}
+                    DEBUG_S_FILECHKSMS => {
+                        let checksums =
+                            stream.move_file_names(&module.name, data, strings, names)?;
+                        stream.add_lines(kind, &checksums);
+                    }
+                    _ if kind & DEBUG_S_IGNORE != 0 => {}
+                    _ => stream.add_lines(kind, data),
+                }
+            }
+        }
+        Ok(stream)
+    }
+
+    /// Appends symbol records, each padded to 4 bytes, and fills in the
+    /// parent and end offsets of scopes, which are left zero in objects.
+    fn add_symbols(
+        &mut self,
+        object: &str,
+        mut data: &[u8],
+        scopes: &mut Vec<usize>,
+    ) -> Result<()> {
+        while data.len() >= 4 {
+            let len = usize::from(u16::from_le_bytes([data[0], data[1]]));
+            let Some(record) = data.get(..len + 2).filter(|_| len >= 2) else {
+                return fatal(
+                    Code::CorruptInput,
+                    format!("{object}: truncated CodeView symbol"),
+                );
+            };
+            data = &data[len + 2..];
+
+            let start = self.symbols.len();
+            let size = record.len().next_multiple_of(4);
+            self.symbols.extend_from_slice(record);
+            self.symbols.resize(start + size, 0);
+            self.symbols[start..start + 2].copy_from_slice(&((size - 2) as u16).to_le_bytes());
+
+            let kind = u16::from_le_bytes([record[2], record[3]]);
+            match kind {
+                S_GPROC32 | S_LPROC32 | S_GPROC32_ID | S_LPROC32_ID | S_BLOCK32 | S_THUNK32
+                | S_INLINESITE | S_SEPCODE
+                    if size >= 12 =>
+                {
+                    let parent = scopes.last().map_or(0, |&parent| parent as u32);
+                    self.symbols[start + 4..start + 8].copy_from_slice(&parent.to_le_bytes());
+                    scopes.push(start);
+                }
+                S_END | S_PROC_ID_END | S_INLINESITE_END => {
+                    if let Some(scope) = scopes.pop() {
+                        self.symbols[scope + 8..scope + 12]
+                            .copy_from_slice(&(start as u32).to_le_bytes());
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrites the file checksums to name files by their offset in `/names`
+    /// instead of in the object's string table.
+    fn move_file_names(
+        &mut self,
+        object: &str,
+        data: &[u8],
+        strings: &[u8],
+        names: &mut StringTable,
+    ) -> Result<Vec<u8>> {
+        let mut checksums = data.to_vec();
+        let mut pos = 0;
+        while pos + 6 <= checksums.len() {
+            let offset = u32::from_le_bytes(checksums[pos..pos + 4].try_into().unwrap());
+            let name = strings
+                .get(offset as usize..)
+                .and_then(|name| name.split(|&b| b == 0).next())
+                .map(String::from_utf8_lossy);
+            let Some(name) = name else {
+                return fatal(
+                    Code::CorruptInput,
+                    format!("{object}: file name offset {offset:#x} is out of bounds"),
+                );
+            };
+            let offset = names.insert(&name);
+            checksums[pos..pos + 4].copy_from_slice(&offset.to_le_bytes());
+            self.files.push(name.into_owned());
+            let checksum_size = usize::from(checksums[pos + 4]);
+            pos = (pos + 6 + checksum_size).next_multiple_of(4);
+        }
+        Ok(checksums)
+    }
+
+    fn add_lines(&mut self, kind: u32, data: &[u8]) {
+        put_u32(&mut self.lines, kind);
+        put_u32(&mut self.lines, data.len() as u32);
+        self.lines.extend_from_slice(data);
+        self.lines.resize(self.lines.len().next_multiple_of(4), 0);
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = self.symbols.clone();
+        out.extend_from_slice(&self.lines);
+        put_u32(&mut out, 0); // global references
+        out
+    }
+}
+
+/// Splits a `.debug$S` section into its subsections.
+fn subsections<'a>(object: &str, section: &'a [u8]) -> Result<Vec<(u32, &'a [u8])>> {
+    let read_u32 = |pos: usize| {
+        section
+            .get(pos..pos + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+    };
+    if read_u32(0) != Some(CV_SIGNATURE_C13) {
+        return fatal(
+            Code::CorruptInput,
+            format!("{object}: .debug$S is not in the C13 CodeView format"),
+        );
+    }
+    let mut subsections = Vec::new();
+    let mut pos = 4;
+    while pos < section.len() {
+        let data = read_u32(pos)
+            .zip(read_u32(pos + 4))
+            .and_then(|(kind, len)| {
+                let data = section.get(pos + 8..pos + 8 + len as usize)?;
+                Some((kind, data))
+            });
+        let Some((kind, data)) = data else {
+            return fatal(
+                Code::CorruptInput,
+                format!("{object}: truncated .debug$S subsection at {pos:#x}"),
+            );
+        };
+        subsections.push((kind, data));
+        pos = (pos + 8 + data.len()).next_multiple_of(4);
+    }
+    Ok(subsections)
+}
+
+fn dbi_stream(
+    age: u32,
+    sections: &[SectionHeader<'_>],
+    modules: &[Module],
+    module_streams: &[ModuleStream],
+) -> Vec<u8> {
+    let mut module_info = Vec::new();
+    for (idx, (module, stream)) in modules.iter().zip(module_streams).enumerate() {
+        put_u32(&mut module_info, 0); // unused
+        match module.contributions.first() {
+            Some(contribution) => section_contribution(&mut module_info, contribution, idx),
+            None => module_info.extend_from_slice(&[0; SECTION_CONTRIBUTION_SIZE]),
+        }
+        put_u16(&mut module_info, 0); // flags
+        put_u16(&mut module_info, FIRST_MODULE_STREAM + idx as u16);
+        put_u32(&mut module_info, stream.symbols.len() as u32);
+        put_u32(&mut module_info, 0); // C11 line info size
+        put_u32(&mut module_info, stream.lines.len() as u32);
+        put_u16(&mut module_info, stream.files.len() as u16);
+        put_u16(&mut module_info, 0); // padding
+        put_u32(&mut module_info, 0); // unused
+        put_u32(&mut module_info, 0); // source file name index
+        put_u32(&mut module_info, 0); // PDB file name index
+        // The module and object file names.
+        for _ in 0..2 {
+            module_info.extend_from_slice(module.name.as_bytes());
+            module_info.push(0);
+        }
+        module_info.resize(module_info.len().next_multiple_of(4), 0);
+    }
+
+    let mut section_contributions = Vec::new();
+    put_u32(&mut section_contributions, SECTION_CONTRIBUTION_VERSION_V60);
+    let mut contributions = modules
+        .iter()
+        .enumerate()
+        .flat_map(|(idx, module)| module.contributions.iter().map(move |c| (c, idx)))
+        .collect::<Vec<_>>();
+    contributions.sort_by_key(|(contribution, _)| (contribution.section, contribution.offset));
+    for (contribution, idx) in contributions {
+        section_contribution(&mut section_contributions, contribution, idx);
+    }
+
+    // One entry per section, then one for absolute addresses.
+    let count = sections.len() as u16 + 1;
+    let mut section_map = Vec::new();
+    put_u16(&mut section_map, count);
+    put_u16(&mut section_map, count);
+    for (idx, section) in sections.iter().enumerate() {
+        let mut flags = SECTION_MAP_ADDRESS_IS_32_BIT | SECTION_MAP_IS_SELECTOR;
+        for (flag, bit) in [
+            (SectionFlags::IMAGE_SCN_MEM_READ, SECTION_MAP_READ),
+            (SectionFlags::IMAGE_SCN_MEM_WRITE, SECTION_MAP_WRITE),
+            (SectionFlags::IMAGE_SCN_MEM_EXECUTE, SECTION_MAP_EXECUTE),
+        ] {
+            if section.characteristics.contains(flag) {
+                flags |= bit;
+            }
+        }
+        section_map_entry(
+            &mut section_map,
+            flags,
+            idx as u16 + 1,
+            section.virtual_size,
+        );
+    }
+    section_map_entry(
+        &mut section_map,
+        SECTION_MAP_ADDRESS_IS_32_BIT | SECTION_MAP_IS_ABSOLUTE_ADDRESS,
+        count,
+        u32::MAX,
+    );
+
+    // The source files of each module, with their names in a separate buffer.
+    let mut file_info = Vec::new();
+    let file_count = module_streams
+        .iter()
+        .map(|stream| stream.files.len())
+        .sum::<usize>();
+    put_u16(&mut file_info, modules.len() as u16);
+    // The total is truncated, readers count the files of each module instead.
+    put_u16(&mut file_info, file_count as u16);
+    let mut first = 0;
+    for stream in module_streams {
+        put_u16(&mut file_info, first as u16);
+        first += stream.files.len();
+    }
+    for stream in module_streams {
+        put_u16(&mut file_info, stream.files.len() as u16);
+    }
+    let mut file_names = Vec::new();
+    let mut file_name_offsets = HashMap::new();
+    for file in module_streams.iter().flat_map(|stream| &stream.files) {
+        let offset = *file_name_offsets.entry(file).or_insert_with(|| {
+            let offset = file_names.len() as u32;
+            file_names.extend_from_slice(file.as_bytes());
+            file_names.push(0);
+            offset
+        });
+        put_u32(&mut file_info, offset);
+    }
+    file_info.extend_from_slice(&file_names);
+    file_info.resize(file_info.len().next_multiple_of(4), 0);
+
+    let mut optional_header = Vec::new();
+    for stream in [
+        NO_STREAM, // FPO
+        NO_STREAM, // exception
+        NO_STREAM, // fixup
+        NO_STREAM, // OMAP to source
+        NO_STREAM, // OMAP from source
+        SECTION_HEADER_STREAM,
+        NO_STREAM, // token RID map
+        NO_STREAM, // xdata
+        NO_STREAM, // pdata
+        NO_STREAM, // new FPO
+        NO_STREAM, // original section headers
+    ] {
+        put_u16(&mut optional_header, stream);
+    }
+
+    let mut out = Vec::new();
+    put_u32(&mut out, u32::MAX); // version signature
+    put_u32(&mut out, DBI_VERSION_V70);
+    put_u32(&mut out, age);
+    put_u16(&mut out, GLOBALS_STREAM);
+    put_u16(&mut out, DBI_BUILD_NUMBER);
+    put_u16(&mut out, PUBLICS_STREAM);
+    put_u16(&mut out, 0); // PDB DLL version
+    put_u16(&mut out, SYMBOL_RECORD_STREAM);
+    put_u16(&mut out, 0); // PDB DLL rebuild
+    put_u32(&mut out, module_info.len() as u32);
+    put_u32(&mut out, section_contributions.len() as u32);
+    put_u32(&mut out, section_map.len() as u32);
+    put_u32(&mut out, file_info.len() as u32);
+    put_u32(&mut out, 0); // type server map size
+    put_u32(&mut out, 0); // MFC type server index
+    put_u32(&mut out, optional_header.len() as u32);
+    // Edit-and-continue names, in the format of `/names`.
+    let ec_names = StringTable::default().encode();
+    put_u32(&mut out, ec_names.len() as u32);
+    put_u16(&mut out, 0); // flags
+    put_u16(&mut out, IMAGE_FILE_MACHINE_AMD64);
+    put_u32(&mut out, 0); // padding
+    out.extend_from_slice(&module_info);
+    out.extend_from_slice(&section_contributions);
+    out.extend_from_slice(&section_map);
+    out.extend_from_slice(&file_info);
+    out.extend_from_slice(&ec_names);
+    out.extend_from_slice(&optional_header);
+    out
+}
+
+fn section_contribution(out: &mut Vec<u8>, contribution: &Contribution, module: usize) {
+    put_u16(out, contribution.section);
+    put_u16(out, 0); // padding
+    put_u32(out, contribution.offset);
+    put_u32(out, contribution.size);
+    put_u32(out, contribution.characteristics);
+    put_u16(out, module as u16);
+    put_u16(out, 0); // padding
+    put_u32(out, 0); // data CRC
+    put_u32(out, 0); // relocation CRC
+}
+
+fn section_map_entry(out: &mut Vec<u8>, flags: u16, frame: u16, length: u32) {
+    put_u16(out, flags);
+    put_u16(out, 0); // overlay
+    put_u16(out, 0); // group
+    put_u16(out, frame);
+    put_u16(out, NO_STREAM); // section name
+    put_u16(out, NO_STREAM); // class name
+    put_u32(out, 0); // offset
+    put_u32(out, length);
+}
+
+/// The `S_PUB32` records, and the offset of each in the stream.
+fn symbol_records(publics: &[Public]) -> (Vec<u8>, Vec<u32>) {
+    let mut out = Vec::new();
+    let mut offsets = Vec::with_capacity(publics.len());
+    for public in publics {
+        let start = out.len();
+        offsets.push(start as u32);
+        // Length, kind, flags, offset, segment and the name, padded to 4 bytes.
+        let size = (2 + 2 + 4 + 4 + 2 + public.name.len() + 1).next_multiple_of(4);
+        // The length doesn't count itself.
+        put_u16(&mut out, (size - 2) as u16);
+        put_u16(&mut out, S_PUB32);
+        let flags = if public.is_code {
+            PUBLIC_CODE | PUBLIC_FUNCTION
+        } else {
+            0
+        };
+        put_u32(&mut out, flags);
+        put_u32(&mut out, public.offset);
+        put_u16(&mut out, public.section);
+        out.extend_from_slice(public.name.as_bytes());
+        out.resize(start + size, 0);
+    }
+    (out, offsets)
+}
+
+/// The `hashStringV1` function used for symbol name hash tables.
+fn hash_string(name: &str) -> u32 {
+    let bytes = name.as_bytes();
+    let mut words = bytes.chunks_exact(4);
+    let mut result = 0;
+    for word in &mut words {
+        result ^= u32::from_le_bytes(word.try_into().unwrap());
+    }
+    let mut rest = words.remainder();
+    if rest.len() >= 2 {
+        result ^= u32::from(u16::from_le_bytes([rest[0], rest[1]]));
+        rest = &rest[2..];
+    }
+    if let [byte] = rest {
+        result ^= u32::from(*byte);
+    }
+    result |= 0x2020_2020;
+    result ^= result >> 11;
+    result ^ (result >> 16)
+}
+
+/// A GSI hash table over the given names and symbol record offsets.
+fn gsi_hash(symbols: &[(&str, u32)]) -> Vec<u8> {
+    let mut buckets = vec![Vec::new(); GSI_HASH_BUCKETS as usize];
+    for &(name, offset) in symbols {
+        buckets[(hash_string(name) % GSI_HASH_BUCKETS) as usize].push((name, offset));
+    }
+
+    let mut records = Vec::new();
+    let mut bitmap = vec![0u32; (GSI_HASH_BUCKETS as usize + 32) / 32];
+    let mut bucket_offsets = Vec::new();
+    let mut count = 0;
+    for (idx, bucket) in buckets.iter_mut().enumerate() {
+        if bucket.is_empty() {
+            continue;
+        }
+        bucket.sort();
+        bitmap[idx / 32] |= 1 << (idx % 32);
+        put_u32(&mut bucket_offsets, count * GSI_HASH_RECORD_CALC_SIZE);
+        for &(_, offset) in bucket.iter() {
+            // Offsets are biased by one, and each record has a reference count.
+            put_u32(&mut records, offset + 1);
+            put_u32(&mut records, 1);
+            count += 1;
+        }
+    }
+
+    let mut out = Vec::new();
+    put_u32(&mut out, GSI_HASH_SIGNATURE);
+    put_u32(&mut out, GSI_HASH_VERSION_V70);
+    put_u32(&mut out, records.len() as u32);
+    put_u32(&mut out, (bitmap.len() * 4 + bucket_offsets.len()) as u32);
+    out.extend_from_slice(&records);
+    for word in bitmap {
+        put_u32(&mut out, word);
+    }
+    out.extend_from_slice(&bucket_offsets);
+    out
+}
+
+fn publics_stream(publics: &[Public], record_offsets: &[u32]) -> Vec<u8> {
+    let symbols = publics
+        .iter()
+        .zip(record_offsets)
+        .map(|(public, &offset)| (public.name.as_str(), offset))
+        .collect::<Vec<_>>();
+    let hash = gsi_hash(&symbols);
+
+    // Record offsets sorted by address, for looking up symbols near one.
+    let mut by_address = (0..publics.len()).collect::<Vec<_>>();
+    by_address.sort_by_key(|&idx| {
+        let public = &publics[idx];
+        (public.section, public.offset, public.name.as_str())
+    });
+
+    let mut out = Vec::new();
+    put_u32(&mut out, hash.len() as u32);
+    put_u32(&mut out, publics.len() as u32 * 4);
+    // No incremental linking thunks and no section map.
+    out.extend_from_slice(&[0; 20]);
+    out.extend_from_slice(&hash);
+    for idx in by_address {
+        put_u32(&mut out, record_offsets[idx]);
+    }
+    out
+}
+
+/// Lays out `streams` in an MSF container with 4 KiB blocks.
+fn write_msf(out: &mut impl Write, streams: &[Vec<u8>]) -> Result<()> {
+    let blocks_for = |len: usize| len.div_ceil(BLOCK_SIZE);
+    let mut next_block = FIRST_DATA_BLOCK;
+    // Every interval of `BLOCK_SIZE` blocks starts with two blocks for the
+    // free block maps, after the superblock in the first one.
+    let mut allocate = |count: usize| {
+        (0..count)
+            .map(|_| {
+                while matches!(next_block as usize % BLOCK_SIZE, 1 | 2) {
+                    next_block += 1;
+                }
+                next_block += 1;
+                next_block - 1
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let stream_blocks = streams
+        .iter()
+        .map(|stream| allocate(blocks_for(stream.len())))
+        .collect::<Vec<_>>();
+    let mut directory = Vec::new();
+    put_u32(&mut directory, streams.len() as u32);
+    for stream in streams {
+        put_u32(&mut directory, stream.len() as u32);
+    }
+    for &block in stream_blocks.iter().flatten() {
+        put_u32(&mut directory, block);
+    }
+    let directory_blocks = allocate(blocks_for(directory.len()));
+    if directory_blocks.len() * 4 > BLOCK_SIZE {
+        return fatal(Code::CannotOpenOutput, "PDB stream directory is too large");
+    }
+    let block_map = allocate(1)[0];
+    let mut block_count = next_block as usize;
+    if matches!(block_count % BLOCK_SIZE, 1 | 2) {
+        block_count += 3 - block_count % BLOCK_SIZE;
+    }
+
+    let mut file = vec![0; block_count * BLOCK_SIZE];
+    let mut put_block = |block: u32, data: &[u8]| {
+        file[block as usize * BLOCK_SIZE..][..data.len()].copy_from_slice(data);
+    };
+
+    let mut superblock = MSF_MAGIC.to_vec();
+    put_u32(&mut superblock, BLOCK_SIZE as u32);
+    put_u32(&mut superblock, 1); // free block map
+    put_u32(&mut superblock, block_count as u32);
+    put_u32(&mut superblock, directory.len() as u32);
+    put_u32(&mut superblock, 0);
+    put_u32(&mut superblock, block_map);
+    put_block(0, &superblock);
+
+    // The free block map is a bit per block, set for free ones, stored in the
+    // first map block of each interval in turn.
+    let mut free_map = vec![0xFF; (block_count / 8 + 1).next_multiple_of(BLOCK_SIZE)];
+    for block in 0..block_count {
+        free_map[block / 8] &= !(1 << (block % 8));
+    }
+    for (interval, map) in free_map.chunks(BLOCK_SIZE).enumerate() {
+        if interval * BLOCK_SIZE < block_count {
+            put_block((interval * BLOCK_SIZE + 1) as u32, map);
+        }
+    }
+
+    for (stream, blocks) in streams.iter().zip(&stream_blocks) {
+        for (data, &block) in stream.chunks(BLOCK_SIZE).zip(blocks) {
+            put_block(block, data);
+        }
+    }
+    for (data, &block) in directory.chunks(BLOCK_SIZE).zip(&directory_blocks) {
+        put_block(block, data);
+    }
+    let mut map = Vec::new();
+    for &block in &directory_blocks {
+        put_u32(&mut map, block);
+    }
+    put_block(block_map, &map);
+
+    out.write_all(&file)?;
+    Ok(())
+}
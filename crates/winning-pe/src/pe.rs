@@ -0,0 +1,532 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+use binrw::{BinRead, BinWrite};
+use color_eyre::Result;
+use winning_coff::diagnostics::{Code, fatal};
+
+use crate::link::SECTION_ALIGNMENT;
+
+pub const MSDOS_STUB: &[u8] = include_bytes!("msdos-stub.bin");
+pub const DOS_MAGIC: &[u8] = b"MZ";
+/// Offset of `e_lfanew`, the file offset of the PE signature, in the DOS header.
+pub const PE_POINTER_OFFSET: usize = 0x3c;
+pub const PE_SIGNATURE: &[u8] = b"PE\0\0";
+/// The size of the `IMAGE_DOS_HEADER` a stub program starts with.
+const DOS_HEADER_SIZE: usize = 0x40;
+/// The magic of [`OptionalHeader32`].
+pub const PE32_MAGIC: u16 = 0x10b;
+/// The magic of [`OptionalHeader`].
+pub const PE32_PLUS_MAGIC: u16 = 0x20b;
+
+/// The `IMAGE_DOS_HEADER` every image starts with. Windows only looks at the
+/// magic and `e_lfanew`, the rest is for the DOS program in the stub.
+#[derive(Debug, Clone, BinRead, BinWrite)]
+#[brw(little, magic = b"MZ")]
+#[repr(C)]
+pub struct DosHeader {
+    pub e_cblp: u16,
+    pub e_cp: u16,
+    pub e_crlc: u16,
+    pub e_cparhdr: u16,
+    pub e_minalloc: u16,
+    pub e_maxalloc: u16,
+    pub e_ss: u16,
+    pub e_sp: u16,
+    pub e_csum: u16,
+    pub e_ip: u16,
+    pub e_cs: u16,
+    pub e_lfarlc: u16,
+    pub e_ovno: u16,
+    pub e_res: [u16; 4],
+    pub e_oemid: u16,
+    pub e_oeminfo: u16,
+    pub e_res2: [u16; 10],
+    /// The file offset of the PE signature.
+    pub e_lfanew: u32,
+}
+
+/// For `--tiny`: a bare DOS header without a program, and the PE signature
+/// right after it.
+pub const TINY_DOS_STUB: &[u8] = &{
+    let mut stub = [0; DOS_HEADER_SIZE + 4];
+    stub[0] = b'M';
+    stub[1] = b'Z';
+    stub[PE_POINTER_OFFSET] = DOS_HEADER_SIZE as u8;
+    stub[DOS_HEADER_SIZE] = b'P';
+    stub[DOS_HEADER_SIZE + 1] = b'E';
+    stub
+};
+
+/// Turns a DOS program from `/STUB` into what goes before the PE headers,
+/// like [`MSDOS_STUB`]: the program padded to 8 bytes, followed by the PE
+/// signature that its `e_lfanew` is changed to point to.
+pub fn custom_dos_stub(program: &[u8], source: &str) -> Result<Vec<u8>> {
+    if !program.starts_with(DOS_MAGIC) || program.len() < DOS_HEADER_SIZE {
+        return fatal(
+            Code::CorruptInput,
+            format!("{source}: a /STUB must be an MS-DOS executable starting with an MZ header"),
+        );
+    }
+    let mut stub = program.to_vec();
+    stub.resize(stub.len().next_multiple_of(8), 0);
+    let Ok(pe_offset) = u32::try_from(stub.len()) else {
+        return fatal(Code::CorruptInput, format!("{source}: /STUB is too large"));
+    };
+    stub[PE_POINTER_OFFSET..][..4].copy_from_slice(&pe_offset.to_le_bytes());
+    stub.extend_from_slice(PE_SIGNATURE);
+    Ok(stub)
+}
+
+#[derive(Debug, Clone, BinRead, BinWrite)]
+#[brw(little, magic = b"\x0b\x02")]
+#[repr(C)]
+pub struct OptionalHeader {
+    pub major_linker_version: u8,
+    pub minor_linker_version: u8,
+    pub size_of_code: u32,
+    pub size_of_initialized_data: u32,
+    pub size_of_uninitialized_data: u32,
+    pub address_of_entry_point: u32,
+    pub base_of_code: u32,
+    // Windows extension
+    pub image_base: u64,
+    pub section_alignment: u32,
+    pub file_alignment: u32,
+    pub major_operating_system_version: u16,
+    pub minor_operating_system_version: u16,
+    pub major_image_version: u16,
+    pub minor_image_version: u16,
+    pub major_subsystem_version: u16,
+    pub minor_subsystem_version: u16,
+    pub win32_version_value: u32,
+    pub size_of_image: u32,
+    pub size_of_headers: u32,
+    pub check_sum: u32,
+    pub subsystem: u16,
+    #[br(map = |val: u16| DllCharacteristics::from_bits_retain(val))]
+    #[bw(map = |val| val.bits())]
+    pub dll_characteristics: DllCharacteristics,
+    pub size_of_stack_reserve: u64,
+    pub size_of_stack_commit: u64,
+    pub size_of_heap_reserve: u64,
+    pub sizeof_heap_commit: u64,
+    pub loader_flags: u32,
+    pub number_of_rva_and_sizes: u32,
+    // Data directories
+    pub export_table: DataDirectory,
+    pub import_table: DataDirectory,
+    pub resource_table: DataDirectory,
+    pub exception_table: DataDirectory,
+    pub certificate_table: DataDirectory,
+    pub base_relocation_table: DataDirectory,
+    pub debug: DataDirectory,
+    pub architecture: DataDirectory,
+    pub global_ptr: DataDirectory,
+    pub tls_table: DataDirectory,
+    pub load_config_table: DataDirectory,
+    pub bound_import: DataDirectory,
+    pub iat: DataDirectory,
+    pub delay_import_descriptor: DataDirectory,
+    pub clr_runtime_header: DataDirectory,
+    pub _reserved: DataDirectory,
+}
+
+/// The optional header of PE32 images, for 32-bit targets, which we only
+/// read and edit. [`ImageModel`](crate::image::ImageModel) widens it into an
+/// [`OptionalHeader`] so that everything else only has to handle one kind.
+#[derive(Debug, Clone, BinRead, BinWrite)]
+#[brw(little, magic = b"\x0b\x01")]
+#[repr(C)]
+pub struct OptionalHeader32 {
+    pub major_linker_version: u8,
+    pub minor_linker_version: u8,
+    pub size_of_code: u32,
+    pub size_of_initialized_data: u32,
+    pub size_of_uninitialized_data: u32,
+    pub address_of_entry_point: u32,
+    pub base_of_code: u32,
+    /// Not in PE32+, so it's kept here for writing the header back.
+    pub base_of_data: u32,
+    // Windows extension
+    pub image_base: u32,
+    pub section_alignment: u32,
+    pub file_alignment: u32,
+    pub major_operating_system_version: u16,
+    pub minor_operating_system_version: u16,
+    pub major_image_version: u16,
+    pub minor_image_version: u16,
+    pub major_subsystem_version: u16,
+    pub minor_subsystem_version: u16,
+    pub win32_version_value: u32,
+    pub size_of_image: u32,
+    pub size_of_headers: u32,
+    pub check_sum: u32,
+    pub subsystem: u16,
+    #[br(map = |val: u16| DllCharacteristics::from_bits_retain(val))]
+    #[bw(map = |val| val.bits())]
+    pub dll_characteristics: DllCharacteristics,
+    pub size_of_stack_reserve: u32,
+    pub size_of_stack_commit: u32,
+    pub size_of_heap_reserve: u32,
+    pub size_of_heap_commit: u32,
+    pub loader_flags: u32,
+    pub number_of_rva_and_sizes: u32,
+    pub data_directories: [DataDirectory; 16],
+}
+
+impl OptionalHeader32 {
+    /// [`OptionalHeader::DATA_DIRECTORIES_OFFSET`] for PE32 headers, which
+    /// have 4-byte stack and heap sizes but a `base_of_data`.
+    pub const DATA_DIRECTORIES_OFFSET: usize = 96;
+
+    pub fn widen(&self) -> OptionalHeader {
+        let [
+            export_table,
+            import_table,
+            resource_table,
+            exception_table,
+            certificate_table,
+            base_relocation_table,
+            debug,
+            architecture,
+            global_ptr,
+            tls_table,
+            load_config_table,
+            bound_import,
+            iat,
+            delay_import_descriptor,
+            clr_runtime_header,
+            _reserved,
+        ] = self.data_directories;
+        OptionalHeader {
+            major_linker_version: self.major_linker_version,
+            minor_linker_version: self.minor_linker_version,
+            size_of_code: self.size_of_code,
+            size_of_initialized_data: self.size_of_initialized_data,
+            size_of_uninitialized_data: self.size_of_uninitialized_data,
+            address_of_entry_point: self.address_of_entry_point,
+            base_of_code: self.base_of_code,
+            image_base: self.image_base.into(),
+            section_alignment: self.section_alignment,
+            file_alignment: self.file_alignment,
+            major_operating_system_version: self.major_operating_system_version,
+            minor_operating_system_version: self.minor_operating_system_version,
+            major_image_version: self.major_image_version,
+            minor_image_version: self.minor_image_version,
+            major_subsystem_version: self.major_subsystem_version,
+            minor_subsystem_version: self.minor_subsystem_version,
+            win32_version_value: self.win32_version_value,
+            size_of_image: self.size_of_image,
+            size_of_headers: self.size_of_headers,
+            check_sum: self.check_sum,
+            subsystem: self.subsystem,
+            dll_characteristics: self.dll_characteristics,
+            size_of_stack_reserve: self.size_of_stack_reserve.into(),
+            size_of_stack_commit: self.size_of_stack_commit.into(),
+            size_of_heap_reserve: self.size_of_heap_reserve.into(),
+            sizeof_heap_commit: self.size_of_heap_commit.into(),
+            loader_flags: self.loader_flags,
+            number_of_rva_and_sizes: self.number_of_rva_and_sizes,
+            export_table,
+            import_table,
+            resource_table,
+            exception_table,
+            certificate_table,
+            base_relocation_table,
+            debug,
+            architecture,
+            global_ptr,
+            tls_table,
+            load_config_table,
+            bound_import,
+            iat,
+            delay_import_descriptor,
+            clr_runtime_header,
+            _reserved,
+        }
+    }
+
+    /// The inverse of [`widen`](Self::widen), for writing back an edited
+    /// header. Values that don't fit are truncated, as a PE32 image can't
+    /// have them anyway.
+    pub fn narrow(&self, header: &OptionalHeader) -> Self {
+        Self {
+            major_linker_version: header.major_linker_version,
+            minor_linker_version: header.minor_linker_version,
+            size_of_code: header.size_of_code,
+            size_of_initialized_data: header.size_of_initialized_data,
+            size_of_uninitialized_data: header.size_of_uninitialized_data,
+            address_of_entry_point: header.address_of_entry_point,
+            base_of_code: header.base_of_code,
+            base_of_data: self.base_of_data,
+            image_base: header.image_base as u32,
+            section_alignment: header.section_alignment,
+            file_alignment: header.file_alignment,
+            major_operating_system_version: header.major_operating_system_version,
+            minor_operating_system_version: header.minor_operating_system_version,
+            major_image_version: header.major_image_version,
+            minor_image_version: header.minor_image_version,
+            major_subsystem_version: header.major_subsystem_version,
+            minor_subsystem_version: header.minor_subsystem_version,
+            win32_version_value: header.win32_version_value,
+            size_of_image: header.size_of_image,
+            size_of_headers: header.size_of_headers,
+            check_sum: header.check_sum,
+            subsystem: header.subsystem,
+            dll_characteristics: header.dll_characteristics,
+            size_of_stack_reserve: header.size_of_stack_reserve as u32,
+            size_of_stack_commit: header.size_of_stack_commit as u32,
+            size_of_heap_reserve: header.size_of_heap_reserve as u32,
+            size_of_heap_commit: header.sizeof_heap_commit as u32,
+            loader_flags: header.loader_flags,
+            number_of_rva_and_sizes: header.number_of_rva_and_sizes,
+            data_directories: header.data_directories(),
+        }
+    }
+}
+
+impl OptionalHeader {
+    /// File offset of `check_sum` relative to the start of the optional header,
+    /// in both PE32 and PE32+ headers.
+    pub const CHECKSUM_OFFSET: usize = 64;
+    /// The size of the header before the data directories, which
+    /// `number_of_rva_and_sizes` says how many of follow.
+    pub const DATA_DIRECTORIES_OFFSET: usize = 112;
+    pub const CERTIFICATE_TABLE_INDEX: usize = 4;
+
+    pub fn data_directories(&self) -> [DataDirectory; 16] {
+        self.clone()
+            .data_directories_mut()
+            .map(|directory| *directory)
+    }
+
+    pub fn data_directories_mut(&mut self) -> [&mut DataDirectory; 16] {
+        [
+            &mut self.export_table,
+            &mut self.import_table,
+            &mut self.resource_table,
+            &mut self.exception_table,
+            &mut self.certificate_table,
+            &mut self.base_relocation_table,
+            &mut self.debug,
+            &mut self.architecture,
+            &mut self.global_ptr,
+            &mut self.tls_table,
+            &mut self.load_config_table,
+            &mut self.bound_import,
+            &mut self.iat,
+            &mut self.delay_import_descriptor,
+            &mut self.clr_runtime_header,
+            &mut self._reserved,
+        ]
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, BinRead, BinWrite)]
+#[brw(little)]
+#[repr(C)]
+pub struct DataDirectory {
+    pub virtual_address: u32,
+    pub size: u32,
+}
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[repr(C)]
+    pub struct DllCharacteristics: u16 {
+        const IMAGE_DLLCHARACTERISTICS_HIGH_ENTROPY_VA = 0x0020; // Image can handle a high entropy 64-bit virtual address space.
+        const IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE = 0x0040; // DLL can be relocated at load time.
+        const IMAGE_DLLCHARACTERISTICS_FORCE_INTEGRITY = 0x0080; // Code Integrity checks are enforced.
+        const IMAGE_DLLCHARACTERISTICS_NX_COMPAT = 0x0100; // Image is NX compatible.
+        const IMAGE_DLLCHARACTERISTICS_NO_ISOLATION = 0x0200; // Isolation aware, but do not isolate the image.
+        const IMAGE_DLLCHARACTERISTICS_NO_SEH = 0x0400; // Does not use structured exception (SE) handling. No SE handler may be called in this image.
+        const IMAGE_DLLCHARACTERISTICS_NO_BIND = 0x0800; // Do not bind the image.
+        const IMAGE_DLLCHARACTERISTICS_APPCONTAINER = 0x1000; // Image must execute in an AppContainer.
+        const IMAGE_DLLCHARACTERISTICS_WDM_DRIVER = 0x2000; // A WDM driver.
+        const IMAGE_DLLCHARACTERISTICS_GUARD_CF = 0x4000; // Image supports Control Flow Guard.
+        const IMAGE_DLLCHARACTERISTICS_TERMINAL_SERVER_AWARE = 0x8000; // Terminal Server aware.
+    }
+}
+
+pub const IMAGE_SUBSYSTEM_NATIVE: u16 = 1;
+pub const IMAGE_SUBSYSTEM_WINDOWS_GUI: u16 = 2;
+pub const IMAGE_SUBSYSTEM_WINDOWS_CUI: u16 = 3;
+pub const IMAGE_SUBSYSTEM_EFI_APPLICATION: u16 = 10;
+pub const IMAGE_SUBSYSTEM_EFI_BOOT_SERVICE_DRIVER: u16 = 11;
+pub const IMAGE_SUBSYSTEM_EFI_RUNTIME_DRIVER: u16 = 12;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    #[default]
+    Console,
+    Windows,
+    /// Drivers and native processes like `smss.exe`, which don't run on
+    /// the Win32 subsystem.
+    Native,
+    EfiApplication,
+    EfiBootServiceDriver,
+    EfiRuntimeDriver,
+}
+
+impl Subsystem {
+    /// Parses a `/SUBSYSTEM` name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "console" => Some(Self::Console),
+            "windows" => Some(Self::Windows),
+            "native" => Some(Self::Native),
+            "efi_application" => Some(Self::EfiApplication),
+            "efi_boot_service_driver" => Some(Self::EfiBootServiceDriver),
+            "efi_runtime_driver" => Some(Self::EfiRuntimeDriver),
+            _ => None,
+        }
+    }
+
+    pub fn value(self) -> u16 {
+        match self {
+            Self::Console => IMAGE_SUBSYSTEM_WINDOWS_CUI,
+            Self::Windows => IMAGE_SUBSYSTEM_WINDOWS_GUI,
+            Self::Native => IMAGE_SUBSYSTEM_NATIVE,
+            Self::EfiApplication => IMAGE_SUBSYSTEM_EFI_APPLICATION,
+            Self::EfiBootServiceDriver => IMAGE_SUBSYSTEM_EFI_BOOT_SERVICE_DRIVER,
+            Self::EfiRuntimeDriver => IMAGE_SUBSYSTEM_EFI_RUNTIME_DRIVER,
+        }
+    }
+
+    /// UEFI images, which the firmware loads and relocates.
+    pub fn is_efi(self) -> bool {
+        matches!(
+            self,
+            Self::EfiApplication | Self::EfiBootServiceDriver | Self::EfiRuntimeDriver
+        )
+    }
+}
+
+pub const IMAGE_REL_BASED_ABSOLUTE: u16 = 0;
+pub const IMAGE_REL_BASED_HIGHLOW: u16 = 3;
+pub const IMAGE_REL_BASED_DIR64: u16 = 10;
+
+/// Encodes sorted base relocation sites into `.reloc` blocks, one per page.
+pub fn encode_base_relocations(sites: &[(u32, u16)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut rest = sites;
+    while let Some(&(first, _)) = rest.first() {
+        let page = first & !(SECTION_ALIGNMENT - 1);
+        let len = rest
+            .iter()
+            .position(|&(rva, _)| rva >= page + SECTION_ALIGNMENT)
+            .unwrap_or(rest.len());
+        let (block, remaining) = rest.split_at(len);
+        rest = remaining;
+
+        // Blocks must be 4-byte aligned, so odd ones get a padding entry.
+        let entries = block.len() + block.len() % 2;
+        out.extend_from_slice(&page.to_le_bytes());
+        out.extend_from_slice(&(8 + entries as u32 * 2).to_le_bytes());
+        for &(rva, kind) in block {
+            out.extend_from_slice(&((kind << 12) | (rva - page) as u16).to_le_bytes());
+        }
+        if block.len() % 2 == 1 {
+            out.extend_from_slice(&IMAGE_REL_BASED_ABSOLUTE.to_le_bytes());
+        }
+    }
+    out
+}
+
+/// Decodes `.reloc` blocks back into sites, skipping padding entries.
+/// Returns `None` if a block is malformed.
+pub fn decode_base_relocations(mut data: &[u8]) -> Option<Vec<(u32, u16)>> {
+    let mut sites = Vec::new();
+    while !data.is_empty() {
+        let page = u32::from_le_bytes(data.get(0..4)?.try_into().unwrap());
+        let size = u32::from_le_bytes(data.get(4..8)?.try_into().unwrap()) as usize;
+        if size < 8 {
+            return None;
+        }
+        for entry in data.get(8..size)?.chunks_exact(2) {
+            let entry = u16::from_le_bytes(entry.try_into().unwrap());
+            let kind = entry >> 12;
+            if kind != IMAGE_REL_BASED_ABSOLUTE {
+                sites.push((page + u32::from(entry & 0xfff), kind));
+            }
+        }
+        data = &data[size..];
+    }
+    Some(sites)
+}
+
+/// The image checksum as computed by `CheckSumMappedFile`: a 16-bit
+/// one's-complement sum of the file, skipping the checksum field itself, plus
+/// the file length.
+pub fn checksum(image: &[u8], checksum_offset: usize) -> u32 {
+    let mut sum = 0u32;
+    for (idx, word) in image.chunks(2).enumerate() {
+        if idx * 2 == checksum_offset || idx * 2 == checksum_offset + 2 {
+            continue;
+        }
+        let word = u16::from_le_bytes([word[0], word.get(1).copied().unwrap_or(0)]);
+        sum += u32::from(word);
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum = (sum & 0xffff) + (sum >> 16);
+    sum + image.len() as u32
+}
+
+/// Computes [`checksum`] of what is written through it, so that an image can
+/// be streamed to its file and the checksum patched in afterwards. Only
+/// appending is supported: seeking anywhere but the current position fails.
+pub struct ChecksumWriter<W> {
+    inner: W,
+    checksum_offset: u64,
+    position: u64,
+    sum: u32,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    /// `checksum_offset` is relative to where writing starts.
+    pub fn new(inner: W, checksum_offset: usize) -> Self {
+        Self {
+            inner,
+            checksum_offset: checksum_offset as u64,
+            position: 0,
+            sum: 0,
+        }
+    }
+
+    /// The checksum of everything written so far.
+    pub fn checksum(&self) -> u32 {
+        let sum = (self.sum & 0xffff) + (self.sum >> 16);
+        sum + self.position as u32
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        for &byte in &buf[..written] {
+            let field = self.checksum_offset..self.checksum_offset + 4;
+            if !field.contains(&self.position) {
+                self.sum += u32::from(byte) << (self.position % 2 * 8);
+                self.sum = (self.sum & 0xffff) + (self.sum >> 16);
+            }
+            self.position += 1;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W> Seek for ChecksumWriter<W> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match pos {
+            SeekFrom::Current(0) => Ok(self.position),
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "a checksummed image can only be appended to",
+            )),
+        }
+    }
+}
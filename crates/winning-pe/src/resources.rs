@@ -0,0 +1,336 @@
+use std::{cmp::Ordering, fmt};
+
+use color_eyre::Result;
+use winning_coff::diagnostics::{Code, fatal};
+
+/// A resource type or name, either a number or a string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResourceId {
+    Id(u16),
+    Name(String),
+}
+
+impl ResourceId {
+    /// Named entries come before numbered ones, as the resource directory requires.
+    fn directory_order(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Name(a), Self::Name(b)) => a.encode_utf16().cmp(b.encode_utf16()),
+            (Self::Name(_), Self::Id(_)) => Ordering::Less,
+            (Self::Id(_), Self::Name(_)) => Ordering::Greater,
+            (Self::Id(a), Self::Id(b)) => a.cmp(b),
+        }
+    }
+}
+
+impl fmt::Display for ResourceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Id(id) => write!(f, "#{id}"),
+            Self::Name(name) => f.write_str(name),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Resource {
+    pub r#type: ResourceId,
+    pub name: ResourceId,
+    pub language: u16,
+    pub code_page: u32,
+    pub data: Vec<u8>,
+}
+
+impl Resource {
+    pub(crate) fn same_key(&self, other: &Self) -> bool {
+        self.r#type == other.r#type && self.name == other.name && self.language == other.language
+    }
+}
+
+/// Replaces resources with the same type, name and language, and adds the rest.
+pub fn merge_resources(resources: &mut Vec<Resource>, updates: Vec<Resource>) {
+    for update in updates {
+        match resources
+            .iter_mut()
+            .find(|resource| resource.same_key(&update))
+        {
+            Some(resource) => *resource = update,
+            None => resources.push(update),
+        }
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().unwrap(),
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().unwrap(),
+    ))
+}
+
+/// Reads a `.res` type or name field: `0xFFFF` followed by a number, or a
+/// NUL-terminated UTF-16 string. Returns the id and the offset after it.
+fn read_res_id(data: &[u8], offset: usize) -> Option<(ResourceId, usize)> {
+    if read_u16(data, offset)? == 0xFFFF {
+        return Some((ResourceId::Id(read_u16(data, offset + 2)?), offset + 4));
+    }
+    let mut units = Vec::new();
+    let mut pos = offset;
+    loop {
+        let unit = read_u16(data, pos)?;
+        pos += 2;
+        if unit == 0 {
+            break;
+        }
+        units.push(unit);
+    }
+    Some((ResourceId::Name(String::from_utf16_lossy(&units)), pos))
+}
+
+/// The empty entry that starts a 32-bit `.res` file: no data, a 32-byte
+/// header, and type and name `#0`.
+const RES_MAGIC: [u8; 16] = [
+    0, 0, 0, 0, 0x20, 0, 0, 0, 0xFF, 0xFF, 0, 0, 0xFF, 0xFF, 0, 0,
+];
+
+pub fn is_res(data: &[u8]) -> bool {
+    data.starts_with(&RES_MAGIC)
+}
+
+/// Parses a compiled `.res` file, as produced by `rc.exe` or `llvm-rc`.
+pub fn parse_res(data: &[u8]) -> Result<Vec<Resource>> {
+    let mut resources = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let Some(resource) = parse_res_entry(data, offset) else {
+            return fatal(
+                Code::CorruptInput,
+                format!("invalid resource entry at {offset:#x}"),
+            );
+        };
+        let (resource, next) = resource;
+        // The file starts with an empty entry marking it as a 32-bit .res file.
+        if resource.r#type != ResourceId::Id(0) {
+            resources.push(resource);
+        }
+        offset = next;
+    }
+    Ok(resources)
+}
+
+fn parse_res_entry(data: &[u8], offset: usize) -> Option<(Resource, usize)> {
+    let data_size = read_u32(data, offset)? as usize;
+    let header_size = read_u32(data, offset + 4)? as usize;
+    let (r#type, pos) = read_res_id(data, offset + 8)?;
+    let (name, pos) = read_res_id(data, pos)?;
+    let pos = pos.next_multiple_of(4);
+    // DataVersion (u32) and MemoryFlags (u16) come before the language.
+    let language = read_u16(data, pos + 6)?;
+
+    let start = offset + header_size;
+    let contents = data.get(start..start + data_size)?;
+    Some((
+        Resource {
+            r#type,
+            name,
+            language,
+            code_page: 0,
+            data: contents.to_vec(),
+        },
+        (start + data_size).next_multiple_of(4),
+    ))
+}
+
+const DIRECTORY_HEADER_SIZE: u32 = 16;
+const DIRECTORY_ENTRY_SIZE: u32 = 8;
+const DATA_ENTRY_SIZE: u32 = 16;
+const SUBDIRECTORY_FLAG: u32 = 0x8000_0000;
+const NAME_FLAG: u32 = 0x8000_0000;
+
+/// Reads the resource tree from the resource directory `rsrc`. `read`
+/// returns image contents at an RVA, for the data entries.
+pub fn read_resource_directory<'a>(
+    rsrc: &[u8],
+    read: impl Fn(u32, u32) -> Option<&'a [u8]>,
+) -> Result<Vec<Resource>> {
+    let mut resources = Vec::new();
+    match read_tree(rsrc, &read, &mut resources) {
+        Some(()) => Ok(resources),
+        None => fatal(Code::CorruptInput, "invalid resource directory"),
+    }
+}
+
+/// The entries of the directory table at `offset`, as ids and their target offsets.
+fn directory_entries(rsrc: &[u8], offset: u32) -> Option<Vec<(ResourceId, u32)>> {
+    let offset = offset as usize;
+    let named = read_u16(rsrc, offset + 12)?;
+    let numbered = read_u16(rsrc, offset + 14)?;
+    let mut entries = Vec::new();
+    for idx in 0..usize::from(named) + usize::from(numbered) {
+        let entry = offset + DIRECTORY_HEADER_SIZE as usize + idx * DIRECTORY_ENTRY_SIZE as usize;
+        let name = read_u32(rsrc, entry)?;
+        let target = read_u32(rsrc, entry + 4)?;
+        let id = if name & NAME_FLAG != 0 {
+            let pos = (name & !NAME_FLAG) as usize;
+            let len = usize::from(read_u16(rsrc, pos)?);
+            let units = (0..len)
+                .map(|i| read_u16(rsrc, pos + 2 + i * 2))
+                .collect::<Option<Vec<_>>>()?;
+            ResourceId::Name(String::from_utf16_lossy(&units))
+        } else {
+            ResourceId::Id(name as u16)
+        };
+        entries.push((id, target));
+    }
+    Some(entries)
+}
+
+fn read_tree<'a>(
+    rsrc: &[u8],
+    read: &impl Fn(u32, u32) -> Option<&'a [u8]>,
+    resources: &mut Vec<Resource>,
+) -> Option<()> {
+    let subdirectory =
+        |target: u32| (target & SUBDIRECTORY_FLAG != 0).then_some(target & !SUBDIRECTORY_FLAG);
+    for (r#type, names) in directory_entries(rsrc, 0)? {
+        for (name, languages) in directory_entries(rsrc, subdirectory(names)?)? {
+            for (language, entry) in directory_entries(rsrc, subdirectory(languages)?)? {
+                let ResourceId::Id(language) = language else {
+                    return None;
+                };
+                let entry = entry as usize;
+                let data_rva = read_u32(rsrc, entry)?;
+                let size = read_u32(rsrc, entry + 4)?;
+                resources.push(Resource {
+                    r#type: r#type.clone(),
+                    name: name.clone(),
+                    language,
+                    code_page: read_u32(rsrc, entry + 8)?,
+                    data: read(data_rva, size)?.to_vec(),
+                });
+            }
+        }
+    }
+    Some(())
+}
+
+/// Builds `.rsrc` contents to be placed at `rva`: the directory tables for
+/// the type, name and language levels, then the data entries, the name strings
+/// and finally the resource data.
+pub fn build_resource_section(resources: &[Resource], rva: u32) -> Vec<u8> {
+    let mut sorted = resources.iter().collect::<Vec<_>>();
+    sorted.sort_by(|a, b| {
+        a.r#type
+            .directory_order(&b.r#type)
+            .then_with(|| a.name.directory_order(&b.name))
+            .then(a.language.cmp(&b.language))
+    });
+
+    // Group into type -> name -> languages, keeping the sorted order.
+    let mut types = Vec::<(&ResourceId, Vec<(&ResourceId, Vec<&Resource>)>)>::new();
+    for resource in &sorted {
+        if types
+            .last()
+            .is_none_or(|(r#type, _)| **r#type != resource.r#type)
+        {
+            types.push((&resource.r#type, Vec::new()));
+        }
+        let names = &mut types.last_mut().unwrap().1;
+        if names.last().is_none_or(|(name, _)| **name != resource.name) {
+            names.push((&resource.name, Vec::new()));
+        }
+        names.last_mut().unwrap().1.push(resource);
+    }
+
+    let table_size = |entries: usize| DIRECTORY_HEADER_SIZE + entries as u32 * DIRECTORY_ENTRY_SIZE;
+    let mut offset = table_size(types.len());
+    let mut name_tables = Vec::new();
+    for (_, names) in &types {
+        name_tables.push(offset);
+        offset += table_size(names.len());
+    }
+    let mut language_tables = Vec::new();
+    for (_, names) in &types {
+        for (_, languages) in names {
+            language_tables.push(offset);
+            offset += table_size(languages.len());
+        }
+    }
+    let data_entries_offset = offset;
+    let strings_offset = data_entries_offset + sorted.len() as u32 * DATA_ENTRY_SIZE;
+
+    let mut strings = Vec::new();
+    let mut name_field = |id: &ResourceId| match id {
+        ResourceId::Id(id) => u32::from(*id),
+        ResourceId::Name(name) => {
+            let offset = strings_offset + strings.len() as u32;
+            let units = name.encode_utf16().collect::<Vec<_>>();
+            strings.extend_from_slice(&(units.len() as u16).to_le_bytes());
+            for unit in units {
+                strings.extend_from_slice(&unit.to_le_bytes());
+            }
+            NAME_FLAG | offset
+        }
+    };
+
+    let mut out = Vec::new();
+    write_table_header(&mut out, types.iter().map(|(r#type, _)| *r#type));
+    for ((r#type, _), table) in types.iter().zip(&name_tables) {
+        out.extend_from_slice(&name_field(r#type).to_le_bytes());
+        out.extend_from_slice(&(SUBDIRECTORY_FLAG | table).to_le_bytes());
+    }
+    let mut language_table = language_tables.iter();
+    for (_, names) in &types {
+        write_table_header(&mut out, names.iter().map(|(name, _)| *name));
+        for (name, _) in names {
+            out.extend_from_slice(&name_field(name).to_le_bytes());
+            let table = language_table.next().unwrap();
+            out.extend_from_slice(&(SUBDIRECTORY_FLAG | table).to_le_bytes());
+        }
+    }
+    let mut data_entry = data_entries_offset;
+    for (_, names) in &types {
+        for (_, languages) in names {
+            write_table_header(&mut out, languages.iter().map(|_| &ResourceId::Id(0)));
+            for resource in languages {
+                out.extend_from_slice(&u32::from(resource.language).to_le_bytes());
+                out.extend_from_slice(&data_entry.to_le_bytes());
+                data_entry += DATA_ENTRY_SIZE;
+            }
+        }
+    }
+
+    strings.resize(strings.len().next_multiple_of(8), 0);
+    let data_offset = strings_offset + strings.len() as u32;
+    let mut data = Vec::new();
+    for resource in &sorted {
+        out.extend_from_slice(&(rva + data_offset + data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(resource.data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&resource.code_page.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&resource.data);
+        data.resize(data.len().next_multiple_of(8), 0);
+    }
+
+    out.extend_from_slice(&strings);
+    out.extend_from_slice(&data);
+    out
+}
+
+/// Writes a directory table header, counting named and numbered entries.
+fn write_table_header<'a>(out: &mut Vec<u8>, ids: impl Iterator<Item = &'a ResourceId>) {
+    let (mut named, mut numbered) = (0u16, 0u16);
+    for id in ids {
+        match id {
+            ResourceId::Name(_) => named += 1,
+            ResourceId::Id(_) => numbered += 1,
+        }
+    }
+    // Characteristics, TimeDateStamp, MajorVersion and MinorVersion are all zero.
+    out.extend_from_slice(&[0; 12]);
+    out.extend_from_slice(&named.to_le_bytes());
+    out.extend_from_slice(&numbered.to_le_bytes());
+}
@@ -0,0 +1,109 @@
+//! The undocumented "Rich" header MSVC's linker puts after the DOS program: a
+//! list of the compiler and tool versions that built the objects, masked
+//! with a checksum of the DOS header.
+
+use winning_coff::coff::ObjectFile;
+
+use crate::pe::{PE_POINTER_OFFSET, PE_SIGNATURE};
+
+/// The absolute symbol MSVC tools record their product id and build in.
+pub const COMP_ID_SYMBOL: &str = "@comp.id";
+const DANS: u32 = u32::from_le_bytes(*b"DanS");
+const RICH: &[u8] = b"Rich";
+
+/// How many inputs a tool version built, as `(product << 16 | build, count)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RichEntry {
+    pub comp_id: u32,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone)]
+pub enum RichHeader {
+    /// From the `@comp.id` of the objects.
+    FromObjects,
+    /// Copied from another image, with [`read_rich_header`].
+    Entries(Vec<RichEntry>),
+}
+
+/// Counts the objects by their `@comp.id`, in the order of first appearance.
+pub fn object_entries(objects: &[ObjectFile<'_>]) -> Vec<RichEntry> {
+    let mut entries = Vec::<RichEntry>::new();
+    for object in objects {
+        let Some((_, sym)) = object
+            .symbols
+            .symbols()
+            .find(|(_, sym)| sym.name == COMP_ID_SYMBOL.as_bytes())
+        else {
+            continue;
+        };
+        match entries.iter_mut().find(|entry| entry.comp_id == sym.value) {
+            Some(entry) => entry.count += 1,
+            None => entries.push(RichEntry {
+                comp_id: sym.value,
+                count: 1,
+            }),
+        }
+    }
+    entries
+}
+
+/// The entries of an image's Rich header, if it has one.
+pub fn read_rich_header(image: &[u8]) -> Option<Vec<RichEntry>> {
+    let pe_offset = u32::from_le_bytes(image.get(PE_POINTER_OFFSET..)?.get(..4)?.try_into().ok()?);
+    let dos = image.get(..pe_offset as usize)?;
+    let rich = dos.windows(4).rposition(|window| window == RICH)?;
+    let key = u32::from_le_bytes(dos.get(rich + 4..rich + 8)?.try_into().ok()?);
+    let words = dos[..rich]
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes(word.try_into().unwrap()) ^ key)
+        .collect::<Vec<_>>();
+    let start = words.iter().rposition(|&word| word == DANS)?;
+    // `DanS` is followed by three zero words, then the entries.
+    Some(
+        words
+            .get(start + 4..)?
+            .chunks_exact(2)
+            .map(|pair| RichEntry {
+                comp_id: pair[0],
+                count: pair[1],
+            })
+            .collect(),
+    )
+}
+
+/// Inserts a Rich header into a DOS stub ending in the PE signature, like
+/// [`crate::pe::MSDOS_STUB`], moving the signature after it.
+pub fn insert_rich_header(stub: &[u8], entries: &[RichEntry]) -> Vec<u8> {
+    let mut data = stub[..stub.len() - PE_SIGNATURE.len()].to_vec();
+    data.resize(data.len().next_multiple_of(16), 0);
+    let start = data.len();
+
+    // The checksum covers the DOS header and program, without `e_lfanew`.
+    let mut key = start as u32;
+    for (idx, &byte) in data.iter().enumerate() {
+        if !(PE_POINTER_OFFSET..PE_POINTER_OFFSET + 4).contains(&idx) {
+            key = key.wrapping_add(u32::from(byte).rotate_left(idx as u32));
+        }
+    }
+    for entry in entries {
+        key = key.wrapping_add(entry.comp_id.rotate_left(entry.count));
+    }
+
+    let words = [DANS, 0, 0, 0].into_iter().chain(
+        entries
+            .iter()
+            .flat_map(|entry| [entry.comp_id, entry.count]),
+    );
+    for word in words {
+        data.extend_from_slice(&(word ^ key).to_le_bytes());
+    }
+    data.extend_from_slice(RICH);
+    data.extend_from_slice(&key.to_le_bytes());
+    data.resize(data.len().next_multiple_of(8), 0);
+
+    let pe_offset = data.len() as u32;
+    data[PE_POINTER_OFFSET..][..4].copy_from_slice(&pe_offset.to_le_bytes());
+    data.extend_from_slice(PE_SIGNATURE);
+    data
+}
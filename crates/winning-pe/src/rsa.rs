@@ -0,0 +1,219 @@
+//! RSA signatures (RFC 8017) with PKCS#1 v1.5 padding, which Authenticode
+//! signatures use. Signing isn't constant time: it runs on the signer's own
+//! machine, not on a server others can time.
+
+use crate::der::{self, Reader, SEQUENCE};
+
+/// The `DigestInfo` of a SHA-256 digest, up to the digest itself.
+const SHA256_DIGEST_INFO: &[u8] = &[
+    0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05,
+    0x00, 0x04, 0x20,
+];
+
+/// The smallest modulus we sign with, in bytes. Anything smaller has been
+/// breakable for decades, and Windows rejects it.
+const MIN_MODULUS_SIZE: usize = 128;
+
+#[derive(Debug, Clone)]
+pub struct RsaPrivateKey {
+    /// Big-endian without leading zeros, like the exponent.
+    modulus: Vec<u8>,
+    private_exponent: Vec<u8>,
+}
+
+impl RsaPrivateKey {
+    /// Reads a PKCS#1 `RSAPrivateKey`, the contents of an `RSA PRIVATE KEY`
+    /// PEM block and of the key of PKCS#8 for RSA.
+    pub fn from_pkcs1(der: &[u8]) -> Option<Self> {
+        let key = Reader::new(der).expect(SEQUENCE)?;
+        let mut fields = key.children();
+        // Version 1 keys have more than two primes, whose extra fields follow
+        // the ones we need.
+        fields.expect(der::INTEGER)?.small_integer()?;
+        let modulus = fields.expect(der::INTEGER)?.unsigned()?;
+        fields.expect(der::INTEGER)?;
+        let private_exponent = fields.expect(der::INTEGER)?.unsigned()?;
+        if modulus.len() < MIN_MODULUS_SIZE || modulus[modulus.len() - 1] & 1 == 0 {
+            return None;
+        }
+        Some(Self {
+            modulus: modulus.to_vec(),
+            private_exponent: private_exponent.to_vec(),
+        })
+    }
+
+    /// The modulus, big-endian, to find the certificate of the key by.
+    pub fn modulus(&self) -> &[u8] {
+        &self.modulus
+    }
+
+    /// Signs a SHA-256 digest, returning a signature as long as the modulus.
+    pub fn sign_sha256(&self, digest: &[u8; 32]) -> Vec<u8> {
+        let size = self.modulus.len();
+        let mut message = vec![0xff; size];
+        message[0] = 0;
+        message[1] = 1;
+        let info_start = size - SHA256_DIGEST_INFO.len() - digest.len();
+        message[info_start - 1] = 0;
+        message[info_start..size - digest.len()].copy_from_slice(SHA256_DIGEST_INFO);
+        message[size - digest.len()..].copy_from_slice(digest);
+
+        let modulus = limbs(&self.modulus);
+        let signature = Montgomery::new(modulus).pow(&limbs(&message), &self.private_exponent);
+        let mut out = vec![0; size];
+        for (idx, byte) in out.iter_mut().rev().enumerate() {
+            *byte = (signature[idx / 8] >> (idx % 8 * 8)) as u8;
+        }
+        out
+    }
+}
+
+/// A big-endian number as little-endian 64-bit limbs.
+fn limbs(bytes: &[u8]) -> Vec<u64> {
+    bytes
+        .rchunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .fold(0, |limb, &byte| limb << 8 | u64::from(byte))
+        })
+        .collect()
+}
+
+/// Arithmetic modulo an odd number in Montgomery form, where `x` is kept as
+/// `x * R mod n` with `R = 2^(64 * limbs)`, so reducing needs no division.
+struct Montgomery {
+    modulus: Vec<u64>,
+    /// `-modulus^-1 mod 2^64`.
+    inverse: u64,
+    /// `R^2 mod n`, to convert into Montgomery form.
+    r_squared: Vec<u64>,
+}
+
+impl Montgomery {
+    fn new(modulus: Vec<u64>) -> Self {
+        // Newton's iteration doubles the correct low bits each step.
+        let mut inverse = 1u64;
+        for _ in 0..6 {
+            inverse = inverse.wrapping_mul(2u64.wrapping_sub(modulus[0].wrapping_mul(inverse)));
+        }
+
+        let mut r_squared = vec![0; modulus.len()];
+        r_squared[0] = 1;
+        for _ in 0..128 * modulus.len() {
+            let carry = r_squared.iter_mut().fold(0, |carry, limb| {
+                let out = *limb >> 63;
+                *limb = *limb << 1 | carry;
+                out
+            });
+            if carry != 0 || !less_than(&r_squared, &modulus) {
+                subtract(&mut r_squared, &modulus);
+            }
+        }
+        Self {
+            modulus,
+            inverse: inverse.wrapping_neg(),
+            r_squared,
+        }
+    }
+
+    /// `a * b / R mod n`.
+    fn multiply(&self, a: &[u64], b: &[u64]) -> Vec<u64> {
+        let size = self.modulus.len();
+        let mut acc = vec![0u64; size + 2];
+        for &limb in a {
+            let mut carry = 0;
+            for (acc, &b) in acc.iter_mut().zip(b) {
+                let sum = u128::from(*acc) + u128::from(limb) * u128::from(b) + carry;
+                *acc = sum as u64;
+                carry = sum >> 64;
+            }
+            let sum = u128::from(acc[size]) + carry;
+            acc[size] = sum as u64;
+            acc[size + 1] = (sum >> 64) as u64;
+
+            // Adding a multiple of the modulus makes the low limb zero, so
+            // the whole thing can be shifted down one limb.
+            let factor = u128::from(acc[0].wrapping_mul(self.inverse));
+            let mut carry = (u128::from(acc[0]) + factor * u128::from(self.modulus[0])) >> 64;
+            for idx in 1..size {
+                let sum = u128::from(acc[idx]) + factor * u128::from(self.modulus[idx]) + carry;
+                acc[idx - 1] = sum as u64;
+                carry = sum >> 64;
+            }
+            let sum = u128::from(acc[size]) + carry;
+            acc[size - 1] = sum as u64;
+            acc[size] = acc[size + 1] + (sum >> 64) as u64;
+            acc[size + 1] = 0;
+        }
+        let overflow = acc[size] != 0;
+        acc.truncate(size);
+        if overflow || !less_than(&acc, &self.modulus) {
+            subtract(&mut acc, &self.modulus);
+        }
+        acc
+    }
+
+    /// `base^exponent mod n`, with `base < n` and a big-endian exponent.
+    fn pow(&self, base: &[u64], exponent: &[u8]) -> Vec<u64> {
+        let mut base = base.to_vec();
+        base.resize(self.modulus.len(), 0);
+        let base = self.multiply(&base, &self.r_squared);
+        let mut one = vec![0; self.modulus.len()];
+        one[0] = 1;
+        let mut acc = self.multiply(&one, &self.r_squared);
+        for byte in exponent {
+            for bit in (0..8).rev() {
+                acc = self.multiply(&acc, &acc);
+                if byte >> bit & 1 != 0 {
+                    acc = self.multiply(&acc, &base);
+                }
+            }
+        }
+        self.multiply(&acc, &one)
+    }
+}
+
+fn less_than(a: &[u64], b: &[u64]) -> bool {
+    a.iter().rev().cmp(b.iter().rev()).is_lt()
+}
+
+/// `a -= b`, wrapping around.
+fn subtract(a: &mut [u64], b: &[u64]) {
+    let mut borrow = false;
+    for (a, &b) in a.iter_mut().zip(b) {
+        let (diff, first) = a.overflowing_sub(b);
+        let (diff, second) = diff.overflowing_sub(u64::from(borrow));
+        *a = diff;
+        borrow = first || second;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest::Sha256;
+
+    /// A 1024-bit key generated with `openssl genrsa`, and the signature of
+    /// `abc` that `openssl dgst -sha256 -sign` made with it.
+    const KEY: &[u8] = include_bytes!("testdata/rsa-1024.der");
+    const SIGNATURE: &[u8] = include_bytes!("testdata/rsa-1024-abc.sig");
+
+    #[test]
+    fn matches_openssl() {
+        let key = RsaPrivateKey::from_pkcs1(KEY).unwrap();
+        assert_eq!(key.modulus().len(), 128);
+        assert_eq!(key.sign_sha256(&Sha256::digest(b"abc")), SIGNATURE);
+    }
+
+    #[test]
+    fn rejects_small_keys() {
+        // A 32-bit key: version, n, e, d and the rest of the CRT fields.
+        let key = [
+            0x30, 0x1b, 0x02, 0x01, 0x00, 0x02, 0x05, 0x00, 0xc4, 0x4f, 0x0d, 0xe5, 0x02, 0x03,
+            0x01, 0x00, 0x01, 0x02, 0x04, 0x1b, 0x4a, 0x6f, 0x81, 0x02, 0x01, 0x01, 0x02, 0x01,
+            0x01,
+        ];
+        assert!(RsaPrivateKey::from_pkcs1(&key).is_none());
+    }
+}
@@ -0,0 +1,89 @@
+//! Linking the clang sanitizer runtimes, as used by `/fsanitize=address`.
+//!
+//! The runtimes define their own versions of CRT functions such as `malloc`
+//! and `free`, so the same symbol is defined both by a runtime member and by
+//! a member of the CRT libraries. link.exe resolves this by searching the
+//! runtimes first and letting their definitions win, and so do we.
+//!
+//! Like link.exe's `/INFERASANLIBS`, the runtimes can also be picked
+//! automatically for ASan-instrumented objects, see [`inferred_runtimes`].
+
+use std::path::Path;
+
+use winning_coff::{
+    archive::ARCHIVE_MAGIC,
+    coff::{IMAGE_SYM_CLASS_EXTERNAL, IMAGE_SYM_UNDEFINED, ObjectFile},
+    import::is_import_object,
+};
+
+use crate::input::Input;
+
+/// Prefixes of the file names of the clang sanitizer runtime libraries, such
+/// as `clang_rt.asan-x86_64.lib` or `clang_rt.asan_dynamic-x86_64.lib`.
+const RUNTIME_PREFIXES: &[&str] = &["clang_rt.asan", "clang_rt.ubsan"];
+
+/// Whether the archive at `path` is a sanitizer runtime.
+pub fn is_runtime(path: &str) -> bool {
+    let name = Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    RUNTIME_PREFIXES
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+}
+
+/// The ASan runtime for objects using the dynamic CRT (`/MD`).
+pub const DYNAMIC_RUNTIME: &[&str] = &[
+    "clang_rt.asan_dynamic-x86_64.lib",
+    "clang_rt.asan_dynamic_runtime_thunk-x86_64.lib",
+];
+/// The ASan runtime for objects using the static CRT (`/MT`).
+pub const STATIC_RUNTIME: &[&str] = &[
+    "clang_rt.asan_dynamic-x86_64.lib",
+    "clang_rt.asan_static_runtime_thunk-x86_64.lib",
+];
+
+/// The ASan runtime libraries the objects among `inputs` need, if any of them
+/// is instrumented. MSVC marks those with `/INFERASANLIBS` in `.drectve`,
+/// clang-cl objects are recognized by their calls into the runtime. Inputs
+/// that don't parse are skipped, the link reports them later.
+pub fn inferred_runtimes(inputs: &[Input]) -> Option<&'static [&'static str]> {
+    let mut instrumented = false;
+    let mut dynamic_crt = false;
+    for input in inputs {
+        let data = input.data();
+        if data.starts_with(ARCHIVE_MAGIC) || is_import_object(data) {
+            continue;
+        }
+        let Ok(object) = ObjectFile::parse(input.name.clone(), data) else {
+            continue;
+        };
+        for (idx, section) in object.sections.iter().enumerate() {
+            if section.name == ".drectve" {
+                let directives = String::from_utf8_lossy(object.section_data(idx));
+                for directive in directives.split_ascii_whitespace() {
+                    let directive = directive.to_ascii_lowercase().replace('"', "");
+                    match directive.trim_start_matches(['/', '-']) {
+                        "inferasanlibs" => instrumented = true,
+                        "defaultlib:msvcrt" | "defaultlib:msvcrt.lib" => dynamic_crt = true,
+                        _ => {}
+                    }
+                }
+            }
+        }
+        instrumented |= object.symbols.symbols().any(|(_, sym)| {
+            sym.storage_class == IMAGE_SYM_CLASS_EXTERNAL
+                && sym.section_number == IMAGE_SYM_UNDEFINED
+                && object
+                    .symbols
+                    .name(sym)
+                    .is_ok_and(|name| name.starts_with("__asan_"))
+        });
+    }
+    instrumented.then_some(if dynamic_crt {
+        DYNAMIC_RUNTIME
+    } else {
+        STATIC_RUNTIME
+    })
+}
@@ -0,0 +1,185 @@
+//! Signing images with Authenticode: a PKCS#7 `SignedData` over the
+//! Authenticode digest, as signtool writes it, in a `WIN_CERTIFICATE` entry of
+//! the certificate table. There is no timestamp, which needs a timestamping
+//! server, so the signature stops verifying once the certificate expires.
+
+use std::io;
+
+use color_eyre::Result;
+use winning_coff::{
+    coff::CoffHeader,
+    diagnostics::{Code, Diagnostics, fatal},
+};
+
+use crate::{
+    authenticode::{CERTIFICATE_ALIGNMENT, authenticode_digest},
+    der::{
+        self, BIT_STRING, OBJECT_IDENTIFIER, OCTET_STRING, Reader, SET, algorithm, context,
+        context_primitive, encode, oid, sequence, set_of,
+    },
+    digest::Sha256,
+    image::ImageModel,
+    keys::SigningIdentity,
+    pe::{DataDirectory, OptionalHeader, PE_SIGNATURE, checksum},
+};
+
+/// `WIN_CERT_REVISION_2_0`.
+const WIN_CERT_REVISION: u16 = 0x0200;
+/// `WIN_CERT_TYPE_PKCS_SIGNED_DATA`.
+const WIN_CERT_TYPE_PKCS_SIGNED_DATA: u16 = 0x0002;
+/// The size of the `WIN_CERTIFICATE` header before the signature.
+const WIN_CERTIFICATE_HEADER_SIZE: usize = 8;
+
+/// Signs an image, replacing the signature it had. The signature goes where
+/// the certificate table is, like one reserved with `--reserve-certificate`,
+/// or at the end of the file. The checksum is recomputed if the image had
+/// one.
+pub fn sign(
+    data: &[u8],
+    identity: &SigningIdentity,
+    diag: &mut Diagnostics<'_>,
+) -> Result<Vec<u8>> {
+    let image = ImageModel::parse(data)?;
+    let mut optional = image.optional_header.clone();
+    if optional.number_of_rva_and_sizes as usize <= OptionalHeader::CERTIFICATE_TABLE_INDEX {
+        return fatal(
+            Code::UnsupportedImage,
+            "the image has no certificate table directory",
+        );
+    }
+
+    let certificate = optional.certificate_table;
+    let start = if certificate.size > 0 {
+        let start = certificate.virtual_address as usize;
+        if start + certificate.size as usize != data.len() {
+            return fatal(
+                Code::UnsupportedImage,
+                "the certificate table isn't at the end of the file",
+            );
+        }
+        // Space reserved at link time is zeros; anything else is a signature.
+        if data[start..].iter().any(|&byte| byte != 0) {
+            diag.warn(
+                Code::SignatureRemoved,
+                "the image was signed; the signature has been replaced",
+            );
+        }
+        start
+    } else {
+        data.len().next_multiple_of(CERTIFICATE_ALIGNMENT as usize)
+    };
+    let mut out = data[..start.min(data.len())].to_vec();
+    out.resize(start, 0);
+
+    // The digest doesn't cover the certificate table directory, but it does
+    // need the table gone to know where the file ends.
+    optional.certificate_table = DataDirectory::default();
+    let optional_offset = image.pe_offset + PE_SIGNATURE.len() + size_of::<CoffHeader>();
+    let cursor = &mut io::Cursor::new(&mut out);
+    cursor.set_position(optional_offset as u64);
+    image.write_optional_header(&optional, cursor)?;
+    let digest = authenticode_digest(&ImageModel::parse(&out)?)?;
+
+    let signature = signed_data(&digest, identity);
+    let size = (WIN_CERTIFICATE_HEADER_SIZE + signature.len())
+        .next_multiple_of(CERTIFICATE_ALIGNMENT as usize);
+    out.extend_from_slice(&(size as u32).to_le_bytes());
+    out.extend_from_slice(&WIN_CERT_REVISION.to_le_bytes());
+    out.extend_from_slice(&WIN_CERT_TYPE_PKCS_SIGNED_DATA.to_le_bytes());
+    out.extend_from_slice(&signature);
+    out.resize(start + size, 0);
+
+    optional.certificate_table = DataDirectory {
+        virtual_address: start as u32,
+        size: size as u32,
+    };
+    let had_checksum = optional.check_sum != 0;
+    optional.check_sum = 0;
+    let cursor = &mut io::Cursor::new(&mut out);
+    cursor.set_position(optional_offset as u64);
+    image.write_optional_header(&optional, cursor)?;
+    if had_checksum {
+        let checksum_offset = optional_offset + OptionalHeader::CHECKSUM_OFFSET;
+        let sum = checksum(&out, checksum_offset);
+        out[checksum_offset..checksum_offset + 4].copy_from_slice(&sum.to_le_bytes());
+    }
+    Ok(out)
+}
+
+/// The PKCS#7 `ContentInfo` with the `SignedData` of an Authenticode
+/// signature over `digest`.
+pub fn signed_data(digest: &[u8; 32], identity: &SigningIdentity) -> Vec<u8> {
+    let sha256 = algorithm(oid::SHA256);
+    // What the signature is over: an `SpcIndirectDataContent` with
+    // `SpcPeImageData`, whose file link is the placeholder signtool writes.
+    let obsolete = "<<<Obsolete>>>"
+        .encode_utf16()
+        .flat_map(u16::to_be_bytes)
+        .collect::<Vec<_>>();
+    let file = encode(
+        context(0),
+        &encode(context(2), &encode(context_primitive(0), &obsolete)),
+    );
+    let indirect_data = sequence(&[
+        sequence(&[
+            encode(OBJECT_IDENTIFIER, oid::SPC_PE_IMAGE_DATA),
+            sequence(&[encode(BIT_STRING, &[0]), file]),
+        ]),
+        sequence(&[sha256.clone(), encode(OCTET_STRING, digest)]),
+    ]);
+    // The message digest of Authenticode leaves out the tag and length.
+    let indirect_data_contents = Reader::new(&indirect_data).read().unwrap().contents;
+
+    let attribute = |oid: &[u8], value: Vec<u8>| {
+        sequence(&[encode(OBJECT_IDENTIFIER, oid), set_of(SET, &[value])])
+    };
+    let attributes = set_of(
+        SET,
+        &[
+            attribute(
+                oid::CONTENT_TYPE,
+                encode(OBJECT_IDENTIFIER, oid::SPC_INDIRECT_DATA),
+            ),
+            attribute(
+                oid::MESSAGE_DIGEST,
+                encode(OCTET_STRING, &Sha256::digest(indirect_data_contents)),
+            ),
+            attribute(
+                oid::SPC_STATEMENT_TYPE,
+                sequence(&[encode(
+                    OBJECT_IDENTIFIER,
+                    oid::SPC_INDIVIDUAL_SP_KEY_PURPOSE,
+                )]),
+            ),
+            attribute(oid::SPC_SP_OPUS_INFO, sequence(&[])),
+        ],
+    );
+    // The attributes are signed as a `SET OF` but stored as `[0] IMPLICIT`.
+    let signature = identity.key.sign_sha256(&Sha256::digest(&attributes));
+    let mut authenticated_attributes = attributes;
+    authenticated_attributes[0] = context(0);
+
+    let signer = identity.signer();
+    let signer_info = sequence(&[
+        encode(der::INTEGER, &[1]),
+        sequence(&[signer.issuer.to_vec(), signer.serial_number.to_vec()]),
+        sha256.clone(),
+        authenticated_attributes,
+        algorithm(oid::RSA_ENCRYPTION),
+        encode(OCTET_STRING, &signature),
+    ]);
+    let signed_data = sequence(&[
+        encode(der::INTEGER, &[1]),
+        set_of(SET, &[sha256]),
+        sequence(&[
+            encode(OBJECT_IDENTIFIER, oid::SPC_INDIRECT_DATA),
+            encode(context(0), &indirect_data),
+        ]),
+        set_of(context(0), &identity.certificates),
+        set_of(SET, &[signer_info]),
+    ]);
+    sequence(&[
+        encode(OBJECT_IDENTIFIER, oid::SIGNED_DATA),
+        encode(context(0), &signed_data),
+    ])
+}
@@ -0,0 +1,47 @@
+//! Counts and memory use of a link, for `--stats`.
+
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    /// Including archive members.
+    pub objects: usize,
+    pub archives: usize,
+    pub input_sections: usize,
+    pub output_sections: usize,
+    pub imports: usize,
+    /// The interned symbol names, one per global symbol.
+    pub names: usize,
+    /// The bytes allocated for the interned names.
+    pub name_bytes: usize,
+    /// The bytes allocated for the symbol table arenas of the objects.
+    pub symbol_table_bytes: usize,
+    /// The peak resident set size of the process, where the platform tells.
+    pub peak_rss: Option<u64>,
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kib = |bytes: u64| format!("{} KiB", bytes.div_ceil(1024));
+        writeln!(f, "objects:         {}", self.objects)?;
+        writeln!(f, "archives:        {}", self.archives)?;
+        writeln!(f, "input sections:  {}", self.input_sections)?;
+        writeln!(f, "output sections: {}", self.output_sections)?;
+        writeln!(f, "imports:         {}", self.imports)?;
+        writeln!(
+            f,
+            "symbols:         {} ({} of names)",
+            self.names,
+            kib(self.name_bytes as u64)
+        )?;
+        writeln!(
+            f,
+            "symbol tables:   {}",
+            kib(self.symbol_table_bytes as u64)
+        )?;
+        match self.peak_rss {
+            Some(rss) => writeln!(f, "peak RSS:        {}", kib(rss)),
+            None => writeln!(f, "peak RSS:        unknown"),
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use std::io;
+
+use binrw::BinWrite;
+use color_eyre::Result;
+use winning_coff::{
+    coff::{Characteristics, SECTION_HEADER_SIZE, SectionFlags, string_table_entry},
+    diagnostics::{Code, Diagnostics, fatal},
+};
+
+use crate::{
+    debug::DEBUG_DIRECTORY_ENTRY_SIZE,
+    image::ImageModel,
+    pe::{OptionalHeader, PE_SIGNATURE, checksum},
+};
+
+/// `IMAGE_DEBUG_TYPE_EX_DLLCHARACTERISTICS`, which holds flags like CET
+/// compatibility that change how the image is loaded.
+const IMAGE_DEBUG_TYPE_EX_DLLCHARACTERISTICS: u32 = 20;
+
+/// What [`strip`] removed.
+#[derive(Debug, Default)]
+pub struct Stripped {
+    pub debug_entries: usize,
+    pub symbols: bool,
+    pub sections: Vec<String>,
+}
+
+/// Removes what only debuggers look at from an existing image: the entries of
+/// the debug directory, the COFF symbol and string table and the DWARF
+/// sections of `/DEBUG:DWARF`. Only discardable sections, which nothing refers
+/// to by address, are moved to close the gap. The checksum is recomputed if
+/// the image had one or `checksum_always` is set.
+pub fn strip(
+    data: &[u8],
+    checksum_always: bool,
+    diag: &mut Diagnostics<'_>,
+) -> Result<(Vec<u8>, Stripped)> {
+    let image = ImageModel::parse(data)?;
+    let mut optional = image.optional_header.clone();
+    let mut header = image.header;
+    let mut stripped = Stripped::default();
+    let section_alignment = optional.section_alignment;
+    let file_alignment = optional.file_alignment;
+
+    // Long section names are in the string table, which is going away.
+    let mut names = Vec::new();
+    for section in &image.sections {
+        let name = match section.name.strip_prefix('/') {
+            Some(offset) => match offset
+                .parse()
+                .ok()
+                .and_then(|offset| string_table_entry(data, &header, offset))
+            {
+                Some(name) => name,
+                None => {
+                    return fatal(
+                        Code::CorruptInput,
+                        format!("invalid long section name {}", section.name),
+                    );
+                }
+            },
+            None => &section.name,
+        };
+        names.push(name);
+    }
+    let is_discardable = |idx: usize| {
+        image.sections[idx]
+            .characteristics
+            .contains(SectionFlags::IMAGE_SCN_MEM_DISCARDABLE)
+    };
+    let is_debug = |idx: usize| is_discardable(idx) && names[idx].starts_with(".debug");
+    for (idx, section) in image.sections.iter().enumerate() {
+        if !is_debug(idx) && section.name.starts_with('/') {
+            return fatal(
+                Code::UnsupportedImage,
+                format!(
+                    "{} has a long name, which needs the string table",
+                    names[idx]
+                ),
+            );
+        }
+    }
+
+    // Everything from the first debug section on is laid out again.
+    let position = (0..image.sections.len())
+        .find(|&idx| is_debug(idx))
+        .unwrap_or(image.sections.len());
+    if let Some(idx) = (position..image.sections.len()).find(|&idx| !is_discardable(idx)) {
+        return fatal(
+            Code::UnsupportedImage,
+            format!(
+                "{} after {} would have to be moved",
+                names[idx], names[position]
+            ),
+        );
+    }
+
+    let prefix = &image.sections[..position];
+    let mut rva = prefix.last().map_or(
+        optional.size_of_headers.next_multiple_of(section_alignment),
+        |section| {
+            (section.virtual_address + section.virtual_size).next_multiple_of(section_alignment)
+        },
+    );
+    let prefix_end = prefix
+        .iter()
+        .map(|section| section.pointer_to_raw_data + section.size_of_raw_data)
+        .max()
+        .unwrap_or(optional.size_of_headers);
+    let mut file_offset = prefix_end.next_multiple_of(file_alignment);
+
+    let mut sections = prefix
+        .iter()
+        .map(|section| (section.clone(), None))
+        .collect::<Vec<_>>();
+    for (idx, section) in image.sections.iter().enumerate().skip(position) {
+        if is_debug(idx) {
+            stripped.sections.push(names[idx].to_owned());
+            continue;
+        }
+        let Some(contents) = image.section_data(section) else {
+            return fatal(
+                Code::CorruptInput,
+                format!("section {} data out of bounds", section.name),
+            );
+        };
+        let mut moved = section.clone();
+        moved.virtual_address = rva;
+        moved.pointer_to_raw_data = if section.size_of_raw_data == 0 {
+            0
+        } else {
+            file_offset
+        };
+        let old = section.virtual_address..section.virtual_address + section.virtual_size;
+        for (idx, directory) in optional.data_directories_mut().into_iter().enumerate() {
+            if idx != OptionalHeader::CERTIFICATE_TABLE_INDEX
+                && directory.size > 0
+                && old.contains(&directory.virtual_address)
+            {
+                directory.virtual_address = directory.virtual_address - old.start + rva;
+            }
+        }
+        rva = (rva + section.virtual_size).next_multiple_of(section_alignment);
+        file_offset += section.size_of_raw_data;
+        sections.push((moved, Some(contents)));
+    }
+
+    let mut out = data[..prefix_end as usize].to_vec();
+    for (section, contents) in &sections {
+        if let Some(contents) = contents {
+            out.resize(section.pointer_to_raw_data as usize, 0);
+            out.extend_from_slice(contents);
+            out.resize(
+                (section.pointer_to_raw_data + section.size_of_raw_data) as usize,
+                0,
+            );
+        }
+    }
+
+    // Debug directory entries are dropped in place, except those that affect
+    // loading. The data they point to stays, nothing else knows where it is.
+    let directory = optional.debug;
+    if directory.size > 0 {
+        let Some(offset) = sections.iter().find_map(|(section, _)| {
+            let start = section.virtual_address;
+            (start..start + section.virtual_size)
+                .contains(&directory.virtual_address)
+                .then(|| (directory.virtual_address - start + section.pointer_to_raw_data) as usize)
+        }) else {
+            return fatal(
+                Code::CorruptInput,
+                "debug directory is not within a section",
+            );
+        };
+        if offset + directory.size as usize > out.len() {
+            return fatal(Code::CorruptInput, "debug directory is out of bounds");
+        }
+        let entries = out[offset..offset + directory.size as usize]
+            .chunks_exact(DEBUG_DIRECTORY_ENTRY_SIZE as usize)
+            .filter(|entry| {
+                u32::from_le_bytes(entry[12..16].try_into().unwrap())
+                    == IMAGE_DEBUG_TYPE_EX_DLLCHARACTERISTICS
+            })
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        stripped.debug_entries =
+            (directory.size as usize - entries.len()) / DEBUG_DIRECTORY_ENTRY_SIZE as usize;
+        out[offset..offset + directory.size as usize].fill(0);
+        out[offset..offset + entries.len()].copy_from_slice(&entries);
+        optional.debug.size = entries.len() as u32;
+        if entries.is_empty() {
+            optional.debug = Default::default();
+        }
+    }
+
+    // What follows the sections in the file is the string table and the
+    // certificate table, which holds a file offset and signs the old
+    // contents. Anything else there is kept.
+    let sections_end = image
+        .sections
+        .iter()
+        .map(|section| (section.pointer_to_raw_data + section.size_of_raw_data) as usize)
+        .max()
+        .unwrap_or(0);
+    let mut overlay = data.get(sections_end..).unwrap_or_default();
+    let certificate = optional.certificate_table;
+    if certificate.size > 0 {
+        diag.warn(
+            Code::SignatureRemoved,
+            "the image was signed; the signature has been removed",
+        );
+        optional.certificate_table = Default::default();
+        let start = (certificate.virtual_address as usize).saturating_sub(sections_end);
+        overlay = &overlay[..start.min(overlay.len())];
+    }
+    if header.pointer_to_symbol_table != 0 {
+        stripped.symbols = true;
+        let start = (header.pointer_to_symbol_table as usize).saturating_sub(sections_end);
+        overlay = &overlay[..start.min(overlay.len())];
+        header.pointer_to_symbol_table = 0;
+        header.number_of_symbols = 0;
+    }
+    out.extend_from_slice(overlay);
+
+    optional.size_of_image = rva;
+    optional.size_of_initialized_data = sections
+        .iter()
+        .filter(|(section, _)| {
+            section
+                .characteristics
+                .contains(SectionFlags::IMAGE_SCN_CNT_INITIALIZED_DATA)
+        })
+        .map(|(section, _)| section.size_of_raw_data)
+        .sum();
+
+    header.number_of_sections = sections.len() as u16;
+    header.characteristics |= Characteristics::IMAGE_FILE_DEBUG_STRIPPED;
+    let cursor = &mut io::Cursor::new(&mut out);
+    cursor.set_position((image.pe_offset + PE_SIGNATURE.len()) as u64);
+    header.write(cursor)?;
+    let optional_offset = cursor.position() as usize;
+    let write_checksum = checksum_always || optional.check_sum != 0;
+    optional.check_sum = 0;
+    image.write_optional_header(&optional, cursor)?;
+    let table_offset = optional_offset + usize::from(image.header.size_of_optional_header);
+    cursor.get_mut()[table_offset..][..image.sections.len() * SECTION_HEADER_SIZE].fill(0);
+    cursor.set_position(table_offset as u64);
+    for (section, _) in &sections {
+        section.write(cursor)?;
+    }
+
+    if write_checksum {
+        let checksum_offset = optional_offset + OptionalHeader::CHECKSUM_OFFSET;
+        let sum = checksum(&out, checksum_offset);
+        out[checksum_offset..checksum_offset + 4].copy_from_slice(&sum.to_le_bytes());
+    }
+
+    Ok((out, stripped))
+}
@@ -0,0 +1,80 @@
+use std::fmt::{self, Display};
+
+use winning_coff::coff::Characteristics;
+
+use crate::pe::{DllCharacteristics, Subsystem};
+
+/// Key facts about a linked image, printed after the link with `-v`.
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub subsystem: Subsystem,
+    pub size_of_image: u32,
+    pub entry: String,
+    pub sections: usize,
+    pub imports: usize,
+    pub import_dlls: usize,
+    pub characteristics: Characteristics,
+    pub dll_characteristics: DllCharacteristics,
+}
+
+impl Summary {
+    /// The names of the enabled security features.
+    pub fn security_features(&self) -> Vec<&'static str> {
+        let mut features = Vec::new();
+        if self
+            .characteristics
+            .contains(Characteristics::IMAGE_FILE_LARGE_ADDRESS_AWARE)
+        {
+            features.push("large address aware");
+        }
+        for (flag, name) in [
+            (
+                DllCharacteristics::IMAGE_DLLCHARACTERISTICS_HIGH_ENTROPY_VA,
+                "high entropy VA",
+            ),
+            (
+                DllCharacteristics::IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE,
+                "ASLR",
+            ),
+            (DllCharacteristics::IMAGE_DLLCHARACTERISTICS_NX_COMPAT, "NX"),
+            (
+                DllCharacteristics::IMAGE_DLLCHARACTERISTICS_GUARD_CF,
+                "Control Flow Guard",
+            ),
+        ] {
+            if self.dll_characteristics.contains(flag) {
+                features.push(name);
+            }
+        }
+        features
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let subsystem = match self.subsystem {
+            Subsystem::Console => "console",
+            Subsystem::Windows => "windows",
+            Subsystem::Native => "native",
+            Subsystem::EfiApplication => "efi application",
+            Subsystem::EfiBootServiceDriver => "efi boot service driver",
+            Subsystem::EfiRuntimeDriver => "efi runtime driver",
+        };
+        let features = self.security_features();
+        writeln!(f, "machine:    x64")?;
+        writeln!(f, "subsystem:  {subsystem}")?;
+        writeln!(f, "image size: {:#x}", self.size_of_image)?;
+        writeln!(f, "entry:      {}", self.entry)?;
+        writeln!(f, "sections:   {}", self.sections)?;
+        writeln!(
+            f,
+            "imports:    {} from {} DLL(s)",
+            self.imports, self.import_dlls
+        )?;
+        if features.is_empty() {
+            writeln!(f, "security:   none")
+        } else {
+            writeln!(f, "security:   {}", features.join(", "))
+        }
+    }
+}
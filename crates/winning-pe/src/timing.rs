@@ -0,0 +1,58 @@
+//! Where a link spends its time, for `--time`.
+
+use std::{
+    fmt::{self, Display},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// A duration that several threads add to.
+#[derive(Debug, Default)]
+pub struct SharedDuration(AtomicU64);
+
+impl SharedDuration {
+    pub fn add(&self, duration: Duration) {
+        self.0
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> Duration {
+        Duration::from_nanos(self.0.load(Ordering::Relaxed))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timings {
+    /// Parsing the objects, archives and images given on the command line.
+    pub parse: Duration,
+    /// Resolving symbols, including loading archive members.
+    pub resolve: Duration,
+    /// Assigning the sections and their chunks addresses.
+    pub layout: Duration,
+    /// Applying relocations, added up over the threads doing it, so it can
+    /// be more than `write`, which includes it.
+    pub relocation: Duration,
+    pub write: Duration,
+}
+
+impl Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.parse + self.resolve + self.layout + self.write;
+        for (phase, duration) in [
+            ("parse", self.parse),
+            ("resolve", self.resolve),
+            ("layout", self.layout),
+            ("relocation", self.relocation),
+            ("write", self.write),
+            ("total", total),
+        ] {
+            writeln!(
+                f,
+                "{:<12}{:>10.3} ms",
+                format!("{phase}:"),
+                duration.as_secs_f64() * 1000.0
+            )?;
+        }
+        Ok(())
+    }
+}
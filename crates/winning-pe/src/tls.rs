@@ -0,0 +1,49 @@
+//! Thread-local storage. The CRT's `_tls_used` is the `IMAGE_TLS_DIRECTORY64`
+//! that tells the loader to copy the `.tls` template for each thread and run
+//! the `.CRT$XL*` callbacks. Without a CRT, the linker writes one itself.
+
+pub const TLS_USED_SYMBOL: &str = "_tls_used";
+/// The slot the loader stores the image's TLS index in, which code reads to
+/// find its thread's copy of the template.
+pub const TLS_INDEX_SYMBOL: &str = "_tls_index";
+pub const TLS_DIRECTORY_SIZE: u32 = 0x28;
+
+/// The input sections holding pointers to TLS callbacks, ordered like the
+/// CRT's `__xl_a` and `__xl_z` sentinels in `.CRT$XLA` and `.CRT$XLZ`.
+pub fn is_callback_section(name: &str) -> bool {
+    name.starts_with(".CRT$XL")
+}
+
+/// Addresses are VAs, or zero when there's nothing to point to.
+#[derive(Debug, Clone, Default)]
+pub struct TlsDirectory {
+    pub start: u64,
+    pub end: u64,
+    pub index: u64,
+    /// The null-terminated array of callback pointers.
+    pub callbacks: u64,
+    /// The template's alignment, as `IMAGE_SCN_ALIGN_*` flags.
+    pub characteristics: u32,
+}
+
+impl TlsDirectory {
+    pub fn write(&self, data: &mut [u8]) {
+        for (offset, value) in [self.start, self.end, self.index, self.callbacks]
+            .into_iter()
+            .enumerate()
+        {
+            data[offset * 8..][..8].copy_from_slice(&value.to_le_bytes());
+        }
+        data[36..40].copy_from_slice(&self.characteristics.to_le_bytes());
+    }
+
+    /// The offsets of the addresses that are set, which need base relocations.
+    pub fn pointer_offsets(&self) -> Vec<u32> {
+        [self.start, self.end, self.index, self.callbacks]
+            .into_iter()
+            .zip(0..)
+            .filter(|&(value, _)| value != 0)
+            .map(|(_, idx)| idx * 8)
+            .collect()
+    }
+}
@@ -0,0 +1,113 @@
+//! Decoding x64 unwind info, the `UNWIND_INFO` structures in `.xdata` that
+//! `.pdata` entries point to. The linker copies them verbatim, so nothing
+//! else notices broken unwind data until an exception fails to propagate
+//! through the function at runtime.
+
+/// The size of a `.pdata` entry: begin, end and unwind info RVAs.
+pub const RUNTIME_FUNCTION_SIZE: usize = 12;
+
+const UNW_FLAG_EHANDLER: u8 = 0x1;
+const UNW_FLAG_UHANDLER: u8 = 0x2;
+const UNW_FLAG_CHAININFO: u8 = 0x4;
+
+const UWOP_PUSH_NONVOL: u8 = 0;
+const UWOP_ALLOC_LARGE: u8 = 1;
+const UWOP_ALLOC_SMALL: u8 = 2;
+const UWOP_SET_FPREG: u8 = 3;
+const UWOP_SAVE_NONVOL: u8 = 4;
+const UWOP_SAVE_NONVOL_FAR: u8 = 5;
+/// `UWOP_SAVE_XMM` in version 1, epilog descriptions in version 2.
+const UWOP_EPILOG: u8 = 6;
+const UWOP_SAVE_XMM128: u8 = 8;
+const UWOP_SAVE_XMM128_FAR: u8 = 9;
+const UWOP_PUSH_MACHFRAME: u8 = 10;
+
+/// Describes what's wrong with the unwind info at the start of `data`, or
+/// returns `None` if it decodes cleanly.
+pub fn problem(data: &[u8]) -> Option<String> {
+    let [version_flags, prolog_size, code_count, frame, ..] = *data else {
+        return Some("the header is truncated".to_owned());
+    };
+    let version = version_flags & 0x7;
+    let flags = version_flags >> 3;
+    if !matches!(version, 1 | 2) {
+        return Some(format!("version {version} is not supported"));
+    }
+    if flags & !(UNW_FLAG_EHANDLER | UNW_FLAG_UHANDLER | UNW_FLAG_CHAININFO) != 0 {
+        return Some(format!("unknown flags {flags:#x}"));
+    }
+    let chained = flags & UNW_FLAG_CHAININFO != 0;
+    if chained && flags & (UNW_FLAG_EHANDLER | UNW_FLAG_UHANDLER) != 0 {
+        return Some("chained unwind info also has an exception handler".to_owned());
+    }
+
+    // The codes array is padded to an even number of slots.
+    let mut size = 4 + usize::from(code_count).next_multiple_of(2) * 2;
+    if chained {
+        size += RUNTIME_FUNCTION_SIZE;
+    } else if flags != 0 {
+        size += 4;
+    }
+    if data.len() < size {
+        return Some(format!(
+            "it is {} bytes, but its {code_count} unwind codes and flags need {size}",
+            data.len()
+        ));
+    }
+
+    let codes = &data[4..][..usize::from(code_count) * 2];
+    let mut sets_frame_pointer = false;
+    let mut last_offset = None;
+    let mut slot = 0;
+    while slot < codes.len() / 2 {
+        let offset = codes[slot * 2];
+        let op = codes[slot * 2 + 1] & 0xf;
+        let info = codes[slot * 2 + 1] >> 4;
+        let slots = match (op, info) {
+            (UWOP_PUSH_NONVOL | UWOP_ALLOC_SMALL | UWOP_SET_FPREG, _) => 1,
+            (UWOP_ALLOC_LARGE, 0) | (UWOP_SAVE_NONVOL | UWOP_SAVE_XMM128, _) => 2,
+            (UWOP_ALLOC_LARGE, 1) | (UWOP_SAVE_NONVOL_FAR | UWOP_SAVE_XMM128_FAR, _) => 3,
+            (UWOP_EPILOG, _) if version == 2 => 2,
+            (UWOP_PUSH_MACHFRAME, 0 | 1) => 1,
+            _ => {
+                return Some(format!(
+                    "unwind code {slot} has invalid operation {op} with info {info}"
+                ));
+            }
+        };
+        if slot + slots > usize::from(code_count) {
+            return Some(format!(
+                "unwind code {slot} needs {slots} slots, but only {} are left",
+                usize::from(code_count) - slot
+            ));
+        }
+        // Epilog descriptions aren't prolog offsets.
+        if op != UWOP_EPILOG {
+            if offset > prolog_size {
+                return Some(format!(
+                    "unwind code {slot} is at offset {offset}, past the {prolog_size}-byte prolog"
+                ));
+            }
+            if last_offset.is_some_and(|last| offset > last) {
+                return Some(format!(
+                    "unwind code {slot} is out of order, codes have to be sorted by descending offset"
+                ));
+            }
+            last_offset = Some(offset);
+        }
+        sets_frame_pointer |= op == UWOP_SET_FPREG;
+        slot += slots;
+    }
+
+    let frame_register = frame & 0xf;
+    if sets_frame_pointer && frame_register == 0 {
+        return Some("UWOP_SET_FPREG is used without a frame register".to_owned());
+    }
+    // Chained unwind info inherits the frame pointer setup of the primary.
+    if !sets_frame_pointer && frame_register != 0 && !chained {
+        return Some(format!(
+            "frame register {frame_register} is named, but the prolog never sets it up with UWOP_SET_FPREG"
+        ));
+    }
+    None
+}
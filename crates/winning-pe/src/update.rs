@@ -0,0 +1,258 @@
+use std::io;
+
+use binrw::BinWrite;
+use color_eyre::Result;
+use winning_coff::{
+    coff::{CoffHeader, SectionFlags, SectionHeader},
+    diagnostics::{Code, Diagnostics, fatal},
+};
+
+use crate::{
+    image::ImageModel,
+    pe::{DataDirectory, OptionalHeader, PE_SIGNATURE, checksum},
+    resources::{Resource, build_resource_section, merge_resources, read_resource_directory},
+};
+
+const SECTION_HEADER_SIZE: usize = 40;
+
+fn align_up(value: u32, align: u32) -> u32 {
+    value.next_multiple_of(align)
+}
+
+/// Replaces or adds resources in an existing image, rebuilding `.rsrc` without
+/// touching the code. Only sections after `.rsrc` that nothing refers to by
+/// address (like `.reloc`) can be moved to make room, so those must all be
+/// discardable.
+pub fn update_resources(
+    data: &[u8],
+    updates: Vec<Resource>,
+    diag: &mut Diagnostics<'_>,
+) -> Result<Vec<u8>> {
+    let image = ImageModel::parse(data)?;
+    let mut optional = image.optional_header.clone();
+    let section_alignment = optional.section_alignment;
+    let file_alignment = optional.file_alignment;
+
+    let directory = optional.resource_table;
+    let existing = if directory.size > 0 {
+        let Some(idx) = image
+            .sections
+            .iter()
+            .position(|section| image.directory_section(directory) == Some(section))
+        else {
+            return fatal(
+                Code::CorruptInput,
+                "resource directory is not within a section",
+            );
+        };
+        if image.sections[idx].name != ".rsrc" {
+            return fatal(
+                Code::UnsupportedImage,
+                format!(
+                    "resources are in {}, which holds other data too",
+                    image.sections[idx].name
+                ),
+            );
+        }
+        Some(idx)
+    } else {
+        None
+    };
+
+    let mut resources = match existing {
+        Some(_) => {
+            let Some(rsrc) = image.read(directory.virtual_address, directory.size) else {
+                return fatal(Code::CorruptInput, "resource directory is out of bounds");
+            };
+            read_resource_directory(rsrc, |rva, len| image.read(rva, len))?
+        }
+        None => Vec::new(),
+    };
+    merge_resources(&mut resources, updates);
+
+    // Everything from `position` on is laid out again.
+    let is_discardable = |section: &SectionHeader| {
+        section
+            .characteristics
+            .contains(SectionFlags::IMAGE_SCN_MEM_DISCARDABLE)
+    };
+    let position = existing.unwrap_or_else(|| {
+        image
+            .sections
+            .iter()
+            .rposition(|section| !is_discardable(section))
+            .map_or(0, |idx| idx + 1)
+    });
+    let moved_start = position + usize::from(existing.is_some());
+    if let Some(section) = image.sections[moved_start..]
+        .iter()
+        .find(|section| !is_discardable(section))
+    {
+        return fatal(
+            Code::UnsupportedImage,
+            format!("{} after .rsrc would have to be moved", section.name),
+        );
+    }
+
+    let section_count = image.sections.len() + usize::from(existing.is_none());
+    let headers_end = image.pe_offset
+        + PE_SIGNATURE.len()
+        + size_of::<CoffHeader>()
+        + usize::from(image.header.size_of_optional_header)
+        + section_count * SECTION_HEADER_SIZE;
+    if headers_end > optional.size_of_headers as usize {
+        return fatal(
+            Code::UnsupportedImage,
+            "no room in the headers for another section",
+        );
+    }
+
+    let prefix = &image.sections[..position];
+    let mut rva = match existing {
+        Some(idx) => image.sections[idx].virtual_address,
+        None => prefix.last().map_or(
+            align_up(optional.size_of_headers, section_alignment),
+            |section| {
+                align_up(
+                    section.virtual_address + section.virtual_size,
+                    section_alignment,
+                )
+            },
+        ),
+    };
+    let prefix_end = prefix
+        .iter()
+        .map(|section| section.pointer_to_raw_data + section.size_of_raw_data)
+        .max()
+        .unwrap_or(optional.size_of_headers);
+    let mut file_offset = align_up(prefix_end, file_alignment);
+
+    let mut sections = prefix
+        .iter()
+        .map(|section| (section.clone(), None))
+        .collect::<Vec<_>>();
+
+    let rsrc = build_resource_section(&resources, rva);
+    let rsrc_characteristics = match existing {
+        Some(idx) => image.sections[idx].characteristics,
+        None => SectionFlags::IMAGE_SCN_CNT_INITIALIZED_DATA | SectionFlags::IMAGE_SCN_MEM_READ,
+    };
+    let resource_table = DataDirectory {
+        virtual_address: rva,
+        size: rsrc.len() as u32,
+    };
+    let mut place = |name: &str, characteristics, contents: Vec<u8>, virtual_size: u32| {
+        let raw_size = align_up(contents.len() as u32, file_alignment);
+        let header = SectionHeader {
+            name: name.to_owned().into(),
+            virtual_size,
+            virtual_address: rva,
+            size_of_raw_data: raw_size,
+            pointer_to_raw_data: if raw_size == 0 { 0 } else { file_offset },
+            pointer_to_relocations: 0,
+            pointer_to_linenumbers: 0,
+            number_of_relocations: 0,
+            number_of_linenumbers: 0,
+            characteristics,
+        };
+        rva = align_up(rva + virtual_size, section_alignment);
+        file_offset += raw_size;
+        (header, Some(contents))
+    };
+    let rsrc_size = rsrc.len() as u32;
+    sections.push(place(".rsrc", rsrc_characteristics, rsrc, rsrc_size));
+
+    for section in &image.sections[moved_start..] {
+        let Some(contents) = image.section_data(section) else {
+            return fatal(
+                Code::CorruptInput,
+                format!("section {} data out of bounds", section.name),
+            );
+        };
+        let moved = place(
+            &section.name,
+            section.characteristics,
+            contents.to_vec(),
+            section.virtual_size,
+        );
+        let old = section.virtual_address..section.virtual_address + section.virtual_size;
+        let new_rva = moved.0.virtual_address;
+        for (idx, directory) in optional.data_directories_mut().into_iter().enumerate() {
+            if idx != OptionalHeader::CERTIFICATE_TABLE_INDEX
+                && directory.size > 0
+                && old.contains(&directory.virtual_address)
+            {
+                directory.virtual_address = directory.virtual_address - old.start + new_rva;
+            }
+        }
+        sections.push(moved);
+    }
+
+    optional.resource_table = resource_table;
+
+    // The certificate table holds a file offset and signs the old contents.
+    let certificate = optional.certificate_table;
+    let sections_end = image
+        .sections
+        .iter()
+        .map(|section| (section.pointer_to_raw_data + section.size_of_raw_data) as usize)
+        .max()
+        .unwrap_or(0);
+    let mut overlay = data.get(sections_end..).unwrap_or_default();
+    if certificate.size > 0 {
+        diag.warn(
+            Code::SignatureRemoved,
+            "the image was signed; the signature has been removed",
+        );
+        optional.certificate_table = Default::default();
+        let start = (certificate.virtual_address as usize).saturating_sub(sections_end);
+        overlay = &overlay[..start.min(overlay.len())];
+    }
+
+    optional.size_of_image = rva;
+    optional.size_of_initialized_data = sections
+        .iter()
+        .filter(|(section, _)| {
+            section
+                .characteristics
+                .contains(SectionFlags::IMAGE_SCN_CNT_INITIALIZED_DATA)
+        })
+        .map(|(section, _)| section.size_of_raw_data)
+        .sum();
+
+    let mut out = data[..prefix_end as usize].to_vec();
+    for (section, contents) in &sections {
+        if let Some(contents) = contents {
+            out.resize(section.pointer_to_raw_data as usize, 0);
+            out.extend_from_slice(contents);
+            out.resize(
+                (section.pointer_to_raw_data + section.size_of_raw_data) as usize,
+                0,
+            );
+        }
+    }
+    out.extend_from_slice(overlay);
+
+    let mut header = image.header;
+    header.number_of_sections = sections.len() as u16;
+    let cursor = &mut io::Cursor::new(&mut out);
+    cursor.set_position((image.pe_offset + PE_SIGNATURE.len()) as u64);
+    header.write(cursor)?;
+    let optional_offset = cursor.position() as usize;
+    let had_checksum = optional.check_sum != 0;
+    optional.check_sum = 0;
+    image.write_optional_header(&optional, cursor)?;
+    cursor
+        .set_position((optional_offset + usize::from(image.header.size_of_optional_header)) as u64);
+    for (section, _) in &sections {
+        section.write(cursor)?;
+    }
+
+    if had_checksum {
+        let checksum_offset = optional_offset + OptionalHeader::CHECKSUM_OFFSET;
+        let sum = checksum(&out, checksum_offset);
+        out[checksum_offset..checksum_offset + 4].copy_from_slice(&sum.to_le_bytes());
+    }
+
+    Ok(out)
+}
@@ -0,0 +1,316 @@
+//! Checking an image against the rules the Windows loader enforces, without
+//! knowing how it was linked. `--self-check` runs this on our own output, and
+//! `winning verify` on any image.
+
+use winning_coff::coff::{CoffHeader, SECTION_HEADER_SIZE, SectionFlags};
+
+use crate::{
+    image::ImageModel,
+    pe::{OptionalHeader, OptionalHeader32, PE_SIGNATURE, checksum},
+    unwind::RUNTIME_FUNCTION_SIZE,
+};
+
+/// Everything about `image` that would make the loader reject it, or that
+/// tools reading it rely on, as messages that say what's wrong and where.
+pub fn verify(image: &ImageModel<'_>) -> Vec<String> {
+    let mut failures = Vec::new();
+    headers(image, &mut failures);
+    sections(image, &mut failures);
+    directories(image, &mut failures);
+    exception_table(image, &mut failures);
+    export_names(image, &mut failures);
+    failures
+}
+
+fn headers(image: &ImageModel<'_>, failures: &mut Vec<String>) {
+    let optional = &image.optional_header;
+    let (section_alignment, file_alignment) = (optional.section_alignment, optional.file_alignment);
+    if !section_alignment.is_power_of_two() {
+        failures.push(format!(
+            "section alignment {section_alignment:#x} is not a power of two"
+        ));
+    }
+    if !file_alignment.is_power_of_two() || !(0x200..=0x1_0000).contains(&file_alignment) {
+        failures.push(format!(
+            "file alignment {file_alignment:#x} is not a power of two from 512 to 64K"
+        ));
+    }
+    if file_alignment > section_alignment {
+        failures.push(format!(
+            "file alignment {file_alignment:#x} is larger than section alignment {section_alignment:#x}"
+        ));
+    }
+
+    let directories_offset = match image.optional_header_32 {
+        Some(_) => OptionalHeader32::DATA_DIRECTORIES_OFFSET,
+        None => OptionalHeader::DATA_DIRECTORIES_OFFSET,
+    };
+    let expected_size = directories_offset + optional.number_of_rva_and_sizes as usize * 8;
+    if optional.number_of_rva_and_sizes > 16 {
+        failures.push(format!(
+            "{} data directories, more than the 16 there are",
+            optional.number_of_rva_and_sizes
+        ));
+    } else if usize::from(image.header.size_of_optional_header) != expected_size {
+        failures.push(format!(
+            "optional header is {:#x} bytes, but {} data directories need {expected_size:#x}",
+            image.header.size_of_optional_header, optional.number_of_rva_and_sizes
+        ));
+    }
+
+    let headers_end = image.pe_offset
+        + PE_SIGNATURE.len()
+        + size_of::<CoffHeader>()
+        + usize::from(image.header.size_of_optional_header)
+        + image.sections.len() * SECTION_HEADER_SIZE;
+    if (optional.size_of_headers as usize) < headers_end {
+        failures.push(format!(
+            "size of headers {:#x} doesn't cover the section table, which ends at {headers_end:#x}",
+            optional.size_of_headers
+        ));
+    }
+    if !optional
+        .size_of_headers
+        .is_multiple_of(file_alignment.max(1))
+    {
+        failures.push(format!(
+            "size of headers {:#x} is not a multiple of the file alignment",
+            optional.size_of_headers
+        ));
+    }
+    if !optional
+        .size_of_image
+        .is_multiple_of(section_alignment.max(1))
+    {
+        failures.push(format!(
+            "size of image {:#x} is not a multiple of the section alignment",
+            optional.size_of_image
+        ));
+    }
+    if !optional.image_base.is_multiple_of(0x1_0000) {
+        failures.push(format!(
+            "image base {:#x} is not a multiple of 64K",
+            optional.image_base
+        ));
+    }
+
+    let entry = optional.address_of_entry_point;
+    if entry != 0
+        && !image.section_containing(entry).is_some_and(|section| {
+            section
+                .characteristics
+                .contains(SectionFlags::IMAGE_SCN_MEM_EXECUTE)
+        })
+    {
+        failures.push(format!(
+            "entry point {entry:#x} is not in an executable section"
+        ));
+    }
+
+    if optional.check_sum != 0 {
+        let offset = image.pe_offset
+            + PE_SIGNATURE.len()
+            + size_of::<CoffHeader>()
+            + OptionalHeader::CHECKSUM_OFFSET;
+        let sum = checksum(image.data(), offset);
+        if sum != optional.check_sum {
+            failures.push(format!(
+                "checksum is {:#x}, but the image sums to {sum:#x}",
+                optional.check_sum
+            ));
+        }
+    }
+}
+
+fn sections(image: &ImageModel<'_>, failures: &mut Vec<String>) {
+    let optional = &image.optional_header;
+    let section_alignment = optional.section_alignment.max(1);
+    let file_alignment = optional.file_alignment.max(1);
+    let file_size = image.data().len() as u64;
+
+    // Sections have to follow the headers and each other in memory, in order.
+    let mut next_rva =
+        u64::from(optional.size_of_headers).next_multiple_of(section_alignment.into());
+    let mut raw_ranges = Vec::new();
+    for section in &image.sections {
+        let name = &section.name;
+        let rva = u64::from(section.virtual_address);
+        if !section.virtual_address.is_multiple_of(section_alignment) {
+            failures.push(format!(
+                "{name} at {rva:#x} is not aligned to the section alignment"
+            ));
+        }
+        if rva < next_rva {
+            failures.push(format!(
+                "{name} at {rva:#x} overlaps the headers or the section before it, which end at {next_rva:#x}"
+            ));
+        }
+        let size = section.virtual_size.max(section.size_of_raw_data);
+        next_rva = (rva + u64::from(size)).next_multiple_of(section_alignment.into());
+
+        if section.size_of_raw_data == 0 {
+            continue;
+        }
+        let start = u64::from(section.pointer_to_raw_data);
+        let end = start + u64::from(section.size_of_raw_data);
+        if !section.pointer_to_raw_data.is_multiple_of(file_alignment) {
+            failures.push(format!(
+                "raw data of {name} at {start:#x} is not aligned to the file alignment"
+            ));
+        }
+        if !section.size_of_raw_data.is_multiple_of(file_alignment) {
+            failures.push(format!(
+                "raw data of {name} is {:#x} bytes, not a multiple of the file alignment",
+                section.size_of_raw_data
+            ));
+        }
+        if end > file_size {
+            failures.push(format!(
+                "raw data of {name} ends at {end:#x}, past the end of the file at {file_size:#x}"
+            ));
+        }
+        if start < u64::from(optional.size_of_headers) {
+            failures.push(format!("raw data of {name} overlaps the headers"));
+        }
+        raw_ranges.push((start, end, name));
+    }
+    if next_rva > u64::from(optional.size_of_image) {
+        failures.push(format!(
+            "sections end at {next_rva:#x}, past the size of image {:#x}",
+            optional.size_of_image
+        ));
+    }
+
+    raw_ranges.sort();
+    for pair in raw_ranges.windows(2) {
+        let ((_, end, first), (start, _, second)) = (pair[0], pair[1]);
+        if start < end {
+            failures.push(format!("raw data of {first} and {second} overlap"));
+        }
+    }
+}
+
+fn directories(image: &ImageModel<'_>, failures: &mut Vec<String>) {
+    let optional = &image.optional_header;
+    let names = [
+        "export",
+        "import",
+        "resource",
+        "exception",
+        "certificate",
+        "base relocation",
+        "debug",
+        "architecture",
+        "global pointer",
+        "TLS",
+        "load config",
+        "bound import",
+        "IAT",
+        "delay import",
+        "CLR runtime header",
+        "reserved",
+    ];
+    for (idx, (name, directory)) in names
+        .into_iter()
+        .zip(optional.data_directories())
+        .enumerate()
+    {
+        if directory.virtual_address == 0 && directory.size == 0 {
+            continue;
+        }
+        if idx >= optional.number_of_rva_and_sizes as usize {
+            failures.push(format!(
+                "{name} directory is set, but past the number of data directories"
+            ));
+            continue;
+        }
+        let end = u64::from(directory.virtual_address) + u64::from(directory.size);
+        if idx == OptionalHeader::CERTIFICATE_TABLE_INDEX {
+            // The certificate table is the one directory that has a file offset.
+            if end > image.data().len() as u64 {
+                failures.push(format!(
+                    "certificate table ends at {end:#x}, past the end of the file"
+                ));
+            }
+            continue;
+        }
+        if end > u64::from(optional.size_of_image) {
+            failures.push(format!(
+                "{name} directory ends at {end:#x}, past the size of image {:#x}",
+                optional.size_of_image
+            ));
+        } else if name != "bound import" && image.directory_section(directory).is_none() {
+            // Bound imports are in the headers, not in a section.
+            failures.push(format!(
+                "{name} directory at {:#x} is not within a single section",
+                directory.virtual_address
+            ));
+        }
+    }
+}
+
+/// `.pdata` is binary-searched by the unwinder, so it has to be sorted and
+/// mustn't overlap.
+fn exception_table(image: &ImageModel<'_>, failures: &mut Vec<String>) {
+    let directory = image.optional_header.exception_table;
+    if directory.size == 0 {
+        if image
+            .sections
+            .iter()
+            .any(|section| section.name == ".pdata")
+        {
+            failures.push(
+                "image has a .pdata section but no exception directory, so it can't be unwound"
+                    .to_owned(),
+            );
+        }
+        return;
+    }
+    if !(directory.size as usize).is_multiple_of(RUNTIME_FUNCTION_SIZE) {
+        failures.push(format!(
+            "exception directory is {:#x} bytes, not a whole number of entries",
+            directory.size
+        ));
+    }
+    let Some(data) = image.read(directory.virtual_address, directory.size) else {
+        return;
+    };
+    let read_u32 = |entry: &[u8], offset: usize| {
+        u32::from_le_bytes(entry[offset..offset + 4].try_into().unwrap())
+    };
+    let mut previous_end = 0;
+    for entry in data.chunks_exact(RUNTIME_FUNCTION_SIZE) {
+        let (begin, end) = (read_u32(entry, 0), read_u32(entry, 4));
+        if begin >= end {
+            failures.push(format!(
+                "exception entry for {begin:#x} ends at {end:#x}, before it begins"
+            ));
+        }
+        if begin < previous_end {
+            failures.push(format!(
+                "exception entry for {begin:#x} is out of order or overlaps the one before it"
+            ));
+        }
+        previous_end = end;
+    }
+}
+
+/// The loader binary-searches export names for `GetProcAddress`.
+fn export_names(image: &ImageModel<'_>, failures: &mut Vec<String>) {
+    if image.optional_header.export_table.size == 0 {
+        return;
+    }
+    let Some(exports) = image.exports() else {
+        failures.push("export directory is unreadable".to_owned());
+        return;
+    };
+    let names = exports.named().map(|(name, _)| name).collect::<Vec<_>>();
+    for pair in names.windows(2) {
+        if pair[0].as_bytes() >= pair[1].as_bytes() {
+            failures.push(format!(
+                "export names {} and {} are not sorted",
+                pair[0], pair[1]
+            ));
+        }
+    }
+}
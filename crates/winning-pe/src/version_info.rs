@@ -0,0 +1,180 @@
+//! The `VS_VERSIONINFO` resource Explorer shows in a file's properties,
+//! written from command-line flags instead of an `.rc` file.
+
+use std::fmt::{self, Display};
+
+use color_eyre::Result;
+use winning_coff::diagnostics::{Code, fatal};
+
+use crate::resources::{Resource, ResourceId};
+
+pub const RT_VERSION: u16 = 16;
+const VS_VERSION_INFO: u16 = 1;
+const VS_FFI_SIGNATURE: u32 = 0xFEEF_04BD;
+const VS_FFI_STRUCVERSION: u32 = 0x0001_0000;
+const VS_FFI_FILEFLAGSMASK: u32 = 0x3F;
+const VOS_NT_WINDOWS32: u32 = 0x0004_0004;
+const VFT_APP: u32 = 1;
+const LANG_EN_US: u16 = 0x409;
+/// UTF-16, the code page of the strings.
+const CODE_PAGE_UNICODE: u16 = 1200;
+
+/// A `major.minor.build.revision` version, with missing parts zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Version(pub [u16; 4]);
+
+impl Version {
+    pub fn parse(value: &str) -> Result<Self> {
+        let mut parts = [0; 4];
+        for (idx, part) in value.split('.').enumerate() {
+            match (parts.get_mut(idx), part.parse()) {
+                (Some(slot), Ok(number)) => *slot = number,
+                _ => {
+                    return fatal(
+                        Code::InvalidOption,
+                        format!(
+                            "invalid version {value}, expected up to four numbers like 1.2.3.4"
+                        ),
+                    );
+                }
+            }
+        }
+        Ok(Self(parts))
+    }
+
+    /// The version as the two `DWORD`s of `VS_FIXEDFILEINFO`.
+    fn dwords(self) -> [u32; 2] {
+        let [a, b, c, d] = self.0.map(u32::from);
+        [a << 16 | b, c << 16 | d]
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d] = self.0;
+        write!(f, "{a}.{b}.{c}.{d}")
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VersionInfo {
+    /// Defaults to the product version.
+    pub file_version: Option<Version>,
+    /// Defaults to the file version.
+    pub product_version: Option<Version>,
+    /// The `StringFileInfo` entries, like `CompanyName`, in order.
+    pub strings: Vec<(&'static str, String)>,
+}
+
+impl VersionInfo {
+    pub fn set_string(&mut self, key: &'static str, value: &str) {
+        self.strings.retain(|(other, _)| *other != key);
+        self.strings.push((key, value.to_owned()));
+    }
+
+    pub fn resource(&self) -> Result<Resource> {
+        Ok(Resource {
+            r#type: ResourceId::Id(RT_VERSION),
+            name: ResourceId::Id(VS_VERSION_INFO),
+            language: LANG_EN_US,
+            code_page: 0,
+            data: self.to_bytes()?,
+        })
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        let file_version = self
+            .file_version
+            .or(self.product_version)
+            .unwrap_or_default();
+        let product_version = self.product_version.unwrap_or(file_version);
+
+        let mut fixed = Vec::with_capacity(52);
+        for value in [
+            VS_FFI_SIGNATURE,
+            VS_FFI_STRUCVERSION,
+            file_version.dwords()[0],
+            file_version.dwords()[1],
+            product_version.dwords()[0],
+            product_version.dwords()[1],
+            VS_FFI_FILEFLAGSMASK,
+            0,
+            VOS_NT_WINDOWS32,
+            VFT_APP,
+            0,
+            0,
+            0,
+        ] {
+            fixed.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let versions = [
+            ("FileVersion", file_version.to_string()),
+            ("ProductVersion", product_version.to_string()),
+        ];
+        let strings = versions
+            .iter()
+            .map(|(key, value)| (*key, value))
+            .chain(self.strings.iter().map(|(key, value)| (*key, value)))
+            .map(|(key, value)| node(key, Value::Text(value), &[]))
+            .collect::<Result<Vec<_>>>()?;
+        let table_key = format!("{LANG_EN_US:04x}{CODE_PAGE_UNICODE:04x}");
+        let string_table = node(&table_key, Value::None, &strings)?;
+        let string_file_info = node("StringFileInfo", Value::None, &[string_table])?;
+
+        let mut translation = LANG_EN_US.to_le_bytes().to_vec();
+        translation.extend_from_slice(&CODE_PAGE_UNICODE.to_le_bytes());
+        let var = node("Translation", Value::Binary(&translation), &[])?;
+        let var_file_info = node("VarFileInfo", Value::None, &[var])?;
+
+        node(
+            "VS_VERSION_INFO",
+            Value::Binary(&fixed),
+            &[string_file_info, var_file_info],
+        )
+    }
+}
+
+enum Value<'a> {
+    None,
+    Text(&'a str),
+    Binary(&'a [u8]),
+}
+
+/// One structure of the version info tree: its length, the length of its
+/// value, whether that's text, a key, the value and the children, each
+/// aligned to 4 bytes.
+fn node(key: &str, value: Value<'_>, children: &[Vec<u8>]) -> Result<Vec<u8>> {
+    let utf16 = |text: &str| {
+        text.encode_utf16()
+            .chain([0])
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>()
+    };
+    let (value, value_length, is_text) = match value {
+        // Like rc.exe, containers without a value are marked as text.
+        Value::None => (Vec::new(), 0, true),
+        // Text lengths are in UTF-16 units, including the NUL.
+        Value::Text(text) => (utf16(text), text.encode_utf16().count() + 1, true),
+        Value::Binary(data) => (data.to_vec(), data.len(), false),
+    };
+    let mut data = vec![0; 6];
+    data.extend_from_slice(&utf16(key));
+    data.resize(data.len().next_multiple_of(4), 0);
+    data.extend_from_slice(&value);
+    for child in children {
+        data.resize(data.len().next_multiple_of(4), 0);
+        data.extend_from_slice(child);
+    }
+    // Lengths are 16 bits, so all strings together must stay below 64 KiB.
+    let Ok(length) = u16::try_from(data.len()) else {
+        return fatal(
+            Code::InvalidOption,
+            format!("version info {key} is too large, it must stay below 64 KiB"),
+        );
+    };
+    data[0..2].copy_from_slice(&length.to_le_bytes());
+    data[2..4].copy_from_slice(&(value_length as u16).to_le_bytes());
+    data[4..6].copy_from_slice(&u16::from(is_text).to_le_bytes());
+    Ok(data)
+}
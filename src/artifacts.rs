@@ -0,0 +1,99 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use color_eyre::Result;
+use winning_coff::diagnostics::{Code, fatal};
+
+/// The output files of one invocation. Each is written to a temporary file
+/// next to its destination or in the intermediates directory, and they're
+/// only moved into place once all of them were written and the link
+/// succeeded, so a failed link leaves the previous set of outputs alone
+/// instead of a mix of old and new files.
+#[derive(Default)]
+pub struct Artifacts {
+    /// `--intermediates-dir`.
+    intermediates: Option<PathBuf>,
+    /// Temporary and final paths.
+    staged: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Artifacts {
+    pub fn new(intermediates: Option<PathBuf>) -> Self {
+        Self {
+            intermediates,
+            staged: Vec::new(),
+        }
+    }
+
+    /// Writes an artifact to a temporary file and returns its path.
+    pub fn stage(
+        &mut self,
+        path: &Path,
+        write: impl FnOnce(&mut BufWriter<File>) -> Result<()>,
+    ) -> Result<&Path> {
+        let mut temp_name = path.file_name().unwrap_or_default().to_owned();
+        temp_name.push(format!(".{}.tmp", std::process::id()));
+        let temp = match &self.intermediates {
+            Some(dir) => dir.join(temp_name),
+            None => path.with_file_name(temp_name),
+        };
+
+        let created = match &self.intermediates {
+            Some(dir) => fs::create_dir_all(dir).and_then(|()| File::create(&temp)),
+            None => File::create(&temp),
+        };
+        let written = created.map_err(color_eyre::Report::from).and_then(|file| {
+            self.staged.push((temp.clone(), path.to_owned()));
+            let mut out = BufWriter::new(file);
+            write(&mut out)?;
+            Ok(out.flush()?)
+        });
+        if let Err(err) = written {
+            return match err.downcast_ref::<io::Error>() {
+                Some(err) => fatal(
+                    Code::CannotOpenOutput,
+                    format!("cannot write {}: {err}", path.display()),
+                ),
+                None => Err(err),
+            };
+        }
+        Ok(&self.staged.last().unwrap().0)
+    }
+
+    /// Moves all artifacts into place.
+    pub fn commit(mut self) -> Result<()> {
+        for (temp, path) in std::mem::take(&mut self.staged) {
+            if let Err(err) = move_file(&temp, &path) {
+                let _ = fs::remove_file(&temp);
+                return fatal(
+                    Code::CannotOpenOutput,
+                    format!("cannot write {}: {err}", path.display()),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Renames `from` to `to`, copying if the intermediates directory is on
+/// another file system.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(from, to)?;
+            fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+impl Drop for Artifacts {
+    fn drop(&mut self) {
+        for (temp, _) in &self.staged {
+            let _ = fs::remove_file(temp);
+        }
+    }
+}
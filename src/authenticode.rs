@@ -0,0 +1,29 @@
+use std::io::Write;
+
+use color_eyre::Result;
+use winning_coff::diagnostics::{Code, fatal};
+use winning_pe::{
+    authenticode::authenticode_digest, digest::hex, image::ImageModel, input::Input, pe::DOS_MAGIC,
+};
+
+/// `winning authenticode <images>...`: prints the SHA-256 Authenticode digest
+/// of each image, like `sha256sum`, for signing it elsewhere. Images linked
+/// with `--reserve-certificate` keep this digest once the signature is in.
+pub fn run(args: Vec<String>, out: &mut dyn Write) -> Result<()> {
+    if args.is_empty() || args.iter().any(|arg| arg.starts_with("--")) {
+        return fatal(
+            Code::InvalidOption,
+            "usage: winning authenticode <images>...",
+        );
+    }
+    for path in &args {
+        let input = Input::open(path)?;
+        let data = input.data();
+        if !data.starts_with(DOS_MAGIC) {
+            return fatal(Code::CorruptInput, format!("{path}: not a PE image"));
+        }
+        let digest = authenticode_digest(&ImageModel::parse(data)?)?;
+        writeln!(out, "{}  {}", hex(&digest), input.name)?;
+    }
+    Ok(())
+}
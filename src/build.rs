@@ -0,0 +1,129 @@
+//! Linking auxiliary Windows executables, like shims or test fixtures, from a
+//! `build.rs` script without needing MSVC tools on the host.
+//!
+//! ```no_run
+//! let exe = winning::build::Build::new("shim.exe")
+//!     .object("shim/shim.obj")
+//!     .library("shim/kernel32.lib")
+//!     .entry("main")
+//!     .link();
+//! println!("cargo:rustc-env=SHIM_PATH={}", exe.display());
+//! ```
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use winning_coff::diagnostics::{Code, Diagnostic};
+use winning_pe::{LinkError, Linker, pe::Subsystem};
+
+pub struct Build {
+    name: String,
+    out_dir: Option<PathBuf>,
+    inputs: Vec<PathBuf>,
+    entry: Option<String>,
+    subsystem: Subsystem,
+}
+
+impl Build {
+    /// `name` is the file name of the output, placed in `OUT_DIR`.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            out_dir: None,
+            inputs: Vec::new(),
+            entry: None,
+            subsystem: Subsystem::Console,
+        }
+    }
+
+    /// Adds a COFF object.
+    pub fn object(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.inputs.push(path.as_ref().to_owned());
+        self
+    }
+
+    /// Adds an archive or import library.
+    pub fn library(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.inputs.push(path.as_ref().to_owned());
+        self
+    }
+
+    pub fn entry(&mut self, entry: impl Into<String>) -> &mut Self {
+        self.entry = Some(entry.into());
+        self
+    }
+
+    pub fn subsystem(&mut self, subsystem: Subsystem) -> &mut Self {
+        self.subsystem = subsystem;
+        self
+    }
+
+    /// Overrides the output directory, which defaults to `OUT_DIR`.
+    pub fn out_dir(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.out_dir = Some(dir.as_ref().to_owned());
+        self
+    }
+
+    /// Links and returns the path of the output, panicking with the
+    /// diagnostics on failure, as build scripts usually want.
+    pub fn link(&self) -> PathBuf {
+        match self.try_link() {
+            Ok(path) => path,
+            Err(err) => {
+                for diagnostic in &err.diagnostics {
+                    println!("cargo:warning={diagnostic}");
+                }
+                panic!("linking {} failed: {err}", self.name);
+            }
+        }
+    }
+
+    /// Links and returns the path of the output. Warnings are passed on to
+    /// cargo, and cargo is told to rerun the build script when an input changes.
+    pub fn try_link(&self) -> Result<PathBuf, LinkError> {
+        let failed = |code, message: String| LinkError {
+            diagnostics: vec![Diagnostic::new(code, message)],
+        };
+
+        let mut linker = Linker::new();
+        linker.set_subsystem(self.subsystem);
+        if let Some(entry) = &self.entry {
+            linker.set_entry(entry.clone());
+        }
+        for path in &self.inputs {
+            println!("cargo:rerun-if-changed={}", path.display());
+            let data = std::fs::read(path).map_err(|err| {
+                failed(
+                    Code::CannotOpenInput,
+                    format!("cannot open {}: {err}", path.display()),
+                )
+            })?;
+            linker.add_object(path.display().to_string(), data);
+        }
+
+        let image = linker.link()?;
+        for diagnostic in linker.diagnostics() {
+            println!("cargo:warning={diagnostic}");
+        }
+
+        let out_dir = match &self.out_dir {
+            Some(dir) => dir.clone(),
+            None => env::var_os("OUT_DIR").map(PathBuf::from).ok_or_else(|| {
+                failed(
+                    Code::CannotOpenOutput,
+                    "OUT_DIR is not set; not running in a build script?".into(),
+                )
+            })?,
+        };
+        let path = out_dir.join(&self.name);
+        std::fs::write(&path, image).map_err(|err| {
+            failed(
+                Code::CannotOpenOutput,
+                format!("cannot write {}: {err}", path.display()),
+            )
+        })?;
+        Ok(path)
+    }
+}
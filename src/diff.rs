@@ -0,0 +1,431 @@
+use std::{collections::HashMap, io::Write};
+
+use color_eyre::Result;
+use winning_coff::diagnostics::{Code, fatal};
+use winning_pe::{
+    image::{ImageImport, ImageImports, ImageModel},
+    input::Input,
+    pe::DOS_MAGIC,
+};
+
+use crate::hash::{HashAlgorithm, content_hash};
+
+const USAGE: &str = "usage: winning diff [--ignore=<noise>,...] <image> <image>\n\
+     noise: timestamp, checksum, linker-version, layout, contents \
+     (default: timestamp,checksum)";
+
+/// Fields that differ between links of the same inputs, or between linkers,
+/// without changing what the image does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Noise {
+    Timestamp,
+    Checksum,
+    LinkerVersion,
+    /// Addresses, file offsets and sizes, which differ between linkers that
+    /// order or pad sections differently.
+    Layout,
+    /// The raw data of sections.
+    Contents,
+}
+
+impl Noise {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "timestamp" => Some(Self::Timestamp),
+            "checksum" => Some(Self::Checksum),
+            "linker-version" => Some(Self::LinkerVersion),
+            "layout" => Some(Self::Layout),
+            "contents" => Some(Self::Contents),
+            _ => None,
+        }
+    }
+}
+
+/// One compared value of an image, like `section[.text].virtual_size`.
+struct Field {
+    key: String,
+    value: String,
+    noise: Option<Noise>,
+}
+
+#[derive(Default)]
+struct Fields(Vec<Field>);
+
+impl Fields {
+    fn push(&mut self, key: impl Into<String>, value: impl ToString, noise: Option<Noise>) {
+        self.0.push(Field {
+            key: key.into(),
+            value: value.to_string(),
+            noise,
+        });
+    }
+
+    fn hex(&mut self, key: impl Into<String>, value: impl Into<u64>, noise: Option<Noise>) {
+        self.push(key, format!("{:#x}", value.into()), noise);
+    }
+}
+
+/// `winning diff [--ignore=<noise>,...] <image> <image>`: compares two images
+/// field by field: the headers, the section table, the data directories and
+/// what they import and export. Fields in the ignored [`Noise`] categories
+/// aren't compared. Fails if anything else differs, for checking our output
+/// against that of another linker.
+pub fn run(args: Vec<String>, out: &mut dyn Write) -> Result<()> {
+    let mut ignored = vec![Noise::Timestamp, Noise::Checksum];
+    let mut paths = Vec::new();
+    for arg in args {
+        if let Some(names) = arg.strip_prefix("--ignore=") {
+            ignored.clear();
+            for name in names.split(',').filter(|name| !name.is_empty()) {
+                let Some(noise) = Noise::from_name(name) else {
+                    return fatal(
+                        Code::InvalidOption,
+                        format!("unknown noise category {name}\n{USAGE}"),
+                    );
+                };
+                ignored.push(noise);
+            }
+        } else if arg.starts_with("--") {
+            return fatal(
+                Code::InvalidOption,
+                format!("unknown diff option {arg}\n{USAGE}"),
+            );
+        } else {
+            paths.push(arg);
+        }
+    }
+    let [left_path, right_path] = paths.as_slice() else {
+        return fatal(Code::InvalidOption, USAGE);
+    };
+
+    let left_input = Input::open(left_path)?;
+    let right_input = Input::open(right_path)?;
+    let left = fields(left_path, left_input.data())?;
+    let right = fields(right_path, right_input.data())?;
+    let is_compared = |field: &&Field| field.noise.is_none_or(|noise| !ignored.contains(&noise));
+
+    let right_values = right
+        .0
+        .iter()
+        .filter(is_compared)
+        .map(|field| (field.key.as_str(), field.value.as_str()))
+        .collect::<HashMap<_, _>>();
+    let left_keys = left
+        .0
+        .iter()
+        .map(|field| field.key.as_str())
+        .collect::<Vec<_>>();
+    let mut differences = 0;
+    for field in left.0.iter().filter(is_compared) {
+        match right_values.get(field.key.as_str()) {
+            Some(&value) if value == field.value => {}
+            Some(&value) => {
+                differences += 1;
+                writeln!(out, "{}: {} != {value}", field.key, field.value)?;
+            }
+            None => {
+                differences += 1;
+                writeln!(out, "only in {left_path}: {} = {}", field.key, field.value)?;
+            }
+        }
+    }
+    for field in right.0.iter().filter(is_compared) {
+        if !left_keys.contains(&field.key.as_str()) {
+            differences += 1;
+            writeln!(out, "only in {right_path}: {} = {}", field.key, field.value)?;
+        }
+    }
+
+    if differences > 0 {
+        let fields = if differences == 1 { "field" } else { "fields" };
+        return fatal(
+            Code::ImagesDiffer,
+            format!("{left_path} and {right_path} differ in {differences} {fields}"),
+        );
+    }
+    Ok(())
+}
+
+fn fields(path: &str, data: &[u8]) -> Result<Fields> {
+    if !data.starts_with(DOS_MAGIC) {
+        return fatal(Code::CorruptInput, format!("{path}: not a PE image"));
+    }
+    let image = ImageModel::parse(data)?;
+    let mut fields = Fields::default();
+    let layout = Some(Noise::Layout);
+
+    let header = &image.header;
+    fields.hex("file_header.machine", header.machine, None);
+    fields.push(
+        "file_header.number_of_sections",
+        header.number_of_sections,
+        None,
+    );
+    fields.hex(
+        "file_header.time_date_stamp",
+        header.time_date_stamp,
+        Some(Noise::Timestamp),
+    );
+    fields.hex(
+        "file_header.pointer_to_symbol_table",
+        header.pointer_to_symbol_table,
+        layout,
+    );
+    fields.push(
+        "file_header.number_of_symbols",
+        header.number_of_symbols,
+        None,
+    );
+    fields.hex(
+        "file_header.characteristics",
+        header.characteristics.bits(),
+        None,
+    );
+
+    let optional = &image.optional_header;
+    let format = if image.optional_header_32.is_some() {
+        "PE32"
+    } else {
+        "PE32+"
+    };
+    fields.push("optional_header.format", format, None);
+    fields.push(
+        "optional_header.linker_version",
+        format!(
+            "{}.{}",
+            optional.major_linker_version, optional.minor_linker_version
+        ),
+        Some(Noise::LinkerVersion),
+    );
+    fields.hex(
+        "optional_header.size_of_code",
+        optional.size_of_code,
+        layout,
+    );
+    fields.hex(
+        "optional_header.size_of_initialized_data",
+        optional.size_of_initialized_data,
+        layout,
+    );
+    fields.hex(
+        "optional_header.size_of_uninitialized_data",
+        optional.size_of_uninitialized_data,
+        layout,
+    );
+    fields.hex(
+        "optional_header.address_of_entry_point",
+        optional.address_of_entry_point,
+        layout,
+    );
+    fields.hex(
+        "optional_header.base_of_code",
+        optional.base_of_code,
+        layout,
+    );
+    fields.hex("optional_header.image_base", optional.image_base, None);
+    fields.hex(
+        "optional_header.section_alignment",
+        optional.section_alignment,
+        None,
+    );
+    fields.hex(
+        "optional_header.file_alignment",
+        optional.file_alignment,
+        None,
+    );
+    for (key, (major, minor)) in [
+        (
+            "operating_system_version",
+            (
+                optional.major_operating_system_version,
+                optional.minor_operating_system_version,
+            ),
+        ),
+        (
+            "image_version",
+            (optional.major_image_version, optional.minor_image_version),
+        ),
+        (
+            "subsystem_version",
+            (
+                optional.major_subsystem_version,
+                optional.minor_subsystem_version,
+            ),
+        ),
+    ] {
+        fields.push(
+            format!("optional_header.{key}"),
+            format!("{major}.{minor}"),
+            None,
+        );
+    }
+    fields.hex(
+        "optional_header.size_of_image",
+        optional.size_of_image,
+        layout,
+    );
+    fields.hex(
+        "optional_header.size_of_headers",
+        optional.size_of_headers,
+        layout,
+    );
+    fields.hex(
+        "optional_header.check_sum",
+        optional.check_sum,
+        Some(Noise::Checksum),
+    );
+    fields.push("optional_header.subsystem", optional.subsystem, None);
+    fields.hex(
+        "optional_header.dll_characteristics",
+        optional.dll_characteristics.bits(),
+        None,
+    );
+    fields.hex(
+        "optional_header.size_of_stack_reserve",
+        optional.size_of_stack_reserve,
+        None,
+    );
+    fields.hex(
+        "optional_header.size_of_stack_commit",
+        optional.size_of_stack_commit,
+        None,
+    );
+    fields.hex(
+        "optional_header.size_of_heap_reserve",
+        optional.size_of_heap_reserve,
+        None,
+    );
+    fields.hex(
+        "optional_header.size_of_heap_commit",
+        optional.sizeof_heap_commit,
+        None,
+    );
+
+    let directory_names = [
+        "export",
+        "import",
+        "resource",
+        "exception",
+        "certificate",
+        "base_relocation",
+        "debug",
+        "architecture",
+        "global_ptr",
+        "tls",
+        "load_config",
+        "bound_import",
+        "iat",
+        "delay_import",
+        "clr_runtime_header",
+        "reserved",
+    ];
+    for (name, directory) in directory_names.into_iter().zip(optional.data_directories()) {
+        // Whether a directory is there isn't layout, where it is is.
+        fields.push(
+            format!("directory[{name}].present"),
+            directory.size != 0,
+            None,
+        );
+        fields.hex(
+            format!("directory[{name}].virtual_address"),
+            directory.virtual_address,
+            layout,
+        );
+        fields.hex(format!("directory[{name}].size"), directory.size, layout);
+    }
+
+    let mut seen = HashMap::new();
+    for section in &image.sections {
+        let count = seen.entry(section.name.as_ref()).or_insert(0);
+        *count += 1;
+        let key = match *count {
+            1 => format!("section[{}]", section.name),
+            count => format!("section[{}#{count}]", section.name),
+        };
+        fields.hex(
+            format!("{key}.virtual_address"),
+            section.virtual_address,
+            layout,
+        );
+        fields.hex(format!("{key}.virtual_size"), section.virtual_size, layout);
+        fields.hex(
+            format!("{key}.size_of_raw_data"),
+            section.size_of_raw_data,
+            layout,
+        );
+        fields.hex(
+            format!("{key}.pointer_to_raw_data"),
+            section.pointer_to_raw_data,
+            layout,
+        );
+        fields.hex(
+            format!("{key}.characteristics"),
+            section.characteristics.bits(),
+            None,
+        );
+        // The data past the virtual size is padding.
+        let contents = image.section_data(section).map_or_else(
+            || "<out of bounds>".to_owned(),
+            |data| {
+                let size = data.len().min(section.virtual_size as usize);
+                content_hash(HashAlgorithm::default(), &data[..size]).to_string()
+            },
+        );
+        fields.push(format!("{key}.contents"), contents, Some(Noise::Contents));
+    }
+
+    let imports = match optional.import_table.size {
+        0 => Some(Vec::new()),
+        _ => image.imports(),
+    };
+    let (Some(imports), Some(delay_imports)) = (imports, image.delay_imports()) else {
+        return fatal(
+            Code::CorruptInput,
+            format!("{path}: import table out of bounds"),
+        );
+    };
+    import_fields(&mut fields, "import", imports);
+    import_fields(&mut fields, "delay_import", delay_imports);
+
+    if optional.export_table.size != 0 {
+        let Some(exports) = image.exports() else {
+            return fatal(
+                Code::CorruptInput,
+                format!("{path}: export table out of bounds"),
+            );
+        };
+        fields.push("exports.dll", exports.dll, None);
+        for export in exports.exports {
+            let key = match export.name {
+                Some(name) => format!("export[{name}]"),
+                None => format!("export[#{}]", export.ordinal),
+            };
+            fields.push(format!("{key}.ordinal"), export.ordinal, None);
+            match export.forwarder {
+                Some(forwarder) => fields.push(format!("{key}.forwarder"), forwarder, None),
+                None => fields.hex(format!("{key}.rva"), export.rva, layout),
+            }
+        }
+    }
+    Ok(fields)
+}
+
+/// What's imported from where, keyed by `dll!name` or `dll!#ordinal` with
+/// DLL names compared case-insensitively like the loader does.
+fn import_fields(fields: &mut Fields, kind: &str, dlls: Vec<ImageImports<'_>>) {
+    for dll in dlls {
+        let dll_name = dll.dll.to_ascii_lowercase();
+        for import in dll.imports {
+            match import {
+                ImageImport::Name { name, hint } => {
+                    let key = format!("{kind}[{dll_name}!{name}]");
+                    fields.push(format!("{key}.present"), true, None);
+                    fields.push(format!("{key}.hint"), hint, Some(Noise::Layout));
+                }
+                ImageImport::Ordinal(ordinal) => {
+                    fields.push(format!("{kind}[{dll_name}!#{ordinal}].present"), true, None);
+                }
+            }
+        }
+    }
+}
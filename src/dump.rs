@@ -0,0 +1,1054 @@
+mod llvm;
+mod text;
+
+use std::{collections::HashMap, io::Write, iter};
+
+use color_eyre::Result;
+use iced_x86::{Decoder, DecoderOptions, Formatter, Instruction, IntelFormatter, OpKind};
+use serde::Serialize;
+use winning_coff::{
+    archive::{ARCHIVE_MAGIC, Archive},
+    coff::{
+        CoffHeader, IMAGE_FILE_MACHINE_AMD64, IMAGE_REL_AMD64_ABSOLUTE, IMAGE_REL_AMD64_ADDR32,
+        IMAGE_REL_AMD64_ADDR32NB, IMAGE_REL_AMD64_ADDR64, IMAGE_REL_AMD64_PAIR,
+        IMAGE_REL_AMD64_REL32, IMAGE_REL_AMD64_REL32_1, IMAGE_REL_AMD64_REL32_2,
+        IMAGE_REL_AMD64_REL32_3, IMAGE_REL_AMD64_REL32_4, IMAGE_REL_AMD64_REL32_5,
+        IMAGE_REL_AMD64_SECREL, IMAGE_REL_AMD64_SECREL7, IMAGE_REL_AMD64_SECTION,
+        IMAGE_REL_AMD64_SREL32, IMAGE_REL_AMD64_SSPAN32, IMAGE_REL_AMD64_TOKEN,
+        IMAGE_SYM_CLASS_EXTERNAL, IMAGE_SYM_CLASS_LABEL, IMAGE_SYM_CLASS_STATIC, ObjectFile,
+        SectionFlags, SectionHeader,
+    },
+    diagnostics::{Code, fatal},
+    import::is_import_object,
+    symbols::{SymbolRecord, SymbolTable},
+};
+use winning_pe::{
+    image::{ImageImport, ImageModel},
+    imphash::imphash,
+    input::Input,
+    pe::{DOS_MAGIC, IMAGE_REL_BASED_DIR64, IMAGE_REL_BASED_HIGHLOW, PE32_MAGIC, PE32_PLUS_MAGIC},
+};
+
+const USAGE: &str = "usage: winning dump [--headers] [--sections] [--symbols] [--relocations] \
+     [--imports] [--exports] [--imphash] [--analyze] [--disasm] [--all] [--format=text|json|llvm] \
+     <inputs>...";
+/// Above this, section data is most likely compressed or encrypted, as packed
+/// executables' is. Code and data rarely reach 7.
+const PACKED_ENTROPY: f64 = 7.2;
+/// The bits of [`SectionFlags`] holding the alignment, which aren't single flags.
+const SECTION_ALIGN_MASK: u32 = 0x00F0_0000;
+
+/// What `dump` prints for each input.
+#[derive(Debug, Clone, Copy, Default)]
+struct Views {
+    headers: bool,
+    sections: bool,
+    symbols: bool,
+    relocations: bool,
+    /// Only for images.
+    imports: bool,
+    /// Only for images.
+    exports: bool,
+    /// Only for images.
+    imphash: bool,
+    /// Only for images.
+    analyze: bool,
+    /// Not part of `--all`, since it's larger than everything else together.
+    disasm: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text,
+    /// An array with an object per input, for scripts.
+    Json,
+    /// The layout of `llvm-readobj --coff-*`, for FileCheck tests.
+    Llvm,
+}
+
+/// `winning dump [views] <inputs>...`: prints the file and optional headers,
+/// the section table, the symbol table, the relocations and the imports and
+/// exports of objects, archive members and images, like `dumpbin`.
+/// Relocations of images are the base relocations, grouped by page with the
+/// address each one adjusts. `--disasm` disassembles the code sections, like
+/// `objdump -d`. Without a view, only relocations are printed.
+pub fn run(args: Vec<String>, out: &mut dyn Write) -> Result<()> {
+    let mut views = Views::default();
+    let mut format = Format::Text;
+    let mut inputs = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--headers" => views.headers = true,
+            "--sections" => views.sections = true,
+            "--symbols" => views.symbols = true,
+            "--relocations" => views.relocations = true,
+            "--imports" => views.imports = true,
+            "--exports" => views.exports = true,
+            "--imphash" => views.imphash = true,
+            "--analyze" => views.analyze = true,
+            "--disasm" => views.disasm = true,
+            "--all" => {
+                views = Views {
+                    headers: true,
+                    sections: true,
+                    symbols: true,
+                    relocations: true,
+                    imports: true,
+                    exports: true,
+                    imphash: true,
+                    analyze: true,
+                    disasm: views.disasm,
+                }
+            }
+            "--format=text" => format = Format::Text,
+            "--format=json" => format = Format::Json,
+            "--format=llvm" => format = Format::Llvm,
+            flag if flag.starts_with("--") => {
+                return fatal(
+                    Code::InvalidOption,
+                    format!("unknown dump option {flag}\n{USAGE}"),
+                );
+            }
+            _ => inputs.push(arg),
+        }
+    }
+    if inputs.is_empty() {
+        return fatal(Code::InvalidOption, USAGE);
+    }
+    if !(views.headers
+        || views.sections
+        || views.symbols
+        || views.imports
+        || views.exports
+        || views.imphash
+        || views.analyze
+        || views.disasm)
+    {
+        views.relocations = true;
+    }
+
+    let mut dumps = Vec::new();
+    for path in &inputs {
+        let input = Input::open(path)?;
+        let data = input.data();
+        if data.starts_with(ARCHIVE_MAGIC) {
+            let archive = Archive::parse(input.name.clone(), data)?;
+            let mut offsets = archive
+                .symbols
+                .iter()
+                .map(|&(_, offset)| offset)
+                .collect::<Vec<_>>();
+            offsets.sort_unstable();
+            offsets.dedup();
+            for offset in offsets {
+                let member = archive.member_at(offset)?;
+                if !is_import_object(member.data) {
+                    let name = format!("{}({})", archive.name, member.name);
+                    dumps.push(dump_object(&ObjectFile::parse(name, member.data)?, views)?);
+                }
+            }
+        } else if data.starts_with(DOS_MAGIC) {
+            let image = ImageModel::parse(data)?;
+            dumps.push(dump_image(&input.name, data, &image, views)?);
+        } else if !is_import_object(data) {
+            let object = ObjectFile::parse(input.name.clone(), data)?;
+            dumps.push(dump_object(&object, views)?);
+        }
+    }
+
+    match format {
+        Format::Text => {
+            for dump in &dumps {
+                text::write(dump, out)?;
+            }
+        }
+        Format::Llvm => {
+            for dump in &dumps {
+                llvm::write(dump, out)?;
+            }
+        }
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *out, &dumps)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum FileKind {
+    Object,
+    Image,
+}
+
+/// Everything `dump` prints about an input. Views that weren't asked for
+/// are `None`.
+#[derive(Serialize)]
+struct FileDump {
+    name: String,
+    kind: FileKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_header: Option<FileHeaderDump>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    optional_header: Option<OptionalHeaderDump>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sections: Option<Vec<SectionDump>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    symbols: Option<Vec<SymbolDump>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    relocations: Option<Vec<RelocationDump>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    base_relocations: Option<Vec<BaseRelocationDump>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    imports: Option<Vec<ImportsDump>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exports: Option<ExportsDump>,
+    /// Empty for images without imports.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    imphash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    analysis: Option<AnalysisDump>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disassembly: Option<Vec<DisassemblyDump>>,
+}
+
+impl FileDump {
+    fn new(name: &str, kind: FileKind) -> Self {
+        Self {
+            name: name.to_owned(),
+            kind,
+            file_header: None,
+            optional_header: None,
+            sections: None,
+            symbols: None,
+            relocations: None,
+            base_relocations: None,
+            imports: None,
+            exports: None,
+            imphash: None,
+            analysis: None,
+            disassembly: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct FileHeaderDump {
+    machine: u16,
+    number_of_sections: u16,
+    time_date_stamp: u32,
+    pointer_to_symbol_table: u32,
+    number_of_symbols: u32,
+    size_of_optional_header: u16,
+    characteristics: u16,
+    /// The names of the set characteristics, like `EXECUTABLE_IMAGE`.
+    flags: Vec<&'static str>,
+}
+
+#[derive(Serialize)]
+struct OptionalHeaderDump {
+    /// [`PE32_MAGIC`] or [`PE32_PLUS_MAGIC`].
+    magic: u16,
+    linker_version: (u8, u8),
+    size_of_code: u32,
+    size_of_initialized_data: u32,
+    size_of_uninitialized_data: u32,
+    address_of_entry_point: u32,
+    base_of_code: u32,
+    /// Only for PE32 images.
+    base_of_data: Option<u32>,
+    image_base: u64,
+    section_alignment: u32,
+    file_alignment: u32,
+    operating_system_version: (u16, u16),
+    image_version: (u16, u16),
+    subsystem_version: (u16, u16),
+    size_of_image: u32,
+    size_of_headers: u32,
+    checksum: u32,
+    subsystem: u16,
+    dll_characteristics: u16,
+    dll_flags: Vec<&'static str>,
+    size_of_stack_reserve: u64,
+    size_of_stack_commit: u64,
+    size_of_heap_reserve: u64,
+    size_of_heap_commit: u64,
+    number_of_rva_and_sizes: u32,
+    /// Only the directories that are set.
+    data_directories: Vec<DataDirectoryDump>,
+}
+
+#[derive(Serialize)]
+struct DataDirectoryDump {
+    name: &'static str,
+    virtual_address: u32,
+    size: u32,
+}
+
+#[derive(Serialize)]
+struct SectionDump {
+    /// 1-based, like symbols' section numbers.
+    number: usize,
+    name: String,
+    virtual_size: u32,
+    virtual_address: u32,
+    size_of_raw_data: u32,
+    pointer_to_raw_data: u32,
+    pointer_to_relocations: u32,
+    pointer_to_linenumbers: u32,
+    number_of_relocations: u16,
+    number_of_linenumbers: u16,
+    characteristics: u32,
+    /// The names of the set characteristics, without the alignment.
+    flags: Vec<&'static str>,
+    /// Only for objects.
+    alignment: Option<u32>,
+}
+
+/// What stands out about an image when reverse engineering it.
+#[derive(Serialize)]
+struct AnalysisDump {
+    sections: Vec<SectionAnalysisDump>,
+    /// Conditions that compilers and linkers don't produce, as messages.
+    anomalies: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct SectionAnalysisDump {
+    name: String,
+    /// The Shannon entropy of the raw data, in bits per byte.
+    entropy: f64,
+}
+
+#[derive(Serialize)]
+struct SymbolDump {
+    index: u32,
+    name: String,
+    value: u32,
+    section_number: u16,
+    /// The name of the section, for symbols defined in one.
+    section: Option<String>,
+    r#type: u16,
+    storage_class: u8,
+    number_of_aux_symbols: u8,
+}
+
+#[derive(Serialize)]
+struct RelocationDump {
+    section: usize,
+    section_name: String,
+    offset: u32,
+    r#type: u16,
+    type_name: String,
+    symbol: String,
+    symbol_index: u32,
+}
+
+#[derive(Serialize)]
+struct BaseRelocationDump {
+    rva: u32,
+    r#type: u16,
+    type_name: Option<&'static str>,
+    section: Option<String>,
+    /// The address at the site, if it's in the file.
+    value: Option<u64>,
+    /// `value` relative to the image base.
+    target_rva: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct ImportsDump {
+    dll: String,
+    import_lookup_table: u32,
+    import_address_table: u32,
+    imports: Vec<ImportDump>,
+}
+
+#[derive(Serialize)]
+struct ImportDump {
+    name: Option<String>,
+    hint: Option<u16>,
+    ordinal: Option<u16>,
+}
+
+#[derive(Serialize)]
+struct ExportsDump {
+    /// The name in the export directory, `None` without one.
+    dll: Option<String>,
+    exports: Vec<ExportDump>,
+}
+
+#[derive(Serialize)]
+struct ExportDump {
+    /// `None` for exports by ordinal only.
+    name: Option<String>,
+    ordinal: u32,
+    rva: u32,
+    is_code: bool,
+}
+
+/// A code section, instruction by instruction.
+#[derive(Serialize)]
+struct DisassemblyDump {
+    section: String,
+    instructions: Vec<InstructionDump>,
+}
+
+#[derive(Serialize)]
+struct InstructionDump {
+    /// The virtual address in images, the offset in the section in objects.
+    address: u64,
+    /// The symbol defined at the instruction, if any.
+    label: Option<String>,
+    /// In hex, separated by spaces.
+    bytes: String,
+    /// In Intel syntax, or `(bad)` for bytes that don't decode.
+    text: String,
+    /// What a branch or RIP-relative operand refers to. Only for images, whose
+    /// targets are final.
+    target: Option<String>,
+    /// The relocations applied within the instruction. Only for objects.
+    relocations: Vec<RelocationDump>,
+}
+
+fn dump_object(object: &ObjectFile<'_>, views: Views) -> Result<FileDump> {
+    let mut dump = FileDump::new(&object.name, FileKind::Object);
+    if views.headers {
+        dump.file_header = Some(file_header(&object.header));
+    }
+    if views.sections {
+        dump.sections = Some(sections(&object.sections, true));
+    }
+    if views.symbols {
+        dump.symbols = Some(symbols(&object.symbols, &object.sections)?);
+    }
+    if views.relocations {
+        dump.relocations = Some(relocations(object)?);
+    }
+    if views.disasm {
+        dump.disassembly = Some(object_disassembly(object)?);
+    }
+    Ok(dump)
+}
+
+fn dump_image(name: &str, data: &[u8], image: &ImageModel<'_>, views: Views) -> Result<FileDump> {
+    let mut dump = FileDump::new(name, FileKind::Image);
+    if views.headers {
+        dump.file_header = Some(file_header(&image.header));
+        dump.optional_header = Some(optional_header(image));
+    }
+    if views.sections {
+        dump.sections = Some(sections(&image.sections, false));
+    }
+    if views.symbols {
+        dump.symbols = Some(if image.header.number_of_symbols == 0 {
+            Vec::new()
+        } else {
+            symbols(&SymbolTable::read(data, &image.header)?, &image.sections)?
+        });
+    }
+    if views.relocations {
+        dump.base_relocations = Some(base_relocations(name, image)?);
+    }
+    if views.imports {
+        dump.imports = Some(imports(name, image)?);
+    }
+    if views.exports {
+        dump.exports = Some(exports(name, image)?);
+    }
+    if views.imphash {
+        let Some(hash) = imphash(image) else {
+            return fatal(
+                Code::CorruptInput,
+                format!("{name}: import table out of bounds"),
+            );
+        };
+        dump.imphash = Some(hash);
+    }
+    if views.analyze {
+        dump.analysis = Some(analysis(image));
+    }
+    if views.disasm {
+        dump.disassembly = Some(image_disassembly(name, data, image)?);
+    }
+    Ok(dump)
+}
+
+fn file_header(header: &CoffHeader) -> FileHeaderDump {
+    FileHeaderDump {
+        machine: header.machine,
+        number_of_sections: header.number_of_sections,
+        time_date_stamp: header.time_date_stamp,
+        pointer_to_symbol_table: header.pointer_to_symbol_table,
+        number_of_symbols: header.number_of_symbols,
+        size_of_optional_header: header.size_of_optional_header,
+        characteristics: header.characteristics.bits(),
+        flags: flag_names(header.characteristics.iter_names(), "IMAGE_FILE_"),
+    }
+}
+
+fn optional_header(image: &ImageModel<'_>) -> OptionalHeaderDump {
+    let header = &image.optional_header;
+    let data_directories = [
+        ("export", header.export_table),
+        ("import", header.import_table),
+        ("resource", header.resource_table),
+        ("exception", header.exception_table),
+        ("certificate", header.certificate_table),
+        ("base relocation", header.base_relocation_table),
+        ("debug", header.debug),
+        ("architecture", header.architecture),
+        ("global pointer", header.global_ptr),
+        ("tls", header.tls_table),
+        ("load config", header.load_config_table),
+        ("bound import", header.bound_import),
+        ("iat", header.iat),
+        ("delay import", header.delay_import_descriptor),
+        ("clr runtime header", header.clr_runtime_header),
+    ];
+    OptionalHeaderDump {
+        magic: match image.optional_header_32 {
+            Some(_) => PE32_MAGIC,
+            None => PE32_PLUS_MAGIC,
+        },
+        linker_version: (header.major_linker_version, header.minor_linker_version),
+        size_of_code: header.size_of_code,
+        size_of_initialized_data: header.size_of_initialized_data,
+        size_of_uninitialized_data: header.size_of_uninitialized_data,
+        address_of_entry_point: header.address_of_entry_point,
+        base_of_code: header.base_of_code,
+        base_of_data: image
+            .optional_header_32
+            .as_ref()
+            .map(|header| header.base_of_data),
+        image_base: header.image_base,
+        section_alignment: header.section_alignment,
+        file_alignment: header.file_alignment,
+        operating_system_version: (
+            header.major_operating_system_version,
+            header.minor_operating_system_version,
+        ),
+        image_version: (header.major_image_version, header.minor_image_version),
+        subsystem_version: (
+            header.major_subsystem_version,
+            header.minor_subsystem_version,
+        ),
+        size_of_image: header.size_of_image,
+        size_of_headers: header.size_of_headers,
+        checksum: header.check_sum,
+        subsystem: header.subsystem,
+        dll_characteristics: header.dll_characteristics.bits(),
+        dll_flags: flag_names(
+            header.dll_characteristics.iter_names(),
+            "IMAGE_DLLCHARACTERISTICS_",
+        ),
+        size_of_stack_reserve: header.size_of_stack_reserve,
+        size_of_stack_commit: header.size_of_stack_commit,
+        size_of_heap_reserve: header.size_of_heap_reserve,
+        size_of_heap_commit: header.sizeof_heap_commit,
+        number_of_rva_and_sizes: header.number_of_rva_and_sizes,
+        data_directories: data_directories
+            .into_iter()
+            .filter(|(_, directory)| directory.virtual_address != 0 || directory.size != 0)
+            .map(|(name, directory)| DataDirectoryDump {
+                name,
+                virtual_address: directory.virtual_address,
+                size: directory.size,
+            })
+            .collect(),
+    }
+}
+
+fn sections(sections: &[SectionHeader<'_>], is_object: bool) -> Vec<SectionDump> {
+    sections
+        .iter()
+        .zip(1..)
+        .map(|(section, number)| {
+            let flags = section.characteristics;
+            let named = flags.difference(SectionFlags::from_bits_retain(SECTION_ALIGN_MASK));
+            let align = (flags.bits() & SECTION_ALIGN_MASK) >> SECTION_ALIGN_MASK.trailing_zeros();
+            SectionDump {
+                number,
+                name: section.name.to_string(),
+                virtual_size: section.virtual_size,
+                virtual_address: section.virtual_address,
+                size_of_raw_data: section.size_of_raw_data,
+                pointer_to_raw_data: section.pointer_to_raw_data,
+                pointer_to_relocations: section.pointer_to_relocations,
+                pointer_to_linenumbers: section.pointer_to_linenumbers,
+                number_of_relocations: section.number_of_relocations,
+                number_of_linenumbers: section.number_of_linenumbers,
+                characteristics: flags.bits(),
+                flags: flag_names(named.iter_names(), "IMAGE_SCN_"),
+                alignment: (is_object && align != 0).then(|| 1 << (align - 1)),
+            }
+        })
+        .collect()
+}
+
+fn analysis(image: &ImageModel<'_>) -> AnalysisDump {
+    let mut anomalies = Vec::new();
+    let mut sections = Vec::new();
+    let mut raw_ranges = Vec::new();
+    for section in &image.sections {
+        let name = &section.name;
+        let data = image.section_data(section).unwrap_or_default();
+        let entropy = entropy(data);
+        if entropy > PACKED_ENTROPY {
+            anomalies.push(format!(
+                "{name} has an entropy of {entropy:.2}, like compressed or encrypted data"
+            ));
+        }
+        sections.push(SectionAnalysisDump {
+            name: name.to_string(),
+            entropy,
+        });
+
+        let flags = section.characteristics;
+        if flags.contains(SectionFlags::IMAGE_SCN_MEM_EXECUTE | SectionFlags::IMAGE_SCN_MEM_WRITE) {
+            anomalies.push(format!("{name} is writable and executable"));
+        }
+        if section.size_of_raw_data == 0 {
+            if !flags.contains(SectionFlags::IMAGE_SCN_CNT_UNINITIALIZED_DATA) {
+                anomalies.push(format!("{name} has no raw data"));
+            }
+        } else {
+            raw_ranges.push((
+                section.pointer_to_raw_data,
+                section.pointer_to_raw_data + section.size_of_raw_data,
+                name,
+            ));
+        }
+    }
+    raw_ranges.sort();
+    for pair in raw_ranges.windows(2) {
+        let ((_, end, first), (start, _, second)) = (pair[0], pair[1]);
+        if start < end {
+            anomalies.push(format!("raw data of {first} and {second} overlap"));
+        }
+    }
+
+    let entry = image.optional_header.address_of_entry_point;
+    if entry != 0 {
+        match image.section_containing(entry) {
+            None => anomalies.push(format!("entry point {entry:#x} is outside of all sections")),
+            Some(section)
+                if !section
+                    .characteristics
+                    .contains(SectionFlags::IMAGE_SCN_CNT_CODE)
+                    || !section
+                        .characteristics
+                        .contains(SectionFlags::IMAGE_SCN_MEM_EXECUTE) =>
+            {
+                anomalies.push(format!(
+                    "entry point {entry:#x} is in {}, which isn't executable code",
+                    section.name
+                ));
+            }
+            Some(_) => {}
+        }
+    }
+    AnalysisDump {
+        sections,
+        anomalies,
+    }
+}
+
+/// The Shannon entropy of `data` in bits per byte, from 0 for a run of one
+/// byte to 8 for uniformly random bytes.
+fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &byte in data {
+        counts[usize::from(byte)] += 1;
+    }
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+fn symbols(symbols: &SymbolTable<'_>, sections: &[SectionHeader<'_>]) -> Result<Vec<SymbolDump>> {
+    let mut dumps = Vec::new();
+    for (record, index) in symbols.iter().zip(0..) {
+        let SymbolRecord::Symbol(sym) = record else {
+            continue;
+        };
+        dumps.push(SymbolDump {
+            index,
+            name: symbols.name(sym)?.to_owned(),
+            value: sym.value,
+            section_number: sym.section_number,
+            section: sections
+                .get(usize::from(sym.section_number).wrapping_sub(1))
+                .map(|section| section.name.to_string()),
+            r#type: sym.r#type,
+            storage_class: sym.storage_class,
+            number_of_aux_symbols: sym.number_of_aux_symbols,
+        });
+    }
+    Ok(dumps)
+}
+
+fn relocations(object: &ObjectFile<'_>) -> Result<Vec<RelocationDump>> {
+    let mut dumps = Vec::new();
+    for (idx, section) in object.sections.iter().enumerate() {
+        for relocation in object.relocations(idx)? {
+            let symbol = match object.symbols.symbol(relocation.symbol_table_index) {
+                Some(sym) => object.symbols.name(sym)?.to_owned(),
+                None => format!("<invalid symbol {}>", relocation.symbol_table_index),
+            };
+            dumps.push(RelocationDump {
+                section: idx + 1,
+                section_name: section.name.to_string(),
+                offset: relocation.virtual_address,
+                r#type: relocation.r#type,
+                type_name: relocation_type_name(relocation.r#type),
+                symbol,
+                symbol_index: relocation.symbol_table_index,
+            });
+        }
+    }
+    Ok(dumps)
+}
+
+fn base_relocations(name: &str, image: &ImageModel<'_>) -> Result<Vec<BaseRelocationDump>> {
+    if image.optional_header.base_relocation_table.size == 0 {
+        return Ok(Vec::new());
+    }
+    let Some(sites) = image.base_relocations() else {
+        return fatal(
+            Code::CorruptInput,
+            format!("{name}: base relocation table out of bounds"),
+        );
+    };
+    let image_base = image.optional_header.image_base;
+    Ok(sites
+        .into_iter()
+        .map(|(rva, kind)| {
+            let (type_name, size) = match kind {
+                IMAGE_REL_BASED_DIR64 => (Some("DIR64"), 8),
+                IMAGE_REL_BASED_HIGHLOW => (Some("HIGHLOW"), 4),
+                _ => (None, 0),
+            };
+            let value = type_name.and(image.read(rva, size)).map(|bytes| {
+                let mut value = [0; 8];
+                value[..bytes.len()].copy_from_slice(bytes);
+                u64::from_le_bytes(value)
+            });
+            BaseRelocationDump {
+                rva,
+                r#type: kind,
+                type_name,
+                section: image
+                    .section_containing(rva)
+                    .map(|section| section.name.to_string()),
+                value,
+                target_rva: value.map(|value| value.wrapping_sub(image_base)),
+            }
+        })
+        .collect())
+}
+
+fn imports(name: &str, image: &ImageModel<'_>) -> Result<Vec<ImportsDump>> {
+    if image.optional_header.import_table.size == 0 {
+        return Ok(Vec::new());
+    }
+    let Some(dlls) = image.imports() else {
+        return fatal(
+            Code::CorruptInput,
+            format!("{name}: import table out of bounds"),
+        );
+    };
+    Ok(dlls
+        .into_iter()
+        .map(|dll| ImportsDump {
+            dll: dll.dll.to_owned(),
+            import_lookup_table: dll.import_lookup_table,
+            import_address_table: dll.import_address_table,
+            imports: dll
+                .imports
+                .into_iter()
+                .map(|import| match import {
+                    ImageImport::Name { name, hint } => ImportDump {
+                        name: Some(name.to_owned()),
+                        hint: Some(hint),
+                        ordinal: None,
+                    },
+                    ImageImport::Ordinal(ordinal) => ImportDump {
+                        name: None,
+                        hint: None,
+                        ordinal: Some(ordinal),
+                    },
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+fn exports(name: &str, image: &ImageModel<'_>) -> Result<ExportsDump> {
+    if image.optional_header.export_table.size == 0 {
+        return Ok(ExportsDump {
+            dll: None,
+            exports: Vec::new(),
+        });
+    }
+    let Some(exports) = image.exports() else {
+        return fatal(
+            Code::CorruptInput,
+            format!("{name}: export table out of bounds"),
+        );
+    };
+    Ok(ExportsDump {
+        dll: Some(exports.dll.to_owned()),
+        exports: exports
+            .exports
+            .into_iter()
+            .map(|export| ExportDump {
+                name: export.name.map(str::to_owned),
+                ordinal: export.ordinal,
+                rva: export.rva,
+                is_code: export.is_code,
+            })
+            .collect(),
+    })
+}
+
+fn object_disassembly(object: &ObjectFile<'_>) -> Result<Vec<DisassemblyDump>> {
+    check_disassembly_machine(&object.name, object.header.machine)?;
+    let mut dumps = Vec::new();
+    for (idx, section) in object.sections.iter().enumerate() {
+        if !section
+            .characteristics
+            .contains(SectionFlags::IMAGE_SCN_CNT_CODE)
+        {
+            continue;
+        }
+        let section_number = idx + 1;
+        let mut labels = HashMap::new();
+        for (_, sym) in object.symbols.symbols() {
+            let is_label = sym.storage_class == IMAGE_SYM_CLASS_EXTERNAL
+                || (matches!(
+                    sym.storage_class,
+                    IMAGE_SYM_CLASS_STATIC | IMAGE_SYM_CLASS_LABEL
+                ) && sym.number_of_aux_symbols == 0);
+            if is_label && usize::from(sym.section_number) == section_number {
+                labels
+                    .entry(u64::from(sym.value))
+                    .or_insert(object.symbols.name(sym)?.to_owned());
+            }
+        }
+        let relocations = relocations(object)?
+            .into_iter()
+            .filter(|relocation| relocation.section == section_number)
+            .collect();
+        let code = object.section_data(idx);
+        if code.is_empty() {
+            continue;
+        }
+        dumps.push(DisassemblyDump {
+            section: section.name.to_string(),
+            instructions: disassemble(code, 0, &labels, None, relocations),
+        });
+    }
+    Ok(dumps)
+}
+
+/// Names addresses after the image's symbols, exports and imports, for
+/// labels and for what instructions refer to.
+fn image_disassembly(
+    name: &str,
+    data: &[u8],
+    image: &ImageModel<'_>,
+) -> Result<Vec<DisassemblyDump>> {
+    check_disassembly_machine(name, image.header.machine)?;
+    let image_base = image.optional_header.image_base;
+    let mut names = HashMap::new();
+    if image.header.number_of_symbols != 0 {
+        let symbols = SymbolTable::read(data, &image.header)?;
+        for (_, sym) in symbols.symbols() {
+            let section = usize::from(sym.section_number).wrapping_sub(1);
+            if let Some(section) = image.sections.get(section)
+                && sym.storage_class == IMAGE_SYM_CLASS_EXTERNAL
+            {
+                let address =
+                    image_base + u64::from(section.virtual_address) + u64::from(sym.value);
+                names
+                    .entry(address)
+                    .or_insert(symbols.name(sym)?.to_owned());
+            }
+        }
+    }
+    if image.optional_header.export_table.size != 0
+        && let Some(exports) = image.exports()
+    {
+        for (export_name, export) in exports.named() {
+            if export.forwarder.is_none() {
+                names
+                    .entry(image_base + u64::from(export.rva))
+                    .or_insert_with(|| export_name.to_owned());
+            }
+        }
+    }
+    let slot_size = if image.optional_header_32.is_some() {
+        4
+    } else {
+        8
+    };
+    let dlls = [image.imports(), image.delay_imports()];
+    for dll in dlls.into_iter().flatten().flatten() {
+        for (import, slot) in dll.imports.iter().zip(0..) {
+            let address = image_base + u64::from(dll.import_address_table) + slot * slot_size;
+            let name = match import {
+                ImageImport::Name { name, .. } => format!("{}!{name}", dll.dll),
+                ImageImport::Ordinal(ordinal) => format!("{}!#{ordinal}", dll.dll),
+            };
+            names.entry(address).or_insert(name);
+        }
+    }
+
+    let mut dumps = Vec::new();
+    for section in &image.sections {
+        if !section
+            .characteristics
+            .contains(SectionFlags::IMAGE_SCN_CNT_CODE)
+        {
+            continue;
+        }
+        let Some(code) = image.section_data(section) else {
+            return fatal(
+                Code::CorruptInput,
+                format!("{name}: raw data of {} out of bounds", section.name),
+            );
+        };
+        // The raw data is padded to the file alignment, the code itself isn't.
+        let size = match section.virtual_size {
+            0 => code.len(),
+            size => code.len().min(size as usize),
+        };
+        let address = image_base + u64::from(section.virtual_address);
+        dumps.push(DisassemblyDump {
+            section: section.name.to_string(),
+            instructions: disassemble(&code[..size], address, &names, Some(&names), Vec::new()),
+        });
+    }
+    Ok(dumps)
+}
+
+fn check_disassembly_machine(name: &str, machine: u16) -> Result<()> {
+    if machine != IMAGE_FILE_MACHINE_AMD64 {
+        return fatal(
+            Code::UnsupportedMachine,
+            format!("{name}: disassembling machine {machine:#x} is not supported"),
+        );
+    }
+    Ok(())
+}
+
+/// Decodes `code` as x86-64 starting at `address`. Branch and RIP-relative
+/// targets are named from `targets`; without them, they're still to be
+/// relocated, so RIP-relative operands are shown as such. `relocations` are
+/// attached to the instructions they apply within.
+fn disassemble(
+    code: &[u8],
+    address: u64,
+    labels: &HashMap<u64, String>,
+    targets: Option<&HashMap<u64, String>>,
+    mut relocations: Vec<RelocationDump>,
+) -> Vec<InstructionDump> {
+    let mut formatter = IntelFormatter::new();
+    let options = formatter.options_mut();
+    options.set_hex_prefix("0x");
+    options.set_hex_suffix("");
+    options.set_uppercase_hex(false);
+    options.set_small_hex_numbers_in_decimal(false);
+    options.set_space_after_operand_separator(true);
+    options.set_branch_leading_zeros(false);
+    options.set_rip_relative_addresses(targets.is_none());
+
+    relocations.sort_by_key(|relocation| relocation.offset);
+    let mut relocations = relocations.into_iter().peekable();
+    let mut decoder = Decoder::with_ip(64, code, address, DecoderOptions::NONE);
+    let mut instruction = Instruction::default();
+    let mut dumps = Vec::new();
+    while decoder.can_decode() {
+        let start = decoder.position();
+        decoder.decode_out(&mut instruction);
+        let end = decoder.position();
+        let bytes = code[start..end]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut text = String::new();
+        let mut referenced = None;
+        if instruction.is_invalid() {
+            text.push_str("(bad)");
+        } else {
+            formatter.format(&instruction, &mut text);
+            if instruction.is_ip_rel_memory_operand() {
+                referenced = Some(instruction.ip_rel_memory_address());
+            } else if (0..instruction.op_count()).any(|operand| {
+                matches!(
+                    instruction.op_kind(operand),
+                    OpKind::NearBranch16 | OpKind::NearBranch32 | OpKind::NearBranch64
+                )
+            }) {
+                referenced = Some(instruction.near_branch_target());
+            }
+        }
+        dumps.push(InstructionDump {
+            address: instruction.ip(),
+            label: labels.get(&instruction.ip()).cloned(),
+            bytes,
+            text,
+            target: referenced
+                .zip(targets)
+                .and_then(|(address, targets)| targets.get(&address).cloned()),
+            relocations: iter::from_fn(|| {
+                relocations.next_if(|relocation| (relocation.offset as usize) < end)
+            })
+            .collect(),
+        });
+    }
+    dumps
+}
+
+/// The names of the set flags, without `prefix`.
+fn flag_names<T>(
+    names: impl Iterator<Item = (&'static str, T)>,
+    prefix: &str,
+) -> Vec<&'static str> {
+    names
+        .map(|(name, _)| name.strip_prefix(prefix).unwrap_or(name))
+        .collect()
+}
+
+fn relocation_type_name(r#type: u16) -> String {
+    let name = match r#type {
+        IMAGE_REL_AMD64_ABSOLUTE => "ABSOLUTE",
+        IMAGE_REL_AMD64_ADDR64 => "ADDR64",
+        IMAGE_REL_AMD64_ADDR32 => "ADDR32",
+        IMAGE_REL_AMD64_ADDR32NB => "ADDR32NB",
+        IMAGE_REL_AMD64_REL32 => "REL32",
+        IMAGE_REL_AMD64_REL32_1 => "REL32_1",
+        IMAGE_REL_AMD64_REL32_2 => "REL32_2",
+        IMAGE_REL_AMD64_REL32_3 => "REL32_3",
+        IMAGE_REL_AMD64_REL32_4 => "REL32_4",
+        IMAGE_REL_AMD64_REL32_5 => "REL32_5",
+        IMAGE_REL_AMD64_SECTION => "SECTION",
+        IMAGE_REL_AMD64_SECREL => "SECREL",
+        IMAGE_REL_AMD64_SECREL7 => "SECREL7",
+        IMAGE_REL_AMD64_TOKEN => "TOKEN",
+        IMAGE_REL_AMD64_SREL32 => "SREL32",
+        IMAGE_REL_AMD64_PAIR => "PAIR",
+        IMAGE_REL_AMD64_SSPAN32 => "SSPAN32",
+        other => return format!("{other:#x}"),
+    };
+    name.to_owned()
+}
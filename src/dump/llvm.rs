@@ -0,0 +1,551 @@
+//! The `llvm-readobj --coff-*` compatible output of `dump`, so FileCheck
+//! tests written against it work on `winning dump --format=llvm` too.
+
+use std::io::Write;
+
+use color_eyre::Result;
+use winning_coff::coff::{
+    Characteristics, IMAGE_FILE_MACHINE_AMD64, IMAGE_SYM_ABSOLUTE, IMAGE_SYM_DEBUG,
+    IMAGE_SYM_UNDEFINED, SectionFlags, encode_section_header_name,
+};
+use winning_pe::pe::{DllCharacteristics, IMAGE_REL_BASED_DIR64, IMAGE_REL_BASED_HIGHLOW};
+
+use super::{
+    BaseRelocationDump, DisassemblyDump, ExportsDump, FileDump, FileHeaderDump, ImportsDump,
+    OptionalHeaderDump, RelocationDump, SECTION_ALIGN_MASK, SectionDump, SymbolDump,
+};
+
+pub fn write(dump: &FileDump, out: &mut dyn Write) -> Result<()> {
+    writeln!(out)?;
+    writeln!(out, "File: {}", dump.name)?;
+    writeln!(out, "Format: COFF-x86-64")?;
+    writeln!(out, "Arch: x86_64")?;
+    writeln!(out, "AddressSize: 64bit")?;
+    if let Some(header) = &dump.file_header {
+        file_header(header, out)?;
+    }
+    if let Some(header) = &dump.optional_header {
+        optional_header(header, out)?;
+    }
+    if let Some(sections) = &dump.sections {
+        section_table(sections, out)?;
+    }
+    if let Some(relocations) = &dump.relocations {
+        object_relocations(relocations, out)?;
+    }
+    if let Some(symbols) = &dump.symbols {
+        symbol_table(symbols, out)?;
+    }
+    if let Some(imports) = &dump.imports {
+        import_table(imports, out)?;
+    }
+    if let Some(exports) = &dump.exports {
+        export_table(exports, out)?;
+    }
+    if let Some(relocations) = &dump.base_relocations {
+        base_relocations(relocations, out)?;
+    }
+    // Not something llvm-readobj prints, but in its style.
+    if let Some(hash) = &dump.imphash {
+        writeln!(out, "ImportHash: {hash}")?;
+    }
+    if let Some(analysis) = &dump.analysis {
+        writeln!(out, "Analysis {{")?;
+        for section in &analysis.sections {
+            writeln!(out, "  Section {{")?;
+            writeln!(out, "    Name: {}", section.name)?;
+            writeln!(out, "    Entropy: {:.3}", section.entropy)?;
+            writeln!(out, "  }}")?;
+        }
+        for anomaly in &analysis.anomalies {
+            writeln!(out, "  Anomaly: {anomaly}")?;
+        }
+        writeln!(out, "}}")?;
+    }
+    if let Some(sections) = &dump.disassembly {
+        disassembly(sections, out)?;
+    }
+    Ok(())
+}
+
+/// The layout of `llvm-objdump -d -r --x86-asm-syntax=intel`.
+fn disassembly(sections: &[DisassemblyDump], out: &mut dyn Write) -> Result<()> {
+    for section in sections {
+        writeln!(out)?;
+        writeln!(out, "Disassembly of section {}:", section.section)?;
+        for instruction in &section.instructions {
+            if let Some(label) = &instruction.label {
+                writeln!(out)?;
+                writeln!(out, "{:016x} <{label}>:", instruction.address)?;
+            }
+            write!(
+                out,
+                "{:>8x}: {:<30}\t{}",
+                instruction.address, instruction.bytes, instruction.text
+            )?;
+            match &instruction.target {
+                Some(target) => writeln!(out, " <{target}>")?,
+                None => writeln!(out)?,
+            }
+            for relocation in &instruction.relocations {
+                writeln!(
+                    out,
+                    "\t\t{:016x}:  IMAGE_REL_AMD64_{}\t{}",
+                    relocation.offset, relocation.type_name, relocation.symbol
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn file_header(header: &FileHeaderDump, out: &mut dyn Write) -> Result<()> {
+    let machine = match header.machine {
+        IMAGE_FILE_MACHINE_AMD64 => "IMAGE_FILE_MACHINE_AMD64",
+        _ => "IMAGE_FILE_MACHINE_UNKNOWN",
+    };
+    writeln!(out, "ImageFileHeader {{")?;
+    writeln!(out, "  Machine: {machine} ({})", hex(header.machine))?;
+    writeln!(out, "  SectionCount: {}", header.number_of_sections)?;
+    writeln!(
+        out,
+        "  TimeDateStamp: {} ({})",
+        date_time(header.time_date_stamp),
+        hex(header.time_date_stamp)
+    )?;
+    writeln!(
+        out,
+        "  PointerToSymbolTable: {}",
+        hex(header.pointer_to_symbol_table)
+    )?;
+    writeln!(out, "  SymbolCount: {}", header.number_of_symbols)?;
+    writeln!(
+        out,
+        "  OptionalHeaderSize: {}",
+        header.size_of_optional_header
+    )?;
+    let characteristics = Characteristics::from_bits_retain(header.characteristics);
+    flags(
+        out,
+        "  ",
+        "Characteristics",
+        header.characteristics.into(),
+        characteristics
+            .iter_names()
+            .map(|(name, flag)| (name.to_owned(), flag.bits().into()))
+            .collect(),
+    )?;
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+fn optional_header(header: &OptionalHeaderDump, out: &mut dyn Write) -> Result<()> {
+    let subsystem = match header.subsystem {
+        1 => "IMAGE_SUBSYSTEM_NATIVE",
+        2 => "IMAGE_SUBSYSTEM_WINDOWS_GUI",
+        3 => "IMAGE_SUBSYSTEM_WINDOWS_CUI",
+        5 => "IMAGE_SUBSYSTEM_OS2_CUI",
+        7 => "IMAGE_SUBSYSTEM_POSIX_CUI",
+        8 => "IMAGE_SUBSYSTEM_NATIVE_WINDOWS",
+        9 => "IMAGE_SUBSYSTEM_WINDOWS_CE_GUI",
+        10 => "IMAGE_SUBSYSTEM_EFI_APPLICATION",
+        11 => "IMAGE_SUBSYSTEM_EFI_BOOT_SERVICE_DRIVER",
+        12 => "IMAGE_SUBSYSTEM_EFI_RUNTIME_DRIVER",
+        13 => "IMAGE_SUBSYSTEM_EFI_ROM",
+        14 => "IMAGE_SUBSYSTEM_XBOX",
+        16 => "IMAGE_SUBSYSTEM_WINDOWS_BOOT_APPLICATION",
+        _ => "IMAGE_SUBSYSTEM_UNKNOWN",
+    };
+    let (linker_major, linker_minor) = header.linker_version;
+    let (os_major, os_minor) = header.operating_system_version;
+    let (image_major, image_minor) = header.image_version;
+    let (subsystem_major, subsystem_minor) = header.subsystem_version;
+    writeln!(out, "ImageOptionalHeader {{")?;
+    writeln!(out, "  Magic: {}", hex(header.magic))?;
+    writeln!(out, "  MajorLinkerVersion: {linker_major}")?;
+    writeln!(out, "  MinorLinkerVersion: {linker_minor}")?;
+    writeln!(out, "  SizeOfCode: {}", header.size_of_code)?;
+    writeln!(
+        out,
+        "  SizeOfInitializedData: {}",
+        header.size_of_initialized_data
+    )?;
+    writeln!(
+        out,
+        "  SizeOfUninitializedData: {}",
+        header.size_of_uninitialized_data
+    )?;
+    writeln!(
+        out,
+        "  AddressOfEntryPoint: {}",
+        hex(header.address_of_entry_point)
+    )?;
+    writeln!(out, "  BaseOfCode: {}", hex(header.base_of_code))?;
+    if let Some(base_of_data) = header.base_of_data {
+        writeln!(out, "  BaseOfData: {}", hex(base_of_data))?;
+    }
+    writeln!(out, "  ImageBase: {}", hex(header.image_base))?;
+    writeln!(out, "  SectionAlignment: {}", header.section_alignment)?;
+    writeln!(out, "  FileAlignment: {}", header.file_alignment)?;
+    writeln!(out, "  MajorOperatingSystemVersion: {os_major}")?;
+    writeln!(out, "  MinorOperatingSystemVersion: {os_minor}")?;
+    writeln!(out, "  MajorImageVersion: {image_major}")?;
+    writeln!(out, "  MinorImageVersion: {image_minor}")?;
+    writeln!(out, "  MajorSubsystemVersion: {subsystem_major}")?;
+    writeln!(out, "  MinorSubsystemVersion: {subsystem_minor}")?;
+    writeln!(out, "  SizeOfImage: {}", header.size_of_image)?;
+    writeln!(out, "  SizeOfHeaders: {}", header.size_of_headers)?;
+    writeln!(out, "  CheckSum: {}", hex(header.checksum))?;
+    writeln!(out, "  Subsystem: {subsystem} ({})", hex(header.subsystem))?;
+    let characteristics = DllCharacteristics::from_bits_retain(header.dll_characteristics);
+    flags(
+        out,
+        "  ",
+        "Characteristics",
+        header.dll_characteristics.into(),
+        characteristics
+            .iter_names()
+            .map(|(name, flag)| {
+                let name = name.replacen("DLLCHARACTERISTICS", "DLL_CHARACTERISTICS", 1);
+                (name, flag.bits().into())
+            })
+            .collect(),
+    )?;
+    writeln!(
+        out,
+        "  SizeOfStackReserve: {}",
+        header.size_of_stack_reserve
+    )?;
+    writeln!(out, "  SizeOfStackCommit: {}", header.size_of_stack_commit)?;
+    writeln!(out, "  SizeOfHeapReserve: {}", header.size_of_heap_reserve)?;
+    writeln!(out, "  SizeOfHeapCommit: {}", header.size_of_heap_commit)?;
+    writeln!(
+        out,
+        "  NumberOfRvaAndSize: {}",
+        header.number_of_rva_and_sizes
+    )?;
+    // The dump only has the directories that are set, llvm-readobj prints
+    // all of them.
+    let directories = [
+        ("export", "ExportTable"),
+        ("import", "ImportTable"),
+        ("resource", "ResourceTable"),
+        ("exception", "ExceptionTable"),
+        ("certificate", "CertificateTable"),
+        ("base relocation", "BaseRelocationTable"),
+        ("debug", "Debug"),
+        ("architecture", "Architecture"),
+        ("global pointer", "GlobalPtr"),
+        ("tls", "TLSTable"),
+        ("load config", "LoadConfigTable"),
+        ("bound import", "BoundImport"),
+        ("iat", "IAT"),
+        ("delay import", "DelayImportDescriptor"),
+        ("clr runtime header", "CLRRuntimeHeader"),
+        ("reserved", "Reserved"),
+    ];
+    writeln!(out, "  DataDirectory {{")?;
+    for (name, llvm_name) in directories {
+        let (rva, size) = header
+            .data_directories
+            .iter()
+            .find(|directory| directory.name == name)
+            .map_or((0, 0), |directory| {
+                (directory.virtual_address, directory.size)
+            });
+        writeln!(out, "    {llvm_name}RVA: {}", hex(rva))?;
+        writeln!(out, "    {llvm_name}Size: {}", hex(size))?;
+    }
+    writeln!(out, "  }}")?;
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+fn section_table(sections: &[SectionDump], out: &mut dyn Write) -> Result<()> {
+    writeln!(out, "Sections [")?;
+    for section in sections {
+        let raw_name = encode_section_header_name(&section.name)
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(out, "  Section {{")?;
+        writeln!(out, "    Number: {}", section.number)?;
+        writeln!(out, "    Name: {} ({raw_name})", section.name)?;
+        writeln!(out, "    VirtualSize: {}", hex(section.virtual_size))?;
+        writeln!(out, "    VirtualAddress: {}", hex(section.virtual_address))?;
+        writeln!(out, "    RawDataSize: {}", section.size_of_raw_data)?;
+        writeln!(
+            out,
+            "    PointerToRawData: {}",
+            hex(section.pointer_to_raw_data)
+        )?;
+        writeln!(
+            out,
+            "    PointerToRelocations: {}",
+            hex(section.pointer_to_relocations)
+        )?;
+        writeln!(
+            out,
+            "    PointerToLineNumbers: {}",
+            hex(section.pointer_to_linenumbers)
+        )?;
+        writeln!(
+            out,
+            "    RelocationCount: {}",
+            section.number_of_relocations
+        )?;
+        writeln!(
+            out,
+            "    LineNumberCount: {}",
+            section.number_of_linenumbers
+        )?;
+        let bits = section.characteristics;
+        let mut names = SectionFlags::from_bits_retain(bits & !SECTION_ALIGN_MASK)
+            .iter_names()
+            .map(|(name, flag)| (name.to_owned(), flag.bits().into()))
+            .collect::<Vec<_>>();
+        let align = bits & SECTION_ALIGN_MASK;
+        if align != 0 {
+            let bytes = 1u32 << ((align >> SECTION_ALIGN_MASK.trailing_zeros()) - 1);
+            names.push((format!("IMAGE_SCN_ALIGN_{bytes}BYTES"), align.into()));
+        }
+        flags(out, "    ", "Characteristics", bits.into(), names)?;
+        writeln!(out, "  }}")?;
+    }
+    writeln!(out, "]")?;
+    Ok(())
+}
+
+fn object_relocations(relocations: &[RelocationDump], out: &mut dyn Write) -> Result<()> {
+    writeln!(out, "Relocations [")?;
+    let mut section = None;
+    for relocation in relocations {
+        if section != Some(relocation.section) {
+            if section.is_some() {
+                writeln!(out, "  }}")?;
+            }
+            section = Some(relocation.section);
+            writeln!(
+                out,
+                "  Section ({}) {} {{",
+                relocation.section, relocation.section_name
+            )?;
+        }
+        let kind = if relocation.type_name.starts_with("0x") {
+            "Unknown".to_owned()
+        } else {
+            format!("IMAGE_REL_AMD64_{}", relocation.type_name)
+        };
+        writeln!(
+            out,
+            "    {} {kind} {} ({})",
+            hex(relocation.offset),
+            relocation.symbol,
+            relocation.symbol_index
+        )?;
+    }
+    if section.is_some() {
+        writeln!(out, "  }}")?;
+    }
+    writeln!(out, "]")?;
+    Ok(())
+}
+
+fn symbol_table(symbols: &[SymbolDump], out: &mut dyn Write) -> Result<()> {
+    writeln!(out, "Symbols [")?;
+    for sym in symbols {
+        let section = match sym.section_number {
+            IMAGE_SYM_UNDEFINED => "IMAGE_SYM_UNDEFINED (0)".to_owned(),
+            IMAGE_SYM_ABSOLUTE => "IMAGE_SYM_ABSOLUTE (-1)".to_owned(),
+            IMAGE_SYM_DEBUG => "IMAGE_SYM_DEBUG (-2)".to_owned(),
+            number => format!(
+                "{} ({number})",
+                sym.section.as_deref().unwrap_or("<invalid section>")
+            ),
+        };
+        let base_type = sym.r#type & 0xf;
+        let complex_type = (sym.r#type >> 4) & 0xf;
+        writeln!(out, "  Symbol {{")?;
+        writeln!(out, "    Name: {}", sym.name)?;
+        writeln!(out, "    Value: {}", sym.value)?;
+        writeln!(out, "    Section: {section}")?;
+        writeln!(
+            out,
+            "    BaseType: {}",
+            named(base_type_name(base_type), base_type)
+        )?;
+        writeln!(
+            out,
+            "    ComplexType: {}",
+            named(complex_type_name(complex_type), complex_type)
+        )?;
+        writeln!(
+            out,
+            "    StorageClass: {}",
+            named(storage_class_name(sym.storage_class), sym.storage_class)
+        )?;
+        writeln!(out, "    AuxSymbolCount: {}", sym.number_of_aux_symbols)?;
+        writeln!(out, "  }}")?;
+    }
+    writeln!(out, "]")?;
+    Ok(())
+}
+
+fn import_table(dlls: &[ImportsDump], out: &mut dyn Write) -> Result<()> {
+    for dll in dlls {
+        writeln!(out, "Import {{")?;
+        writeln!(out, "  Name: {}", dll.dll)?;
+        writeln!(
+            out,
+            "  ImportLookupTableRVA: {}",
+            hex(dll.import_lookup_table)
+        )?;
+        writeln!(
+            out,
+            "  ImportAddressTableRVA: {}",
+            hex(dll.import_address_table)
+        )?;
+        for import in &dll.imports {
+            // llvm-readobj prints the hint of imports by name as their ordinal.
+            let name = import.name.as_deref().unwrap_or("");
+            let ordinal = import.hint.or(import.ordinal).unwrap_or(0);
+            writeln!(out, "  Symbol: {name} ({ordinal})")?;
+        }
+        writeln!(out, "}}")?;
+    }
+    Ok(())
+}
+
+fn export_table(exports: &ExportsDump, out: &mut dyn Write) -> Result<()> {
+    for export in &exports.exports {
+        writeln!(out, "Export {{")?;
+        writeln!(out, "  Ordinal: {}", export.ordinal)?;
+        writeln!(
+            out,
+            "  Name: {}",
+            export.name.as_deref().unwrap_or_default()
+        )?;
+        writeln!(out, "  RVA: {}", hex(export.rva))?;
+        writeln!(out, "}}")?;
+    }
+    Ok(())
+}
+
+fn base_relocations(relocations: &[BaseRelocationDump], out: &mut dyn Write) -> Result<()> {
+    writeln!(out, "BaseReloc [")?;
+    for relocation in relocations {
+        let kind = match relocation.r#type {
+            IMAGE_REL_BASED_HIGHLOW => "HIGHLOW".to_owned(),
+            IMAGE_REL_BASED_DIR64 => "DIR64".to_owned(),
+            other => format!("unknown ({other})"),
+        };
+        writeln!(out, "  Entry {{")?;
+        writeln!(out, "    Type: {kind}")?;
+        writeln!(out, "    Address: {}", hex(relocation.rva))?;
+        writeln!(out, "  }}")?;
+    }
+    writeln!(out, "]")?;
+    Ok(())
+}
+
+/// Prints flags like `ScopedPrinter::printFlags`: the value, then the set
+/// flags sorted by name.
+fn flags(
+    out: &mut dyn Write,
+    indent: &str,
+    label: &str,
+    bits: u64,
+    mut names: Vec<(String, u64)>,
+) -> Result<()> {
+    names.sort();
+    writeln!(out, "{indent}{label} [ ({})", hex(bits))?;
+    for (name, value) in names {
+        writeln!(out, "{indent}  {name} ({})", hex(value))?;
+    }
+    writeln!(out, "{indent}]")?;
+    Ok(())
+}
+
+/// LLVM's hex formatting: a lowercase prefix and uppercase digits.
+fn hex(value: impl Into<u64>) -> String {
+    format!("0x{:X}", value.into())
+}
+
+/// An enum value like `ScopedPrinter::printEnum`: the name and the value, or
+/// only the value if it has no name.
+fn named(name: Option<&str>, value: impl Into<u64> + Copy) -> String {
+    match name {
+        Some(name) => format!("{name} ({})", hex(value)),
+        None => hex(value),
+    }
+}
+
+fn base_type_name(base_type: u16) -> Option<&'static str> {
+    let names = [
+        "Null", "Void", "Char", "Short", "Int", "Long", "Float", "Double", "Struct", "Union",
+        "Enum", "MOE", "Byte", "Word", "UInt", "DWord",
+    ];
+    names.get(usize::from(base_type)).copied()
+}
+
+fn complex_type_name(complex_type: u16) -> Option<&'static str> {
+    let names = ["Null", "Pointer", "Function", "Array"];
+    names.get(usize::from(complex_type)).copied()
+}
+
+fn storage_class_name(class: u8) -> Option<&'static str> {
+    Some(match class {
+        0xff => "EndOfFunction",
+        0 => "Null",
+        1 => "Automatic",
+        2 => "External",
+        3 => "Static",
+        4 => "Register",
+        5 => "ExternalDef",
+        6 => "Label",
+        7 => "UndefinedLabel",
+        8 => "MemberOfStruct",
+        9 => "Argument",
+        10 => "StructTag",
+        11 => "MemberOfUnion",
+        12 => "UnionTag",
+        13 => "TypeDefinition",
+        14 => "UndefinedStatic",
+        15 => "EnumTag",
+        16 => "MemberOfEnum",
+        17 => "RegisterParam",
+        18 => "BitField",
+        100 => "Block",
+        101 => "Function",
+        102 => "EndOfStruct",
+        103 => "File",
+        104 => "Section",
+        105 => "WeakExternal",
+        107 => "CLRToken",
+        _ => return None,
+    })
+}
+
+/// A time stamp as `YYYY-MM-DD hh:mm:ss` in UTC.
+fn date_time(time_stamp: u32) -> String {
+    let days = i64::from(time_stamp / 86400);
+    let seconds = time_stamp % 86400;
+    // Howard Hinnant's `civil_from_days`.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
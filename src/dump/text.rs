@@ -0,0 +1,410 @@
+//! The readable output of `dump`.
+
+use std::io::Write;
+
+use color_eyre::Result;
+use winning_coff::coff::{
+    IMAGE_FILE_MACHINE_AMD64, IMAGE_SYM_ABSOLUTE, IMAGE_SYM_CLASS_EXTERNAL, IMAGE_SYM_CLASS_FILE,
+    IMAGE_SYM_CLASS_FUNCTION, IMAGE_SYM_CLASS_LABEL, IMAGE_SYM_CLASS_SECTION,
+    IMAGE_SYM_CLASS_STATIC, IMAGE_SYM_CLASS_WEAK_EXTERNAL, IMAGE_SYM_DEBUG, IMAGE_SYM_UNDEFINED,
+};
+use winning_pe::pe::{
+    IMAGE_SUBSYSTEM_EFI_APPLICATION, IMAGE_SUBSYSTEM_EFI_BOOT_SERVICE_DRIVER,
+    IMAGE_SUBSYSTEM_EFI_RUNTIME_DRIVER, IMAGE_SUBSYSTEM_NATIVE, IMAGE_SUBSYSTEM_WINDOWS_CUI,
+    IMAGE_SUBSYSTEM_WINDOWS_GUI, PE32_MAGIC,
+};
+
+use super::{
+    AnalysisDump, BaseRelocationDump, DisassemblyDump, ExportsDump, FileDump, FileHeaderDump,
+    FileKind, ImportsDump, OptionalHeaderDump, RelocationDump, SectionDump, SymbolDump,
+};
+
+pub fn write(dump: &FileDump, out: &mut dyn Write) -> Result<()> {
+    writeln!(out, "{}:", dump.name)?;
+    if let Some(header) = &dump.file_header {
+        file_header(header, out)?;
+    }
+    if let Some(header) = &dump.optional_header {
+        optional_header(header, out)?;
+    }
+    if let Some(sections) = &dump.sections {
+        section_table(sections, out)?;
+    }
+    if let Some(symbols) = &dump.symbols {
+        symbol_table(symbols, out)?;
+    }
+    if let Some(relocations) = &dump.relocations {
+        object_relocations(relocations, out)?;
+    }
+    if let Some(relocations) = &dump.base_relocations {
+        base_relocations(relocations, out)?;
+    }
+    if let Some(imports) = &dump.imports {
+        import_table(imports, out)?;
+    }
+    if let Some(exports) = &dump.exports {
+        export_table(exports, out)?;
+    }
+    match dump.imphash.as_deref() {
+        Some("") => writeln!(out, "  imphash: none, no imports")?,
+        Some(hash) => writeln!(out, "  imphash: {hash}")?,
+        None => {}
+    }
+    if let Some(analysis) = &dump.analysis {
+        analysis_report(analysis, out)?;
+    }
+    if let Some(sections) = &dump.disassembly {
+        disassembly(sections, &dump.kind, out)?;
+    }
+    Ok(())
+}
+
+fn file_header(header: &FileHeaderDump, out: &mut dyn Write) -> Result<()> {
+    let machine = match header.machine {
+        IMAGE_FILE_MACHINE_AMD64 => "x64".to_owned(),
+        other => format!("{other:#x}"),
+    };
+    writeln!(out, "  file header:")?;
+    writeln!(out, "    machine:              {machine}")?;
+    writeln!(
+        out,
+        "    sections:             {}",
+        header.number_of_sections
+    )?;
+    writeln!(
+        out,
+        "    time stamp:           {:#x}",
+        header.time_date_stamp
+    )?;
+    writeln!(
+        out,
+        "    symbol table:         {:#x} ({} symbols)",
+        header.pointer_to_symbol_table, header.number_of_symbols
+    )?;
+    writeln!(
+        out,
+        "    optional header size: {:#x}",
+        header.size_of_optional_header
+    )?;
+    writeln!(
+        out,
+        "    characteristics:      {}",
+        flags(header.characteristics.into(), &header.flags)
+    )?;
+    Ok(())
+}
+
+fn optional_header(header: &OptionalHeaderDump, out: &mut dyn Write) -> Result<()> {
+    let subsystem = match header.subsystem {
+        IMAGE_SUBSYSTEM_NATIVE => "native",
+        IMAGE_SUBSYSTEM_WINDOWS_GUI => "windows",
+        IMAGE_SUBSYSTEM_WINDOWS_CUI => "console",
+        IMAGE_SUBSYSTEM_EFI_APPLICATION => "efi application",
+        IMAGE_SUBSYSTEM_EFI_BOOT_SERVICE_DRIVER => "efi boot service driver",
+        IMAGE_SUBSYSTEM_EFI_RUNTIME_DRIVER => "efi runtime driver",
+        _ => "unknown",
+    };
+    let version = |(major, minor): (u16, u16)| format!("{major}.{minor}");
+    let (linker_major, linker_minor) = header.linker_version;
+    let format = match header.magic {
+        PE32_MAGIC => "PE32",
+        _ => "PE32+",
+    };
+    writeln!(out, "  optional header:")?;
+    writeln!(
+        out,
+        "    magic:                {:#x} ({format})",
+        header.magic
+    )?;
+    writeln!(
+        out,
+        "    linker version:       {linker_major}.{linker_minor}"
+    )?;
+    writeln!(out, "    size of code:         {:#x}", header.size_of_code)?;
+    writeln!(
+        out,
+        "    initialized data:     {:#x}",
+        header.size_of_initialized_data
+    )?;
+    writeln!(
+        out,
+        "    uninitialized data:   {:#x}",
+        header.size_of_uninitialized_data
+    )?;
+    writeln!(
+        out,
+        "    entry point:          {:#x}",
+        header.address_of_entry_point
+    )?;
+    writeln!(out, "    base of code:         {:#x}", header.base_of_code)?;
+    if let Some(base_of_data) = header.base_of_data {
+        writeln!(out, "    base of data:         {base_of_data:#x}")?;
+    }
+    writeln!(out, "    image base:           {:#x}", header.image_base)?;
+    writeln!(
+        out,
+        "    section alignment:    {:#x}",
+        header.section_alignment
+    )?;
+    writeln!(
+        out,
+        "    file alignment:       {:#x}",
+        header.file_alignment
+    )?;
+    writeln!(
+        out,
+        "    os version:           {}",
+        version(header.operating_system_version)
+    )?;
+    writeln!(
+        out,
+        "    image version:        {}",
+        version(header.image_version)
+    )?;
+    writeln!(
+        out,
+        "    subsystem version:    {}",
+        version(header.subsystem_version)
+    )?;
+    writeln!(out, "    size of image:        {:#x}", header.size_of_image)?;
+    writeln!(
+        out,
+        "    size of headers:      {:#x}",
+        header.size_of_headers
+    )?;
+    writeln!(out, "    checksum:             {:#x}", header.checksum)?;
+    writeln!(
+        out,
+        "    subsystem:            {} ({subsystem})",
+        header.subsystem
+    )?;
+    writeln!(
+        out,
+        "    dll characteristics:  {}",
+        flags(header.dll_characteristics.into(), &header.dll_flags)
+    )?;
+    writeln!(
+        out,
+        "    stack:                {:#x} reserved, {:#x} committed",
+        header.size_of_stack_reserve, header.size_of_stack_commit
+    )?;
+    writeln!(
+        out,
+        "    heap:                 {:#x} reserved, {:#x} committed",
+        header.size_of_heap_reserve, header.size_of_heap_commit
+    )?;
+    writeln!(out, "  data directories:")?;
+    for directory in &header.data_directories {
+        writeln!(
+            out,
+            "    {:<22}{:#010x} {:#x}",
+            directory.name, directory.virtual_address, directory.size
+        )?;
+    }
+    Ok(())
+}
+
+fn section_table(sections: &[SectionDump], out: &mut dyn Write) -> Result<()> {
+    writeln!(out, "  sections:")?;
+    writeln!(
+        out,
+        "    {:>3} {:<8} {:<10} {:<10} {:<10} {:<10} {:>6}  characteristics",
+        "#", "name", "vsize", "vaddr", "raw size", "raw ptr", "relocs"
+    )?;
+    for section in sections {
+        let mut characteristics = flags(section.characteristics, &section.flags);
+        if let Some(alignment) = section.alignment {
+            characteristics.push_str(&format!(" align {alignment}"));
+        }
+        writeln!(
+            out,
+            "    {:>3} {:<8} {:#010x} {:#010x} {:#010x} {:#010x} {:>6}  {characteristics}",
+            section.number,
+            section.name,
+            section.virtual_size,
+            section.virtual_address,
+            section.size_of_raw_data,
+            section.pointer_to_raw_data,
+            section.number_of_relocations,
+        )?;
+    }
+    Ok(())
+}
+
+fn symbol_table(symbols: &[SymbolDump], out: &mut dyn Write) -> Result<()> {
+    if symbols.is_empty() {
+        writeln!(out, "  no symbols")?;
+        return Ok(());
+    }
+    writeln!(out, "  symbols:")?;
+    for sym in symbols {
+        let section = match sym.section_number {
+            IMAGE_SYM_UNDEFINED => "UNDEF".to_owned(),
+            IMAGE_SYM_ABSOLUTE => "ABS".to_owned(),
+            IMAGE_SYM_DEBUG => "DEBUG".to_owned(),
+            number => format!("SECT{number}"),
+        };
+        let class = match sym.storage_class {
+            IMAGE_SYM_CLASS_EXTERNAL => "External".to_owned(),
+            IMAGE_SYM_CLASS_STATIC => "Static".to_owned(),
+            IMAGE_SYM_CLASS_LABEL => "Label".to_owned(),
+            IMAGE_SYM_CLASS_FUNCTION => "Function".to_owned(),
+            IMAGE_SYM_CLASS_FILE => "File".to_owned(),
+            IMAGE_SYM_CLASS_SECTION => "Section".to_owned(),
+            IMAGE_SYM_CLASS_WEAK_EXTERNAL => "WeakExternal".to_owned(),
+            other => format!("class {other}"),
+        };
+        let aux = match sym.number_of_aux_symbols {
+            0 => String::new(),
+            count => format!(" (+{count} aux)"),
+        };
+        writeln!(
+            out,
+            "    [{:>4}] {:#010x} {section:<7} {class:<12} {}{aux}",
+            sym.index, sym.value, sym.name,
+        )?;
+    }
+    Ok(())
+}
+
+fn object_relocations(relocations: &[RelocationDump], out: &mut dyn Write) -> Result<()> {
+    let mut section = None;
+    for relocation in relocations {
+        if section != Some(relocation.section) {
+            section = Some(relocation.section);
+            writeln!(
+                out,
+                "  section {} {}:",
+                relocation.section, relocation.section_name
+            )?;
+        }
+        writeln!(
+            out,
+            "    {:#010x} {:<8} {}",
+            relocation.offset, relocation.type_name, relocation.symbol
+        )?;
+    }
+    Ok(())
+}
+
+fn base_relocations(relocations: &[BaseRelocationDump], out: &mut dyn Write) -> Result<()> {
+    if relocations.is_empty() {
+        writeln!(out, "  no base relocations")?;
+        return Ok(());
+    }
+    let mut page = None;
+    for relocation in relocations {
+        let rva = relocation.rva;
+        if page != Some(rva & !0xfff) {
+            page = Some(rva & !0xfff);
+            let section = relocation.section.as_deref().unwrap_or("no section");
+            writeln!(out, "  page {:#010x} ({section}):", rva & !0xfff)?;
+        }
+        let Some(kind) = relocation.type_name else {
+            writeln!(out, "    {rva:#010x} type {}", relocation.r#type)?;
+            continue;
+        };
+        match relocation.value.zip(relocation.target_rva) {
+            Some((value, target)) => writeln!(
+                out,
+                "    {rva:#010x} {kind:<8} {value:#x} (rva {target:#x})"
+            )?,
+            None => writeln!(out, "    {rva:#010x} {kind:<8} <out of bounds>")?,
+        }
+    }
+    Ok(())
+}
+
+fn import_table(dlls: &[ImportsDump], out: &mut dyn Write) -> Result<()> {
+    if dlls.is_empty() {
+        writeln!(out, "  no imports")?;
+        return Ok(());
+    }
+    writeln!(out, "  imports:")?;
+    for dll in dlls {
+        writeln!(out, "    {}:", dll.dll)?;
+        for import in &dll.imports {
+            match (&import.name, import.hint, import.ordinal) {
+                (Some(name), Some(hint), _) => writeln!(out, "      {hint:>5} {name}")?,
+                (_, _, Some(ordinal)) => writeln!(out, "      ordinal {ordinal}")?,
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+fn export_table(exports: &ExportsDump, out: &mut dyn Write) -> Result<()> {
+    let Some(dll) = &exports.dll else {
+        writeln!(out, "  no exports")?;
+        return Ok(());
+    };
+    writeln!(out, "  exports of {dll}:")?;
+    for export in &exports.exports {
+        let kind = if export.is_code { "code" } else { "data" };
+        match &export.name {
+            Some(name) => writeln!(out, "    {:#010x} {kind} {name}", export.rva)?,
+            None => writeln!(out, "    {:#010x} {kind} #{}", export.rva, export.ordinal)?,
+        }
+    }
+    Ok(())
+}
+
+fn analysis_report(analysis: &AnalysisDump, out: &mut dyn Write) -> Result<()> {
+    writeln!(out, "  section entropy:")?;
+    for section in &analysis.sections {
+        writeln!(out, "    {:<8} {:.3}", section.name, section.entropy)?;
+    }
+    if analysis.anomalies.is_empty() {
+        writeln!(out, "  no anomalies")?;
+        return Ok(());
+    }
+    writeln!(out, "  anomalies:")?;
+    for anomaly in &analysis.anomalies {
+        writeln!(out, "    {anomaly}")?;
+    }
+    Ok(())
+}
+
+fn disassembly(sections: &[DisassemblyDump], kind: &FileKind, out: &mut dyn Write) -> Result<()> {
+    // Virtual addresses of images need all 64 bits, offsets in objects don't.
+    let width = match kind {
+        FileKind::Image => 16,
+        FileKind::Object => 8,
+    };
+    for section in sections {
+        writeln!(out, "  disassembly of {}:", section.section)?;
+        for instruction in &section.instructions {
+            if let Some(label) = &instruction.label {
+                writeln!(out, "  {label}:")?;
+            }
+            let mut line = format!(
+                "    {:0width$x}  {:<20}  {}",
+                instruction.address, instruction.bytes, instruction.text
+            );
+            let notes =
+                instruction
+                    .target
+                    .iter()
+                    .cloned()
+                    .chain(instruction.relocations.iter().map(|relocation| {
+                        format!("{} {}", relocation.type_name, relocation.symbol)
+                    }))
+                    .collect::<Vec<_>>();
+            if !notes.is_empty() {
+                line = format!("{line}  ; {}", notes.join(", "));
+            }
+            writeln!(out, "{line}")?;
+        }
+    }
+    Ok(())
+}
+
+/// `bits` in hex, followed by the names of the set flags.
+fn flags(bits: u32, names: &[&str]) -> String {
+    if names.is_empty() {
+        format!("{bits:#x}")
+    } else {
+        format!("{bits:#x} ({})", names.join(" | "))
+    }
+}
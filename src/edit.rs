@@ -0,0 +1,149 @@
+use std::{io::Write, path::PathBuf};
+
+use color_eyre::Result;
+use winning_coff::diagnostics::{Code, DiagnosticOptions, Diagnostics, fatal};
+use winning_pe::{
+    edit::{HeaderEdits, edit},
+    link::{Reservation, parse_major_minor},
+    pe::{DllCharacteristics, Subsystem},
+};
+
+const USAGE: &str = "usage: winning edit <image> [/OUT:<path>] [/SUBSYSTEM:name[,major.minor]] \
+     [/BASE:address] [/STACK:reserve[,commit]] [/HEAP:reserve[,commit]] [/DYNAMICBASE[:NO]] \
+     [/NXCOMPAT[:NO]] [/HIGHENTROPYVA[:NO]] [/LARGEADDRESSAWARE[:NO]] [/TSAWARE[:NO]] \
+     [/APPCONTAINER[:NO]] [/INTEGRITYCHECK[:NO]]";
+
+/// `winning edit <image> [/OUT:<path>] <options>...`: changes header fields of
+/// an image like editbin, in place unless `/OUT` is given. The options are
+/// spelled like the linker's.
+pub fn run(args: Vec<String>, out: &mut dyn Write) -> Result<()> {
+    let mut output = None;
+    let mut edits = HeaderEdits::default();
+    let mut paths = Vec::new();
+    for arg in args {
+        let Some(flag) = arg.strip_prefix(['/', '-']) else {
+            paths.push(arg);
+            continue;
+        };
+        let (name, value) = match flag.split_once(':') {
+            Some((name, value)) => (name, Some(value)),
+            None => (flag, None),
+        };
+        let required = || match value {
+            Some(value) => Ok(value),
+            None => fatal(Code::InvalidOption, format!("{arg} needs a value\n{USAGE}")),
+        };
+        let is_no = value.is_some_and(|value| value.eq_ignore_ascii_case("no"));
+        let dll_characteristic = match name.to_ascii_lowercase().as_str() {
+            "out" => {
+                output = Some(PathBuf::from(required()?));
+                continue;
+            }
+            "subsystem" => {
+                let value = required()?;
+                let (name, version) = match value.split_once(',') {
+                    Some((name, version)) => (name, Some(version)),
+                    None => (value, None),
+                };
+                let Some(subsystem) = Subsystem::from_name(name) else {
+                    return fatal(Code::InvalidOption, format!("unknown subsystem {name}"));
+                };
+                edits.subsystem = Some(subsystem);
+                if let Some(version) = version {
+                    let Some(version) = parse_major_minor(version) else {
+                        return fatal(
+                            Code::InvalidOption,
+                            format!("{arg}: invalid version {version}, expected major[.minor]"),
+                        );
+                    };
+                    edits.subsystem_version = Some(version);
+                }
+                continue;
+            }
+            "base" => {
+                let value = required()?;
+                // Like `/STACK`, but a commit size makes no sense here.
+                let Some(Reservation {
+                    reserve: base,
+                    commit: None,
+                }) = Reservation::parse(value)
+                else {
+                    return fatal(Code::InvalidOption, format!("invalid base address {value}"));
+                };
+                edits.image_base = Some(base);
+                continue;
+            }
+            flag @ ("stack" | "heap") => {
+                let value = required()?;
+                let Some(reservation) = Reservation::parse(value) else {
+                    return fatal(
+                        Code::InvalidOption,
+                        format!(
+                            "invalid /{}:{value}, expected reserve[,commit]",
+                            flag.to_ascii_uppercase()
+                        ),
+                    );
+                };
+                if flag == "stack" {
+                    edits.stack = Some(reservation);
+                } else {
+                    edits.heap = Some(reservation);
+                }
+                continue;
+            }
+            "largeaddressaware" => {
+                edits.large_address_aware = Some(!is_no);
+                continue;
+            }
+            "dynamicbase" => DllCharacteristics::IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE,
+            "nxcompat" => DllCharacteristics::IMAGE_DLLCHARACTERISTICS_NX_COMPAT,
+            "highentropyva" => DllCharacteristics::IMAGE_DLLCHARACTERISTICS_HIGH_ENTROPY_VA,
+            "tsaware" => DllCharacteristics::IMAGE_DLLCHARACTERISTICS_TERMINAL_SERVER_AWARE,
+            "appcontainer" => DllCharacteristics::IMAGE_DLLCHARACTERISTICS_APPCONTAINER,
+            "integritycheck" => DllCharacteristics::IMAGE_DLLCHARACTERISTICS_FORCE_INTEGRITY,
+            // An absolute path rather than an option.
+            _ if arg.starts_with('/') && std::path::Path::new(&arg).exists() => {
+                paths.push(arg);
+                continue;
+            }
+            _ => {
+                return fatal(
+                    Code::InvalidOption,
+                    format!("unknown edit option {arg}\n{USAGE}"),
+                );
+            }
+        };
+        if is_no {
+            edits.set -= dll_characteristic;
+            edits.clear |= dll_characteristic;
+        } else {
+            edits.set |= dll_characteristic;
+            edits.clear -= dll_characteristic;
+        }
+    }
+    let [image_path] = paths.as_slice() else {
+        return fatal(Code::InvalidOption, USAGE);
+    };
+
+    let data = match std::fs::read(image_path) {
+        Ok(data) => data,
+        Err(err) => {
+            return fatal(
+                Code::CannotOpenInput,
+                format!("cannot open {image_path}: {err}"),
+            );
+        }
+    };
+    let options = DiagnosticOptions::default();
+    let mut diag = Diagnostics::new(&options, out);
+    let image = edit(&data, &edits, &mut diag)?;
+
+    let path = output.unwrap_or_else(|| image_path.into());
+    if let Err(err) = std::fs::write(&path, image) {
+        return fatal(
+            Code::CannotOpenOutput,
+            format!("cannot write {}: {err}", path.display()),
+        );
+    }
+    diag.finish()
+}
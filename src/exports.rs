@@ -0,0 +1,118 @@
+use std::io::Write;
+
+use color_eyre::Result;
+use serde::Serialize;
+use winning_coff::diagnostics::{Code, fatal};
+use winning_pe::{image::ImageModel, input::Input, pe::DOS_MAGIC};
+
+const USAGE: &str = "usage: winning exports [--format=text|json] <images>...";
+
+#[derive(Serialize)]
+struct ImageDump {
+    name: String,
+    /// The name in the export directory, `None` without one.
+    dll: Option<String>,
+    exports: Vec<ExportDump>,
+}
+
+#[derive(Serialize)]
+struct ExportDump {
+    ordinal: u32,
+    /// `None` for exports by ordinal only.
+    name: Option<String>,
+    rva: u32,
+    is_code: bool,
+    /// The `DLL.name` the export is forwarded to.
+    forwarder: Option<String>,
+}
+
+/// `winning exports [--format=text|json] <images>...`: lists the exports
+/// of the images with their ordinals and names, including those exported by
+/// ordinal only, and where forwarded exports go.
+pub fn run(args: Vec<String>, out: &mut dyn Write) -> Result<()> {
+    let mut json = false;
+    let mut paths = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--format=text" => json = false,
+            "--format=json" => json = true,
+            flag if flag.starts_with("--") => {
+                return fatal(
+                    Code::InvalidOption,
+                    format!("unknown exports option {flag}\n{USAGE}"),
+                );
+            }
+            _ => paths.push(arg),
+        }
+    }
+    if paths.is_empty() {
+        return fatal(Code::InvalidOption, USAGE);
+    }
+
+    let mut images = Vec::new();
+    for path in &paths {
+        let input = Input::open(path)?;
+        let data = input.data();
+        if !data.starts_with(DOS_MAGIC) {
+            return fatal(Code::CorruptInput, format!("{path}: not a PE image"));
+        }
+        let image = ImageModel::parse(data)?;
+        let mut dump = ImageDump {
+            name: input.name.clone(),
+            dll: None,
+            exports: Vec::new(),
+        };
+        if image.optional_header.export_table.size != 0 {
+            let Some(exports) = image.exports() else {
+                return fatal(
+                    Code::CorruptInput,
+                    format!("{path}: export table out of bounds"),
+                );
+            };
+            dump.dll = Some(exports.dll.to_owned());
+            dump.exports = exports
+                .exports
+                .into_iter()
+                .map(|export| ExportDump {
+                    ordinal: export.ordinal,
+                    name: export.name.map(str::to_owned),
+                    rva: export.rva,
+                    is_code: export.is_code,
+                    forwarder: export.forwarder.map(str::to_owned),
+                })
+                .collect();
+            dump.exports.sort_by_key(|export| export.ordinal);
+        }
+        images.push(dump);
+    }
+
+    if json {
+        serde_json::to_writer_pretty(&mut *out, &images)?;
+        writeln!(out)?;
+        return Ok(());
+    }
+    for image in &images {
+        let Some(dll) = &image.dll else {
+            writeln!(out, "{}: no exports", image.name)?;
+            continue;
+        };
+        writeln!(out, "{}: exports of {dll}", image.name)?;
+        writeln!(out, "  {:>7}  {:<10}  name", "ordinal", "rva")?;
+        for export in &image.exports {
+            let name = export.name.as_deref().unwrap_or("(by ordinal)");
+            match &export.forwarder {
+                Some(forwarder) => writeln!(
+                    out,
+                    "  {:>7}  {:<10}  {} -> {forwarder}",
+                    export.ordinal, "", name
+                )?,
+                None => writeln!(
+                    out,
+                    "  {:>7}  {:#010x}  {}",
+                    export.ordinal, export.rva, name
+                )?,
+            }
+        }
+    }
+    Ok(())
+}
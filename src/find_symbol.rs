@@ -0,0 +1,38 @@
+use std::io::Write;
+
+use color_eyre::Result;
+use winning_coff::{
+    demangle,
+    diagnostics::{Code, fatal},
+};
+use winning_pe::{find::external_symbols, input::Input};
+
+use crate::pattern::Pattern;
+
+/// `winning find-symbol <pattern> <inputs>...`: prints the external symbols
+/// defined or referenced by the inputs whose name matches `pattern`, see
+/// [`Pattern`]. Mangled names match both as they are and demangled.
+pub fn run(args: Vec<String>, out: &mut dyn Write) -> Result<()> {
+    let Some((pattern, paths)) = args.split_first().filter(|(_, paths)| !paths.is_empty()) else {
+        return fatal(
+            Code::InvalidOption,
+            "usage: winning find-symbol <pattern> <inputs>...",
+        );
+    };
+    let pattern = Pattern::parse(pattern)?;
+    for path in paths {
+        let input = Input::open(path)?;
+        for entry in external_symbols(&input)? {
+            let demangled = demangle::symbol_name(&entry.name, true).to_string();
+            if !pattern.is_match(&entry.name) && !pattern.is_match(&demangled) {
+                continue;
+            }
+            write!(out, "{}: {demangled}", entry.location)?;
+            if demangled != entry.name {
+                write!(out, " ({})", entry.name)?;
+            }
+            writeln!(out, ": {}", entry.kind)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,181 @@
+use std::fmt::{self, Display};
+
+use serde::{Deserialize, Serialize};
+use winning_pe::input::Input;
+
+/// A 128-bit content digest. Wide enough that collisions between sections of a
+/// single link aren't a practical concern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ContentHash(pub u128);
+
+impl Display for ContentHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl Serialize for ContentHash {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ContentHash {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        u128::from_str_radix(&hex, 16)
+            .map(Self)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Streaming hash over section contents. Implementations must be stable across
+/// runs and machines, since the results end up in reports and caches.
+pub trait ContentHasher {
+    fn update(&mut self, bytes: &[u8]);
+    fn finish(&self) -> ContentHash;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    #[cfg(feature = "xxhash")]
+    Xxh3,
+    #[cfg(feature = "blake3")]
+    Blake3,
+    Fnv1a,
+}
+
+impl HashAlgorithm {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            #[cfg(feature = "xxhash")]
+            "xxh3" => Some(Self::Xxh3),
+            #[cfg(feature = "blake3")]
+            "blake3" => Some(Self::Blake3),
+            "fnv1a" => Some(Self::Fnv1a),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            #[cfg(feature = "xxhash")]
+            Self::Xxh3 => "xxh3",
+            #[cfg(feature = "blake3")]
+            Self::Blake3 => "blake3",
+            Self::Fnv1a => "fnv1a",
+        }
+    }
+
+    pub fn hasher(self) -> Box<dyn ContentHasher> {
+        match self {
+            #[cfg(feature = "xxhash")]
+            Self::Xxh3 => Box::new(xxhash_rust::xxh3::Xxh3::new()),
+            #[cfg(feature = "blake3")]
+            Self::Blake3 => Box::new(blake3::Hasher::new()),
+            Self::Fnv1a => Box::new(Fnv1a::new()),
+        }
+    }
+}
+
+impl Default for HashAlgorithm {
+    fn default() -> Self {
+        #[cfg(feature = "xxhash")]
+        return Self::Xxh3;
+        #[cfg(all(feature = "blake3", not(feature = "xxhash")))]
+        return Self::Blake3;
+        #[cfg(not(any(feature = "xxhash", feature = "blake3")))]
+        return Self::Fnv1a;
+    }
+}
+
+#[cfg(feature = "xxhash")]
+impl ContentHasher for xxhash_rust::xxh3::Xxh3 {
+    fn update(&mut self, bytes: &[u8]) {
+        xxhash_rust::xxh3::Xxh3::update(self, bytes);
+    }
+
+    fn finish(&self) -> ContentHash {
+        ContentHash(self.digest128())
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl ContentHasher for blake3::Hasher {
+    fn update(&mut self, bytes: &[u8]) {
+        blake3::Hasher::update(self, bytes);
+    }
+
+    fn finish(&self) -> ContentHash {
+        let bytes = self.finalize();
+        ContentHash(u128::from_le_bytes(
+            bytes.as_bytes()[..16].try_into().unwrap(),
+        ))
+    }
+}
+
+/// 128-bit FNV-1a, available without any hashing features enabled.
+pub struct Fnv1a(u128);
+
+impl Fnv1a {
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    pub fn new() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+}
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ContentHasher for Fnv1a {
+    fn update(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= u128::from(b);
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn finish(&self) -> ContentHash {
+        ContentHash(self.0)
+    }
+}
+
+/// The content-defined identity of an input section: its characteristics, size
+/// and raw data, independent of its name, owning object, or position in the
+/// file. Sections with equal UIDs are byte-for-byte interchangeable.
+pub fn section_uid(
+    algorithm: HashAlgorithm,
+    characteristics: u32,
+    size: u32,
+    data: &[u8],
+) -> ContentHash {
+    let mut hasher = algorithm.hasher();
+    hasher.update(&characteristics.to_le_bytes());
+    hasher.update(&size.to_le_bytes());
+    hasher.update(data);
+    hasher.finish()
+}
+
+/// The digest of a whole file.
+pub fn content_hash(algorithm: HashAlgorithm, data: &[u8]) -> ContentHash {
+    let mut hasher = algorithm.hasher();
+    hasher.update(data);
+    hasher.finish()
+}
+
+/// Identifies a build by its inputs, for the PDB GUID and REPRO hash. Linking
+/// the same inputs to the same PDB path gives the same id.
+pub fn build_id(algorithm: HashAlgorithm, inputs: &[Input], pdb: Option<&str>) -> ContentHash {
+    let mut hasher = algorithm.hasher();
+    for input in inputs {
+        hasher.update(&(input.data().len() as u64).to_le_bytes());
+        hasher.update(input.data());
+    }
+    hasher.update(pdb.unwrap_or_default().as_bytes());
+    hasher.finish()
+}
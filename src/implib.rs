@@ -0,0 +1,89 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::Result;
+use winning_coff::diagnostics::{Code, fatal};
+use winning_pe::{
+    def::ModuleDefinition,
+    image::ImageModel,
+    implib::{def_import_entries, image_import_entries, write_import_library},
+    input::Input,
+    pe::DOS_MAGIC,
+};
+
+const USAGE: &str = "usage: winning implib <dll|file.def> [/OUT:<path>] [/NAME:<dll>]";
+
+/// `winning implib <dll|file.def> [/OUT:<path>] [/NAME:<dll>]`: writes an
+/// import library for the exports of a DLL or a module-definition file, next
+/// to it unless `/OUT` is given. The DLL name comes from `/NAME`, the export
+/// directory or `LIBRARY`, or else the input's file name.
+pub fn run(args: Vec<String>, out: &mut dyn Write) -> Result<()> {
+    let mut output = None;
+    let mut name = None;
+    let mut paths = Vec::new();
+    for arg in args {
+        match arg.split_once(':') {
+            Some((flag, value))
+                if flag.eq_ignore_ascii_case("/out") || flag.eq_ignore_ascii_case("-out") =>
+            {
+                output = Some(PathBuf::from(value));
+            }
+            Some((flag, value))
+                if flag.eq_ignore_ascii_case("/name") || flag.eq_ignore_ascii_case("-name") =>
+            {
+                name = Some(value.to_owned());
+            }
+            _ => paths.push(arg),
+        }
+    }
+    let [path] = paths.as_slice() else {
+        return fatal(Code::InvalidOption, USAGE);
+    };
+
+    let input = Input::open(path)?;
+    let data = input.data();
+    let (recorded_name, entries) = if data.starts_with(DOS_MAGIC) {
+        let image = ImageModel::parse(data)?;
+        let dll = image.exports().map(|exports| exports.dll.to_owned());
+        (dll, image_import_entries(&image)?)
+    } else {
+        let Ok(text) = std::str::from_utf8(data) else {
+            return fatal(
+                Code::CorruptInput,
+                format!("{path}: neither an image nor a module-definition file"),
+            );
+        };
+        let def = ModuleDefinition::parse(text)?;
+        let entries = def_import_entries(&def)?;
+        (def.library, entries)
+    };
+    let dll = name.or(recorded_name).unwrap_or_else(|| {
+        Path::new(path)
+            .with_extension("dll")
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    });
+    if entries.is_empty() {
+        return fatal(Code::InvalidOption, format!("{path}: nothing is exported"));
+    }
+    let library = write_import_library(&dll, &entries)?;
+
+    let output = output.unwrap_or_else(|| Path::new(path).with_extension("lib"));
+    if let Err(err) = std::fs::write(&output, library) {
+        return fatal(
+            Code::CannotOpenOutput,
+            format!("cannot write {}: {err}", output.display()),
+        );
+    }
+    writeln!(
+        out,
+        "{}: {} imports from {dll}",
+        output.display(),
+        entries.len()
+    )?;
+    Ok(())
+}
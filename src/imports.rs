@@ -0,0 +1,136 @@
+use std::io::Write;
+
+use color_eyre::Result;
+use serde::Serialize;
+use winning_coff::diagnostics::{Code, fatal};
+use winning_pe::{
+    image::{ImageImport, ImageImports, ImageModel},
+    input::Input,
+    pe::DOS_MAGIC,
+};
+
+const USAGE: &str = "usage: winning imports [--format=text|json] <images>...";
+
+#[derive(Serialize)]
+struct ImageDump {
+    name: String,
+    dlls: Vec<DllDump>,
+}
+
+#[derive(Serialize)]
+struct DllDump {
+    dll: String,
+    delay_loaded: bool,
+    imports: Vec<ImportDump>,
+}
+
+#[derive(Serialize)]
+struct ImportDump {
+    name: Option<String>,
+    hint: Option<u16>,
+    ordinal: Option<u16>,
+}
+
+/// `winning imports [--format=text|json] <images>...`: lists the DLLs the
+/// images import from and what they import from each, including delay-loaded
+/// ones.
+pub fn run(args: Vec<String>, out: &mut dyn Write) -> Result<()> {
+    let mut json = false;
+    let mut paths = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--format=text" => json = false,
+            "--format=json" => json = true,
+            flag if flag.starts_with("--") => {
+                return fatal(
+                    Code::InvalidOption,
+                    format!("unknown imports option {flag}\n{USAGE}"),
+                );
+            }
+            _ => paths.push(arg),
+        }
+    }
+    if paths.is_empty() {
+        return fatal(Code::InvalidOption, USAGE);
+    }
+
+    let mut images = Vec::new();
+    for path in &paths {
+        let input = Input::open(path)?;
+        let data = input.data();
+        if !data.starts_with(DOS_MAGIC) {
+            return fatal(Code::CorruptInput, format!("{path}: not a PE image"));
+        }
+        let image = ImageModel::parse(data)?;
+        let imports = match image.optional_header.import_table.size {
+            0 => Some(Vec::new()),
+            _ => image.imports(),
+        };
+        let (Some(imports), Some(delay_imports)) = (imports, image.delay_imports()) else {
+            return fatal(
+                Code::CorruptInput,
+                format!("{path}: import table out of bounds"),
+            );
+        };
+        let dlls = imports
+            .into_iter()
+            .map(|dll| dll_dump(dll, false))
+            .chain(delay_imports.into_iter().map(|dll| dll_dump(dll, true)))
+            .collect();
+        images.push(ImageDump {
+            name: input.name.clone(),
+            dlls,
+        });
+    }
+
+    if json {
+        serde_json::to_writer_pretty(&mut *out, &images)?;
+        writeln!(out)?;
+        return Ok(());
+    }
+    for image in &images {
+        writeln!(out, "{}:", image.name)?;
+        if image.dlls.is_empty() {
+            writeln!(out, "  no imports")?;
+        }
+        for dll in &image.dlls {
+            let delay = if dll.delay_loaded {
+                " (delay-loaded)"
+            } else {
+                ""
+            };
+            writeln!(out, "  {}{delay}", dll.dll)?;
+            for import in &dll.imports {
+                match (&import.name, import.hint, import.ordinal) {
+                    (Some(name), Some(hint), _) => writeln!(out, "    {hint:>5} {name}")?,
+                    (_, _, Some(ordinal)) => writeln!(out, "          ordinal {ordinal}")?,
+                    _ => {}
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn dll_dump(dll: ImageImports<'_>, delay_loaded: bool) -> DllDump {
+    DllDump {
+        dll: dll.dll.to_owned(),
+        delay_loaded,
+        imports: dll
+            .imports
+            .into_iter()
+            .map(|import| match import {
+                ImageImport::Name { name, hint } => ImportDump {
+                    name: Some(name.to_owned()),
+                    hint: Some(hint),
+                    ordinal: None,
+                },
+                ImageImport::Ordinal(ordinal) => ImportDump {
+                    name: None,
+                    hint: None,
+                    ordinal: Some(ordinal),
+                },
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,35 @@
+//! Ctrl-C handling. The first interrupt cancels the running link, which then
+//! fails and removes its temporary files; a second one exits right away.
+
+use std::sync::OnceLock;
+
+use winning_pe::cancel::CancellationToken;
+
+static TOKEN: OnceLock<CancellationToken> = OnceLock::new();
+
+/// The token cancelled by Ctrl-C.
+pub fn token() -> CancellationToken {
+    TOKEN.get_or_init(CancellationToken::new).clone()
+}
+
+pub fn install() {
+    token();
+    // SAFETY: the handler only does an atomic store and resets the handler,
+    // which are both async-signal-safe.
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_interrupt as extern "C" fn(_) as libc::sighandler_t,
+        );
+    }
+}
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    if let Some(token) = TOKEN.get() {
+        token.cancel();
+    }
+    // SAFETY: see `install`.
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+}
@@ -0,0 +1,5 @@
+//! The `winning` linker. The command-line tool is the main interface; the
+//! library side only has helpers for driving it from other Rust code.
+
+#[cfg(feature = "build")]
+pub mod build;
@@ -0,0 +1,45 @@
+use std::io;
+
+use tracing::{Subscriber, level_filters::LevelFilter};
+use tracing_subscriber::{filter::Targets, layer::SubscriberExt};
+
+/// Targets that can be enabled individually with `--verbose=a,b`. Everything
+/// logged under them is shown, regardless of the `-v` level.
+pub const CATEGORIES: &[&str] = &[
+    "archives",
+    "headers",
+    "layout",
+    "relocations",
+    "sections",
+    "symbols",
+];
+
+#[derive(Default)]
+pub struct LogOptions {
+    /// The number of `-v`s: 1 is info, 2 is debug and 3 or more is trace.
+    pub verbosity: u8,
+    pub categories: Vec<String>,
+}
+
+pub fn subscriber(options: &LogOptions) -> impl Subscriber + Send + Sync {
+    let level = match options.verbosity {
+        0 => LevelFilter::WARN,
+        1 => LevelFilter::INFO,
+        2 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    };
+    let filter = options
+        .categories
+        .iter()
+        .fold(Targets::new().with_default(level), |filter, category| {
+            filter.with_target(category.clone(), LevelFilter::TRACE)
+        });
+
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(io::stderr)
+                .without_time(),
+        )
+        .with(filter)
+}
@@ -0,0 +1,705 @@
+use std::path::{Path, PathBuf};
+
+use color_eyre::Result;
+
+use winning_coff::diagnostics::{Code, DiagnosticOptions, fatal};
+use winning_pe::{
+    def::ModuleDefinition,
+    export::{ExportList, ExportSpec},
+    icon::icon_resources,
+    link::{
+        AlternateName, Driver, LinkOptions, Reservation, SectionMerge, SectionOverride,
+        parse_major_minor,
+    },
+    manifest::{ManifestOptions, manifest_resource},
+    order::StartupOrder,
+    pe::{Subsystem, custom_dos_stub},
+    rich::{RichHeader, read_rich_header},
+    version_info::{Version, VersionInfo},
+};
+
+use crate::{
+    hash::HashAlgorithm,
+    logging::{self, LogOptions},
+    sysroot::Sysroot,
+};
+
+#[derive(Default)]
+pub struct Options {
+    pub inputs: Vec<String>,
+    pub link: LinkOptions,
+    /// `/OUT`, defaulting to the [`Options::library`] or the first input with
+    /// an `.exe` extension, or `.dll` for DLLs.
+    pub out: Option<PathBuf>,
+    /// The `LIBRARY` of a `/DEF` file, the name recorded in the export
+    /// directory and import library.
+    pub library: Option<String>,
+    /// `/DEBUG`, writing a PDB and recording its path in the image.
+    pub debug: bool,
+    /// `/PDB`, defaulting to the output with a `.pdb` extension.
+    pub pdb: Option<PathBuf>,
+    /// `/IMPLIB`, defaulting to the output with a `.lib` extension. Only
+    /// written if the image has exports.
+    pub implib: Option<PathBuf>,
+    pub report: Option<PathBuf>,
+    /// `--link-record`, see [`crate::relink::LinkRecord`].
+    pub link_record: Option<PathBuf>,
+    /// `--why-extract`, listing the archive members that were extracted and
+    /// why, or to stderr for `-`.
+    pub why_extract: Option<PathBuf>,
+    /// `--cref`, listing every global symbol with the objects defining and
+    /// referencing it, or to stderr for `-`.
+    pub cref: Option<PathBuf>,
+    /// `--time`, printing how long the phases of the link took.
+    pub time: bool,
+    /// `--stats`, printing counts and memory use of the link.
+    pub stats: bool,
+    /// `/LIBPATH`, then the directories of the [`Sysroot`], searched before
+    /// the directories in `LIB`.
+    pub libpath: Vec<PathBuf>,
+    pub sysroot: Sysroot,
+    /// `/INFERASANLIBS:NO`, not adding the ASan runtime for instrumented objects.
+    pub skip_asan_libs: bool,
+    /// `--gui-manifest`, embedding a manifest for `/SUBSYSTEM:WINDOWS` images.
+    pub gui_manifest: Option<ManifestOptions>,
+    /// `--file-version`, `--company` and the like, for a version resource.
+    pub version_info: Option<VersionInfo>,
+    /// `/MANIFEST`: where the application manifest goes, if anywhere.
+    pub manifest: Option<ManifestMode>,
+    /// `/MANIFESTINPUT`, used instead of the manifest we'd write.
+    pub manifest_input: Option<String>,
+    /// `/MANIFESTUAC:NO`, leaving the UAC execution level out of the manifest.
+    pub no_manifest_uac: bool,
+    /// `--intermediates-dir`, for temporary files. Defaults to next to the outputs.
+    pub intermediates_dir: Option<PathBuf>,
+    pub hash_algorithm: HashAlgorithm,
+    pub diagnostics: DiagnosticOptions,
+    pub logging: LogOptions,
+    /// Options we didn't recognize, reported as warnings once diagnostics are set up.
+    pub unknown: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestMode {
+    /// `/MANIFEST:EMBED`, as a resource in the image.
+    Embed,
+    /// `/MANIFEST`, as a `.manifest` file next to the image.
+    SideBySide,
+}
+
+impl Options {
+    /// Parses both link.exe-style options (`/NAME[:value]` or `-NAME[:value]`,
+    /// case-insensitive) and our own GNU-style extensions (`--name[=value]`).
+    /// Since `/` also starts absolute paths, a `/`-argument that doesn't name a
+    /// known option is treated as an input.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut options = Self::default();
+        let mut subsystem_given = false;
+
+        for arg in args {
+            if let Some(flag) = arg.strip_prefix("--") {
+                let (name, value) = match flag.split_once('=') {
+                    Some((name, value)) => (name, Some(value)),
+                    None => (flag, None),
+                };
+                match name {
+                    "report" => options.report = Some(required(&arg, value)?.into()),
+                    "link-record" => options.link_record = Some(required(&arg, value)?.into()),
+                    "why-extract" => options.why_extract = Some(required(&arg, value)?.into()),
+                    "cref" => options.cref = Some(required(&arg, value)?.into()),
+                    "time" => options.time = true,
+                    "stats" => options.stats = true,
+                    "intermediates-dir" => {
+                        options.intermediates_dir = Some(required(&arg, value)?.into());
+                    }
+                    "hash" => {
+                        let value = required(&arg, value)?;
+                        let Some(algorithm) = HashAlgorithm::from_name(value) else {
+                            return fatal(
+                                Code::InvalidOption,
+                                format!("unknown or disabled hash algorithm {value}"),
+                            );
+                        };
+                        options.hash_algorithm = algorithm;
+                    }
+                    "export-list" => {
+                        let text = read_text(required(&arg, value)?)?;
+                        options.link.exports = Some(ExportList::parse(&text));
+                    }
+                    "startup-order" => {
+                        let text = read_text(required(&arg, value)?)?;
+                        options.link.startup_order = Some(StartupOrder::parse(&text)?);
+                    }
+                    "file-version" | "product-version" => {
+                        let version = Version::parse(required(&arg, value)?)?;
+                        let info = options.version_info.get_or_insert_default();
+                        if name == "file-version" {
+                            info.file_version = Some(version);
+                        } else {
+                            info.product_version = Some(version);
+                        }
+                    }
+                    "company" | "description" | "product-name" | "copyright" => {
+                        let key = match name {
+                            "company" => "CompanyName",
+                            "description" => "FileDescription",
+                            "product-name" => "ProductName",
+                            _ => "LegalCopyright",
+                        };
+                        let value = required(&arg, value)?;
+                        options
+                            .version_info
+                            .get_or_insert_default()
+                            .set_string(key, value);
+                    }
+                    "icon" => {
+                        let path = required(&arg, value)?;
+                        let data = read_data(path)?;
+                        options.link.resources.extend(icon_resources(&data, path)?);
+                    }
+                    "gui-manifest" => {
+                        // Everything by default, or a list of what to enable.
+                        let mut manifest = ManifestOptions {
+                            dpi_aware: value.is_none(),
+                            common_controls: value.is_none(),
+                            ..ManifestOptions::default()
+                        };
+                        for feature in value.into_iter().flat_map(|value| value.split(',')) {
+                            match feature {
+                                "dpi" => manifest.dpi_aware = true,
+                                "common-controls" => manifest.common_controls = true,
+                                _ => {
+                                    return fatal(
+                                        Code::InvalidOption,
+                                        format!(
+                                            "unknown manifest feature {feature}, expected dpi or common-controls"
+                                        ),
+                                    );
+                                }
+                            }
+                        }
+                        options.gui_manifest = Some(manifest);
+                    }
+                    "demangle" => options.link.demangle = true,
+                    "self-check" => options.link.self_check = true,
+                    "tiny" => options.link.tiny = true,
+                    // Archives are always scanned until nothing changes, as
+                    // if they were all in one group.
+                    "start-group" | "end-group" => {}
+                    "data-padding" => {
+                        let value = required(&arg, value)?;
+                        let Some(byte) = parse_number(value).and_then(|n| u8::try_from(n).ok())
+                        else {
+                            return fatal(
+                                Code::InvalidOption,
+                                format!("invalid padding byte {value}"),
+                            );
+                        };
+                        options.link.data_padding = byte;
+                    }
+                    "reserve-certificate" => {
+                        let value = required(&arg, value)?;
+                        let Some(size) = parse_number(value)
+                            .and_then(|n| u32::try_from(n).ok())
+                            .filter(|&size| size > 0)
+                        else {
+                            return fatal(
+                                Code::InvalidOption,
+                                format!("invalid certificate table size {value}"),
+                            );
+                        };
+                        options.link.certificate_reserve = Some(size);
+                    }
+                    "rich-header" => {
+                        // From the objects, or copied from a template image.
+                        options.link.rich_header = Some(match value {
+                            None => RichHeader::FromObjects,
+                            Some(template) => match read_rich_header(&read_data(template)?) {
+                                Some(entries) => RichHeader::Entries(entries),
+                                None => {
+                                    return fatal(
+                                        Code::CorruptInput,
+                                        format!("{template} has no Rich header to copy"),
+                                    );
+                                }
+                            },
+                        });
+                    }
+                    "no-demangle" => options.link.demangle = false,
+                    "trace-symbol" => {
+                        let name = required(&arg, value)?;
+                        options.link.trace_symbols.push(name.to_owned());
+                        options.logging.categories.push("trace-symbol".to_owned());
+                    }
+                    "verbose" => match value {
+                        None => options.logging.verbosity += 1,
+                        Some(categories) => {
+                            for category in categories.split(',') {
+                                if !logging::CATEGORIES.contains(&category) {
+                                    return fatal(
+                                        Code::InvalidOption,
+                                        format!(
+                                            "unknown log category {category}, expected one of {}",
+                                            logging::CATEGORIES.join(", ")
+                                        ),
+                                    );
+                                }
+                                options.logging.categories.push(category.to_owned());
+                            }
+                        }
+                    },
+                    _ => options.unknown.push(arg),
+                }
+            } else if let Some(flag) = arg.strip_prefix(['/', '-']) {
+                let (name, value) = match flag.split_once(':') {
+                    Some((name, value)) => (name, Some(value)),
+                    None => (flag, None),
+                };
+                match name.to_ascii_lowercase().as_str() {
+                    "out" => options.out = Some(required(&arg, value)?.into()),
+                    "stub" => {
+                        let path = required(&arg, value)?;
+                        options.link.dos_stub = Some(custom_dos_stub(&read_data(path)?, path)?);
+                    }
+                    "implib" => options.implib = Some(required(&arg, value)?.into()),
+                    "manifest" => {
+                        options.manifest = match value.map(str::to_ascii_lowercase).as_deref() {
+                            None => Some(ManifestMode::SideBySide),
+                            Some("embed") => Some(ManifestMode::Embed),
+                            Some("no") => None,
+                            Some(_) => {
+                                return fatal(
+                                    Code::InvalidOption,
+                                    format!("invalid {arg}, expected /MANIFEST[:EMBED|NO]"),
+                                );
+                            }
+                        }
+                    }
+                    "manifestinput" => {
+                        if options.manifest_input.is_some() {
+                            return fatal(
+                                Code::InvalidOption,
+                                "merging several /MANIFESTINPUT files isn't supported",
+                            );
+                        }
+                        options.manifest_input = Some(read_text(required(&arg, value)?)?);
+                    }
+                    "manifestuac" => options.no_manifest_uac = is_no(value),
+                    "debug" => match value.map(str::to_ascii_lowercase).as_deref() {
+                        Some("none") => {
+                            options.debug = false;
+                            options.link.debug.dwarf = false;
+                        }
+                        // Like lld-link: DWARF in the image instead of a PDB.
+                        Some("dwarf") => options.link.debug.dwarf = true,
+                        _ => options.debug = true,
+                    },
+                    "pdb" => options.pdb = Some(required(&arg, value)?.into()),
+                    "brepro" => options.link.debug.repro = !is_no(value),
+                    "libpath" => options.libpath.push(required(&arg, value)?.into()),
+                    "winsysroot" => options.sysroot.root = Some(required(&arg, value)?.into()),
+                    "vctoolsdir" => {
+                        options.sysroot.vc_tools = Some(required(&arg, value)?.into());
+                    }
+                    "winsdkdir" => options.sysroot.sdk = Some(required(&arg, value)?.into()),
+                    "inferasanlibs" => options.skip_asan_libs = is_no(value),
+                    "base" => {
+                        // The `,size` suffix is accepted but only checked by link.exe.
+                        let value = required(&arg, value)?;
+                        let address = value.split_once(',').map_or(value, |(address, _)| address);
+                        let Some(base) = parse_number(address) else {
+                            return fatal(
+                                Code::InvalidOption,
+                                format!("invalid base address {address}"),
+                            );
+                        };
+                        options.link.image_base = Some(base);
+                    }
+                    "fixed" => options.link.fixed = !is_no(value),
+                    "dynamicbase" => options.link.no_dynamic_base = is_no(value),
+                    "highentropyva" => options.link.no_high_entropy_va = is_no(value),
+                    "guard" => {
+                        for value in required(&arg, value)?.split(',') {
+                            match value.to_ascii_lowercase().as_str() {
+                                "cf" => options.link.guard_cf = true,
+                                "no" => options.link.guard_cf = false,
+                                _ => {
+                                    return fatal(
+                                        Code::InvalidOption,
+                                        format!("unsupported /GUARD value {value}"),
+                                    );
+                                }
+                            }
+                        }
+                    }
+                    "dependentloadflag" => {
+                        let value = required(&arg, value)?;
+                        let Some(flags) = parse_number(value).and_then(|n| u16::try_from(n).ok())
+                        else {
+                            return fatal(
+                                Code::InvalidOption,
+                                format!("invalid dependent load flags {value}"),
+                            );
+                        };
+                        options.link.dependent_load_flags = flags;
+                    }
+                    "force" => match value.map(str::to_ascii_lowercase).as_deref() {
+                        None => {
+                            options.link.force_unresolved = true;
+                            options.link.force_multiple = true;
+                        }
+                        Some("unresolved") => options.link.force_unresolved = true,
+                        Some("multiple") => options.link.force_multiple = true,
+                        Some(kind) => {
+                            return fatal(
+                                Code::InvalidOption,
+                                format!("unknown /FORCE:{kind}, expected MULTIPLE or UNRESOLVED"),
+                            );
+                        }
+                    },
+                    "alternatename" => {
+                        let value = required(&arg, value)?;
+                        let Some(alternate) = AlternateName::parse(value) else {
+                            return fatal(
+                                Code::InvalidOption,
+                                format!("invalid /ALTERNATENAME:{value}, expected from=to"),
+                            );
+                        };
+                        options.link.alternate_names.push(alternate);
+                    }
+                    "merge" => {
+                        let value = required(&arg, value)?;
+                        let Some(merge) = SectionMerge::parse(value) else {
+                            return fatal(
+                                Code::InvalidOption,
+                                format!("invalid /MERGE:{value}, expected from=to"),
+                            );
+                        };
+                        options.link.merges.push(merge);
+                    }
+                    "order" => {
+                        let value = required(&arg, value)?;
+                        let Some(path) = value.strip_prefix('@') else {
+                            return fatal(
+                                Code::InvalidOption,
+                                format!("invalid /ORDER:{value}, expected @file"),
+                            );
+                        };
+                        let text = read_text(path)?;
+                        options.link.order.extend(
+                            text.lines()
+                                .map(str::trim)
+                                .filter(|line| !line.is_empty())
+                                .map(str::to_owned),
+                        );
+                    }
+                    "section" => {
+                        let value = required(&arg, value)?;
+                        let Some(section) = SectionOverride::parse(value) else {
+                            return fatal(
+                                Code::InvalidOption,
+                                format!("invalid /SECTION:{value}, expected name,[!]DEKPRSW"),
+                            );
+                        };
+                        options.link.section_overrides.push(section);
+                    }
+                    flag @ ("align" | "filealign") => {
+                        let value = required(&arg, value)?;
+                        let Some(alignment) =
+                            parse_number(value).and_then(|n| u32::try_from(n).ok())
+                        else {
+                            return fatal(
+                                Code::InvalidOption,
+                                format!("invalid alignment {value}"),
+                            );
+                        };
+                        if flag == "align" {
+                            options.link.section_alignment = Some(alignment);
+                        } else {
+                            options.link.file_alignment = Some(alignment);
+                        }
+                    }
+                    "largeaddressaware" => options.link.no_large_address_aware = is_no(value),
+                    "nxcompat" => options.link.no_nx_compat = is_no(value),
+                    "export" => {
+                        let spec = ExportSpec::parse(required(&arg, value)?)?;
+                        options.link.export_specs.push(spec);
+                    }
+                    "version" => {
+                        let version = major_minor(&arg, required(&arg, value)?)?;
+                        options.link.image_version = Some(version);
+                    }
+                    "osversion" => {
+                        let version = major_minor(&arg, required(&arg, value)?)?;
+                        options.link.os_version = Some(version);
+                    }
+                    flag @ ("stack" | "heap") => {
+                        let value = required(&arg, value)?;
+                        let Some(reservation) = Reservation::parse(value) else {
+                            return fatal(
+                                Code::InvalidOption,
+                                format!(
+                                    "invalid /{}:{value}, expected reserve[,commit]",
+                                    flag.to_ascii_uppercase()
+                                ),
+                            );
+                        };
+                        if flag == "stack" {
+                            options.link.stack = Some(reservation);
+                        } else {
+                            options.link.heap = Some(reservation);
+                        }
+                    }
+                    "def" => {
+                        let text = read_text(required(&arg, value)?)?;
+                        let def = ModuleDefinition::parse(&text)?;
+                        options.link.export_specs.extend(def.exports);
+                        // Like link.exe, a `LIBRARY` statement makes a DLL.
+                        options.link.dll |= def.library.is_some();
+                        options.library = def.library.or(options.library);
+                        options.link.stack = def.stack.or(options.link.stack);
+                        options.link.heap = def.heap.or(options.link.heap);
+                        options.link.image_version = def.version.or(options.link.image_version);
+                    }
+                    "entry" => options.link.entry = Some(required(&arg, value)?.to_owned()),
+                    "subsystem" => {
+                        let value = required(&arg, value)?;
+                        let (name, version) = match value.split_once(',') {
+                            Some((name, version)) => (name, Some(version)),
+                            None => (value, None),
+                        };
+                        let Some(subsystem) = Subsystem::from_name(name) else {
+                            return fatal(Code::InvalidOption, format!("unknown subsystem {name}"));
+                        };
+                        options.link.subsystem = subsystem;
+                        if let Some(version) = version {
+                            options.link.subsystem_version = Some(major_minor(&arg, version)?);
+                        }
+                        subsystem_given = true;
+                    }
+                    "dll" => options.link.dll = true,
+                    "driver" => {
+                        options.link.driver = Some(match value.map(str::to_ascii_lowercase) {
+                            None => Driver::Plain,
+                            Some(kind) if kind == "wdm" => Driver::Wdm,
+                            Some(kind) if kind == "uponly" => Driver::UpOnly,
+                            Some(kind) => {
+                                return fatal(
+                                    Code::InvalidOption,
+                                    format!("unknown driver kind {kind}, expected WDM or UPONLY"),
+                                );
+                            }
+                        });
+                    }
+                    "integritycheck" => options.link.integrity_check = !is_no(value),
+                    "release" => options.link.checksum = !is_no(value),
+                    "wx" => options.diagnostics.warnings_as_errors = !is_no(value),
+                    "ignore" => {
+                        for code in required(&arg, value)?.split(',') {
+                            let Ok(code) = code.parse() else {
+                                return fatal(
+                                    Code::InvalidOption,
+                                    format!("invalid warning number {code} in {arg}"),
+                                );
+                            };
+                            options.diagnostics.ignored.insert(code);
+                        }
+                    }
+                    "v" | "vv" | "vvv" if arg.starts_with('-') => {
+                        options.logging.verbosity += name.len() as u8;
+                    }
+                    _ if arg.starts_with('/') => options.inputs.push(arg),
+                    _ => options.unknown.push(arg),
+                }
+            } else {
+                options.inputs.push(arg);
+            }
+        }
+
+        if options.gui_manifest.is_some() && options.link.subsystem != Subsystem::Windows {
+            return fatal(
+                Code::InvalidOption,
+                "--gui-manifest needs /SUBSYSTEM:WINDOWS",
+            );
+        }
+        if options.manifest_input.is_some() && options.manifest != Some(ManifestMode::Embed) {
+            return fatal(Code::InvalidOption, "/MANIFESTINPUT needs /MANIFEST:EMBED");
+        }
+        let sysroot_dirs = options.sysroot.library_dirs()?;
+        options.libpath.extend(sysroot_dirs);
+        if options.link.driver.is_some() && !subsystem_given {
+            options.link.subsystem = Subsystem::Native;
+        }
+        if options.manifest == Some(ManifestMode::Embed) {
+            if let Some(xml) = options.manifest_xml() {
+                options.link.resources.push(manifest_resource(xml));
+            }
+        } else if let Some(manifest) = options.gui_manifest {
+            options.link.resources.push(manifest.resource());
+        }
+        if let Some(info) = &options.version_info
+            && !options.inputs.is_empty()
+        {
+            let mut info = info.clone();
+            if let Some(file) = options.output_path().file_name() {
+                info.set_string("OriginalFilename", &file.to_string_lossy());
+            }
+            options.link.resources.push(info.resource()?);
+        }
+        if !options.inputs.is_empty() {
+            options.link.image_name = options.library.clone().or_else(|| {
+                options
+                    .output_path()
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            });
+            if options.debug {
+                let pdb = match &options.pdb {
+                    Some(pdb) => pdb.clone(),
+                    None => options.output_path().with_extension("pdb"),
+                };
+                options.link.debug.pdb = Some(pdb.to_string_lossy().into_owned());
+            }
+        }
+        Ok(options)
+    }
+
+    /// Looks for a library in the `/LIBPATH` directories, then in `LIB`.
+    /// DLLs are also looked for in the Windows system directory, so that
+    /// system DLLs can be linked against without their import libraries.
+    pub fn find_library(&self, name: &str) -> Option<PathBuf> {
+        let lib = std::env::var("LIB").unwrap_or_default();
+        let system = std::env::var_os("SystemRoot")
+            .filter(|_| {
+                Path::new(name)
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("dll"))
+            })
+            .map(|root| Path::new(&root).join("System32"));
+        self.libpath
+            .iter()
+            .cloned()
+            .chain(
+                lib.split(';')
+                    .filter(|dir| !dir.is_empty())
+                    .map(PathBuf::from),
+            )
+            .chain(system)
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+    }
+
+    /// Where to read an input from: the path as given if it exists, or else
+    /// a bare file name found with [`Options::find_library`].
+    pub fn input_path(&self, input: &str) -> String {
+        let path = Path::new(input);
+        if path.exists() || path.components().count() != 1 {
+            return input.to_owned();
+        }
+        self.find_library(input).map_or_else(
+            || input.to_owned(),
+            |path| path.to_string_lossy().into_owned(),
+        )
+    }
+
+    /// The application manifest for `/MANIFEST`: the `/MANIFESTINPUT`, or
+    /// one asking for the caller's privileges and visual styles.
+    pub fn manifest_xml(&self) -> Option<String> {
+        self.manifest?;
+        if let Some(xml) = &self.manifest_input {
+            return Some(xml.clone());
+        }
+        let features = self.gui_manifest.unwrap_or(ManifestOptions {
+            common_controls: true,
+            ..ManifestOptions::default()
+        });
+        let manifest = ManifestOptions {
+            uac: !self.no_manifest_uac,
+            ..features
+        };
+        Some(manifest.to_xml())
+    }
+
+    /// Where `/MANIFEST` writes the manifest next to the image.
+    pub fn manifest_path(&self) -> PathBuf {
+        let mut path = self.output_path().into_os_string();
+        path.push(".manifest");
+        path.into()
+    }
+
+    pub fn implib_path(&self) -> PathBuf {
+        match &self.implib {
+            Some(implib) => implib.clone(),
+            None => self.output_path().with_extension("lib"),
+        }
+    }
+
+    pub fn output_path(&self) -> PathBuf {
+        match &self.out {
+            Some(out) => out.clone(),
+            None => match &self.library {
+                Some(library) => library.into(),
+                None => {
+                    let extension = if self.link.subsystem.is_efi() {
+                        "efi"
+                    } else if self.link.driver.is_some() {
+                        "sys"
+                    } else if self.link.dll {
+                        "dll"
+                    } else {
+                        "exe"
+                    };
+                    Path::new(&self.inputs[0]).with_extension(extension)
+                }
+            },
+        }
+    }
+}
+
+fn required<'a>(arg: &str, value: Option<&'a str>) -> Result<&'a str> {
+    match value {
+        Some(value) if !value.is_empty() => Ok(value),
+        _ => fatal(Code::InvalidOption, format!("{arg} requires a value")),
+    }
+}
+
+fn read_text(path: &str) -> Result<String> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => Ok(text),
+        Err(err) => fatal(Code::CannotOpenInput, format!("cannot open {path}: {err}")),
+    }
+}
+
+fn read_data(path: &str) -> Result<Vec<u8>> {
+    match std::fs::read(path) {
+        Ok(data) => Ok(data),
+        Err(err) => fatal(Code::CannotOpenInput, format!("cannot open {path}: {err}")),
+    }
+}
+
+/// Parses the `major[.minor]` version of option `arg`.
+fn major_minor(arg: &str, value: &str) -> Result<(u16, u16)> {
+    match parse_major_minor(value) {
+        Some(version) => Ok(version),
+        None => fatal(
+            Code::InvalidOption,
+            format!("{arg}: invalid version {value}, expected major[.minor]"),
+        ),
+    }
+}
+
+/// Parses a number in decimal or, with a `0x` prefix, in hex, like link.exe.
+fn parse_number(value: &str) -> Option<u64> {
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+/// Whether the value of a `/FLAG[:NO]`-style option turns the flag off.
+fn is_no(value: Option<&str>) -> bool {
+    value.is_some_and(|value| value.eq_ignore_ascii_case("no"))
+}
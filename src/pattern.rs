@@ -0,0 +1,274 @@
+use color_eyre::Result;
+use winning_coff::diagnostics::{Code, fatal};
+
+/// A regular expression, limited to what's useful for symbol names: literals,
+/// `.`, classes like `[a-z_]` or `[^0-9]`, the `\d`, `\w` and `\s` escapes,
+/// the `*`, `+` and `?` repetitions, and the `^` and `$` anchors. Like grep,
+/// a pattern without anchors matches anywhere in the name. Alternation `|`,
+/// groups `(...)` and counted repetitions `{n,m}` aren't supported and are
+/// rejected rather than matched literally; escape them with `\` to match the
+/// character.
+pub struct Pattern {
+    nodes: Vec<Node>,
+    anchored_start: bool,
+    anchored_end: bool,
+}
+
+struct Node {
+    atom: Atom,
+    repeat: Repeat,
+}
+
+enum Atom {
+    Any,
+    Char(char),
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum Repeat {
+    One,
+    ZeroOrOne,
+    ZeroOrMore,
+    OneOrMore,
+}
+
+impl Atom {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Char(expected) => c == *expected,
+            Self::Class { negated, ranges } => {
+                ranges
+                    .iter()
+                    .any(|&(start, end)| (start..=end).contains(&c))
+                    != *negated
+            }
+        }
+    }
+
+    /// The class for `\d`, `\w` or `\s`, or the escaped character itself.
+    fn escape(c: char) -> Self {
+        let ranges = match c {
+            'd' => vec![('0', '9')],
+            'w' => vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')],
+            's' => vec![(' ', ' '), ('\t', '\r')],
+            _ => return Self::Char(c),
+        };
+        Self::Class {
+            negated: false,
+            ranges,
+        }
+    }
+}
+
+impl Pattern {
+    pub fn parse(pattern: &str) -> Result<Self> {
+        let invalid = |reason: &str| {
+            fatal(
+                Code::InvalidOption,
+                format!("invalid pattern {pattern}: {reason}"),
+            )
+        };
+        let mut chars = pattern.chars().peekable();
+        let anchored_start = chars.next_if_eq(&'^').is_some();
+        let mut nodes = Vec::<Node>::new();
+        let mut anchored_end = false;
+        while let Some(c) = chars.next() {
+            if anchored_end {
+                return invalid("`$` is only supported at the end");
+            }
+            let atom = match c {
+                '.' => Atom::Any,
+                '$' => {
+                    anchored_end = true;
+                    continue;
+                }
+                '\\' => match chars.next() {
+                    Some(c) => Atom::escape(c),
+                    None => return invalid("trailing `\\`"),
+                },
+                '[' => {
+                    let negated = chars.next_if_eq(&'^').is_some();
+                    let mut ranges = Vec::new();
+                    loop {
+                        let start = match chars.next() {
+                            Some(']') if !ranges.is_empty() => break,
+                            Some('\\') => chars.next(),
+                            other => other,
+                        };
+                        let Some(start) = start else {
+                            return invalid("unterminated `[`");
+                        };
+                        let end = match chars.next_if_eq(&'-') {
+                            Some(_) if chars.peek() == Some(&']') => {
+                                ranges.push(('-', '-'));
+                                start
+                            }
+                            Some(_) => match chars.next() {
+                                Some(end) if end >= start => end,
+                                _ => return invalid("bad range in `[`"),
+                            },
+                            None => start,
+                        };
+                        ranges.push((start, end));
+                    }
+                    Atom::Class { negated, ranges }
+                }
+                '*' | '+' | '?' => {
+                    let repeat = match c {
+                        '*' => Repeat::ZeroOrMore,
+                        '+' => Repeat::OneOrMore,
+                        _ => Repeat::ZeroOrOne,
+                    };
+                    match nodes.last_mut() {
+                        Some(node) if matches!(node.repeat, Repeat::One) => node.repeat = repeat,
+                        _ => return invalid("nothing to repeat"),
+                    }
+                    continue;
+                }
+                '|' => return invalid("alternation `|` is not supported"),
+                '(' | ')' => return invalid("groups `(...)` are not supported"),
+                '{' | '}' => return invalid("counted repetitions `{n,m}` are not supported"),
+                c => Atom::Char(c),
+            };
+            nodes.push(Node {
+                atom,
+                repeat: Repeat::One,
+            });
+        }
+        Ok(Self {
+            nodes,
+            anchored_start,
+            anchored_end,
+        })
+    }
+
+    /// Simulates the nodes as an NFA whose states are positions in the node
+    /// list, so matching takes time linear in the length of the text for
+    /// any pattern.
+    pub fn is_match(&self, text: &str) -> bool {
+        let accept = self.nodes.len();
+        let mut states = vec![false; accept + 1];
+        self.enter(&mut states, 0);
+        for c in text.chars() {
+            if states[accept] && !self.anchored_end {
+                return true;
+            }
+            let mut next = vec![false; accept + 1];
+            for (idx, node) in self.nodes.iter().enumerate() {
+                if !states[idx] || !node.atom.matches(c) {
+                    continue;
+                }
+                if matches!(node.repeat, Repeat::ZeroOrMore | Repeat::OneOrMore) {
+                    next[idx] = true;
+                }
+                self.enter(&mut next, idx + 1);
+            }
+            // Like grep, an unanchored pattern may start anywhere.
+            if !self.anchored_start {
+                self.enter(&mut next, 0);
+            }
+            states = next;
+        }
+        states[accept]
+    }
+
+    /// Adds the state before node `idx` to `states`, and those after the
+    /// optional nodes that follow it, which can be skipped.
+    fn enter(&self, states: &mut [bool], mut idx: usize) {
+        loop {
+            states[idx] = true;
+            match self.nodes.get(idx) {
+                Some(node) if matches!(node.repeat, Repeat::ZeroOrOne | Repeat::ZeroOrMore) => {
+                    idx += 1;
+                }
+                _ => return,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, text: &str) -> bool {
+        Pattern::parse(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn literals_and_repetitions() {
+        assert!(is_match("Init", "?Init@Foo@@QEAAXXZ"));
+        assert!(!is_match("Init", "initialize"));
+        assert!(is_match("ab*c", "ac"));
+        assert!(is_match("ab*c", "abbbc"));
+        assert!(!is_match("ab+c", "ac"));
+        assert!(is_match("ab+c", "abbc"));
+        assert!(is_match("colou?r", "color"));
+        assert!(is_match("colou?r", "colour"));
+        assert!(!is_match("colou?r", "colouur"));
+        assert!(is_match("a.c", "abc"));
+        assert!(is_match("", "anything"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(is_match("^[a-z_]+$", "do_thing"));
+        assert!(!is_match("^[a-z_]+$", "DoThing"));
+        assert!(is_match("^[^0-9]+$", "name"));
+        assert!(!is_match("^[^0-9]+$", "name2"));
+        // A trailing `-` and a leading `]` are literal.
+        assert!(is_match("^[a-]+$", "a-a"));
+        assert!(!is_match("^[a-]+$", "ab"));
+        assert!(is_match("^[]]$", "]"));
+        assert!(is_match("^[]a]+$", "]a]"));
+        assert!(is_match("[\\]]", "x]"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(is_match("^\\d+$", "2024"));
+        assert!(!is_match("^\\d+$", "20x4"));
+        assert!(is_match("^\\w+$", "snake_Case9"));
+        assert!(is_match("a\\sb", "a\tb"));
+        assert!(is_match("^\\.text\\$mn$", ".text$mn"));
+        assert!(!is_match("^\\.text$", "xtext"));
+        assert!(is_match("operator\\(\\)", "operator()"));
+        assert!(is_match("\\*", "a*b"));
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(is_match("^main", "mainCRTStartup"));
+        assert!(!is_match("^main", "wmain"));
+        assert!(is_match("Startup$", "mainCRTStartup"));
+        assert!(!is_match("Startup$", "StartupOrder"));
+        assert!(is_match("^main$", "main"));
+        assert!(!is_match("^main$", "mains"));
+        assert!(is_match("^a*$", ""));
+        assert!(Pattern::parse("a$b").is_err());
+    }
+
+    #[test]
+    fn rejects_unsupported() {
+        for pattern in [
+            "a|b", "(ab)", "a)", "a{2}", "a}", "*a", "a**", "[abc", "[z-a]", "a\\",
+        ] {
+            assert!(Pattern::parse(pattern).is_err(), "{pattern}");
+        }
+    }
+
+    #[test]
+    fn linear_time() {
+        let name = "x".repeat(300);
+        assert!(!is_match(".*x.*x.*x.*y", &name));
+        assert!(!is_match("a*a*a*a*a*a*a*b", &"a".repeat(60)));
+        assert!(is_match("a*a*a*a*a*a*a*b", &format!("{}b", "a".repeat(60))));
+        let long = format!("?{}@@YAXXZ", "Template".repeat(1000));
+        assert!(is_match("^\\?.*Template.*@@YAXXZ$", &long));
+    }
+}
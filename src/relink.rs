@@ -0,0 +1,122 @@
+use std::path::Path;
+
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use winning_coff::diagnostics::{Code, fatal};
+use winning_pe::{debug::DebugOptions, input::Input};
+
+use crate::{
+    hash::{self, ContentHash, HashAlgorithm},
+    options::Options,
+};
+
+/// What `--link-record` keeps about a link, so that relinking the same inputs
+/// reproduces it byte for byte even when the build system passes them in
+/// another order or from another directory. Everything else that goes into
+/// the output only depends on the inputs' contents.
+#[derive(Serialize, Deserialize)]
+pub struct LinkRecord {
+    /// Also used for the build id, which the PDB GUID and REPRO hash come from.
+    pub hash_algorithm: String,
+    /// In the order they were linked, which decides symbol resolution and layout.
+    pub inputs: Vec<RecordedInput>,
+    /// The PDB path recorded in the image.
+    pub pdb: Option<String>,
+    pub output: ContentHash,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RecordedInput {
+    /// The path as given, which ends up in the PDB's module list.
+    pub name: String,
+    pub hash: ContentHash,
+}
+
+impl LinkRecord {
+    /// Reads the record at `path`, if there is one yet.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return fatal(
+                    Code::CannotOpenInput,
+                    format!("cannot open {}: {err}", path.display()),
+                );
+            }
+        };
+        match serde_json::from_str(&text) {
+            Ok(record) => Ok(Some(record)),
+            Err(err) => fatal(
+                Code::CorruptInput,
+                format!("{} is not a link record: {err}", path.display()),
+            ),
+        }
+    }
+
+    pub fn new(options: &Options, inputs: &[Input], output: &[u8]) -> Self {
+        Self {
+            hash_algorithm: options.hash_algorithm.name().to_owned(),
+            inputs: inputs
+                .iter()
+                .map(|input| RecordedInput {
+                    name: input.name.clone(),
+                    hash: hash::content_hash(options.hash_algorithm, input.data()),
+                })
+                .collect(),
+            pdb: options.link.debug.pdb.clone(),
+            output: hash::content_hash(options.hash_algorithm, output),
+        }
+    }
+
+    /// If `inputs` are the recorded ones, puts them back in the recorded
+    /// order under their recorded names and links with the recorded PDB path
+    /// and hash algorithm. Returns whether they were.
+    pub fn replay(
+        &self,
+        inputs: &mut Vec<Input>,
+        hash_algorithm: &mut HashAlgorithm,
+        debug: &mut DebugOptions,
+    ) -> bool {
+        let Some(algorithm) = HashAlgorithm::from_name(&self.hash_algorithm) else {
+            info!(
+                "link record uses hash algorithm {}, which isn't available",
+                self.hash_algorithm
+            );
+            return false;
+        };
+        if inputs.len() != self.inputs.len() {
+            info!("inputs were added or removed since the link was recorded");
+            return false;
+        }
+        let hashes = inputs
+            .iter()
+            .map(|input| hash::content_hash(algorithm, input.data()))
+            .collect::<Vec<_>>();
+        let mut used = vec![false; inputs.len()];
+        let mut order = Vec::with_capacity(inputs.len());
+        for recorded in &self.inputs {
+            let Some(idx) =
+                (0..hashes.len()).find(|&idx| !used[idx] && hashes[idx] == recorded.hash)
+            else {
+                info!("{} changed since the link was recorded", recorded.name);
+                return false;
+            };
+            used[idx] = true;
+            order.push(idx);
+        }
+
+        let mut unordered = inputs.drain(..).map(Some).collect::<Vec<_>>();
+        for (idx, recorded) in order.into_iter().zip(&self.inputs) {
+            let mut input = unordered[idx].take().expect("inputs are matched once");
+            input.name.clone_from(&recorded.name);
+            inputs.push(input);
+        }
+        *hash_algorithm = algorithm;
+        if debug.pdb.is_some() && self.pdb.is_some() {
+            debug.pdb.clone_from(&self.pdb);
+        }
+        true
+    }
+}
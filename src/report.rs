@@ -0,0 +1,24 @@
+use serde::Serialize;
+
+use crate::hash::ContentHash;
+
+/// Machine-readable summary of a link, written with `--report`.
+#[derive(Serialize)]
+pub struct Report {
+    pub hash_algorithm: &'static str,
+    pub inputs: Vec<InputReport>,
+}
+
+#[derive(Serialize)]
+pub struct InputReport {
+    pub path: String,
+    pub sections: Vec<SectionReport>,
+}
+
+#[derive(Serialize)]
+pub struct SectionReport {
+    pub name: String,
+    pub size: u32,
+    pub characteristics: u32,
+    pub hash: ContentHash,
+}
@@ -0,0 +1,108 @@
+use std::{io::Write, path::PathBuf};
+
+use color_eyre::Result;
+use winning_coff::diagnostics::{Code, DiagnosticOptions, Diagnostics, fatal};
+use winning_pe::{keys::SigningIdentity, sign::sign};
+
+const USAGE: &str = "usage: winning sign <image> (--pfx=<file> | --key=<pem> [--cert=<pem>]...) \
+     [--password-env=<variable>] [/OUT:<path>]";
+
+/// `winning sign <image> <key> [/OUT:<path>]`: signs an image with
+/// Authenticode, in place unless `/OUT` is given. The key and certificates
+/// come from a PKCS#12 file or from PEM files, and the password is read from
+/// an environment variable so that it stays out of CI logs and process lists.
+pub fn run(args: Vec<String>, out: &mut dyn Write) -> Result<()> {
+    let mut output = None;
+    let mut pfx = None;
+    let mut pem_files = Vec::new();
+    let mut password_variable = None;
+    let mut paths = Vec::new();
+    for arg in args {
+        if let Some(path) = arg.strip_prefix("--pfx=") {
+            pfx = Some(path.to_owned());
+        } else if let Some(path) = arg.strip_prefix("--key=") {
+            pem_files.push(path.to_owned());
+        } else if let Some(path) = arg.strip_prefix("--cert=") {
+            pem_files.push(path.to_owned());
+        } else if let Some(variable) = arg.strip_prefix("--password-env=") {
+            password_variable = Some(variable.to_owned());
+        } else if arg.starts_with("--") {
+            return fatal(
+                Code::InvalidOption,
+                format!("unknown sign option {arg}\n{USAGE}"),
+            );
+        } else {
+            match arg.split_once(':') {
+                Some((flag, value))
+                    if flag.eq_ignore_ascii_case("/out") || flag.eq_ignore_ascii_case("-out") =>
+                {
+                    output = Some(PathBuf::from(value));
+                }
+                _ => paths.push(arg),
+            }
+        }
+    }
+    let [image_path] = paths.as_slice() else {
+        return fatal(Code::InvalidOption, USAGE);
+    };
+    if pfx.is_some() != pem_files.is_empty() {
+        return fatal(
+            Code::InvalidOption,
+            format!("sign with either --pfx or --key\n{USAGE}"),
+        );
+    }
+    let password = match &password_variable {
+        Some(variable) => match std::env::var(variable) {
+            Ok(password) => Some(password),
+            Err(err) => {
+                return fatal(
+                    Code::InvalidOption,
+                    format!("cannot read the password from {variable}: {err}"),
+                );
+            }
+        },
+        None => None,
+    };
+
+    let read = |path: &str| match std::fs::read(path) {
+        Ok(data) => Ok(data),
+        Err(err) => fatal(Code::CannotOpenInput, format!("cannot open {path}: {err}")),
+    };
+    let identity = match &pfx {
+        Some(path) => {
+            SigningIdentity::from_pkcs12(&read(path)?, password.as_deref().unwrap_or_default())?
+        }
+        None => {
+            let mut text = String::new();
+            for path in &pem_files {
+                match String::from_utf8(read(path)?) {
+                    Ok(contents) => text.push_str(&contents),
+                    Err(_) => {
+                        return fatal(Code::CorruptInput, format!("{path}: not a PEM file"));
+                    }
+                }
+                text.push('\n');
+            }
+            SigningIdentity::from_pem(&text, password.as_deref())?
+        }
+    };
+
+    let data = read(image_path)?;
+    let options = DiagnosticOptions::default();
+    let mut diag = Diagnostics::new(&options, out);
+    let image = sign(&data, &identity, &mut diag)?;
+    let path = output.unwrap_or_else(|| image_path.into());
+    if let Err(err) = std::fs::write(&path, image) {
+        return fatal(
+            Code::CannotOpenOutput,
+            format!("cannot write {}: {err}", path.display()),
+        );
+    }
+    diag.finish()?;
+
+    match identity.signer().common_name {
+        Some(name) => writeln!(out, "{}: signed by {name}", path.display())?,
+        None => writeln!(out, "{}: signed", path.display())?,
+    }
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use std::{io::Write, path::PathBuf};
+
+use color_eyre::Result;
+use winning_coff::diagnostics::{Code, DiagnosticOptions, Diagnostics, fatal};
+use winning_pe::strip::strip;
+
+const USAGE: &str = "usage: winning strip <image> [/OUT:<path>] [--checksum]";
+
+/// `winning strip <image> [/OUT:<path>] [--checksum]`: removes the debug
+/// directory, the symbol table and DWARF sections from an image, in place
+/// unless `/OUT` is given. `--checksum` computes a checksum even if the image
+/// had none.
+pub fn run(args: Vec<String>, out: &mut dyn Write) -> Result<()> {
+    let mut output = None;
+    let mut checksum = false;
+    let mut paths = Vec::new();
+    for arg in args {
+        match arg.split_once(':') {
+            Some((flag, value))
+                if flag.eq_ignore_ascii_case("/out") || flag.eq_ignore_ascii_case("-out") =>
+            {
+                output = Some(PathBuf::from(value));
+            }
+            _ if arg == "--checksum" => checksum = true,
+            _ if arg.starts_with("--") => {
+                return fatal(
+                    Code::InvalidOption,
+                    format!("unknown strip option {arg}\n{USAGE}"),
+                );
+            }
+            _ => paths.push(arg),
+        }
+    }
+    let [image_path] = paths.as_slice() else {
+        return fatal(Code::InvalidOption, USAGE);
+    };
+
+    let data = match std::fs::read(image_path) {
+        Ok(data) => data,
+        Err(err) => {
+            return fatal(
+                Code::CannotOpenInput,
+                format!("cannot open {image_path}: {err}"),
+            );
+        }
+    };
+    let options = DiagnosticOptions::default();
+    let mut diag = Diagnostics::new(&options, out);
+    let (image, stripped) = strip(&data, checksum, &mut diag)?;
+    let path = output.unwrap_or_else(|| image_path.into());
+    if let Err(err) = std::fs::write(&path, image) {
+        return fatal(
+            Code::CannotOpenOutput,
+            format!("cannot write {}: {err}", path.display()),
+        );
+    }
+    diag.finish()?;
+
+    let mut removed = Vec::new();
+    if stripped.debug_entries > 0 {
+        removed.push(format!(
+            "{} debug directory entries",
+            stripped.debug_entries
+        ));
+    }
+    if stripped.symbols {
+        removed.push("the symbol table".to_owned());
+    }
+    removed.extend(stripped.sections);
+    if removed.is_empty() {
+        writeln!(out, "{image_path}: nothing to strip")?;
+    } else {
+        writeln!(out, "{image_path}: removed {}", removed.join(", "))?;
+    }
+
+    Ok(())
+}
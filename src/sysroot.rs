@@ -0,0 +1,82 @@
+//! The library directories of an MSVC and Windows SDK installation, for
+//! `/WINSYSROOT`, `/VCTOOLSDIR` and `/WINSDKDIR` like lld-link, so that a
+//! link can find the CRT and SDK libraries without `LIB`, for example from a
+//! copy of the installation on Linux.
+
+use std::path::{Path, PathBuf};
+
+use color_eyre::Result;
+use winning_coff::diagnostics::{Code, fatal};
+
+#[derive(Debug, Clone, Default)]
+pub struct Sysroot {
+    /// `/WINSYSROOT`, containing `VC/Tools/MSVC` and `Windows Kits/10`.
+    pub root: Option<PathBuf>,
+    /// `/VCTOOLSDIR`, one version of the MSVC tools, like
+    /// `VC/Tools/MSVC/14.38.33130`.
+    pub vc_tools: Option<PathBuf>,
+    /// `/WINSDKDIR`, like `Windows Kits/10`.
+    pub sdk: Option<PathBuf>,
+}
+
+impl Sysroot {
+    /// The x64 library directories: the CRT, then the UCRT and the SDK's
+    /// umbrella libraries. Without a version, the newest one is used.
+    pub fn library_dirs(&self) -> Result<Vec<PathBuf>> {
+        let mut dirs = Vec::new();
+        let vc_tools = match (&self.vc_tools, &self.root) {
+            (Some(dir), _) => Some(dir.clone()),
+            (None, Some(root)) => Some(newest_version(&root.join("VC/Tools/MSVC"))?),
+            (None, None) => None,
+        };
+        if let Some(vc_tools) = vc_tools {
+            dirs.push(vc_tools.join("lib/x64"));
+            dirs.push(vc_tools.join("atlmfc/lib/x64"));
+        }
+        let sdk = match (&self.sdk, &self.root) {
+            (Some(dir), _) => Some(dir.clone()),
+            (None, Some(root)) => Some(root.join("Windows Kits/10")),
+            (None, None) => None,
+        };
+        if let Some(sdk) = sdk {
+            let lib = newest_version(&sdk.join("Lib"))?;
+            dirs.push(lib.join("ucrt/x64"));
+            dirs.push(lib.join("um/x64"));
+        }
+        Ok(dirs)
+    }
+}
+
+/// The subdirectory of `dir` named like the highest version, like
+/// `10.0.22621.0`.
+fn newest_version(dir: &Path) -> Result<PathBuf> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            return fatal(
+                Code::InvalidOption,
+                format!("cannot read {}: {err}", dir.display()),
+            );
+        }
+    };
+    let newest = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let version = entry
+                .file_name()
+                .to_str()?
+                .split('.')
+                .map(str::parse)
+                .collect::<Result<Vec<u32>, _>>()
+                .ok()?;
+            entry.path().is_dir().then(|| (version, entry.path()))
+        })
+        .max();
+    match newest {
+        Some((_, path)) => Ok(path),
+        None => fatal(
+            Code::InvalidOption,
+            format!("no versions found in {}", dir.display()),
+        ),
+    }
+}
@@ -0,0 +1,51 @@
+use std::{io::Write, path::PathBuf};
+
+use color_eyre::Result;
+use winning_coff::diagnostics::{Code, DiagnosticOptions, Diagnostics, fatal};
+use winning_pe::{resources::parse_res, update::update_resources};
+
+/// `winning update-resources <image> <file.res>... [/OUT:<path>]`: replaces or
+/// adds the resources from the `.res` files in the image, in place unless
+/// `/OUT` is given.
+pub fn run(args: Vec<String>, out: &mut dyn Write) -> Result<()> {
+    let mut output = None;
+    let mut files = Vec::new();
+    for arg in args {
+        match arg.split_once(':') {
+            Some((flag, value))
+                if flag.eq_ignore_ascii_case("/out") || flag.eq_ignore_ascii_case("-out") =>
+            {
+                output = Some(PathBuf::from(value));
+            }
+            _ => files.push(arg),
+        }
+    }
+    let Some((image_path, res_paths)) = files.split_first() else {
+        return fatal(
+            Code::InvalidOption,
+            "usage: winning update-resources <image> <file.res>... [/OUT:<path>]",
+        );
+    };
+
+    let read = |path: &String| match std::fs::read(path) {
+        Ok(data) => Ok(data),
+        Err(err) => fatal(Code::CannotOpenInput, format!("cannot open {path}: {err}")),
+    };
+    let mut resources = Vec::new();
+    for path in res_paths {
+        resources.extend(parse_res(&read(path)?)?);
+    }
+
+    let options = DiagnosticOptions::default();
+    let mut diag = Diagnostics::new(&options, out);
+    let image = update_resources(&read(image_path)?, resources, &mut diag)?;
+
+    let path = output.unwrap_or_else(|| image_path.into());
+    if let Err(err) = std::fs::write(&path, image) {
+        return fatal(
+            Code::CannotOpenOutput,
+            format!("cannot write {}: {err}", path.display()),
+        );
+    }
+    diag.finish()
+}
@@ -0,0 +1,38 @@
+use std::io::Write;
+
+use color_eyre::Result;
+use winning_coff::diagnostics::{Code, fatal};
+use winning_pe::{image::ImageModel, input::Input, pe::DOS_MAGIC, verify::verify};
+
+/// `winning verify <images>...`: checks the images against the rules of the
+/// loader, see [`verify`], and prints what each one breaks.
+pub fn run(args: Vec<String>, out: &mut dyn Write) -> Result<()> {
+    if args.is_empty() || args.iter().any(|arg| arg.starts_with("--")) {
+        return fatal(Code::InvalidOption, "usage: winning verify <images>...");
+    }
+    let mut invalid = 0;
+    for path in &args {
+        let input = Input::open(path)?;
+        let data = input.data();
+        if !data.starts_with(DOS_MAGIC) {
+            return fatal(Code::CorruptInput, format!("{path}: not a PE image"));
+        }
+        let failures = verify(&ImageModel::parse(data)?);
+        if failures.is_empty() {
+            writeln!(out, "{}: ok", input.name)?;
+            continue;
+        }
+        invalid += 1;
+        writeln!(out, "{}:", input.name)?;
+        for failure in failures {
+            writeln!(out, "  {failure}")?;
+        }
+    }
+    if invalid > 0 {
+        return fatal(
+            Code::InvalidImage,
+            format!("{invalid} of {} images failed verification", args.len()),
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,79 @@
+//! Persistent worker mode, speaking the JSON flavor of the Bazel worker
+//! protocol: one `WorkRequest` object per line on stdin, one `WorkResponse`
+//! per line on stdout. Build systems keep the process alive and send it links.
+
+use std::io::{self, BufRead, Write};
+
+use color_eyre::{Result, eyre::Context};
+use serde::{Deserialize, Serialize};
+
+use crate::interrupt;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WorkRequest {
+    #[serde(default)]
+    arguments: Vec<String>,
+    #[serde(default)]
+    request_id: i32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WorkResponse {
+    exit_code: i32,
+    output: String,
+    request_id: i32,
+}
+
+pub fn is_worker_invocation(args: &[String]) -> bool {
+    args.iter()
+        .any(|arg| arg == "--persistent-worker" || arg == "--persistent_worker")
+}
+
+pub fn run(link: impl Fn(Vec<String>, &mut dyn Write) -> Result<()>) -> Result<()> {
+    let stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+
+    for line in stdin.lines() {
+        let line = line.wrap_err("reading work request")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<WorkRequest>(&line) {
+            Ok(request) => {
+                let mut output = Vec::new();
+                let result = link(request.arguments, &mut output);
+                let mut output = String::from_utf8_lossy(&output).into_owned();
+                let exit_code = match result {
+                    Ok(()) => 0,
+                    Err(err) => {
+                        output.push_str(&format!("error: {err:#}\n"));
+                        1
+                    }
+                };
+                WorkResponse {
+                    exit_code,
+                    output,
+                    request_id: request.request_id,
+                }
+            }
+            Err(err) => WorkResponse {
+                exit_code: 1,
+                output: format!("error: invalid work request: {err}\n"),
+                request_id: 0,
+            },
+        };
+
+        serde_json::to_writer(&mut stdout, &response)?;
+        stdout.write_all(b"\n")?;
+        stdout.flush()?;
+        // After Ctrl-C, finish the cancelled request but don't take new ones.
+        if interrupt::token().is_cancelled() {
+            break;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,402 @@
+use std::{path::PathBuf, process::Command};
+
+const IMAGE_SCN_CNT_CODE: u32 = 0x20;
+const IMAGE_SCN_CNT_INITIALIZED_DATA: u32 = 0x40;
+const IMAGE_SCN_CNT_UNINITIALIZED_DATA: u32 = 0x80;
+const IMAGE_SCN_ALIGN_16BYTES: u32 = 0x0050_0000;
+const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+const IMAGE_SCN_MEM_READ: u32 = 0x4000_0000;
+const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+const IMAGE_REL_AMD64_ADDR64: u16 = 1;
+const IMAGE_REL_AMD64_REL32: u16 = 4;
+
+struct Section {
+    name: &'static str,
+    characteristics: u32,
+    /// Only the length counts for uninitialized sections, which have no
+    /// data in the file.
+    data: Vec<u8>,
+    /// Offset, symbol index and type.
+    relocations: Vec<(u32, u32, u16)>,
+}
+
+/// Name, value, 1-based section number (0 for undefined) and storage class.
+type Symbol = (&'static str, u32, u16, u8);
+
+/// Writes a minimal x86-64 COFF object.
+fn object(sections: &[Section], symbols: &[Symbol]) -> Vec<u8> {
+    let headers_size = 20 + 40 * sections.len();
+    let mut contents = Vec::new();
+    let mut section_headers = Vec::new();
+    for section in sections {
+        let uninitialized = section.characteristics & IMAGE_SCN_CNT_UNINITIALIZED_DATA != 0;
+        let data_offset = if uninitialized {
+            0
+        } else {
+            let offset = headers_size + contents.len();
+            contents.extend_from_slice(&section.data);
+            offset
+        };
+        let relocations_offset = headers_size + contents.len();
+        for &(offset, symbol, kind) in &section.relocations {
+            contents.extend_from_slice(&offset.to_le_bytes());
+            contents.extend_from_slice(&symbol.to_le_bytes());
+            contents.extend_from_slice(&kind.to_le_bytes());
+        }
+
+        let mut name = [0; 8];
+        name[..section.name.len()].copy_from_slice(section.name.as_bytes());
+        section_headers.extend_from_slice(&name);
+        section_headers.extend_from_slice(&[0; 8]);
+        section_headers.extend_from_slice(&(section.data.len() as u32).to_le_bytes());
+        section_headers.extend_from_slice(&(data_offset as u32).to_le_bytes());
+        section_headers.extend_from_slice(&(relocations_offset as u32).to_le_bytes());
+        section_headers.extend_from_slice(&0u32.to_le_bytes());
+        section_headers.extend_from_slice(&(section.relocations.len() as u16).to_le_bytes());
+        section_headers.extend_from_slice(&0u16.to_le_bytes());
+        section_headers.extend_from_slice(&section.characteristics.to_le_bytes());
+    }
+
+    let mut strings = Vec::new();
+    let mut symbol_table = Vec::new();
+    for &(name, value, section, class) in symbols {
+        if name.len() <= 8 {
+            let mut short = [0; 8];
+            short[..name.len()].copy_from_slice(name.as_bytes());
+            symbol_table.extend_from_slice(&short);
+        } else {
+            symbol_table.extend_from_slice(&0u32.to_le_bytes());
+            symbol_table.extend_from_slice(&(4 + strings.len() as u32).to_le_bytes());
+            strings.extend_from_slice(name.as_bytes());
+            strings.push(0);
+        }
+        symbol_table.extend_from_slice(&value.to_le_bytes());
+        symbol_table.extend_from_slice(&section.to_le_bytes());
+        symbol_table.extend_from_slice(&0u16.to_le_bytes());
+        symbol_table.extend_from_slice(&[class, 0]);
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&0x8664u16.to_le_bytes());
+    out.extend_from_slice(&(sections.len() as u16).to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&((headers_size + contents.len()) as u32).to_le_bytes());
+    out.extend_from_slice(&(symbols.len() as u32).to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&section_headers);
+    out.extend_from_slice(&contents);
+    out.extend_from_slice(&symbol_table);
+    out.extend_from_slice(&(4 + strings.len() as u32).to_le_bytes());
+    out.extend_from_slice(&strings);
+    out
+}
+
+/// Writes a short import object for a code import by name.
+fn import_object(symbol: &str, dll: &str) -> Vec<u8> {
+    let names = format!("{symbol}\0{dll}\0");
+    let mut out = vec![0, 0, 0xff, 0xff, 0, 0];
+    out.extend_from_slice(&0x8664u16.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&(names.len() as u32).to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    // IMPORT_OBJECT_CODE, IMPORT_OBJECT_NAME
+    out.extend_from_slice(&(1u16 << 2).to_le_bytes());
+    out.extend_from_slice(names.as_bytes());
+    out
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("winning-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn link(dir: &PathBuf, inputs: &[(&str, Vec<u8>)]) -> Vec<u8> {
+    link_with(dir, inputs, &[])
+}
+
+fn link_with(dir: &PathBuf, inputs: &[(&str, Vec<u8>)], options: &[&str]) -> Vec<u8> {
+    let mut args = vec!["--self-check".to_owned(), "/OUT:out.exe".to_owned()];
+    args.extend(options.iter().map(|&option| option.to_owned()));
+    for (name, data) in inputs {
+        std::fs::write(dir.join(name), data).unwrap();
+        args.push((*name).to_owned());
+    }
+    let output = Command::new(env!("CARGO_BIN_EXE_winning"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "link failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    std::fs::read(dir.join("out.exe")).unwrap()
+}
+
+#[test]
+fn imports_and_base_relocations() {
+    // call [rip + __imp_ExitProcess]; call ExitProcess; ret
+    let text = Section {
+        name: ".text",
+        characteristics: IMAGE_SCN_CNT_CODE
+            | IMAGE_SCN_ALIGN_16BYTES
+            | IMAGE_SCN_MEM_EXECUTE
+            | IMAGE_SCN_MEM_READ,
+        data: vec![0xff, 0x15, 0, 0, 0, 0, 0xe8, 0, 0, 0, 0, 0xc3],
+        relocations: vec![(2, 1, IMAGE_REL_AMD64_REL32), (7, 2, IMAGE_REL_AMD64_REL32)],
+    };
+    let data = Section {
+        name: ".data",
+        characteristics: IMAGE_SCN_CNT_INITIALIZED_DATA
+            | IMAGE_SCN_ALIGN_16BYTES
+            | IMAGE_SCN_MEM_READ
+            | IMAGE_SCN_MEM_WRITE,
+        data: vec![0; 8],
+        relocations: vec![(0, 0, IMAGE_REL_AMD64_ADDR64)],
+    };
+    let symbols = [
+        ("mainCRTStartup", 0, 1, 2),
+        ("__imp_ExitProcess", 0, 0, 2),
+        ("ExitProcess", 0, 0, 2),
+    ];
+
+    let dir = temp_dir("imports");
+    let image = link(
+        &dir,
+        &[
+            ("main.obj", object(&[text, data], &symbols)),
+            (
+                "ExitProcess.imp",
+                import_object("ExitProcess", "kernel32.dll"),
+            ),
+        ],
+    );
+    assert_eq!(&image[..2], b"MZ");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn code_only() {
+    let text = Section {
+        name: ".text",
+        characteristics: IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ,
+        data: vec![0xc3],
+        relocations: Vec::new(),
+    };
+    let dir = temp_dir("code-only");
+    link(
+        &dir,
+        &[("main.obj", object(&[text], &[("mainCRTStartup", 0, 1, 2)]))],
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn bss_merged_into_data() {
+    let text = Section {
+        name: ".text",
+        characteristics: IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ,
+        data: vec![0xc3],
+        relocations: Vec::new(),
+    };
+    let data = Section {
+        name: ".data",
+        characteristics: IMAGE_SCN_CNT_INITIALIZED_DATA
+            | IMAGE_SCN_ALIGN_16BYTES
+            | IMAGE_SCN_MEM_READ
+            | IMAGE_SCN_MEM_WRITE,
+        data: vec![0xaa; 16],
+        relocations: Vec::new(),
+    };
+    let bss = Section {
+        name: ".bss",
+        characteristics: IMAGE_SCN_CNT_UNINITIALIZED_DATA
+            | IMAGE_SCN_ALIGN_16BYTES
+            | IMAGE_SCN_MEM_READ
+            | IMAGE_SCN_MEM_WRITE,
+        data: vec![0; 64],
+        relocations: Vec::new(),
+    };
+    let symbols = [
+        ("mainCRTStartup", 0, 1, 2),
+        ("value", 0, 2, 2),
+        ("buffer", 0, 3, 2),
+    ];
+
+    let dir = temp_dir("bss-merge");
+    let image = link_with(
+        &dir,
+        &[("main.obj", object(&[text, data, bss], &symbols))],
+        &["/MERGE:.bss=.data"],
+    );
+    // The zeroed `.bss` follows the initialized data in the file.
+    let start = image
+        .windows(16)
+        .position(|window| window == [0xaa; 16])
+        .unwrap();
+    assert!(image[start + 16..start + 80].iter().all(|&byte| byte == 0));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn exports_by_ordinal_only() {
+    let text = Section {
+        name: ".text",
+        characteristics: IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ,
+        data: vec![0xc3; 3],
+        relocations: Vec::new(),
+    };
+    let symbols = [
+        ("_DllMainCRTStartup", 0, 1, 2),
+        ("foo", 1, 1, 2),
+        ("bar", 2, 1, 2),
+    ];
+
+    let dir = temp_dir("exports");
+    std::fs::write(
+        dir.join("b.def"),
+        "LIBRARY b\nEXPORTS\n    foo @3\n    bar @1 NONAME\n    baz=foo\n",
+    )
+    .unwrap();
+    link_with(
+        &dir,
+        &[("dll.obj", object(&[text], &symbols))],
+        &["/DEF:b.def"],
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_winning"))
+        .args(["exports", "out.exe"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let listing = String::from_utf8(output.stdout).unwrap();
+    let rows = listing
+        .lines()
+        .skip(2)
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        [
+            vec!["1", "0x00001002", "(by", "ordinal)"],
+            vec!["3", "0x00001001", "foo"],
+            vec!["4", "0x00001001", "baz"],
+        ]
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn disassembly() {
+    // call [rip + __imp_ExitProcess]; call ExitProcess; ret
+    let text = Section {
+        name: ".text",
+        characteristics: IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ,
+        data: vec![0xff, 0x15, 0, 0, 0, 0, 0xe8, 0, 0, 0, 0, 0xc3],
+        relocations: vec![(2, 1, IMAGE_REL_AMD64_REL32), (7, 2, IMAGE_REL_AMD64_REL32)],
+    };
+    let symbols = [
+        ("mainCRTStartup", 0, 1, 2),
+        ("__imp_ExitProcess", 0, 0, 2),
+        ("ExitProcess", 0, 0, 2),
+    ];
+
+    let dir = temp_dir("disasm");
+    link(
+        &dir,
+        &[
+            ("main.obj", object(&[text], &symbols)),
+            (
+                "ExitProcess.imp",
+                import_object("ExitProcess", "kernel32.dll"),
+            ),
+        ],
+    );
+    let disassemble = |input: &str| {
+        let output = Command::new(env!("CARGO_BIN_EXE_winning"))
+            .args(["dump", "--disasm", input])
+            .current_dir(&dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .skip(2)
+            .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect::<Vec<_>>()
+    };
+
+    // Objects get the relocations, ...
+    assert_eq!(
+        disassemble("main.obj"),
+        [
+            "mainCRTStartup:",
+            "00000000 ff 15 00 00 00 00 call qword ptr [rip] ; REL32 __imp_ExitProcess",
+            "00000006 e8 00 00 00 00 call 0xb ; REL32 ExitProcess",
+            "0000000b c3 ret",
+        ]
+    );
+    // ... images the imports their calls end up at, through the thunk.
+    let image = disassemble("out.exe");
+    assert_eq!(
+        image[0],
+        "0000000140001000 ff 15 fa 0f 00 00 call qword ptr [0x140002000] ; kernel32.dll!ExitProcess"
+    );
+    assert!(
+        image
+            .last()
+            .unwrap()
+            .ends_with("jmp qword ptr [0x140002000] ; kernel32.dll!ExitProcess"),
+        "{image:?}"
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn decorated_exports() {
+    let text = Section {
+        name: ".text",
+        characteristics: IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ,
+        data: vec![0xc3; 3],
+        relocations: Vec::new(),
+    };
+    let symbols = [
+        ("_DllMainCRTStartup", 0, 1, 2),
+        ("_foo@8", 1, 1, 2),
+        ("@bar@4", 2, 1, 2),
+    ];
+
+    let dir = temp_dir("decorated-exports");
+    std::fs::write(dir.join("b.def"), "LIBRARY b\nEXPORTS\n    @bar@4\n").unwrap();
+    // `foo` is only defined decorated, `@bar@4` is exported undecorated.
+    link_with(
+        &dir,
+        &[("dll.obj", object(&[text], &symbols))],
+        &["/DEF:b.def", "/EXPORT:foo"],
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_winning"))
+        .args(["exports", "out.exe"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let listing = String::from_utf8(output.stdout).unwrap();
+    let rows = listing
+        .lines()
+        .skip(2)
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        [
+            vec!["1", "0x00001002", "bar"],
+            vec!["2", "0x00001001", "foo"],
+        ]
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}